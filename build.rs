@@ -0,0 +1,108 @@
+//! Generate OID constants of standard types from `resources/pg_type.tsv`.
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const PG_TYPE_FILE: &str = "resources/pg_type.tsv";
+
+struct PgType {
+    oid: u32,
+    name: String,
+    category: &'static str,
+    varlena: bool,
+    array_oid: u32,
+}
+
+fn parse_pg_types(content: &str) -> Vec<PgType> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(6, fields.len(), "malformed line in {PG_TYPE_FILE}: {line}");
+
+            let name = fields[1].to_owned();
+            let category = match (fields[2], fields[3]) {
+                // array types are named with a leading underscore, while
+                // types like `int2vector` are arrays in category only
+                ("b", "A") if name.starts_with('_') => "Array",
+                ("b", _) => "Base",
+                ("c", _) => "Composite",
+                ("r", _) => "Range",
+                ("m", _) => "Multirange",
+                ("p", _) => "Pseudo",
+                (t, _) => panic!("unknown typtype {t} of {name}"),
+            };
+            PgType {
+                oid: fields[0].parse().expect("invalid oid"),
+                category,
+                varlena: fields[4] == "-1",
+                array_oid: fields[5].parse().expect("invalid array oid"),
+                name,
+            }
+        })
+        .collect()
+}
+
+fn const_name(ty: &PgType) -> String {
+    let name = if ty.category == "Array" {
+        format!("{}_ARRAY", &ty.name[1..])
+    } else {
+        ty.name.clone()
+    };
+    format!("{}_OID", name.to_ascii_uppercase())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={PG_TYPE_FILE}");
+
+    let content = fs::read_to_string(PG_TYPE_FILE).expect("failed to read pg_type data");
+    let mut types = parse_pg_types(&content);
+    types.sort_by_key(|ty| ty.oid);
+
+    let mut out = String::new();
+    let mut names = HashSet::new();
+    for ty in &types {
+        let name = const_name(ty);
+        assert!(names.insert(name.clone()), "duplicate constant {name}");
+        let varlena = if ty.varlena { ", varlena" } else { "" };
+        writeln!(
+            out,
+            "/// `{}`, {} type{}\npub const {}: u32 = {};",
+            ty.name,
+            ty.category.to_ascii_lowercase(),
+            varlena,
+            name,
+            ty.oid
+        )
+        .unwrap();
+    }
+
+    out.push_str(
+        "\n/// All standard types, sorted by oid\npub const PG_TYPES: &[PgTypeInfo] = &[\n",
+    );
+    for ty in &types {
+        let array_oid = if ty.array_oid == 0 {
+            "None".to_owned()
+        } else {
+            format!("Some({})", ty.array_oid)
+        };
+        writeln!(
+            out,
+            "    PgTypeInfo {{ oid: {}, name: {:?}, category: TypeCategory::{}, varlena: {}, array_oid: {} }},",
+            const_name(ty),
+            ty.name,
+            ty.category,
+            ty.varlena,
+            array_oid
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("oid_constants.rs");
+    fs::write(dest, out).expect("failed to write oid constants");
+}
@@ -7,7 +7,9 @@ use tokio::net::TcpListener;
 use gluesql::prelude::*;
 use pgwire::api::auth::noop::NoopStartupHandler;
 use pgwire::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
-use pgwire::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use pgwire::api::results::{
+    CommandCompleteBuilder, DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response, Tag,
+};
 use pgwire::api::{ClientInfo, MakeHandler, StatelessMakeHandler, Type};
 use pgwire::error::{PgWireError, PgWireResult};
 use pgwire::tokio::process_socket;
@@ -16,6 +18,14 @@ pub struct GluesqlProcessor {
     glue: Arc<Mutex<Glue<MemoryStorage>>>,
 }
 
+fn row_count_tag(command: &str, rows: usize) -> PgWireResult<Response<'static>> {
+    CommandCompleteBuilder::new()
+        .command(command)
+        .affected_rows(rows as u64)
+        .build()
+        .map(Response::Execution)
+}
+
 #[async_trait]
 impl SimpleQueryHandler for GluesqlProcessor {
     async fn do_query<'a, C>(
@@ -132,23 +142,19 @@ impl SimpleQueryHandler for GluesqlProcessor {
 
                             Ok(Response::Query(QueryResponse::new(
                                 fields,
-                                stream::iter(results.into_iter()),
+                                stream::iter(results),
                             )))
                         }
-                        Payload::Insert(rows) => Ok(Response::Execution(
-                            Tag::new("INSERT").with_oid(0).with_rows(*rows),
-                        )),
-                        Payload::Delete(rows) => {
-                            Ok(Response::Execution(Tag::new("DELETE").with_rows(*rows)))
-                        }
-                        Payload::Update(rows) => {
-                            Ok(Response::Execution(Tag::new("UPDATE").with_rows(*rows)))
-                        }
-                        Payload::Create => Ok(Response::Execution(Tag::new("CREATE TABLE"))),
-                        Payload::AlterTable => Ok(Response::Execution(Tag::new("ALTER TABLE"))),
-                        Payload::DropTable => Ok(Response::Execution(Tag::new("DROP TABLE"))),
-                        Payload::CreateIndex => Ok(Response::Execution(Tag::new("CREATE INDEX"))),
-                        Payload::DropIndex => Ok(Response::Execution(Tag::new("DROP INDEX"))),
+                        // `INSERT 0 1`, `DELETE 1` and `UPDATE 1`
+                        Payload::Insert(rows) => row_count_tag("INSERT", *rows),
+                        Payload::Delete(rows) => row_count_tag("DELETE", *rows),
+                        Payload::Update(rows) => row_count_tag("UPDATE", *rows),
+                        // DDL tags are the full command name without row count
+                        Payload::Create => Ok(Response::Execution(Tag::ddl("CREATE TABLE"))),
+                        Payload::AlterTable => Ok(Response::Execution(Tag::ddl("ALTER TABLE"))),
+                        Payload::DropTable => Ok(Response::Execution(Tag::ddl("DROP TABLE"))),
+                        Payload::CreateIndex => Ok(Response::Execution(Tag::ddl("CREATE INDEX"))),
+                        Payload::DropIndex => Ok(Response::Execution(Tag::ddl("DROP INDEX"))),
                         _ => {
                             unimplemented!()
                         }
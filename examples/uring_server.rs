@@ -0,0 +1,95 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{stream, Sink, StreamExt};
+use tokio_uring::buf::fixed::FixedBufPool;
+use tokio_uring::net::TcpListener;
+
+use pgwire::api::auth::noop::NoopStartupHandler;
+use pgwire::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+use pgwire::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use pgwire::api::{ClientInfo, MakeHandler, StatelessMakeHandler, Type};
+use pgwire::error::{PgWireError, PgWireResult};
+use pgwire::io_uring::{process_socket_uring_with_options, UringSocketOptions};
+use pgwire::messages::PgWireBackendMessage;
+
+pub struct DummyProcessor;
+
+#[async_trait]
+impl SimpleQueryHandler for DummyProcessor {
+    async fn do_query<'a, C>(
+        &self,
+        _client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if query.starts_with("SELECT") {
+            let f1 = FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Text);
+            let f2 = FieldInfo::new("name".into(), None, None, Type::VARCHAR, FieldFormat::Text);
+            let schema = Arc::new(vec![f1, f2]);
+
+            let data = vec![
+                (Some(0), Some("Tom")),
+                (Some(1), Some("Jerry")),
+                (Some(2), None),
+            ];
+            let schema_ref = schema.clone();
+            let data_row_stream = stream::iter(data).map(move |r| {
+                let mut encoder = DataRowEncoder::new(schema_ref.clone());
+                encoder.encode_field(&r.0)?;
+                encoder.encode_field(&r.1)?;
+
+                encoder.finish()
+            });
+
+            Ok(vec![Response::Query(QueryResponse::new(
+                schema,
+                data_row_stream,
+            ))])
+        } else {
+            Ok(vec![Response::Execution(Tag::new("OK").with_rows(1))])
+        }
+    }
+}
+
+pub fn main() {
+    tokio_uring::start(async {
+        let processor = Arc::new(StatelessMakeHandler::new(Arc::new(DummyProcessor)));
+        let placeholder = Arc::new(StatelessMakeHandler::new(Arc::new(
+            PlaceholderExtendedQueryHandler,
+        )));
+        let authenticator = Arc::new(StatelessMakeHandler::new(Arc::new(NoopStartupHandler)));
+
+        // registered buffers shared by all connections of this thread
+        let buffer_pool = FixedBufPool::new((0..64).map(|_| Vec::with_capacity(8192)));
+        buffer_pool.register().unwrap();
+
+        let server_addr = "127.0.0.1:5432";
+        let listener = TcpListener::bind(server_addr.parse().unwrap()).unwrap();
+        println!("Listening to {}", server_addr);
+        loop {
+            let (socket, addr) = listener.accept().await.unwrap();
+            let authenticator_ref = authenticator.make();
+            let processor_ref = processor.make();
+            let placeholder_ref = placeholder.make();
+            let mut options = UringSocketOptions::default();
+            options.buffer_pool = Some(buffer_pool.clone());
+            tokio_uring::spawn(async move {
+                process_socket_uring_with_options(
+                    socket,
+                    addr,
+                    authenticator_ref,
+                    processor_ref,
+                    placeholder_ref,
+                    options,
+                )
+                .await
+            });
+        }
+    });
+}
@@ -0,0 +1,231 @@
+//! Conversion between result schemas and Apache Arrow schemas, for serving
+//! Arrow record batches from a query engine.
+
+use std::sync::Arc;
+
+use arrow_schema::{DataType, Field, IntervalUnit, Schema, TimeUnit};
+use postgres_types::Kind;
+
+use super::results::{FieldFormat, FieldInfo};
+use super::Type;
+use crate::messages::data::RowDescription;
+use crate::oid_constants::type_info;
+
+/// Offset of `numeric` type modifier, which is `(precision << 16 | scale) + 4`
+const VARHDRSZ: i32 = 4;
+/// Max precision of `Decimal128`
+const DECIMAL128_MAX_PRECISION: i32 = 38;
+
+/// Arrow type of values of postgres type `ty`.
+///
+/// `numeric` with precision up to 38 in `type_modifier` is `Decimal128`.
+/// Types without a native Arrow counterpart are mapped to `Utf8`, in which
+/// their text format can be stored.
+pub fn to_arrow_type(ty: &Type, type_modifier: i32) -> DataType {
+    if let Kind::Array(element) = ty.kind() {
+        return DataType::List(Arc::new(Field::new(
+            "item",
+            to_arrow_type(element, type_modifier),
+            true,
+        )));
+    }
+
+    match *ty {
+        Type::BOOL => DataType::Boolean,
+        Type::CHAR => DataType::Int8,
+        Type::INT2 => DataType::Int16,
+        Type::INT4 => DataType::Int32,
+        Type::INT8 => DataType::Int64,
+        Type::OID => DataType::UInt32,
+        Type::FLOAT4 => DataType::Float32,
+        Type::FLOAT8 => DataType::Float64,
+        Type::NUMERIC => {
+            let precision = (type_modifier - VARHDRSZ) >> 16;
+            let scale = (type_modifier - VARHDRSZ) & 0xffff;
+            if type_modifier >= VARHDRSZ && (1..=DECIMAL128_MAX_PRECISION).contains(&precision) {
+                DataType::Decimal128(precision as u8, scale as i8)
+            } else {
+                DataType::Utf8
+            }
+        }
+        Type::BYTEA => DataType::Binary,
+        Type::UUID => DataType::FixedSizeBinary(16),
+        Type::DATE => DataType::Date32,
+        Type::TIME => DataType::Time64(TimeUnit::Microsecond),
+        Type::TIMESTAMP => DataType::Timestamp(TimeUnit::Microsecond, None),
+        Type::TIMESTAMPTZ => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        Type::INTERVAL => DataType::Interval(IntervalUnit::MonthDayNano),
+        _ => DataType::Utf8,
+    }
+}
+
+/// Postgres type for values of Arrow type `data_type`.
+///
+/// Integers are widened to the smallest postgres type that holds all values.
+/// Types without a postgres counterpart are mapped to `text`.
+pub fn from_arrow_type(data_type: &DataType) -> Type {
+    match data_type {
+        DataType::Boolean => Type::BOOL,
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => Type::INT2,
+        DataType::Int32 | DataType::UInt16 => Type::INT4,
+        DataType::Int64 | DataType::UInt32 => Type::INT8,
+        DataType::UInt64 | DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => Type::NUMERIC,
+        DataType::Float16 | DataType::Float32 => Type::FLOAT4,
+        DataType::Float64 => Type::FLOAT8,
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => Type::BYTEA,
+        DataType::Date32 | DataType::Date64 => Type::DATE,
+        DataType::Time32(_) | DataType::Time64(_) => Type::TIME,
+        DataType::Timestamp(_, None) => Type::TIMESTAMP,
+        DataType::Timestamp(_, Some(_)) => Type::TIMESTAMPTZ,
+        DataType::Interval(_) | DataType::Duration(_) => Type::INTERVAL,
+        DataType::Dictionary(_, value) => from_arrow_type(value),
+        DataType::List(field)
+        | DataType::LargeList(field)
+        | DataType::FixedSizeList(field, _)
+        | DataType::ListView(field)
+        | DataType::LargeListView(field) => {
+            let element = from_arrow_type(field.data_type());
+            type_info(element.oid())
+                .and_then(|info| info.array_oid)
+                .and_then(Type::from_oid)
+                .unwrap_or(Type::TEXT_ARRAY)
+        }
+        _ => Type::TEXT,
+    }
+}
+
+impl RowDescription {
+    /// Arrow schema of the result, with all fields nullable.
+    ///
+    /// Types unknown to this library are mapped to `Utf8`, see
+    /// [`to_arrow_type`].
+    pub fn to_arrow_schema(&self) -> Schema {
+        Schema::new(
+            self.fields
+                .iter()
+                .map(|field| {
+                    let data_type = Type::from_oid(field.type_id)
+                        .map(|ty| to_arrow_type(&ty, field.type_modifier))
+                        .unwrap_or(DataType::Utf8);
+                    Field::new(&field.name, data_type, true)
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Result schema of Arrow `schema`, in text format, see
+    /// [`from_arrow_type`].
+    pub fn from_arrow_schema(schema: &Schema) -> Vec<FieldInfo> {
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                FieldInfo::new(
+                    field.name().clone(),
+                    None,
+                    None,
+                    from_arrow_type(field.data_type()),
+                    FieldFormat::Text,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::messages::data::FieldDescription;
+
+    #[test]
+    fn test_to_arrow_schema() {
+        let field = |name: &str, ty: Type, type_modifier: i32| {
+            FieldDescription::new(name.to_owned(), 0, 0, ty.oid(), -1, type_modifier, 0)
+        };
+        let row_description = RowDescription::new(vec![
+            field("id", Type::INT4, -1),
+            field("score", Type::FLOAT8, -1),
+            field("name", Type::VARCHAR, -1),
+            field("active", Type::BOOL, -1),
+            field("created", Type::TIMESTAMP, -1),
+            field("price", Type::NUMERIC, (10 << 16 | 2) + 4),
+            field("total", Type::NUMERIC, -1),
+            field("tags", Type::TEXT_ARRAY, -1),
+        ]);
+
+        let schema = row_description.to_arrow_schema();
+        let types = schema
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                DataType::Int32,
+                DataType::Float64,
+                DataType::Utf8,
+                DataType::Boolean,
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                DataType::Decimal128(10, 2),
+                DataType::Utf8,
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            ],
+            types
+        );
+        assert_eq!("price", schema.field(5).name());
+        assert!(schema.field(0).is_nullable());
+    }
+
+    #[test]
+    fn test_from_arrow_schema() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("small", DataType::UInt8, true),
+            Field::new("name", DataType::LargeUtf8, true),
+            Field::new(
+                "at",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("+08:00".into())),
+                true,
+            ),
+            Field::new(
+                "ids",
+                DataType::List(Arc::new(Field::new("item", DataType::Int32, true))),
+                true,
+            ),
+            Field::new(
+                "city",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                true,
+            ),
+        ]);
+
+        let fields = RowDescription::from_arrow_schema(&schema);
+        let types = fields
+            .iter()
+            .map(|f| f.datatype().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Type::INT8,
+                Type::INT2,
+                Type::TEXT,
+                Type::TIMESTAMPTZ,
+                Type::INT4_ARRAY,
+                Type::TEXT
+            ],
+            types
+        );
+        assert_eq!("id", fields[0].name());
+        assert_eq!(FieldFormat::Text, fields[0].format());
+
+        // types round trip through arrow
+        let row_description = RowDescription::new(fields.iter().map(Into::into).collect());
+        assert_eq!(
+            schema.field(0).data_type(),
+            row_description.to_arrow_schema().field(0).data_type()
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Client certificate authentication, like the `cert` method of Postgres.
+//!
+//! Client is authenticated by the certificate it presented during TLS
+//! handshake, no password is exchanged. Verifying the certificate chain is
+//! the job of TLS acceptor: it should be configured with a client certificate
+//! verifier, otherwise any self-signed certificate will be accepted here.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+use x509_certificate::certificate::CapturedX509Certificate;
+
+use super::{
+    ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider, StartupHandler,
+};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// DER encoded OID of subjectAltName extension, 2.5.29.17
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+/// Context specific tags of `GeneralName` choices
+const GENERAL_NAME_RFC822: u8 = 0x81;
+const GENERAL_NAME_DNS: u8 = 0x82;
+
+/// Identities extracted from the client certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientCertificate {
+    common_name: Option<String>,
+    dns_names: Vec<String>,
+    email_addresses: Vec<String>,
+}
+
+impl ClientCertificate {
+    /// Parse a DER encoded certificate.
+    pub fn from_der(der: &[u8]) -> PgWireResult<ClientCertificate> {
+        let x509 = CapturedX509Certificate::from_der(der.to_vec())
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+
+        let mut certificate = ClientCertificate {
+            common_name: x509.subject_common_name(),
+            ..Default::default()
+        };
+        for ext in x509.iter_extensions() {
+            if ext.id.as_ref() == OID_SUBJECT_ALT_NAME {
+                certificate.read_subject_alt_names(&ext.value.to_bytes());
+            }
+        }
+        Ok(certificate)
+    }
+
+    /// CN attribute of the certificate subject.
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    /// dNSName entries of subjectAltName extension.
+    pub fn dns_names(&self) -> &[String] {
+        &self.dns_names
+    }
+
+    /// rfc822Name entries of subjectAltName extension.
+    pub fn email_addresses(&self) -> &[String] {
+        &self.email_addresses
+    }
+
+    fn read_subject_alt_names(&mut self, data: &[u8]) {
+        // GeneralNames ::= SEQUENCE OF GeneralName
+        let Some((0x30, mut names, _)) = read_der(data) else {
+            return;
+        };
+        while let Some((tag, value, rest)) = read_der(names) {
+            let value = String::from_utf8_lossy(value).into_owned();
+            match tag {
+                GENERAL_NAME_DNS => self.dns_names.push(value),
+                GENERAL_NAME_RFC822 => self.email_addresses.push(value),
+                _ => {}
+            }
+            names = rest;
+        }
+    }
+}
+
+/// Read a DER tag-length-value, returns tag, value and remaining bytes.
+fn read_der(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+    let len = if len < 0x80 {
+        len as usize
+    } else {
+        let octets = (len & 0x7f) as usize;
+        if octets == 0 || octets > 4 || data.len() < octets {
+            return None;
+        }
+        let (len_bytes, rest) = data.split_at(octets);
+        data = rest;
+        len_bytes
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize)
+    };
+    if data.len() < len {
+        return None;
+    }
+    let (value, rest) = data.split_at(len);
+    Some((tag, value, rest))
+}
+
+/// Maps a client certificate to the database user it identifies.
+#[async_trait]
+pub trait CertificateUserMapper: Send + Sync {
+    /// Returns the database user for `certificate`, or `None` if it's not
+    /// allowed to login as anyone.
+    ///
+    /// The login is accepted only when the returned user equals the `user`
+    /// startup parameter of `login`.
+    async fn map_user(
+        &self,
+        login: &LoginInfo,
+        certificate: &ClientCertificate,
+    ) -> PgWireResult<Option<String>>;
+}
+
+/// Maps certificate to the user named by its CN, which is the default of
+/// Postgres.
+#[derive(Debug, Default)]
+pub struct CommonNameUserMapper;
+
+#[async_trait]
+impl CertificateUserMapper for CommonNameUserMapper {
+    async fn map_user(
+        &self,
+        _login: &LoginInfo,
+        certificate: &ClientCertificate,
+    ) -> PgWireResult<Option<String>> {
+        Ok(certificate.common_name().map(str::to_owned))
+    }
+}
+
+/// Authenticates client by its TLS certificate, without password exchange.
+#[derive(new)]
+pub struct CertAuthStartupHandler<M, P> {
+    user_mapper: M,
+    parameter_provider: P,
+}
+
+#[async_trait]
+impl<M: CertificateUserMapper, P: ServerParameterProvider> StartupHandler
+    for CertAuthStartupHandler<M, P>
+{
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let PgWireFrontendMessage::Startup(ref startup) = message else {
+            return Err(PgWireError::unexpected_message("Startup", &message));
+        };
+        super::save_startup_parameters_to_metadata(client, startup);
+        client.set_state(PgWireConnectionState::AuthenticationInProgress);
+
+        let Some(der) = client.client_certificates().and_then(|certs| certs.first()) else {
+            return super::reject_authentication(
+                client,
+                "28000",
+                "connection requires a valid client certificate".to_owned(),
+            )
+            .await;
+        };
+        let certificate = ClientCertificate::from_der(der)?;
+
+        let login_info = LoginInfo::from_client_info(client);
+        let user = login_info.user().unwrap_or_default().to_owned();
+        let mapped = self.user_mapper.map_user(&login_info, &certificate).await?;
+        if mapped.as_deref() == Some(user.as_str()) {
+            super::finish_authentication(client, &self.parameter_provider).await;
+            Ok(())
+        } else {
+            super::reject_authentication(
+                client,
+                "28000",
+                format!("certificate authentication failed for user \"{user}\""),
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // self-signed, CN=alice, SAN DNS:alice.example.com, email:alice@example.com
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBqTCCAVCgAwIBAgIUPcRb2ghLSCiJHXgxHR2g8LJ1PsQwCgYIKoZIzj0EAwIw
+EDEOMAwGA1UEAwwFYWxpY2UwIBcNMjYxMDE2MDkxMzI3WhgPMjEyNjA5MjIwOTEz
+MjdaMBAxDjAMBgNVBAMMBWFsaWNlMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE
+Lnl41HHCIRg+x6nEfDOBH5dp/UXw6SLMtGy1UfOYdmaZM6siqxpWAca251a0G7En
+mLkpr6HvHpTf6rf+RkH/+KOBhTCBgjAdBgNVHQ4EFgQUuJHvG1XKJXYIf5kh74UX
+QVGmvzMwHwYDVR0jBBgwFoAUuJHvG1XKJXYIf5kh74UXQVGmvzMwDwYDVR0TAQH/
+BAUwAwEB/zAvBgNVHREEKDAmghFhbGljZS5leGFtcGxlLmNvbYERYWxpY2VAZXhh
+bXBsZS5jb20wCgYIKoZIzj0EAwIDRwAwRAIgVVTX3CFtp5KfiRgIDwj4BrLJfIRw
+ScHOSiGBzBgQAMQCIHlIKBiMDy8EilgWbatS0sxR2oohEDo0xVd4kQsV1fgk
+-----END CERTIFICATE-----
+";
+
+    fn cert_der() -> Vec<u8> {
+        CapturedX509Certificate::from_pem(CERT)
+            .unwrap()
+            .constructed_data()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_client_certificate() {
+        let certificate = ClientCertificate::from_der(&cert_der()).unwrap();
+        assert_eq!(Some("alice"), certificate.common_name());
+        assert_eq!(&["alice.example.com".to_owned()], certificate.dns_names());
+        assert_eq!(
+            &["alice@example.com".to_owned()],
+            certificate.email_addresses()
+        );
+
+        assert!(ClientCertificate::from_der(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_read_der() {
+        assert_eq!(
+            Some((0x04, &[1u8, 2][..], &[9u8][..])),
+            read_der(&[4, 2, 1, 2, 9])
+        );
+        let mut long = vec![0x04, 0x81, 0x80];
+        long.extend(std::iter::repeat(0).take(0x80));
+        assert_eq!(0x80, read_der(&long).unwrap().1.len());
+        assert_eq!(None, read_der(&[4, 3, 1, 2]));
+        assert_eq!(None, read_der(&[4, 0x80]));
+    }
+}
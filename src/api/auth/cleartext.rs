@@ -2,13 +2,14 @@ use std::fmt::Debug;
 
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
+use tracing::Instrument;
 
 use super::{
-    AuthSource, ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider,
-    StartupHandler,
+    AuthSource, ClientInfo, LoginInfo, PasswordVerifier, PgWireConnectionState,
+    ServerParameterProvider, StartupHandler,
 };
-use crate::error::{ErrorInfo, PgWireError, PgWireResult};
-use crate::messages::response::ErrorResponse;
+use crate::api::trace::auth_span;
+use crate::error::{PgWireError, PgWireResult};
 use crate::messages::startup::Authentication;
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
@@ -45,24 +46,71 @@ impl<V: AuthSource, P: ServerParameterProvider> StartupHandler
             PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
                 let pwd = pwd.into_password()?;
                 let login_info = LoginInfo::from_client_info(client);
-                let pass = self.auth_source.get_password(&login_info).await?;
+                let pass = self
+                    .auth_source
+                    .get_password(&login_info)
+                    .instrument(auth_span(&login_info))
+                    .await?;
                 if pass.password == pwd.password.as_bytes() {
                     super::finish_authentication(client, &self.parameter_provider).await
                 } else {
-                    let error_info = ErrorInfo::new(
-                        "FATAL".to_owned(),
-                        "28P01".to_owned(),
-                        "Password authentication failed".to_owned(),
-                    );
-                    let error = ErrorResponse::from(error_info);
+                    super::reject_password(client).await?;
+                }
+            }
+            msg => return Err(PgWireError::unexpected_message("PasswordMessage", &msg)),
+        }
+        Ok(())
+    }
+}
 
-                    client
-                        .feed(PgWireBackendMessage::ErrorResponse(error))
-                        .await?;
-                    client.close().await?;
+/// Cleartext password authentication, with password checked by a
+/// `PasswordVerifier`.
+#[derive(new)]
+pub struct CleartextPasswordStartupHandler<V, P> {
+    verifier: V,
+    parameter_provider: P,
+}
+
+#[async_trait]
+impl<V: PasswordVerifier, P: ServerParameterProvider> StartupHandler
+    for CleartextPasswordStartupHandler<V, P>
+{
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                super::save_startup_parameters_to_metadata(client, startup);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        Authentication::CleartextPassword,
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
+                let pwd = pwd.into_password()?;
+                let login_info = LoginInfo::from_client_info(client);
+                let username = login_info.user().unwrap_or_default();
+                if self
+                    .verifier
+                    .verify(username, &pwd.password)
+                    .instrument(auth_span(&login_info))
+                    .await?
+                {
+                    super::finish_authentication(client, &self.parameter_provider).await
+                } else {
+                    super::reject_password(client).await?;
                 }
             }
-            _ => {}
+            msg => return Err(PgWireError::unexpected_message("PasswordMessage", &msg)),
         }
         Ok(())
     }
@@ -0,0 +1,373 @@
+//! GSSAPI authentication, like the `gss` method of Postgres.
+//!
+//! pgwire only drives the message flow: `AuthenticationGSS`, then
+//! `GSSResponse`/`AuthenticationGSSContinue` until the security context is
+//! established. Tokens are processed by a [`GssProvider`], which is usually a
+//! binding to the system GSSAPI library with the server keytab.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::sink::{Sink, SinkExt};
+
+use super::{
+    ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider, StartupHandler,
+};
+use crate::api::MakeHandler;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::startup::Authentication;
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Result of processing one token from client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GssAcceptStep {
+    /// Context is not established yet, the token is sent to client, which
+    /// will reply with another `GSSResponse`.
+    Continue(Bytes),
+    /// Context is established. `token`, if any, is sent to client before
+    /// authentication completes.
+    Complete {
+        token: Option<Bytes>,
+        /// Authenticated principal of client, like `alice@EXAMPLE.COM`
+        principal: String,
+    },
+}
+
+/// Security context on acceptor side, created for each connection.
+pub trait GssContext: Send {
+    /// Process a token received from client.
+    fn step(&mut self, token: &[u8]) -> PgWireResult<GssAcceptStep>;
+}
+
+/// Message protection of an established context, used by GSSAPI transport
+/// encryption.
+pub trait GssWrap: Send + Sync {
+    /// Encrypt `data` into a token, like `gss_wrap` with confidentiality.
+    fn wrap(&mut self, data: &[u8]) -> PgWireResult<Vec<u8>>;
+
+    /// Decrypt a token from client, like `gss_unwrap`.
+    fn unwrap(&mut self, token: &[u8]) -> PgWireResult<Vec<u8>>;
+
+    /// Max length of data whose token fits in `max_token` bytes, like
+    /// `gss_wrap_size_limit`. The default leaves room for the overhead of
+    /// Kerberos tokens.
+    fn wrap_size_limit(&self, max_token: usize) -> usize {
+        max_token.saturating_sub(128)
+    }
+}
+
+/// Creates acceptor security contexts, typically with the server credential
+/// acquired from keytab.
+pub trait GssProvider: Send + Sync {
+    type Context: GssContext;
+
+    fn new_context(&self) -> PgWireResult<Self::Context>;
+}
+
+/// Mechanism requested from client. SSPI shares the token exchange of
+/// GSSAPI, only the initial authentication request differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GssMechanism {
+    Gss,
+    #[cfg_attr(not(all(windows, feature = "sspi")), allow(dead_code))]
+    Sspi,
+}
+
+impl GssMechanism {
+    fn request(&self) -> Authentication {
+        match self {
+            GssMechanism::Gss => Authentication::GSS,
+            GssMechanism::Sspi => Authentication::SSPI,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            GssMechanism::Gss => "GSSAPI",
+            GssMechanism::Sspi => "SSPI",
+        }
+    }
+}
+
+pub struct GssAuthStartupHandler<G: GssProvider, P> {
+    provider: Arc<G>,
+    parameter_provider: Arc<P>,
+    context: Mutex<Option<G::Context>>,
+    include_realm: bool,
+    mechanism: GssMechanism,
+}
+
+#[async_trait]
+impl<G: GssProvider, P: ServerParameterProvider> StartupHandler for GssAuthStartupHandler<G, P> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                super::save_startup_parameters_to_metadata(client, startup);
+                *self.context.lock().unwrap() = Some(self.provider.new_context()?);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        self.mechanism.request(),
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(msg) => {
+                let response = msg.into_gss_response()?;
+                let step = {
+                    let mut context = self.context.lock().unwrap();
+                    let context = context
+                        .as_mut()
+                        .ok_or(PgWireError::UnsupportedAuthenticationMethod)?;
+                    context.step(&response.data)?
+                };
+
+                match step {
+                    GssAcceptStep::Continue(token) => {
+                        client
+                            .send(PgWireBackendMessage::Authentication(
+                                Authentication::GSSContinue(token),
+                            ))
+                            .await?;
+                    }
+                    GssAcceptStep::Complete { token, principal } => {
+                        self.context.lock().unwrap().take();
+                        if let Some(token) = token {
+                            client
+                                .feed(PgWireBackendMessage::Authentication(
+                                    Authentication::GSSContinue(token),
+                                ))
+                                .await?;
+                        }
+
+                        let login_info = LoginInfo::from_client_info(client);
+                        let user = login_info.user().unwrap_or_default().to_owned();
+                        if principal_matches(&principal, &user, self.include_realm) {
+                            super::finish_authentication(client, self.parameter_provider.as_ref())
+                                .await;
+                        } else {
+                            super::reject_authentication(
+                                client,
+                                "28000",
+                                format!(
+                                    "{} authentication failed for user \"{user}\"",
+                                    self.mechanism.name()
+                                ),
+                            )
+                            .await?;
+                        }
+                    }
+                }
+            }
+            msg => return Err(PgWireError::unexpected_message("GSSResponse", &msg)),
+        }
+        Ok(())
+    }
+}
+
+/// Compare authenticated principal with requested user. Without
+/// `include_realm`, the realm part of principal is stripped before
+/// comparison.
+fn principal_matches(principal: &str, user: &str, include_realm: bool) -> bool {
+    if include_realm {
+        principal == user
+    } else {
+        principal
+            .split_once('@')
+            .map_or(principal, |(name, _realm)| name)
+            == user
+    }
+}
+
+#[derive(Debug)]
+pub struct MakeGssAuthStartupHandler<G, P> {
+    provider: Arc<G>,
+    parameter_provider: Arc<P>,
+    include_realm: bool,
+    mechanism: GssMechanism,
+}
+
+impl<G, P> MakeGssAuthStartupHandler<G, P> {
+    pub fn new(provider: Arc<G>, parameter_provider: Arc<P>) -> Self {
+        Self::with_mechanism(provider, parameter_provider, GssMechanism::Gss)
+    }
+
+    pub(crate) fn with_mechanism(
+        provider: Arc<G>,
+        parameter_provider: Arc<P>,
+        mechanism: GssMechanism,
+    ) -> Self {
+        MakeGssAuthStartupHandler {
+            provider,
+            parameter_provider,
+            include_realm: true,
+            mechanism,
+        }
+    }
+
+    /// Whether realm of the principal is kept when comparing with user name,
+    /// `true` by default as in Postgres.
+    pub fn set_include_realm(&mut self, include_realm: bool) {
+        self.include_realm = include_realm;
+    }
+}
+
+impl<G, P> MakeHandler for MakeGssAuthStartupHandler<G, P>
+where
+    G: GssProvider,
+    P: ServerParameterProvider,
+{
+    type Handler = Arc<GssAuthStartupHandler<G, P>>;
+
+    fn make(&self) -> Self::Handler {
+        Arc::new(GssAuthStartupHandler {
+            provider: self.provider.clone(),
+            parameter_provider: self.parameter_provider.clone(),
+            context: Mutex::new(None),
+            include_realm: self.include_realm,
+            mechanism: self.mechanism,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use bytes::BytesMut;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::api::auth::DefaultServerParameterProvider;
+    use crate::messages::startup::{GSSResponse, PasswordMessageFamily, Startup};
+    use crate::messages::Message;
+    use crate::sync::SyncClient;
+
+    /// Accepts after two tokens, `ping` then `pong`.
+    struct TwoStepContext {
+        steps: usize,
+    }
+
+    impl GssContext for TwoStepContext {
+        fn step(&mut self, token: &[u8]) -> PgWireResult<GssAcceptStep> {
+            self.steps += 1;
+            match (self.steps, token) {
+                (1, b"ping") => Ok(GssAcceptStep::Continue(Bytes::from_static(b"pong"))),
+                (2, b"pong") => Ok(GssAcceptStep::Complete {
+                    token: Some(Bytes::from_static(b"done")),
+                    principal: "alice@EXAMPLE.COM".to_owned(),
+                }),
+                _ => Err(PgWireError::UnsupportedAuthenticationMethod),
+            }
+        }
+    }
+
+    struct TwoStepProvider;
+
+    impl GssProvider for TwoStepProvider {
+        type Context = TwoStepContext;
+
+        fn new_context(&self) -> PgWireResult<Self::Context> {
+            Ok(TwoStepContext { steps: 0 })
+        }
+    }
+
+    fn authenticate(
+        user: &str,
+        include_realm: bool,
+        mechanism: GssMechanism,
+    ) -> Vec<PgWireBackendMessage> {
+        let mut make = MakeGssAuthStartupHandler::with_mechanism(
+            Arc::new(TwoStepProvider),
+            Arc::new(DefaultServerParameterProvider::default()),
+            mechanism,
+        );
+        make.set_include_realm(include_realm);
+        let handler = make.make();
+        let mut client = SyncClient::<_, String>::new(Cursor::new(Vec::new()));
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), user.to_owned());
+        let gss_response = |data: &'static [u8]| {
+            let mut buf = BytesMut::new();
+            GSSResponse::new(Bytes::from_static(data))
+                .encode_body(&mut buf)
+                .unwrap();
+            PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(buf))
+        };
+        for message in [
+            PgWireFrontendMessage::Startup(startup),
+            gss_response(b"ping"),
+            gss_response(b"pong"),
+        ] {
+            block_on(handler.on_startup(&mut client, message)).unwrap();
+        }
+
+        let mut buf = BytesMut::from(&client.get_ref().get_ref()[..]);
+        let mut messages = Vec::new();
+        while let Some(message) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn is_auth(message: &PgWireBackendMessage, expected: &Authentication) -> bool {
+        matches!(message, PgWireBackendMessage::Authentication(auth) if auth == expected)
+    }
+
+    #[test]
+    fn test_gss_authentication() {
+        let messages = authenticate("alice@EXAMPLE.COM", true, GssMechanism::Gss);
+        assert!(is_auth(&messages[0], &Authentication::GSS));
+        assert!(is_auth(
+            &messages[1],
+            &Authentication::GSSContinue(Bytes::from_static(b"pong"))
+        ));
+        assert!(is_auth(
+            &messages[2],
+            &Authentication::GSSContinue(Bytes::from_static(b"done"))
+        ));
+        assert!(is_auth(&messages[3], &Authentication::Ok));
+
+        let messages = authenticate("alice", false, GssMechanism::Gss);
+        assert!(is_auth(&messages[3], &Authentication::Ok));
+
+        let messages = authenticate("alice", true, GssMechanism::Gss);
+        assert!(matches!(
+            messages[3],
+            PgWireBackendMessage::ErrorResponse(_)
+        ));
+    }
+
+    #[test]
+    fn test_sspi_authentication() {
+        let messages = authenticate("alice@EXAMPLE.COM", true, GssMechanism::Sspi);
+        assert!(is_auth(&messages[0], &Authentication::SSPI));
+        assert!(is_auth(&messages[3], &Authentication::Ok));
+    }
+
+    #[test]
+    fn test_principal_matches() {
+        assert!(principal_matches(
+            "alice@EXAMPLE.COM",
+            "alice@EXAMPLE.COM",
+            true
+        ));
+        assert!(!principal_matches("alice@EXAMPLE.COM", "alice", true));
+        assert!(principal_matches("alice@EXAMPLE.COM", "alice", false));
+        assert!(principal_matches("alice", "alice", false));
+        assert!(!principal_matches("bob@EXAMPLE.COM", "alice", false));
+    }
+}
@@ -0,0 +1,579 @@
+//! Host based authentication rules, like `pg_hba.conf` of Postgres.
+//!
+//! [`HbaRules`] picks the authentication method for a connection with the
+//! first rule matching its connection type, database, user and client
+//! address. [`MakeHbaStartupHandler`] then delegates startup messages to the
+//! handler of that method, so different clients can use different methods
+//! on the same server.
+//!
+//! ```text
+//! # TYPE     DATABASE  USER   ADDRESS       METHOD
+//! hostssl    all       all    0.0.0.0/0     cert
+//! host       all       admin  all           reject
+//! host       sameuser  all    10.0.0.0/8    scram-sha-256
+//! host       all       all    127.0.0.1/32  trust
+//! ```
+
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+
+use super::{ClientInfo, StartupHandler};
+use crate::api::{MakeHandler, METADATA_DATABASE, METADATA_USER};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::startup::{CancelRequest, Startup};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Which connections a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HbaConnectionType {
+    /// TCP connection with or without TLS
+    Host,
+    /// TCP connection with TLS
+    HostSsl,
+    /// TCP connection without TLS
+    HostNoSsl,
+}
+
+/// Authentication method of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HbaMethod {
+    Trust,
+    Reject,
+    Md5,
+    ScramSha256,
+    Cert,
+}
+
+impl FromStr for HbaMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trust" => Ok(HbaMethod::Trust),
+            "reject" => Ok(HbaMethod::Reject),
+            "md5" => Ok(HbaMethod::Md5),
+            "scram-sha-256" => Ok(HbaMethod::ScramSha256),
+            "cert" => Ok(HbaMethod::Cert),
+            _ => Err(format!("unsupported authentication method \"{s}\"")),
+        }
+    }
+}
+
+/// Client address of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HbaAddress {
+    All,
+    Cidr(IpAddr, u8),
+}
+
+impl HbaAddress {
+    fn matches(&self, addr: IpAddr) -> bool {
+        match (self, addr) {
+            (HbaAddress::All, _) => true,
+            (HbaAddress::Cidr(IpAddr::V4(net), len), IpAddr::V4(addr)) => {
+                prefix_matches(&net.octets(), &addr.octets(), *len)
+            }
+            (HbaAddress::Cidr(IpAddr::V6(net), len), IpAddr::V6(addr)) => {
+                prefix_matches(&net.octets(), &addr.octets(), *len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for HbaAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(HbaAddress::All);
+        }
+        let (addr, len) = s.split_once('/').map_or((s, None), |(a, l)| (a, Some(l)));
+        let addr = IpAddr::from_str(addr).map_err(|_| format!("invalid IP address \"{s}\""))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let len = match len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid CIDR mask in address \"{s}\""))?,
+            None => max_len,
+        };
+        Ok(HbaAddress::Cidr(addr, len))
+    }
+}
+
+fn prefix_matches(net: &[u8], addr: &[u8], len: u8) -> bool {
+    let len = len as usize;
+    let (full, rest) = (len / 8, len % 8);
+    if net[..full] != addr[..full] {
+        return false;
+    }
+    if rest == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rest);
+    net[full] & mask == addr[full] & mask
+}
+
+/// A line of rules
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct HbaRule {
+    pub connection_type: HbaConnectionType,
+    /// Database names, or keywords `all` and `sameuser`
+    pub databases: Vec<String>,
+    /// User names, or keyword `all`
+    pub users: Vec<String>,
+    pub address: HbaAddress,
+    pub method: HbaMethod,
+}
+
+impl HbaRule {
+    fn matches(&self, secure: bool, addr: IpAddr, database: &str, user: &str) -> bool {
+        let type_matches = match self.connection_type {
+            HbaConnectionType::Host => true,
+            HbaConnectionType::HostSsl => secure,
+            HbaConnectionType::HostNoSsl => !secure,
+        };
+        type_matches
+            && self.databases.iter().any(|d| match d.as_str() {
+                "all" => true,
+                "sameuser" => database == user,
+                d => d == database,
+            })
+            && self.users.iter().any(|u| u == "all" || u == user)
+            && self.address.matches(addr)
+    }
+}
+
+/// Ordered authentication rules, the first matching rule wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HbaRules {
+    rules: Vec<HbaRule>,
+}
+
+impl HbaRules {
+    pub fn new(rules: Vec<HbaRule>) -> HbaRules {
+        HbaRules { rules }
+    }
+
+    /// Parse rules in `pg_hba.conf` format. Each line has connection type,
+    /// database, user, address and method, separated by whitespace. Database
+    /// and user can be comma separated lists. `#` starts a comment.
+    pub fn parse(conf: &str) -> PgWireResult<HbaRules> {
+        let mut rules = Vec::new();
+        for (lineno, line) in conf.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.is_empty() {
+                continue;
+            }
+            let rule = Self::parse_rule(&fields)
+                .map_err(|e| PgWireError::ApiError(format!("line {}: {e}", lineno + 1).into()))?;
+            rules.push(rule);
+        }
+        Ok(HbaRules { rules })
+    }
+
+    fn parse_rule(fields: &[&str]) -> Result<HbaRule, String> {
+        let [connection_type, databases, users, address, method] = fields else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+        let connection_type = match *connection_type {
+            "host" => HbaConnectionType::Host,
+            "hostssl" => HbaConnectionType::HostSsl,
+            "hostnossl" => HbaConnectionType::HostNoSsl,
+            t => return Err(format!("unsupported connection type \"{t}\"")),
+        };
+        let list = |s: &str| s.split(',').map(str::to_owned).collect::<Vec<_>>();
+        Ok(HbaRule {
+            connection_type,
+            databases: list(databases),
+            users: list(users),
+            address: address.parse()?,
+            method: method.parse()?,
+        })
+    }
+
+    /// Method of the first rule matching the connection, `None` if no rule
+    /// matches.
+    pub fn match_method(
+        &self,
+        secure: bool,
+        addr: IpAddr,
+        database: &str,
+        user: &str,
+    ) -> Option<HbaMethod> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(secure, addr, database, user))
+            .map(|rule| rule.method)
+    }
+}
+
+/// Placeholder for a method without handler. Clients selecting it are
+/// rejected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unconfigured;
+
+impl MakeHandler for Unconfigured {
+    type Handler = Unconfigured;
+
+    fn make(&self) -> Self::Handler {
+        Unconfigured
+    }
+}
+
+#[async_trait]
+impl StartupHandler for Unconfigured {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        _message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        super::reject_authentication(
+            client,
+            "28000",
+            "authentication method is not configured".to_owned(),
+        )
+        .await
+    }
+}
+
+/// Creates a [`HbaStartupHandler`] for each connection, with handlers of
+/// each method created by their `MakeHandler`.
+///
+/// Clients selecting a method without handler are rejected.
+#[derive(Debug)]
+pub struct MakeHbaStartupHandler<T, M, S, C> {
+    rules: HbaRules,
+    trust: T,
+    md5: M,
+    scram: S,
+    cert: C,
+}
+
+impl MakeHbaStartupHandler<Unconfigured, Unconfigured, Unconfigured, Unconfigured> {
+    pub fn new(rules: HbaRules) -> Self {
+        MakeHbaStartupHandler {
+            rules,
+            trust: Unconfigured,
+            md5: Unconfigured,
+            scram: Unconfigured,
+            cert: Unconfigured,
+        }
+    }
+}
+
+impl<T, M, S, C> MakeHbaStartupHandler<T, M, S, C> {
+    /// Handler of `trust`, like `TrustAuthStartupHandler` with a custom
+    /// parameter provider.
+    pub fn with_trust<T2>(self, trust: T2) -> MakeHbaStartupHandler<T2, M, S, C> {
+        MakeHbaStartupHandler {
+            rules: self.rules,
+            trust,
+            md5: self.md5,
+            scram: self.scram,
+            cert: self.cert,
+        }
+    }
+
+    /// Handler of `md5`
+    pub fn with_md5<M2>(self, md5: M2) -> MakeHbaStartupHandler<T, M2, S, C> {
+        MakeHbaStartupHandler {
+            rules: self.rules,
+            trust: self.trust,
+            md5,
+            scram: self.scram,
+            cert: self.cert,
+        }
+    }
+
+    /// Handler of `scram-sha-256`
+    pub fn with_scram<S2>(self, scram: S2) -> MakeHbaStartupHandler<T, M, S2, C> {
+        MakeHbaStartupHandler {
+            rules: self.rules,
+            trust: self.trust,
+            md5: self.md5,
+            scram,
+            cert: self.cert,
+        }
+    }
+
+    /// Handler of `cert`
+    pub fn with_cert<C2>(self, cert: C2) -> MakeHbaStartupHandler<T, M, S, C2> {
+        MakeHbaStartupHandler {
+            rules: self.rules,
+            trust: self.trust,
+            md5: self.md5,
+            scram: self.scram,
+            cert,
+        }
+    }
+}
+
+impl<T, M, S, C> MakeHandler for MakeHbaStartupHandler<T, M, S, C>
+where
+    T: MakeHandler,
+    M: MakeHandler,
+    S: MakeHandler,
+    C: MakeHandler,
+{
+    type Handler = HbaStartupHandler<T::Handler, M::Handler, S::Handler, C::Handler>;
+
+    fn make(&self) -> Self::Handler {
+        HbaStartupHandler {
+            rules: self.rules.clone(),
+            trust: self.trust.make(),
+            md5: self.md5.make(),
+            scram: self.scram.make(),
+            cert: self.cert.make(),
+            method: Mutex::new(None),
+        }
+    }
+}
+
+/// Selects authentication method on `Startup` by rules, and delegates
+/// messages to handler of the method.
+pub struct HbaStartupHandler<T, M, S, C> {
+    rules: HbaRules,
+    trust: T,
+    md5: M,
+    scram: S,
+    cert: C,
+    method: Mutex<Option<HbaMethod>>,
+}
+
+impl<T, M, S, C> HbaStartupHandler<T, M, S, C>
+where
+    T: StartupHandler,
+    M: StartupHandler,
+    S: StartupHandler,
+    C: StartupHandler,
+{
+    fn select_method<CI: ClientInfo>(&self, client: &CI, startup: &Startup) -> Option<HbaMethod> {
+        let user = startup
+            .parameters
+            .get(METADATA_USER)
+            .map(String::as_str)
+            .unwrap_or_default();
+        // database defaults to user name
+        let database = startup
+            .parameters
+            .get(METADATA_DATABASE)
+            .map(String::as_str)
+            .unwrap_or(user);
+        self.rules.match_method(
+            client.is_secure(),
+            client.socket_addr().ip(),
+            database,
+            user,
+        )
+    }
+}
+
+#[async_trait]
+impl<T, M, S, C> StartupHandler for HbaStartupHandler<T, M, S, C>
+where
+    T: StartupHandler,
+    M: StartupHandler,
+    S: StartupHandler,
+    C: StartupHandler,
+{
+    async fn on_startup<CI>(
+        &self,
+        client: &mut CI,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        CI: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        CI::Error: Debug,
+        PgWireError: From<<CI as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let method =
+            if let PgWireFrontendMessage::Startup(ref startup) = message {
+                let method = self.select_method(client, startup);
+                *self.method.lock().unwrap() = method;
+
+                let Some(method) = method else {
+                    let user = startup.parameters.get(METADATA_USER);
+                    let database = startup.parameters.get(METADATA_DATABASE).or(user);
+                    let message =
+                        format!(
+                    "no pg_hba.conf entry for host \"{}\", user \"{}\", database \"{}\", {}",
+                    client.socket_addr().ip(),
+                    user.map(String::as_str).unwrap_or_default(),
+                    database.map(String::as_str).unwrap_or_default(),
+                    if client.is_secure() { "SSL on" } else { "SSL off" },
+                );
+                    return super::reject_authentication(client, "28000", message).await;
+                };
+                method
+            } else {
+                let method = *self.method.lock().unwrap();
+                method.ok_or_else(|| PgWireError::unexpected_message("Startup", &message))?
+            };
+
+        match method {
+            HbaMethod::Trust => self.trust.on_startup(client, message).await,
+            HbaMethod::Reject => {
+                super::reject_authentication(
+                    client,
+                    "28000",
+                    "pg_hba.conf rejects connection".to_owned(),
+                )
+                .await
+            }
+            HbaMethod::Md5 => self.md5.on_startup(client, message).await,
+            HbaMethod::ScramSha256 => self.scram.on_startup(client, message).await,
+            HbaMethod::Cert => self.cert.on_startup(client, message).await,
+        }
+    }
+
+    async fn on_cancel_request(&self, request: CancelRequest) {
+        // cancel requests are not authenticated, any handler can serve it
+        self.trust.on_cancel_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use bytes::BytesMut;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::api::auth::trust::TrustAuthStartupHandler;
+    use crate::api::StatelessMakeHandler;
+    use crate::messages::startup::Authentication;
+    use crate::sync::SyncClient;
+
+    const CONF: &str = "
+# TYPE     DATABASE      USER   ADDRESS       METHOD
+hostssl    all           all    0.0.0.0/0     cert
+host       all           admin  all           reject
+host       sameuser      all    10.0.0.0/8    md5
+host       app,reports   all    10.1.0.0/16   scram-sha-256 # not reached for sameuser
+hostnossl  all           all    127.0.0.1     trust
+";
+
+    #[test]
+    fn test_parse_and_match() {
+        let rules = HbaRules::parse(CONF).unwrap();
+        let local = IpAddr::from([127, 0, 0, 1]);
+        let internal = IpAddr::from([10, 1, 2, 3]);
+
+        assert_eq!(
+            Some(HbaMethod::Cert),
+            rules.match_method(true, internal, "app", "alice")
+        );
+        assert_eq!(
+            Some(HbaMethod::Reject),
+            rules.match_method(false, local, "app", "admin")
+        );
+        assert_eq!(
+            Some(HbaMethod::Md5),
+            rules.match_method(false, internal, "alice", "alice")
+        );
+        assert_eq!(
+            Some(HbaMethod::ScramSha256),
+            rules.match_method(false, internal, "reports", "alice")
+        );
+        assert_eq!(None, rules.match_method(false, internal, "other", "alice"));
+        assert_eq!(
+            Some(HbaMethod::Trust),
+            rules.match_method(false, local, "app", "alice")
+        );
+        assert_eq!(
+            None,
+            rules.match_method(false, IpAddr::from([127, 0, 0, 2]), "app", "alice")
+        );
+        assert_eq!(
+            None,
+            rules.match_method(false, "::1".parse().unwrap(), "app", "alice")
+        );
+
+        assert!(HbaRules::parse("local all all trust").is_err());
+        assert!(HbaRules::parse("host all all 10.0.0.0/33 trust").is_err());
+        assert!(HbaRules::parse("host all all all ident").is_err());
+        assert!(HbaRules::parse("host all all trust").is_err());
+    }
+
+    #[test]
+    fn test_prefix_matches() {
+        let net: HbaAddress = "192.168.0.0/23".parse().unwrap();
+        assert!(net.matches(IpAddr::from([192, 168, 1, 200])));
+        assert!(!net.matches(IpAddr::from([192, 168, 2, 1])));
+
+        let net: HbaAddress = "fd00::/8".parse().unwrap();
+        assert!(net.matches("fd12::1".parse().unwrap()));
+        assert!(!net.matches("fe80::1".parse().unwrap()));
+
+        let all: HbaAddress = "0.0.0.0/0".parse().unwrap();
+        assert!(all.matches(IpAddr::from([8, 8, 8, 8])));
+    }
+
+    fn startup(conf: &str, user: &str) -> Vec<PgWireBackendMessage> {
+        let make = MakeHbaStartupHandler::new(HbaRules::parse(conf).unwrap()).with_trust(
+            StatelessMakeHandler::new(Arc::new(TrustAuthStartupHandler::default())),
+        );
+        let handler = make.make();
+        let mut client = SyncClient::<_, String>::with_socket_addr(
+            Cursor::new(Vec::new()),
+            "127.0.0.1:5432".parse().unwrap(),
+        );
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), user.to_owned());
+        block_on(handler.on_startup(&mut client, PgWireFrontendMessage::Startup(startup))).unwrap();
+
+        let mut buf = BytesMut::from(&client.get_ref().get_ref()[..]);
+        let mut messages = Vec::new();
+        while let Some(message) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn test_hba_startup_handler() {
+        let conf = "host all admin all reject\nhost all alice all md5\nhost all all all trust";
+        let messages = startup(conf, "bob");
+        assert!(matches!(
+            messages[0],
+            PgWireBackendMessage::Authentication(Authentication::Ok)
+        ));
+
+        for user in ["admin", "alice"] {
+            let messages = startup(conf, user);
+            assert_eq!(1, messages.len());
+            assert!(matches!(
+                messages[0],
+                PgWireBackendMessage::ErrorResponse(_)
+            ));
+        }
+
+        let messages = startup("host all all 10.0.0.0/8 trust", "bob");
+        let PgWireBackendMessage::ErrorResponse(ref error) = messages[0] else {
+            panic!("expected error");
+        };
+        assert!(error
+            .fields
+            .iter()
+            .any(|(_, value)| value.starts_with("no pg_hba.conf entry for host \"127.0.0.1\"")));
+    }
+}
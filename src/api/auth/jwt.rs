@@ -0,0 +1,493 @@
+//! Bearer token authentication, with a JWT sent as cleartext password.
+//!
+//! The token signature is verified against a JSON Web Key Set, then issuer,
+//! audience and validity period are checked. Claims of the verified token are
+//! saved to client metadata as JSON, see [`jwt_claims`], so handlers can use
+//! them for authorization later.
+//!
+//! Since the token is sent in cleartext, this should only be used over TLS.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures::sink::{Sink, SinkExt};
+use ring::{hmac, signature};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{
+    ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider, StartupHandler,
+};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::startup::Authentication;
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Metadata key of verified claims, in JSON
+pub const METADATA_JWT_CLAIMS: &str = "jwt_claims";
+
+/// Claims of the token client authenticated with.
+pub fn jwt_claims<C: ClientInfo>(client: &C) -> Option<Map<String, Value>> {
+    client
+        .metadata()
+        .get(METADATA_JWT_CLAIMS)
+        .and_then(|claims| serde_json::from_str(claims).ok())
+}
+
+/// A key of JSON Web Key Set, RFC 7517.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub alg: Option<String>,
+    #[serde(default)]
+    pub crv: Option<String>,
+    // RSA
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    // EC and OKP
+    #[serde(default)]
+    pub x: Option<String>,
+    #[serde(default)]
+    pub y: Option<String>,
+    // symmetric
+    #[serde(default)]
+    pub k: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    pub fn from_json(json: &str) -> PgWireResult<JwkSet> {
+        serde_json::from_str(json).map_err(|e| PgWireError::ApiError(Box::new(e)))
+    }
+
+    fn find<'a>(&'a self, kid: Option<&'a str>) -> impl Iterator<Item = &'a Jwk> + 'a {
+        self.keys
+            .iter()
+            .filter(move |key| kid.is_none() || key.kid.as_deref() == kid)
+    }
+}
+
+/// Source of verification keys, like a JWKS endpoint of identity provider.
+/// Implementations are expected to cache keys.
+#[async_trait]
+pub trait JwksProvider: Send + Sync {
+    async fn jwks(&self) -> PgWireResult<Arc<JwkSet>>;
+}
+
+#[async_trait]
+impl JwksProvider for Arc<JwkSet> {
+    async fn jwks(&self) -> PgWireResult<Arc<JwkSet>> {
+        Ok(self.clone())
+    }
+}
+
+/// Checks applied to token claims, after signature is verified.
+#[derive(Debug, Clone)]
+pub struct JwtValidation {
+    /// Required `iss`
+    pub issuer: Option<String>,
+    /// Required in `aud`
+    pub audience: Option<String>,
+    /// Claim that must equal the requested user, `sub` by default. `None`
+    /// to accept the token for any user.
+    pub user_claim: Option<String>,
+    /// Tolerance of clock skew for `exp` and `nbf`
+    pub leeway: Duration,
+}
+
+impl Default for JwtValidation {
+    fn default() -> Self {
+        JwtValidation {
+            issuer: None,
+            audience: None,
+            user_claim: Some("sub".to_owned()),
+            leeway: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// Why a token is rejected
+#[derive(Debug, PartialEq, Eq)]
+enum InvalidToken {
+    Malformed,
+    UnsupportedAlgorithm,
+    BadSignature,
+    Expired,
+    NotYetValid,
+    WrongIssuer,
+    WrongAudience,
+    WrongUser,
+}
+
+impl JwtValidation {
+    /// Verify `token` for `user` and return its claims.
+    fn validate(
+        &self,
+        token: &str,
+        keys: &JwkSet,
+        user: &str,
+    ) -> Result<Map<String, Value>, InvalidToken> {
+        let mut parts = token.splitn(3, '.');
+        let (Some(header), Some(payload), Some(sig)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(InvalidToken::Malformed);
+        };
+        let header: Header = decode_json(header)?;
+        let claims: Map<String, Value> = decode_json(payload)?;
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig)
+            .map_err(|_| InvalidToken::Malformed)?;
+
+        let message = &token[..header_payload_len(token)];
+        let mut verified = false;
+        for key in keys.find(header.kid.as_deref()) {
+            if matches!(key.alg.as_deref(), Some(alg) if alg != header.alg) {
+                continue;
+            }
+            if verify_signature(&header.alg, key, message.as_bytes(), &sig)? {
+                verified = true;
+                break;
+            }
+        }
+        if !verified {
+            return Err(InvalidToken::BadSignature);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let leeway = self.leeway.as_secs();
+        if let Some(exp) = claims.get("exp") {
+            let exp = exp.as_u64().ok_or(InvalidToken::Malformed)?;
+            if now > exp.saturating_add(leeway) {
+                return Err(InvalidToken::Expired);
+            }
+        }
+        if let Some(nbf) = claims.get("nbf") {
+            let nbf = nbf.as_u64().ok_or(InvalidToken::Malformed)?;
+            if now.saturating_add(leeway) < nbf {
+                return Err(InvalidToken::NotYetValid);
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+                return Err(InvalidToken::WrongIssuer);
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matched = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matched {
+                return Err(InvalidToken::WrongAudience);
+            }
+        }
+        if let Some(user_claim) = &self.user_claim {
+            if claims.get(user_claim).and_then(Value::as_str) != Some(user) {
+                return Err(InvalidToken::WrongUser);
+            }
+        }
+        Ok(claims)
+    }
+}
+
+fn header_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, InvalidToken> {
+    let json = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| InvalidToken::Malformed)?;
+    serde_json::from_slice(&json).map_err(|_| InvalidToken::Malformed)
+}
+
+fn decode_param(param: &Option<String>) -> Result<Vec<u8>, InvalidToken> {
+    param
+        .as_deref()
+        .and_then(|p| URL_SAFE_NO_PAD.decode(p).ok())
+        .ok_or(InvalidToken::Malformed)
+}
+
+/// Verify signature with `key`. Returns `false` if the key doesn't fit the
+/// algorithm, so the next key can be tried.
+fn verify_signature(
+    alg: &str,
+    key: &Jwk,
+    message: &[u8],
+    sig: &[u8],
+) -> Result<bool, InvalidToken> {
+    match (alg, key.kty.as_str()) {
+        ("HS256" | "HS384" | "HS512", "oct") => {
+            let algorithm = match alg {
+                "HS256" => hmac::HMAC_SHA256,
+                "HS384" => hmac::HMAC_SHA384,
+                _ => hmac::HMAC_SHA512,
+            };
+            let key = hmac::Key::new(algorithm, &decode_param(&key.k)?);
+            Ok(hmac::verify(&key, message, sig).is_ok())
+        }
+        ("RS256" | "RS384" | "RS512", "RSA") => {
+            let algorithm = match alg {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                _ => &signature::RSA_PKCS1_2048_8192_SHA512,
+            };
+            let public_key = signature::RsaPublicKeyComponents {
+                n: decode_param(&key.n)?,
+                e: decode_param(&key.e)?,
+            };
+            Ok(public_key.verify(algorithm, message, sig).is_ok())
+        }
+        ("ES256" | "ES384", "EC") => {
+            let (algorithm, crv): (&'static signature::EcdsaVerificationAlgorithm, _) = match alg {
+                "ES256" => (&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+                _ => (&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+            };
+            if key.crv.as_deref() != Some(crv) {
+                return Ok(false);
+            }
+            // uncompressed point
+            let mut point = vec![0x04];
+            point.extend(decode_param(&key.x)?);
+            point.extend(decode_param(&key.y)?);
+            let public_key = signature::UnparsedPublicKey::new(algorithm, point);
+            Ok(public_key.verify(message, sig).is_ok())
+        }
+        ("EdDSA", "OKP") => {
+            if key.crv.as_deref() != Some("Ed25519") {
+                return Ok(false);
+            }
+            let public_key =
+                signature::UnparsedPublicKey::new(&signature::ED25519, decode_param(&key.x)?);
+            Ok(public_key.verify(message, sig).is_ok())
+        }
+        (
+            "HS256" | "HS384" | "HS512" | "RS256" | "RS384" | "RS512" | "ES256" | "ES384" | "EdDSA",
+            _,
+        ) => Ok(false),
+        _ => Err(InvalidToken::UnsupportedAlgorithm),
+    }
+}
+
+/// Authenticates client by a JWT sent as password.
+#[derive(new)]
+pub struct JwtAuthStartupHandler<K, P> {
+    jwks_provider: K,
+    validation: JwtValidation,
+    parameter_provider: P,
+}
+
+#[async_trait]
+impl<K: JwksProvider, P: ServerParameterProvider> StartupHandler for JwtAuthStartupHandler<K, P> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                super::save_startup_parameters_to_metadata(client, startup);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        Authentication::CleartextPassword,
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
+                let pwd = pwd.into_password()?;
+                let keys = self.jwks_provider.jwks().await?;
+                let login_info = LoginInfo::from_client_info(client);
+                let user = login_info.user().unwrap_or_default();
+                match self.validation.validate(&pwd.password, &keys, user) {
+                    Ok(claims) => {
+                        client.metadata_mut().insert(
+                            METADATA_JWT_CLAIMS.to_owned(),
+                            Value::Object(claims).to_string(),
+                        );
+                        super::finish_authentication(client, &self.parameter_provider).await
+                    }
+                    Err(reason) => {
+                        tracing::debug!(?reason, "token rejected");
+                        super::reject_password(client).await?;
+                    }
+                }
+            }
+            msg => return Err(PgWireError::unexpected_message("PasswordMessage", &msg)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn encode(value: &Value) -> String {
+        URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn hs256_token(header: Value, claims: Value, secret: &[u8]) -> String {
+        let message = format!("{}.{}", encode(&header), encode(&claims));
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        let sig = hmac::sign(&key, message.as_bytes());
+        format!("{message}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn hs256_keys() -> JwkSet {
+        JwkSet::from_json(
+            &json!({"keys": [
+                {"kty": "oct", "kid": "other", "k": URL_SAFE_NO_PAD.encode(b"other")},
+                {"kty": "oct", "kid": "k1", "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(b"secret")},
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_hs256() {
+        let keys = hs256_keys();
+        let validation = JwtValidation {
+            issuer: Some("https://idp.example.com".to_owned()),
+            audience: Some("pgwire".to_owned()),
+            ..Default::default()
+        };
+        let header = json!({"alg": "HS256", "kid": "k1"});
+        let claims = json!({
+            "iss": "https://idp.example.com",
+            "aud": ["other", "pgwire"],
+            "sub": "alice",
+            "exp": now() + 600,
+            "role": "reader",
+        });
+
+        let token = hs256_token(header.clone(), claims.clone(), b"secret");
+        let verified = validation.validate(&token, &keys, "alice").unwrap();
+        assert_eq!(Some(&json!("reader")), verified.get("role"));
+
+        assert_eq!(
+            Err(InvalidToken::WrongUser),
+            validation.validate(&token, &keys, "bob")
+        );
+        assert_eq!(
+            Err(InvalidToken::BadSignature),
+            validation.validate(
+                &hs256_token(header.clone(), claims.clone(), b"wrong"),
+                &keys,
+                "alice"
+            )
+        );
+        assert_eq!(
+            Err(InvalidToken::Malformed),
+            validation.validate("not.a-token", &keys, "alice")
+        );
+
+        let mut expired = claims.clone();
+        expired["exp"] = json!(now() - 600);
+        assert_eq!(
+            Err(InvalidToken::Expired),
+            validation.validate(
+                &hs256_token(header.clone(), expired, b"secret"),
+                &keys,
+                "alice"
+            )
+        );
+
+        let mut wrong_aud = claims.clone();
+        wrong_aud["aud"] = json!("other");
+        assert_eq!(
+            Err(InvalidToken::WrongAudience),
+            validation.validate(
+                &hs256_token(header.clone(), wrong_aud, b"secret"),
+                &keys,
+                "alice"
+            )
+        );
+
+        let mut wrong_iss = claims;
+        wrong_iss["iss"] = json!("https://evil.example.com");
+        assert_eq!(
+            Err(InvalidToken::WrongIssuer),
+            validation.validate(&hs256_token(header, wrong_iss, b"secret"), &keys, "alice")
+        );
+    }
+
+    #[test]
+    fn test_validate_eddsa() {
+        let key_pair = signature::Ed25519KeyPair::from_seed_unchecked(&[7u8; 32]).unwrap();
+        let keys = JwkSet::from_json(
+            &json!({"keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": URL_SAFE_NO_PAD.encode(signature::KeyPair::public_key(&key_pair)),
+            }]})
+            .to_string(),
+        )
+        .unwrap();
+
+        let message = format!(
+            "{}.{}",
+            encode(&json!({"alg": "EdDSA"})),
+            encode(&json!({"sub": "alice"}))
+        );
+        let sig = key_pair.sign(message.as_bytes());
+        let token = format!("{message}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref()));
+
+        let validation = JwtValidation::default();
+        assert!(validation.validate(&token, &keys, "alice").is_ok());
+
+        let token = format!("{message}.{}", URL_SAFE_NO_PAD.encode([0u8; 64]));
+        assert_eq!(
+            Err(InvalidToken::BadSignature),
+            validation.validate(&token, &keys, "alice")
+        );
+
+        let none_token = format!(
+            "{}.{}.",
+            encode(&json!({"alg": "none"})),
+            encode(&json!({}))
+        );
+        assert_eq!(
+            Err(InvalidToken::UnsupportedAlgorithm),
+            validation.validate(&none_token, &keys, "alice")
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! LDAP authentication, like the `ldap` method of Postgres.
+//!
+//! [`LdapPasswordVerifier`] checks the cleartext password sent by client by
+//! binding to a directory server, so it's used with
+//! [`super::cleartext::CleartextPasswordStartupHandler`]. Two modes are
+//! supported:
+//!
+//! * simple bind, with DN built as `prefix + user + suffix`
+//! * search+bind, which looks up the DN of user with a search filter, then
+//!   binds as it
+//!
+//! The LDAP protocol itself is provided by an [`LdapConnector`], usually a
+//! thin wrapper of an LDAP client library.
+
+use async_trait::async_trait;
+
+use super::PasswordVerifier;
+use crate::error::{PgWireError, PgWireResult};
+
+/// Placeholder of user name in search filter
+pub const USERNAME_PLACEHOLDER: &str = "$username";
+
+/// An established connection to directory server.
+#[async_trait]
+pub trait LdapConnection: Send {
+    /// Simple bind as `dn`. Returns `false` if the credential is rejected by
+    /// server.
+    async fn simple_bind(&mut self, dn: &str, password: &str) -> PgWireResult<bool>;
+
+    /// Search entries matching `filter` under `base_dn` in subtree scope,
+    /// and return their DNs.
+    async fn search(&mut self, base_dn: &str, filter: &str) -> PgWireResult<Vec<String>>;
+}
+
+/// Opens connections to directory server.
+#[async_trait]
+pub trait LdapConnector: Send + Sync {
+    type Connection: LdapConnection;
+
+    /// Connect to `url`, like `ldaps://ldap.example.com:636`.
+    async fn connect(&self, url: &str) -> PgWireResult<Self::Connection>;
+}
+
+/// How the DN of user is determined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LdapBindMode {
+    /// Bind as `prefix + user + suffix`, like `cn=` and `,dc=example,dc=com`
+    Simple { prefix: String, suffix: String },
+    /// Search `filter` under `base_dn`, optionally bound as `bind_dn` first,
+    /// then bind as the only entry found. `$username` in `filter` is
+    /// replaced with user name.
+    SearchBind {
+        base_dn: String,
+        filter: String,
+        bind_dn: Option<String>,
+        bind_password: Option<String>,
+    },
+}
+
+impl LdapBindMode {
+    /// Search+bind with the default filter `(uid=$username)`, and anonymous
+    /// search.
+    pub fn search_bind(base_dn: String) -> LdapBindMode {
+        LdapBindMode::SearchBind {
+            base_dn,
+            filter: format!("(uid={USERNAME_PLACEHOLDER})"),
+            bind_dn: None,
+            bind_password: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct LdapConfig {
+    url: String,
+    mode: LdapBindMode,
+}
+
+/// `PasswordVerifier` backed by LDAP bind.
+#[derive(new)]
+pub struct LdapPasswordVerifier<L> {
+    connector: L,
+    config: LdapConfig,
+}
+
+#[async_trait]
+impl<L: LdapConnector> PasswordVerifier for LdapPasswordVerifier<L> {
+    async fn verify(&self, username: &str, password: &str) -> PgWireResult<bool> {
+        // an empty password would be an unauthenticated bind, which most
+        // servers accept
+        if username.is_empty() || password.is_empty() {
+            return Ok(false);
+        }
+
+        let mut conn = self.connector.connect(&self.config.url).await?;
+        let dn = match &self.config.mode {
+            LdapBindMode::Simple { prefix, suffix } => {
+                format!("{prefix}{}{suffix}", escape_dn_value(username))
+            }
+            LdapBindMode::SearchBind {
+                base_dn,
+                filter,
+                bind_dn,
+                bind_password,
+            } => {
+                if let Some(bind_dn) = bind_dn {
+                    let bind_password = bind_password.as_deref().unwrap_or_default();
+                    if !conn.simple_bind(bind_dn, bind_password).await? {
+                        let message = format!("could not perform initial LDAP bind for {bind_dn}");
+                        return Err(PgWireError::ApiError(message.into()));
+                    }
+                }
+
+                let filter = filter.replace(USERNAME_PLACEHOLDER, &escape_filter_value(username));
+                let mut entries = conn.search(base_dn, &filter).await?;
+                if entries.len() != 1 {
+                    return Ok(false);
+                }
+                entries.remove(0)
+            }
+        };
+
+        conn.simple_bind(&dn, password).await
+    }
+
+    async fn verify_md5(
+        &self,
+        _username: &str,
+        _hash: &str,
+        _salt: &[u8; 4],
+    ) -> PgWireResult<bool> {
+        // LDAP bind requires the cleartext password
+        Err(PgWireError::UnsupportedAuthenticationMethod)
+    }
+}
+
+/// Escape a value in search filter, RFC 4515.
+fn escape_filter_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' | '(' | ')' | '\\' | '\0' => escaped.push_str(&format!("\\{:02x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape an attribute value in DN, RFC 4514.
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' if i == 0 => escaped.push_str("\\#"),
+            ' ' if i == 0 || i == last => escaped.push_str("\\ "),
+            '\0' => escaped.push_str("\\00"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    /// Directory of (dn, uid, password)
+    struct MockDirectory(Vec<(&'static str, &'static str, &'static str)>);
+
+    struct MockConnection {
+        entries: HashMap<String, (String, String)>,
+    }
+
+    #[async_trait]
+    impl LdapConnection for MockConnection {
+        async fn simple_bind(&mut self, dn: &str, password: &str) -> PgWireResult<bool> {
+            Ok(self.entries.get(dn).map(|(_, p)| p.as_str()) == Some(password))
+        }
+
+        async fn search(&mut self, base_dn: &str, filter: &str) -> PgWireResult<Vec<String>> {
+            Ok(self
+                .entries
+                .iter()
+                .filter(|(dn, (uid, _))| dn.ends_with(base_dn) && filter == format!("(uid={uid})"))
+                .map(|(dn, _)| dn.clone())
+                .collect())
+        }
+    }
+
+    #[async_trait]
+    impl LdapConnector for MockDirectory {
+        type Connection = MockConnection;
+
+        async fn connect(&self, url: &str) -> PgWireResult<Self::Connection> {
+            assert_eq!("ldap://localhost", url);
+            Ok(MockConnection {
+                entries: self
+                    .0
+                    .iter()
+                    .map(|(dn, uid, pass)| (dn.to_string(), (uid.to_string(), pass.to_string())))
+                    .collect(),
+            })
+        }
+    }
+
+    fn mock_verifier(mode: LdapBindMode) -> LdapPasswordVerifier<MockDirectory> {
+        LdapPasswordVerifier::new(
+            MockDirectory(vec![
+                ("cn=alice,dc=example,dc=com", "alice", "secret"),
+                ("cn=bob,ou=people,dc=example,dc=com", "bob", "hunter2"),
+                ("cn=service,dc=example,dc=com", "service", "svc"),
+            ]),
+            LdapConfig::new("ldap://localhost".to_owned(), mode),
+        )
+    }
+
+    #[test]
+    fn test_simple_bind() {
+        let verifier = mock_verifier(LdapBindMode::Simple {
+            prefix: "cn=".to_owned(),
+            suffix: ",dc=example,dc=com".to_owned(),
+        });
+        assert!(block_on(verifier.verify("alice", "secret")).unwrap());
+        assert!(!block_on(verifier.verify("alice", "wrong")).unwrap());
+        assert!(!block_on(verifier.verify("alice", "")).unwrap());
+        assert!(!block_on(verifier.verify("bob", "hunter2")).unwrap());
+        assert!(block_on(verifier.verify_md5("alice", "md5", &[0; 4])).is_err());
+    }
+
+    #[test]
+    fn test_search_bind() {
+        let verifier = mock_verifier(LdapBindMode::search_bind("dc=example,dc=com".to_owned()));
+        assert!(block_on(verifier.verify("alice", "secret")).unwrap());
+        assert!(block_on(verifier.verify("bob", "hunter2")).unwrap());
+        assert!(!block_on(verifier.verify("bob", "secret")).unwrap());
+        assert!(!block_on(verifier.verify("carol", "secret")).unwrap());
+        assert!(!block_on(verifier.verify("*", "secret")).unwrap());
+
+        let verifier = mock_verifier(LdapBindMode::SearchBind {
+            base_dn: "dc=example,dc=com".to_owned(),
+            filter: "(uid=$username)".to_owned(),
+            bind_dn: Some("cn=service,dc=example,dc=com".to_owned()),
+            bind_password: Some("wrong".to_owned()),
+        });
+        assert!(block_on(verifier.verify("alice", "secret")).is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a\\2a\\28b\\29\\5c", escape_filter_value("a*(b)\\"));
+        assert_eq!("\\#a\\,b\\=c\\ ", escape_dn_value("#a,b=c "));
+        assert_eq!("alice", escape_dn_value("alice"));
+    }
+}
@@ -4,14 +4,15 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
 use tokio::sync::Mutex;
+use tracing::Instrument;
 
 use super::{
-    AuthSource, ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider,
-    StartupHandler,
+    AuthSource, ClientInfo, LoginInfo, PasswordVerifier, PgWireConnectionState,
+    ServerParameterProvider, StartupHandler,
 };
+use crate::api::trace::auth_span;
 use crate::api::MakeHandler;
-use crate::error::{ErrorInfo, PgWireError, PgWireResult};
-use crate::messages::response::ErrorResponse;
+use crate::error::{PgWireError, PgWireResult};
 use crate::messages::startup::Authentication;
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
@@ -41,7 +42,11 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                 client.set_state(PgWireConnectionState::AuthenticationInProgress);
 
                 let login_info = LoginInfo::from_client_info(client);
-                let salt_and_pass = self.auth_source.get_password(&login_info).await?;
+                let salt_and_pass = self
+                    .auth_source
+                    .get_password(&login_info)
+                    .instrument(auth_span(&login_info))
+                    .await?;
 
                 let salt = salt_and_pass
                     .salt
@@ -66,20 +71,10 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                 if pwd.password.as_bytes() == *cached_pass {
                     super::finish_authentication(client, self.parameter_provider.as_ref()).await
                 } else {
-                    let error_info = ErrorInfo::new(
-                        "FATAL".to_owned(),
-                        "28P01".to_owned(),
-                        "Password authentication failed".to_owned(),
-                    );
-                    let error = ErrorResponse::from(error_info);
-
-                    client
-                        .feed(PgWireBackendMessage::ErrorResponse(error))
-                        .await?;
-                    client.close().await?;
+                    super::reject_password(client).await?;
                 }
             }
-            _ => {}
+            msg => return Err(PgWireError::unexpected_message("PasswordMessage", &msg)),
         }
         Ok(())
     }
@@ -123,6 +118,90 @@ where
     }
 }
 
+/// Md5 password authentication, with password checked by a
+/// `PasswordVerifier`.
+///
+/// A random salt is generated for each connection, so use
+/// `MakeMd5PasswordStartupHandler` to create a handler per connection.
+pub struct Md5PasswordStartupHandler<V, P> {
+    verifier: Arc<V>,
+    parameter_provider: Arc<P>,
+    salt: Mutex<[u8; 4]>,
+}
+
+#[async_trait]
+impl<V: PasswordVerifier, P: ServerParameterProvider> StartupHandler
+    for Md5PasswordStartupHandler<V, P>
+{
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                super::save_startup_parameters_to_metadata(client, startup);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+
+                let salt = rand::random::<[u8; 4]>();
+                *self.salt.lock().await = salt;
+
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        Authentication::MD5Password(salt.to_vec()),
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
+                let pwd = pwd.into_password()?;
+                let salt = *self.salt.lock().await;
+                let login_info = LoginInfo::from_client_info(client);
+                let username = login_info.user().unwrap_or_default();
+
+                if self
+                    .verifier
+                    .verify_md5(username, &pwd.password, &salt)
+                    .instrument(auth_span(&login_info))
+                    .await?
+                {
+                    super::finish_authentication(client, self.parameter_provider.as_ref()).await
+                } else {
+                    super::reject_password(client).await?;
+                }
+            }
+            msg => return Err(PgWireError::unexpected_message("PasswordMessage", &msg)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, new)]
+pub struct MakeMd5PasswordStartupHandler<V, P> {
+    verifier: Arc<V>,
+    parameter_provider: Arc<P>,
+}
+
+impl<V, P> MakeHandler for MakeMd5PasswordStartupHandler<V, P>
+where
+    V: PasswordVerifier,
+    P: ServerParameterProvider,
+{
+    type Handler = Arc<Md5PasswordStartupHandler<V, P>>;
+
+    fn make(&self) -> Self::Handler {
+        Arc::new(Md5PasswordStartupHandler {
+            verifier: self.verifier.clone(),
+            parameter_provider: self.parameter_provider.clone(),
+            salt: Mutex::new([0; 4]),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
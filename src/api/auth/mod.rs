@@ -1,14 +1,19 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::{Arc, PoisonError};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use futures::sink::{Sink, SinkExt};
 use futures::stream;
 
+use super::negotiate::ProtocolFeatureNegotiator;
 use super::{ClientInfo, PgWireConnectionState, METADATA_DATABASE, METADATA_USER};
-use crate::error::{PgWireError, PgWireResult};
-use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
-use crate::messages::startup::{Authentication, BackendKeyData, ParameterStatus, Startup};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::response::{ErrorResponse, ReadyForQuery, READY_STATUS_IDLE};
+use crate::messages::startup::{
+    Authentication, BackendKeyData, CancelRequest, ParameterStatus, Startup,
+};
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
 /// Handles startup process and frontend messages
@@ -24,6 +29,46 @@ pub trait StartupHandler: Send + Sync {
         C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
         C::Error: Debug,
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>;
+
+    /// Protocol features supported by this server.
+    ///
+    /// Protocol version and `_pq_.` options of startup message are negotiated
+    /// before it's passed to `on_startup`, with options not accepted removed.
+    /// Without a negotiator, all protocol options are declined.
+    fn protocol_negotiator(&self) -> Option<&ProtocolFeatureNegotiator> {
+        None
+    }
+
+    /// Callback of `CancelRequest`, which is sent on a new connection to
+    /// cancel the query of session with matching `BackendKeyData`. No
+    /// response is sent for it, and the connection is closed after.
+    ///
+    /// Requests are ignored by default.
+    async fn on_cancel_request(&self, _request: CancelRequest) {}
+}
+
+#[async_trait]
+impl<H: StartupHandler> StartupHandler for Arc<H> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.as_ref().on_startup(client, message).await
+    }
+
+    fn protocol_negotiator(&self) -> Option<&ProtocolFeatureNegotiator> {
+        self.as_ref().protocol_negotiator()
+    }
+
+    async fn on_cancel_request(&self, request: CancelRequest) {
+        self.as_ref().on_cancel_request(request).await
+    }
 }
 
 pub trait ServerParameterProvider: Send + Sync {
@@ -37,8 +82,8 @@ pub trait ServerParameterProvider: Send + Sync {
 /// This provider responds frontend with default parameters:
 ///
 /// - `DateStyle: ISO YMD`: the default text serialization in this library is
-/// using `YMD` style date. If you override this, or use your own serialization
-/// for date types, remember to update this as well.
+///   using `YMD` style date. If you override this, or use your own serialization
+///   for date types, remember to update this as well.
 /// - `server_encoding: UTF8`
 /// - `client_encoding: UTF8`
 /// - `integer_datetimes: on`:
@@ -120,7 +165,7 @@ impl<'a> LoginInfo<'a> {
         &self.host
     }
 
-    pub fn from_client_info<C>(client: &'a C) -> LoginInfo
+    pub fn from_client_info<C>(client: &'a C) -> LoginInfo<'a>
     where
         C: ClientInfo,
     {
@@ -147,6 +192,24 @@ pub trait AuthSource: Send + Sync {
     async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password>;
 }
 
+/// Verifies passwords sent by client, without knowledge of the authentication
+/// protocol.
+///
+/// Unlike `AuthSource`, the stored password never leaves the verifier, which
+/// makes it suitable for backends that only keep password hashes.
+#[async_trait]
+pub trait PasswordVerifier: Send + Sync {
+    /// Verify a cleartext password sent by client.
+    async fn verify(&self, username: &str, password: &str) -> PgWireResult<bool>;
+
+    /// Verify a md5 hashed password sent by client.
+    ///
+    /// `hash` is in the form of
+    /// `concat('md5', md5(concat(md5(concat(password, username)), salt)))`.
+    /// See [`md5pass::hash_md5_password`].
+    async fn verify_md5(&self, username: &str, hash: &str, salt: &[u8; 4]) -> PgWireResult<bool>;
+}
+
 pub fn save_startup_parameters_to_metadata<C>(client: &mut C, startup_message: &Startup)
 where
     C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
@@ -158,8 +221,20 @@ where
             .iter()
             .map(|(k, v)| (k.to_owned(), v.to_owned())),
     );
+    if let Some(registry) = client.guc_registry() {
+        registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .apply_startup_parameters(&startup_message.parameters);
+    }
 }
 
+/// Length of secret key issued in `BackendKeyData` since protocol 3.2, same
+/// as postgres
+const SECRET_KEY_LENGTH: usize = 32;
+/// Length of secret key in protocol 3.0
+const LEGACY_SECRET_KEY_LENGTH: usize = 4;
+
 pub async fn finish_authentication<C, P>(client: &mut C, server_parameter_provider: &P)
 where
     C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
@@ -176,11 +251,18 @@ where
         }
     }
 
-    // TODO: store this backend key
-    messages.push(PgWireBackendMessage::BackendKeyData(BackendKeyData::new(
-        std::process::id() as i32,
-        rand::random::<i32>(),
-    )));
+    // protocol 3.0 only supports 4 bytes secret key
+    let secret_key_len = if client.protocol_minor_version() >= 2 {
+        SECRET_KEY_LENGTH
+    } else {
+        LEGACY_SECRET_KEY_LENGTH
+    };
+    let secret_key = (0..secret_key_len)
+        .map(|_| rand::random::<u8>())
+        .collect::<Bytes>();
+    let backend_key_data = BackendKeyData::new(std::process::id() as i32, secret_key);
+    client.set_backend_key_data(backend_key_data.clone());
+    messages.push(PgWireBackendMessage::BackendKeyData(backend_key_data));
     messages.push(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
         READY_STATUS_IDLE,
     )));
@@ -189,7 +271,50 @@ where
     client.set_state(PgWireConnectionState::ReadyForQuery);
 }
 
+pub(crate) async fn reject_password<C>(client: &mut C) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    reject_authentication(client, "28P01", "Password authentication failed".to_owned()).await
+}
+
+/// Send a FATAL error with `code` and close the connection.
+pub(crate) async fn reject_authentication<C>(
+    client: &mut C,
+    code: &str,
+    message: String,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let error_info = ErrorInfo::new("FATAL".to_owned(), code.to_owned(), message);
+    let error = ErrorResponse::from(error_info);
+
+    client
+        .feed(PgWireBackendMessage::ErrorResponse(error))
+        .await?;
+    client.close().await?;
+    Ok(())
+}
+
+pub mod cert;
 pub mod cleartext;
+#[cfg(feature = "gss")]
+pub mod gss;
+pub mod hba;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod ldap;
 pub mod md5pass;
 pub mod noop;
+pub mod peer;
+#[cfg(feature = "radius")]
+pub mod radius;
 pub mod scram;
+#[cfg(all(windows, feature = "sspi"))]
+pub mod sspi;
+pub mod trust;
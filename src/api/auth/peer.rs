@@ -0,0 +1,240 @@
+//! Peer authentication, like the `peer` method of Postgres.
+//!
+//! Client connected over Unix domain socket is authenticated by the
+//! credentials of its process, which are obtained from the operating system
+//! when the connection is accepted (`SO_PEERCRED` or `getpeereid`), so no
+//! `AuthenticationSCMCredential` exchange is needed. The uid of client
+//! process must map to the requested database user.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+
+use super::{
+    ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider, StartupHandler,
+};
+use crate::api::PeerCredentials;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+#[async_trait]
+pub trait PeerUserMapper: Send + Sync {
+    /// Returns the database user for client process with `credentials`, or
+    /// `None` if it's not allowed to login as anyone.
+    ///
+    /// The login is accepted only when the returned user equals the `user`
+    /// startup parameter of `login`.
+    async fn map_user(
+        &self,
+        login: &LoginInfo,
+        credentials: &PeerCredentials,
+    ) -> PgWireResult<Option<String>>;
+}
+
+/// Maps uid to the name of operating system user, which is the default of
+/// Postgres.
+#[cfg(unix)]
+#[derive(Debug, Default)]
+pub struct SystemUserMapper;
+
+#[cfg(unix)]
+#[async_trait]
+impl PeerUserMapper for SystemUserMapper {
+    async fn map_user(
+        &self,
+        _login: &LoginInfo,
+        credentials: &PeerCredentials,
+    ) -> PgWireResult<Option<String>> {
+        system_user_name(credentials.uid)
+    }
+}
+
+/// Look up name of user `uid` in system user database.
+#[cfg(unix)]
+fn system_user_name(uid: u32) -> PgWireResult<Option<String>> {
+    use std::ffi::CStr;
+
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: `passwd` is plain data, and `buf` outlives the strings it
+        // points to, which are copied before returning
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let ret =
+            unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+        match ret {
+            0 if result.is_null() => return Ok(None),
+            0 => {
+                let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+                return Ok(Some(name.to_string_lossy().into_owned()));
+            }
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            errno => return Err(std::io::Error::from_raw_os_error(errno).into()),
+        }
+    }
+}
+
+/// Authenticates client by credentials of its process, without password
+/// exchange. Only connections over Unix domain socket can be accepted.
+#[derive(new)]
+pub struct PeerAuthStartupHandler<M, P> {
+    user_mapper: M,
+    parameter_provider: P,
+}
+
+#[async_trait]
+impl<M: PeerUserMapper, P: ServerParameterProvider> StartupHandler
+    for PeerAuthStartupHandler<M, P>
+{
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let PgWireFrontendMessage::Startup(ref startup) = message else {
+            return Err(PgWireError::unexpected_message("Startup", &message));
+        };
+        super::save_startup_parameters_to_metadata(client, startup);
+        client.set_state(PgWireConnectionState::AuthenticationInProgress);
+
+        let login_info = LoginInfo::from_client_info(client);
+        let user = login_info.user().unwrap_or_default().to_owned();
+        let Some(credentials) = client.peer_credentials() else {
+            return super::reject_authentication(
+                client,
+                "28000",
+                "peer authentication is only supported on local sockets".to_owned(),
+            )
+            .await;
+        };
+
+        let mapped = self.user_mapper.map_user(&login_info, &credentials).await?;
+        if mapped.as_deref() == Some(user.as_str()) {
+            super::finish_authentication(client, &self.parameter_provider).await;
+            Ok(())
+        } else {
+            super::reject_authentication(
+                client,
+                "28000",
+                format!("peer authentication failed for user \"{user}\""),
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_system_user_name() {
+        // the user running tests, whichever it is, has a passwd entry
+        let uid = unsafe { libc::getuid() };
+        let name = system_user_name(uid).unwrap();
+        assert!(matches!(name, Some(name) if !name.is_empty()));
+    }
+
+    #[cfg(all(target_os = "linux", feature = "tokio"))]
+    #[tokio::test]
+    async fn test_peer_authentication() {
+        use std::os::unix::fs::MetadataExt;
+        use std::sync::Arc;
+
+        use bytes::BytesMut;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::UnixStream;
+
+        use crate::api::auth::DefaultServerParameterProvider;
+        use crate::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+        use crate::api::results::Response;
+        use crate::messages::startup::{Authentication, Startup};
+        use crate::messages::Message;
+        use crate::tokio::process_unix_socket;
+
+        /// Maps uid of this process to `alice`
+        struct AliceUserMapper(u32);
+
+        #[async_trait]
+        impl PeerUserMapper for AliceUserMapper {
+            async fn map_user(
+                &self,
+                _login: &LoginInfo,
+                credentials: &PeerCredentials,
+            ) -> PgWireResult<Option<String>> {
+                Ok((credentials.uid == self.0).then(|| "alice".to_owned()))
+            }
+        }
+
+        struct EmptyQueryHandler;
+
+        #[async_trait]
+        impl SimpleQueryHandler for EmptyQueryHandler {
+            async fn do_query<'a, 'b: 'a, C>(
+                &'b self,
+                _client: &mut C,
+                _query: &'a str,
+            ) -> PgWireResult<Vec<Response<'a>>>
+            where
+                C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+                C::Error: Debug,
+                PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+            {
+                Ok(vec![])
+            }
+        }
+
+        async fn authenticate(user: &str) -> PgWireBackendMessage {
+            let uid = std::fs::metadata("/proc/self").unwrap().uid();
+            let handler = PeerAuthStartupHandler::new(
+                AliceUserMapper(uid),
+                DefaultServerParameterProvider::default(),
+            );
+            let (mut client, server_socket) = UnixStream::pair().unwrap();
+            let server = tokio::spawn(process_unix_socket(
+                server_socket,
+                Arc::new(handler),
+                Arc::new(EmptyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+            ));
+
+            let mut startup = Startup::new();
+            startup
+                .parameters
+                .insert("user".to_owned(), user.to_owned());
+            let mut buf = BytesMut::new();
+            startup.encode(&mut buf).unwrap();
+            client.write_all(&buf).await.unwrap();
+
+            let mut buf = BytesMut::new();
+            let message = loop {
+                if let Some(message) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+                    break message;
+                }
+                assert!(client.read_buf(&mut buf).await.unwrap() > 0);
+            };
+            drop(client);
+            server.await.unwrap().unwrap();
+            message
+        }
+
+        assert!(matches!(
+            authenticate("alice").await,
+            PgWireBackendMessage::Authentication(Authentication::Ok)
+        ));
+        assert!(matches!(
+            authenticate("bob").await,
+            PgWireBackendMessage::ErrorResponse(_)
+        ));
+    }
+}
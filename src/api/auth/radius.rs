@@ -0,0 +1,355 @@
+//! RADIUS authentication, like the `radius` method of Postgres.
+//!
+//! [`RadiusPasswordVerifier`] sends the cleartext password of client in an
+//! `Access-Request` to RADIUS servers, RFC 2865, so it's used with
+//! [`super::cleartext::CleartextPasswordStartupHandler`]. Servers are tried in
+//! order until one of them answers.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use super::PasswordVerifier;
+use crate::error::{PgWireError, PgWireResult};
+
+const RADIUS_ACCESS_REQUEST: u8 = 1;
+const RADIUS_ACCESS_ACCEPT: u8 = 2;
+const RADIUS_ACCESS_REJECT: u8 = 3;
+
+const RADIUS_USER_NAME: u8 = 1;
+const RADIUS_USER_PASSWORD: u8 = 2;
+const RADIUS_SERVICE_TYPE: u8 = 6;
+const RADIUS_NAS_IDENTIFIER: u8 = 32;
+
+const RADIUS_AUTHENTICATE_ONLY: u32 = 8;
+
+const RADIUS_HEADER_LENGTH: usize = 20;
+const RADIUS_VECTOR_LENGTH: usize = 16;
+const RADIUS_MAX_PASSWORD_LENGTH: usize = 128;
+const RADIUS_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct RadiusServer {
+    pub address: SocketAddr,
+    pub secret: Vec<u8>,
+    /// NAS-Identifier sent in requests
+    pub identifier: String,
+}
+
+impl RadiusServer {
+    pub fn new(address: SocketAddr, secret: Vec<u8>) -> RadiusServer {
+        RadiusServer {
+            address,
+            secret,
+            identifier: "postgresql".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RadiusConfig {
+    pub servers: Vec<RadiusServer>,
+    /// Time to wait for a response
+    pub timeout: Duration,
+    /// Number of retransmissions to each server after a timeout
+    pub retries: usize,
+}
+
+impl RadiusConfig {
+    pub fn new(servers: Vec<RadiusServer>) -> RadiusConfig {
+        RadiusConfig {
+            servers,
+            timeout: Duration::from_secs(3),
+            retries: 0,
+        }
+    }
+}
+
+/// `PasswordVerifier` backed by RADIUS `Access-Request`.
+#[derive(Debug, new)]
+pub struct RadiusPasswordVerifier {
+    config: RadiusConfig,
+}
+
+#[async_trait]
+impl PasswordVerifier for RadiusPasswordVerifier {
+    async fn verify(&self, username: &str, password: &str) -> PgWireResult<bool> {
+        if password.is_empty() || password.len() > RADIUS_MAX_PASSWORD_LENGTH {
+            return Ok(false);
+        }
+
+        let mut last_error = None;
+        for server in &self.config.servers {
+            match self.verify_with_server(server, username, password).await {
+                Ok(accepted) => return Ok(accepted),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            PgWireError::ApiError("no RADIUS server configured".to_owned().into())
+        }))
+    }
+
+    async fn verify_md5(
+        &self,
+        _username: &str,
+        _hash: &str,
+        _salt: &[u8; 4],
+    ) -> PgWireResult<bool> {
+        // RADIUS requires the cleartext password
+        Err(PgWireError::UnsupportedAuthenticationMethod)
+    }
+}
+
+impl RadiusPasswordVerifier {
+    async fn verify_with_server(
+        &self,
+        server: &RadiusServer,
+        username: &str,
+        password: &str,
+    ) -> PgWireResult<bool> {
+        let local: SocketAddr = if server.address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(server.address).await?;
+
+        let identifier = rand::random::<u8>();
+        let vector = rand::random::<[u8; RADIUS_VECTOR_LENGTH]>();
+        let request = encode_access_request(server, identifier, &vector, username, password);
+
+        let mut buf = [0u8; RADIUS_BUFFER_SIZE];
+        for _ in 0..=self.config.retries {
+            socket.send(&request).await?;
+            // ignore packets not answering this request until timeout
+            let receive = async {
+                loop {
+                    let n = socket.recv(&mut buf).await?;
+                    if let Some(accepted) =
+                        check_response(&buf[..n], identifier, &vector, &server.secret)
+                    {
+                        return Ok::<bool, PgWireError>(accepted);
+                    }
+                }
+            };
+            if let Ok(result) = timeout(self.config.timeout, receive).await {
+                return result;
+            }
+        }
+
+        Err(PgWireError::ApiError(
+            format!(
+                "timeout waiting for RADIUS response from {}",
+                server.address
+            )
+            .into(),
+        ))
+    }
+}
+
+fn put_attribute(packet: &mut Vec<u8>, attr: u8, value: &[u8]) {
+    packet.push(attr);
+    packet.push((value.len() + 2) as u8);
+    packet.extend_from_slice(value);
+}
+
+fn encode_access_request(
+    server: &RadiusServer,
+    identifier: u8,
+    vector: &[u8; RADIUS_VECTOR_LENGTH],
+    username: &str,
+    password: &str,
+) -> Vec<u8> {
+    let mut packet = vec![RADIUS_ACCESS_REQUEST, identifier, 0, 0];
+    packet.extend_from_slice(vector);
+
+    put_attribute(&mut packet, RADIUS_USER_NAME, username.as_bytes());
+    put_attribute(
+        &mut packet,
+        RADIUS_USER_PASSWORD,
+        &hide_password(password.as_bytes(), &server.secret, vector),
+    );
+    put_attribute(
+        &mut packet,
+        RADIUS_SERVICE_TYPE,
+        &RADIUS_AUTHENTICATE_ONLY.to_be_bytes(),
+    );
+    put_attribute(
+        &mut packet,
+        RADIUS_NAS_IDENTIFIER,
+        server.identifier.as_bytes(),
+    );
+
+    let len = packet.len() as u16;
+    packet[2..4].copy_from_slice(&len.to_be_bytes());
+    packet
+}
+
+/// Hide password as described in RFC 2865, section 5.2: the password padded
+/// to 16 bytes blocks is xored with `MD5(secret + previous block)`.
+fn hide_password(password: &[u8], secret: &[u8], vector: &[u8]) -> Vec<u8> {
+    let blocks = (password.len() + RADIUS_VECTOR_LENGTH - 1) / RADIUS_VECTOR_LENGTH;
+    let padded_len = blocks * RADIUS_VECTOR_LENGTH;
+    let mut hidden = password.to_vec();
+    hidden.resize(padded_len, 0);
+
+    let mut previous = vector.to_vec();
+    for block in hidden.chunks_mut(RADIUS_VECTOR_LENGTH) {
+        let mut context = md5::Context::new();
+        context.consume(secret);
+        context.consume(&previous);
+        let digest = context.compute();
+        for (b, d) in block.iter_mut().zip(digest.iter()) {
+            *b ^= d;
+        }
+        previous = block.to_vec();
+    }
+    hidden
+}
+
+/// Returns whether access is accepted, or `None` if the packet is not a
+/// valid response of the request.
+fn check_response(packet: &[u8], identifier: u8, vector: &[u8], secret: &[u8]) -> Option<bool> {
+    if packet.len() < RADIUS_HEADER_LENGTH || packet[1] != identifier {
+        return None;
+    }
+    let len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if len < RADIUS_HEADER_LENGTH || len > packet.len() {
+        return None;
+    }
+
+    // ResponseAuth = MD5(Code+ID+Length+RequestAuth+Attributes+Secret)
+    let mut context = md5::Context::new();
+    context.consume(&packet[..4]);
+    context.consume(vector);
+    context.consume(&packet[RADIUS_HEADER_LENGTH..len]);
+    context.consume(secret);
+    if context.compute().0 != packet[4..RADIUS_HEADER_LENGTH] {
+        return None;
+    }
+
+    match packet[0] {
+        RADIUS_ACCESS_ACCEPT => Some(true),
+        RADIUS_ACCESS_REJECT => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Read attributes of a request
+    fn attributes(packet: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut attrs = Vec::new();
+        let mut rest = &packet[RADIUS_HEADER_LENGTH..];
+        while rest.len() >= 2 {
+            let len = rest[1] as usize;
+            attrs.push((rest[0], rest[2..len].to_vec()));
+            rest = &rest[len..];
+        }
+        attrs
+    }
+
+    fn response(request: &[u8], code: u8, secret: &[u8]) -> Vec<u8> {
+        let mut packet = vec![code, request[1], 0, RADIUS_HEADER_LENGTH as u8];
+        let mut context = md5::Context::new();
+        context.consume(&packet);
+        context.consume(&request[4..RADIUS_HEADER_LENGTH]);
+        context.consume(secret);
+        packet.extend_from_slice(&context.compute().0);
+        packet
+    }
+
+    /// A RADIUS server accepting alice with `secret`, drops the first
+    /// `drop` requests
+    async fn serve(secret: &'static [u8], mut drop: usize) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; RADIUS_BUFFER_SIZE];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                if drop > 0 {
+                    drop -= 1;
+                    continue;
+                }
+                let request = &buf[..n];
+                let vector = &request[4..RADIUS_HEADER_LENGTH];
+                let attrs = attributes(request);
+                let user = &attrs.iter().find(|a| a.0 == RADIUS_USER_NAME).unwrap().1;
+                let hidden = &attrs
+                    .iter()
+                    .find(|a| a.0 == RADIUS_USER_PASSWORD)
+                    .unwrap()
+                    .1;
+                // hiding is its own inverse for the first block
+                let password = hide_password(hidden, secret, vector);
+                let accepted = user == b"alice" && password.starts_with(b"hunter2\0");
+                let code = if accepted {
+                    RADIUS_ACCESS_ACCEPT
+                } else {
+                    RADIUS_ACCESS_REJECT
+                };
+                socket
+                    .send_to(&response(request, code, secret), peer)
+                    .await
+                    .unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_radius_verify() {
+        let addr = serve(b"s3cr3t", 0).await;
+        let verifier = RadiusPasswordVerifier::new(RadiusConfig::new(vec![RadiusServer::new(
+            addr,
+            b"s3cr3t".to_vec(),
+        )]));
+        assert!(verifier.verify("alice", "hunter2").await.unwrap());
+        assert!(!verifier.verify("alice", "wrong").await.unwrap());
+        assert!(!verifier.verify("bob", "hunter2").await.unwrap());
+        assert!(!verifier.verify("alice", "").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_radius_retry_and_failover() {
+        let addr = serve(b"s3cr3t", 1).await;
+        let mut config = RadiusConfig::new(vec![RadiusServer::new(addr, b"s3cr3t".to_vec())]);
+        config.timeout = Duration::from_millis(200);
+        config.retries = 1;
+        let verifier = RadiusPasswordVerifier::new(config);
+        assert!(verifier.verify("alice", "hunter2").await.unwrap());
+
+        // responses signed with another secret are ignored, so the first
+        // server times out and the second is used
+        let wrong_secret = serve(b"other", 0).await;
+        let mut config = RadiusConfig::new(vec![
+            RadiusServer::new(wrong_secret, b"s3cr3t".to_vec()),
+            RadiusServer::new(addr, b"s3cr3t".to_vec()),
+        ]);
+        config.timeout = Duration::from_millis(200);
+        let verifier = RadiusPasswordVerifier::new(config);
+        assert!(verifier.verify("alice", "hunter2").await.unwrap());
+
+        let mut config =
+            RadiusConfig::new(vec![RadiusServer::new(wrong_secret, b"s3cr3t".to_vec())]);
+        config.timeout = Duration::from_millis(100);
+        let verifier = RadiusPasswordVerifier::new(config);
+        assert!(verifier.verify("alice", "hunter2").await.is_err());
+    }
+
+    #[test]
+    fn test_hide_password() {
+        let vector = [1u8; RADIUS_VECTOR_LENGTH];
+        let hidden = hide_password(b"a password longer than 16 bytes", b"secret", &vector);
+        assert_eq!(32, hidden.len());
+        assert_ne!(&hidden[..16], b"a password longe");
+    }
+}
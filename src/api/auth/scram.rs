@@ -1,7 +1,8 @@
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::num::NonZeroU32;
 use std::ops::BitXor;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -13,10 +14,12 @@ use ring::digest;
 use ring::hmac;
 use ring::pbkdf2;
 use tokio::sync::Mutex;
+use tracing::Instrument;
 use x509_certificate::certificate::CapturedX509Certificate;
 use x509_certificate::SignatureAlgorithm;
 
 use crate::api::auth::{AuthSource, LoginInfo, Password};
+use crate::api::trace::auth_span;
 use crate::api::{ClientInfo, MakeHandler, PgWireConnectionState};
 use crate::error::{PgWireError, PgWireResult};
 use crate::messages::startup::Authentication;
@@ -27,8 +30,8 @@ use super::{ServerParameterProvider, StartupHandler};
 #[derive(Debug)]
 pub enum ScramState {
     Initial,
-    // cached password, channel_binding and partial auth-message
-    ServerFirstSent(Password, String, String),
+    // cached verifier, channel_binding and partial auth-message
+    ServerFirstSent(ScramVerifier, String, String),
 }
 
 #[derive(Debug)]
@@ -37,12 +40,43 @@ pub struct SASLScramAuthStartupHandler<A, P> {
     parameter_provider: Arc<P>,
     /// state of the client-server communication
     state: Mutex<ScramState>,
-    /// base64 encoded certificate signature for tls-server-end-point channel binding
-    server_cert_sig: Option<Arc<String>>,
+    /// certificate hash for tls-server-end-point channel binding
+    server_cert_sig: Option<Arc<Vec<u8>>>,
     /// iterations
     iterations: usize,
+    /// server secret mixed into stored salted passwords
+    pepper: Option<Arc<Vec<u8>>>,
 }
 
+/// SCRAM mechanism without channel binding
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+/// SCRAM mechanism with `tls-server-end-point` channel binding
+pub const SCRAM_SHA_256_PLUS: &str = "SCRAM-SHA-256-PLUS";
+/// The only channel binding type supported by `SCRAM-SHA-256-PLUS`
+pub const TLS_SERVER_END_POINT: &str = "tls-server-end-point";
+
+/// SASL mechanisms advertised in `AuthenticationSASL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct SaslMechanismList {
+    /// server certificate is configured for channel binding
+    channel_binding: bool,
+}
+
+impl SaslMechanismList {
+    /// Mechanisms supported by a connection. `SCRAM-SHA-256-PLUS` is only
+    /// included for TLS connections with channel binding data available.
+    pub fn supported_mechanisms(&self, tls_active: bool) -> Vec<&'static str> {
+        if tls_active && self.channel_binding {
+            vec![SCRAM_SHA_256, SCRAM_SHA_256_PLUS]
+        } else {
+            vec![SCRAM_SHA_256]
+        }
+    }
+}
+
+/// Length of pepper, identical to output of HMAC-SHA-256
+pub const PEPPER_LENGTH: usize = 32;
+
 /// Compute salted password from raw password as defined in
 /// [RFC5802](https://www.rfc-editor.org/rfc/rfc5802#section-3)
 ///
@@ -60,6 +94,152 @@ pub fn gen_salted_password(password: &str, salt: &[u8], iters: usize) -> Vec<u8>
     hi(pass_bytes, salt, iters)
 }
 
+/// Same as [`gen_salted_password`], with `pepper` XORed into the salted
+/// password, so stored value alone is not enough for offline attacks. The
+/// handler has to be configured with the same pepper by
+/// [`MakeSASLScramAuthStartupHandler::with_pepper`].
+///
+/// Returns `InvalidPepperLength` error if length of `pepper` is not
+/// [`PEPPER_LENGTH`].
+pub fn gen_salted_password_with_pepper(
+    password: &str,
+    salt: &[u8],
+    iters: usize,
+    pepper: &[u8],
+) -> PgWireResult<Vec<u8>> {
+    check_pepper_length(pepper)?;
+    Ok(xor(&gen_salted_password(password, salt, iters), pepper))
+}
+
+fn check_pepper_length(pepper: &[u8]) -> PgWireResult<()> {
+    if pepper.len() == PEPPER_LENGTH {
+        Ok(())
+    } else {
+        Err(PgWireError::InvalidPepperLength(pepper.len()))
+    }
+}
+
+/// Prefix of SCRAM verifier
+pub const SCRAM_VERIFIER_PREFIX: &str = "SCRAM-SHA-256$";
+/// Salt length of generated verifiers, same as postgres
+const SCRAM_DEFAULT_SALT_LENGTH: usize = 16;
+
+/// SCRAM secret in the format postgres stores in `pg_authid.rolpassword`:
+///
+/// ```text
+/// SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>
+/// ```
+///
+/// with salt and keys in base64. Only the keys are needed to authenticate a
+/// client, so `AuthSource` can return verifiers exported from postgres as
+/// password, with `None` salt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramVerifier {
+    iterations: usize,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl ScramVerifier {
+    /// Generate verifier of `password` with a random salt.
+    pub fn generate(password: &str, iterations: usize) -> ScramVerifier {
+        let salt = (0..SCRAM_DEFAULT_SALT_LENGTH)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<u8>>();
+        Self::from_password(password, &salt, iterations)
+    }
+
+    /// Compute verifier of `password` with given salt.
+    pub fn from_password(password: &str, salt: &[u8], iterations: usize) -> ScramVerifier {
+        let salted_password = gen_salted_password(password, salt, iterations);
+        Self::from_salted_password(&salted_password, salt, iterations)
+    }
+
+    /// Compute verifier from a salted password generated by
+    /// [`gen_salted_password`].
+    pub fn from_salted_password(
+        salted_password: &[u8],
+        salt: &[u8],
+        iterations: usize,
+    ) -> ScramVerifier {
+        let client_key = hmac(salted_password, b"Client Key");
+        ScramVerifier {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: h(&client_key),
+            server_key: hmac(salted_password, b"Server Key"),
+        }
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// Check client proof of `auth_msg`, returns the server signature if
+    /// it's valid.
+    fn verify_client_proof(&self, auth_msg: &[u8], proof: &[u8]) -> Option<Vec<u8>> {
+        if proof.len() != self.stored_key.len() {
+            return None;
+        }
+        let client_signature = hmac(&self.stored_key, auth_msg);
+        let client_key = xor(proof, &client_signature);
+        if h(&client_key) == self.stored_key {
+            Some(hmac(&self.server_key, auth_msg))
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for ScramVerifier {
+    type Err = PgWireError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PgWireError::InvalidScramVerifier(s.to_owned());
+        let rest = s.strip_prefix(SCRAM_VERIFIER_PREFIX).ok_or_else(invalid)?;
+        let (iter_salt, keys) = rest.split_once('$').ok_or_else(invalid)?;
+        let (iterations, salt) = iter_salt.split_once(':').ok_or_else(invalid)?;
+        let (stored_key, server_key) = keys.split_once(':').ok_or_else(invalid)?;
+
+        let iterations = iterations
+            .parse::<usize>()
+            .ok()
+            .filter(|i| *i > 0)
+            .ok_or_else(invalid)?;
+        let decode = |v: &str| STANDARD.decode(v).map_err(|_| invalid());
+        let verifier = ScramVerifier {
+            iterations,
+            salt: decode(salt)?,
+            stored_key: decode(stored_key)?,
+            server_key: decode(server_key)?,
+        };
+        if verifier.stored_key.len() != digest::SHA256_OUTPUT_LEN
+            || verifier.server_key.len() != digest::SHA256_OUTPUT_LEN
+        {
+            return Err(invalid());
+        }
+        Ok(verifier)
+    }
+}
+
+impl Display for ScramVerifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{SCRAM_VERIFIER_PREFIX}{}:{}${}:{}",
+            self.iterations,
+            STANDARD.encode(&self.salt),
+            STANDARD.encode(&self.stored_key),
+            STANDARD.encode(&self.server_key)
+        )
+    }
+}
+
 pub fn random_nonce() -> String {
     let mut buf = [0u8; 18];
     for v in buf.iter_mut() {
@@ -70,19 +250,45 @@ pub fn random_nonce() -> String {
 }
 
 impl<A, P> SASLScramAuthStartupHandler<A, P> {
-    fn compute_channel_binding(&self, client_channel_binding: &str) -> String {
-        if client_channel_binding.starts_with("p=tls-server-end-point") {
-            format!(
-                "{}{}",
-                STANDARD.encode(client_channel_binding),
-                self.server_cert_sig
-                    .as_deref()
-                    .map(|v| &v[..])
-                    .unwrap_or("")
-            )
-        } else {
-            STANDARD.encode(client_channel_binding.as_bytes())
+    /// Keys of the password returned by `AuthSource`, which is either a
+    /// SCRAM verifier, or a (peppered) salted password with salt.
+    fn scram_verifier(&self, password: Password) -> PgWireResult<ScramVerifier> {
+        if password
+            .password
+            .starts_with(SCRAM_VERIFIER_PREFIX.as_bytes())
+        {
+            return String::from_utf8_lossy(&password.password).parse();
+        }
+
+        let salt = password.salt.as_deref().ok_or_else(|| {
+            PgWireError::InvalidScramVerifier("salt required for salted password".to_owned())
+        })?;
+        // remove pepper to get the SaltedPassword of client
+        let salted_password = match self.pepper.as_deref() {
+            Some(pepper) => xor(&password.password, pepper),
+            None => password.password,
+        };
+        Ok(ScramVerifier::from_salted_password(
+            &salted_password,
+            salt,
+            self.iterations,
+        ))
+    }
+
+    fn mechanism_list(&self) -> SaslMechanismList {
+        SaslMechanismList::new(self.server_cert_sig.is_some())
+    }
+
+    /// Expected `c=` attribute of client-final: gs2 header of client-first,
+    /// followed by certificate hash when channel binding is used.
+    fn compute_channel_binding(&self, gs2_header: &str) -> String {
+        let mut data = gs2_header.as_bytes().to_vec();
+        if gs2_header.starts_with("p=") {
+            if let Some(sig) = self.server_cert_sig.as_deref() {
+                data.extend_from_slice(sig);
+            }
         }
+        STANDARD.encode(data)
     }
 }
 
@@ -104,11 +310,12 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
             PgWireFrontendMessage::Startup(ref startup) => {
                 super::save_startup_parameters_to_metadata(client, startup);
                 client.set_state(PgWireConnectionState::AuthenticationInProgress);
-                let supported_mechanisms = if self.server_cert_sig.is_some() {
-                    vec!["SCRAM-SHA-256".to_owned(), "SCRAM-SHA-256-PLUS".to_owned()]
-                } else {
-                    vec!["SCRAM-SHA-256".to_owned()]
-                };
+                let supported_mechanisms = self
+                    .mechanism_list()
+                    .supported_mechanisms(client.is_secure())
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect();
                 client
                     .send(PgWireBackendMessage::Authentication(Authentication::SASL(
                         supported_mechanisms,
@@ -116,14 +323,19 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                     .await?;
             }
             PgWireFrontendMessage::PasswordMessageFamily(msg) => {
-                let salt_and_salted_pass = {
+                let verifier = {
                     let state = self.state.lock().await;
                     match *state {
                         ScramState::Initial => {
                             let login_info = LoginInfo::from_client_info(client);
-                            self.auth_db.get_password(&login_info).await?
+                            let password = self
+                                .auth_db
+                                .get_password(&login_info)
+                                .instrument(auth_span(&login_info))
+                                .await?;
+                            self.scram_verifier(password)?
                         }
-                        ScramState::ServerFirstSent(ref pass, _, _) => pass.clone(),
+                        ScramState::ServerFirstSent(ref verifier, _, _) => verifier.clone(),
                     }
                 };
 
@@ -135,6 +347,16 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                         ScramState::Initial => {
                             // initial response, client_first
                             let resp = msg.into_sasl_initial_response()?;
+                            if !self
+                                .mechanism_list()
+                                .supported_mechanisms(client.is_secure())
+                                .contains(&resp.auth_method.as_str())
+                            {
+                                return Err(PgWireError::InvalidScramMessage(format!(
+                                    "Unsupported SASL mechanism: {}",
+                                    resp.auth_method
+                                )));
+                            }
                             // parse into client_first
                             let client_first = resp
                                 .data
@@ -147,6 +369,12 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                                 .and_then(|data| {
                                     ClientFirst::try_new(String::from_utf8_lossy(data).as_ref())
                                 })?;
+                            client_first.validate_channel_binding(
+                                &resp.auth_method,
+                                self.mechanism_list()
+                                    .supported_mechanisms(client.is_secure())
+                                    .contains(&SCRAM_SHA_256_PLUS),
+                            )?;
                             // dbg!(&client_first);
 
                             // create server_first and send
@@ -155,18 +383,13 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
 
                             let server_first = ServerFirst::new(
                                 new_nonce,
-                                STANDARD.encode(
-                                    salt_and_salted_pass
-                                        .salt
-                                        .as_ref()
-                                        .expect("Salt required for SCRAM auth source"),
-                                ),
-                                self.iterations,
+                                STANDARD.encode(verifier.salt()),
+                                verifier.iterations(),
                             );
                             let server_first_message = server_first.message();
 
                             *state = ScramState::ServerFirstSent(
-                                salt_and_salted_pass,
+                                verifier,
                                 client_first.channel_binding(),
                                 format!("{},{}", client_first.bare(), &server_first_message),
                             );
@@ -187,21 +410,14 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                                 self.compute_channel_binding(channel_binding_prefix);
                             client_final.validate_channel_binding(&channel_binding)?;
 
-                            let salted_password = salt_and_salted_pass.password;
-                            let client_key = hmac(salted_password.as_ref(), b"Client Key");
-                            let stored_key = h(client_key.as_ref());
                             let auth_msg =
                                 format!("{},{}", partial_auth_msg, client_final.without_proof());
-                            let client_signature = hmac(stored_key.as_ref(), auth_msg.as_bytes());
+                            let server_signature =
+                                STANDARD.decode(&client_final.proof).ok().and_then(|proof| {
+                                    verifier.verify_client_proof(auth_msg.as_bytes(), &proof)
+                                });
 
-                            let computed_client_proof = STANDARD.encode(
-                                xor(client_key.as_ref(), client_signature.as_ref()).as_slice(),
-                            );
-
-                            if computed_client_proof == client_final.proof {
-                                let server_key = hmac(salted_password.as_ref(), b"Server Key");
-                                let server_signature =
-                                    hmac(server_key.as_ref(), auth_msg.as_bytes());
+                            if let Some(server_signature) = server_signature {
                                 let server_final =
                                     ServerFinalSuccess::new(STANDARD.encode(server_signature));
                                 success = true;
@@ -223,7 +439,7 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                     super::finish_authentication(client, self.parameter_provider.as_ref()).await
                 }
             }
-            _ => {}
+            msg => return Err(PgWireError::unexpected_message("SASLResponse", &msg)),
         }
 
         Ok(())
@@ -235,9 +451,11 @@ pub struct MakeSASLScramAuthStartupHandler<A, P> {
     auth_db: Arc<A>,
     parameter_provider: Arc<P>,
     #[new(default)]
-    server_cert_sig: Option<Arc<String>>,
+    server_cert_sig: Option<Arc<Vec<u8>>>,
     #[new(value = "4096")]
     iterations: usize,
+    #[new(default)]
+    pepper: Option<Arc<Vec<u8>>>,
 }
 
 impl<A, P> MakeSASLScramAuthStartupHandler<A, P> {
@@ -247,8 +465,22 @@ impl<A, P> MakeSASLScramAuthStartupHandler<A, P> {
     /// Original pem data is required here. We will decode pem and use the first
     /// certificate as server certificate.
     pub fn configure_certificate(&mut self, certs_pem: &[u8]) -> PgWireResult<()> {
-        let sig = compute_cert_signature(certs_pem)?;
-        self.server_cert_sig = Some(Arc::new(STANDARD.encode(sig)));
+        let certs = CapturedX509Certificate::from_pem_multiple(certs_pem)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let cert = certs
+            .first()
+            .ok_or_else(|| PgWireError::ApiError("No certificate found in pem".into()))?;
+        self.server_cert_sig = Some(Arc::new(compute_cert_signature(cert)?));
+        Ok(())
+    }
+
+    /// enable channel binding (SCRAM-SHA-256-PLUS) with DER encoded server
+    /// certificate, for example the first entry of the certificate chain
+    /// configured in rustls `ServerConfig`.
+    pub fn configure_certificate_der(&mut self, cert_der: &[u8]) -> PgWireResult<()> {
+        let cert = CapturedX509Certificate::from_der(cert_der.to_vec())
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.server_cert_sig = Some(Arc::new(compute_cert_signature(&cert)?));
         Ok(())
     }
 
@@ -263,6 +495,22 @@ impl<A, P> MakeSASLScramAuthStartupHandler<A, P> {
     pub fn set_iterations(&mut self, iterations: usize) {
         self.iterations = iterations;
     }
+
+    /// Set a server side secret mixed into stored salted passwords.
+    ///
+    /// Passwords returned by `AuthSource` are expected to be generated by
+    /// [`gen_salted_password_with_pepper`] with the same pepper, so a leaked
+    /// password database cannot be attacked offline without the pepper. The
+    /// pepper should be generated from a secure random source and kept out of
+    /// the database.
+    ///
+    /// Returns `InvalidPepperLength` error if length of `pepper` is not
+    /// [`PEPPER_LENGTH`].
+    pub fn with_pepper(mut self, pepper: Vec<u8>) -> PgWireResult<Self> {
+        check_pepper_length(&pepper)?;
+        self.pepper = Some(Arc::new(pepper));
+        Ok(self)
+    }
 }
 
 impl<A, P> MakeHandler for MakeSASLScramAuthStartupHandler<A, P>
@@ -279,6 +527,7 @@ where
             state: Mutex::new(ScramState::Initial),
             server_cert_sig: self.server_cert_sig.clone(),
             iterations: self.iterations,
+            pepper: self.pepper.clone(),
         })
     }
 }
@@ -304,13 +553,6 @@ impl ClientFirst {
         }
         // now it's safe to unwrap
         let cbind_flag = parts[0].to_owned();
-        // add additional check when we don't have channel binding
-        // if cbind_flag != 'n' {
-        //     return Err(PgWireError::InvalidScramMessage(format!(
-        //         "cbing_flag: {}, but channel binding not supported.",
-        //         cbind_flag
-        //     )));
-        // }
 
         let auth_zid = parts[1].to_owned();
         let username = parts[2].strip_prefix("n=").unwrap().to_owned();
@@ -328,6 +570,27 @@ impl ClientFirst {
         flag == "n" || flag == "y" || flag.starts_with("p=")
     }
 
+    /// Check channel binding flag of gs2 header against the selected
+    /// `mechanism`, as defined in RFC 5802 section 6.
+    fn validate_channel_binding(&self, mechanism: &str, plus_supported: bool) -> PgWireResult<()> {
+        let plus_selected = mechanism == SCRAM_SHA_256_PLUS;
+        let error = match self.cbind_flag.as_str() {
+            "n" if plus_selected => "channel binding is required by SCRAM-SHA-256-PLUS",
+            "n" => return Ok(()),
+            // client supports channel binding, but thinks server does not
+            "y" if plus_selected || plus_supported => {
+                "channel binding is supported by server, possible downgrade attack"
+            }
+            "y" => return Ok(()),
+            _ if !plus_selected => "channel binding requires SCRAM-SHA-256-PLUS",
+            flag if flag.strip_prefix("p=") != Some(TLS_SERVER_END_POINT) => {
+                "unsupported channel binding type"
+            }
+            _ => return Ok(()),
+        };
+        Err(PgWireError::InvalidScramMessage(error.to_owned()))
+    }
+
     fn bare(&self) -> String {
         format!("n={},r={}", self.username, self.nonce)
     }
@@ -457,11 +720,8 @@ fn xor(lhs: &[u8], rhs: &[u8]) -> Vec<u8> {
 /// 1. use sha-256 if the certificate's algorithm is md5 or sha-1
 /// 2. use the certificate's algorithm if it's neither md5 or sha-1
 /// 3. if the certificate has 0 or more than 1 signature algorithm, the
-/// behaviour is undefined at the time.
-fn compute_cert_signature(cert: &[u8]) -> PgWireResult<Vec<u8>> {
-    let certs = CapturedX509Certificate::from_pem_multiple(cert)
-        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-    let x509 = &certs[0];
+///    behaviour is undefined at the time.
+fn compute_cert_signature(x509: &CapturedX509Certificate) -> PgWireResult<Vec<u8>> {
     let raw = x509.constructed_data();
     match x509.signature_algorithm() {
         Some(SignatureAlgorithm::RsaSha1)
@@ -478,3 +738,147 @@ fn compute_cert_signature(cert: &[u8]) -> PgWireResult<Vec<u8>> {
         _ => Err(PgWireError::UnsupportedCertificateSignatureAlgorithm),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::auth::DefaultServerParameterProvider;
+
+    #[test]
+    fn test_gen_salted_password_with_pepper() {
+        let salt = b"salt";
+        let salted_password = gen_salted_password("pencil", salt, 4096);
+        assert_eq!(32, salted_password.len());
+
+        let pepper = vec![0x5a; PEPPER_LENGTH];
+        let peppered = gen_salted_password_with_pepper("pencil", salt, 4096, &pepper).unwrap();
+        assert_ne!(salted_password, peppered);
+        assert_eq!(salted_password, xor(&peppered, &pepper));
+    }
+
+    #[test]
+    fn test_scram_verifier() {
+        // test vector of RFC7677
+        let salt = STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let verifier = ScramVerifier::from_password("pencil", &salt, 4096);
+        let auth_msg = "n=user,r=rOprNGfwEbeRWgbNEkqO,\
+            r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096,\
+            c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+        let proof = STANDARD
+            .decode("dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+            .unwrap();
+        assert_eq!(
+            Some(
+                STANDARD
+                    .decode("6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                    .unwrap()
+            ),
+            verifier.verify_client_proof(auth_msg.as_bytes(), &proof)
+        );
+        assert_eq!(
+            None,
+            verifier.verify_client_proof(auth_msg.as_bytes(), &[0u8; 32])
+        );
+
+        let text = verifier.to_string();
+        assert!(text.starts_with("SCRAM-SHA-256$4096:W22ZaJ0SNY7soEsUEjb6gQ==$"));
+        assert_eq!(verifier, text.parse().unwrap());
+
+        let generated = ScramVerifier::generate("pencil", 4096);
+        assert_eq!(16, generated.salt().len());
+        assert_eq!(
+            generated,
+            ScramVerifier::from_password("pencil", generated.salt(), 4096)
+        );
+
+        for invalid in [
+            "md5abc",
+            "SCRAM-SHA-256$4096:c2FsdA==",
+            "SCRAM-SHA-256$0:c2FsdA==$AAAA:AAAA",
+            "SCRAM-SHA-256$4096:c2FsdA==$AAAA:AAAA",
+            "SCRAM-SHA-256$4096:!!$AAAA:AAAA",
+        ] {
+            assert!(matches!(
+                invalid.parse::<ScramVerifier>(),
+                Err(PgWireError::InvalidScramVerifier(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_supported_mechanisms() {
+        let mechanisms = SaslMechanismList::new(true);
+        assert_eq!(vec![SCRAM_SHA_256], mechanisms.supported_mechanisms(false));
+        assert_eq!(
+            vec![SCRAM_SHA_256, SCRAM_SHA_256_PLUS],
+            mechanisms.supported_mechanisms(true)
+        );
+
+        let mechanisms = SaslMechanismList::new(false);
+        assert_eq!(vec![SCRAM_SHA_256], mechanisms.supported_mechanisms(false));
+        assert_eq!(vec![SCRAM_SHA_256], mechanisms.supported_mechanisms(true));
+    }
+
+    #[test]
+    fn test_invalid_pepper_length() {
+        assert!(matches!(
+            gen_salted_password_with_pepper("pencil", b"salt", 4096, &[0u8; 16]),
+            Err(PgWireError::InvalidPepperLength(16))
+        ));
+
+        let make_handler = MakeSASLScramAuthStartupHandler::new(
+            Arc::new(()),
+            Arc::new(DefaultServerParameterProvider::default()),
+        );
+        assert!(matches!(
+            make_handler.with_pepper(vec![0u8; 16]),
+            Err(PgWireError::InvalidPepperLength(16))
+        ));
+    }
+
+    #[test]
+    fn test_validate_channel_binding() {
+        let cases = vec![
+            ("n", SCRAM_SHA_256, false, true),
+            ("n", SCRAM_SHA_256, true, true),
+            ("n", SCRAM_SHA_256_PLUS, true, false),
+            ("y", SCRAM_SHA_256, false, true),
+            // downgrade from SCRAM-SHA-256-PLUS
+            ("y", SCRAM_SHA_256, true, false),
+            ("p=tls-server-end-point", SCRAM_SHA_256_PLUS, true, true),
+            ("p=tls-server-end-point", SCRAM_SHA_256, true, false),
+            ("p=tls-unique", SCRAM_SHA_256_PLUS, true, false),
+        ];
+        for (flag, mechanism, plus_supported, valid) in cases {
+            let client_first = ClientFirst::try_new(&format!("{flag},,n=,r=nonce")).unwrap();
+            assert_eq!(
+                valid,
+                client_first
+                    .validate_channel_binding(mechanism, plus_supported)
+                    .is_ok(),
+                "{flag} with {mechanism}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compute_channel_binding() {
+        let handler = SASLScramAuthStartupHandler {
+            auth_db: Arc::new(()),
+            parameter_provider: Arc::new(DefaultServerParameterProvider::default()),
+            state: Mutex::new(ScramState::Initial),
+            server_cert_sig: Some(Arc::new(vec![1, 2, 3])),
+            iterations: 4096,
+            pepper: None,
+        };
+        assert_eq!("biws", handler.compute_channel_binding("n,,"));
+        assert_eq!("eSws", handler.compute_channel_binding("y,,"));
+
+        let mut expected = b"p=tls-server-end-point,,".to_vec();
+        expected.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(
+            STANDARD.encode(expected),
+            handler.compute_channel_binding("p=tls-server-end-point,,")
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! SSPI authentication, like the `sspi` method of Postgres on Windows.
+//!
+//! Windows clients using integrated authentication answer `AuthenticationSSPI`
+//! with the same token exchange as GSSAPI, so an SSPI provider, usually
+//! backed by `AcceptSecurityContext` with the Negotiate package, implements
+//! the traits of [`super::gss`].
+
+use std::sync::Arc;
+
+use super::gss::{GssAuthStartupHandler, GssMechanism, GssProvider, MakeGssAuthStartupHandler};
+use super::ServerParameterProvider;
+use crate::api::MakeHandler;
+
+pub use super::gss::{
+    GssAcceptStep as SspiAcceptStep, GssContext as SspiContext, GssProvider as SspiProvider,
+};
+
+#[derive(Debug)]
+pub struct MakeSspiAuthStartupHandler<G, P>(MakeGssAuthStartupHandler<G, P>);
+
+impl<G, P> MakeSspiAuthStartupHandler<G, P> {
+    pub fn new(provider: Arc<G>, parameter_provider: Arc<P>) -> Self {
+        MakeSspiAuthStartupHandler(MakeGssAuthStartupHandler::with_mechanism(
+            provider,
+            parameter_provider,
+            GssMechanism::Sspi,
+        ))
+    }
+
+    /// Whether realm (domain) of the principal is kept when comparing with
+    /// user name, `true` by default as in Postgres.
+    pub fn set_include_realm(&mut self, include_realm: bool) {
+        self.0.set_include_realm(include_realm);
+    }
+}
+
+impl<G, P> MakeHandler for MakeSspiAuthStartupHandler<G, P>
+where
+    G: GssProvider,
+    P: ServerParameterProvider,
+{
+    type Handler = Arc<GssAuthStartupHandler<G, P>>;
+
+    fn make(&self) -> Self::Handler {
+        self.0.make()
+    }
+}
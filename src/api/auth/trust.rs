@@ -0,0 +1,49 @@
+//! Trust authentication, like the `trust` method of Postgres.
+//!
+//! Any client is accepted as the user it claims to be. Only use it for
+//! development, or when the server is reachable by trusted clients only.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+
+use super::{ClientInfo, DefaultServerParameterProvider, ServerParameterProvider, StartupHandler};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Sends `AuthenticationOk` and server parameters right after `Startup`,
+/// without asking for password.
+#[derive(new)]
+pub struct TrustAuthStartupHandler<P> {
+    parameter_provider: P,
+}
+
+impl Default for TrustAuthStartupHandler<DefaultServerParameterProvider> {
+    fn default() -> Self {
+        TrustAuthStartupHandler::new(DefaultServerParameterProvider::default())
+    }
+}
+
+#[async_trait]
+impl<P: ServerParameterProvider> StartupHandler for TrustAuthStartupHandler<P> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                super::save_startup_parameters_to_metadata(client, startup);
+                super::finish_authentication(client, &self.parameter_provider).await;
+                Ok(())
+            }
+            msg => Err(PgWireError::unexpected_message("Startup", &msg)),
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Result cache for simple queries.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ahash::RandomState;
+use async_trait::async_trait;
+use futures::{stream, Sink, StreamExt};
+use tokio::time::Instant;
+
+use super::query::SimpleQueryHandler;
+use super::results::{FieldInfo, QueryResponse, Response, Tag};
+use super::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::data::DataRow;
+use crate::messages::PgWireBackendMessage;
+
+/// Counters of `QueryCache`
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// A `SimpleQueryHandler` that tells `QueryCache` which queries can be
+/// answered from memory.
+pub trait CacheableQueryHandler: SimpleQueryHandler {
+    /// Whether `query` only reads data, and returns the same rows when it's
+    /// repeated until some other statement modifies data.
+    ///
+    /// Statements with side effects, like `INSERT`, `BEGIN`, `SET` or
+    /// `SELECT nextval(...)`, must return `false`. They are always passed to
+    /// the handler, and clear the cache.
+    fn is_read_only(&self, query: &str) -> bool;
+}
+
+#[derive(Debug)]
+struct CachedQuery {
+    tag: Tag,
+    row_schema: Arc<Vec<FieldInfo>>,
+    data_rows: Vec<DataRow>,
+}
+
+impl CachedQuery {
+    fn to_response<'a>(&self) -> Response<'a> {
+        let rows = stream::iter(self.data_rows.clone().into_iter().map(Ok));
+        Response::Query(
+            QueryResponse::new(self.row_schema.clone(), rows).with_tag(self.tag.clone()),
+        )
+    }
+}
+
+/// Minimal least-recently-used map
+///
+/// Eviction scans all entries, which is fine for the small number of distinct
+/// queries a cache is expected to hold.
+#[derive(Debug)]
+struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64), RandomState>,
+    capacity: NonZeroUsize,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    fn new(capacity: NonZeroUsize) -> LruCache<K, V> {
+        LruCache {
+            entries: HashMap::with_hasher(RandomState::new()),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        self.entries.get_mut(key).map(|(v, t)| {
+            *t = tick;
+            &*v
+        })
+    }
+
+    fn pop(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(v, _)| v)
+    }
+
+    /// Insert value, returns true if another entry is evicted
+    fn push(&mut self, key: K, value: V) -> bool {
+        let tick = self.next_tick();
+        if self.entries.insert(key, (value, tick)).is_some() {
+            return false;
+        }
+
+        if self.entries.len() > self.capacity.get() {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, t))| *t)
+                .map(|(k, _)| k.clone());
+            if let Some(lru) = lru {
+                return self.pop(&lru).is_some();
+            }
+        }
+        false
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Query string, database and user of the client
+type CacheKey = (String, Option<String>, Option<String>);
+
+#[derive(Debug)]
+struct CacheEntry {
+    results: Arc<Vec<CachedQuery>>,
+    created_at: Instant,
+}
+
+/// A `SimpleQueryHandler` wrapper that caches query results of `H`.
+///
+/// Only queries that `H::is_read_only` accepts are cached, and only when all
+/// of their responses are query results. Results are keyed by query string,
+/// database and user of the client, so repeated identical queries are
+/// answered from memory without calling `H`, and users never see results
+/// cached for another user. Any other statement is passed to `H` and clears
+/// the whole cache, whether it succeeds or not.
+///
+/// Only `H::do_query` is called, a customized `H::on_query` is bypassed.
+pub struct QueryCache<H> {
+    inner: H,
+    cache: Mutex<LruCache<CacheKey, CacheEntry>>,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<H> QueryCache<H> {
+    /// Create cache for `inner` holding at most `capacity` queries
+    pub fn new(inner: H, capacity: NonZeroUsize) -> QueryCache<H> {
+        QueryCache {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+            ttl: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Expire cached results after `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> QueryCache<H> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Get the wrapped handler
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    /// Get current counters
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Remove all cached results, for changes of data made outside of this
+    /// cache
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<Arc<Vec<CachedQuery>>> {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.get(key)?;
+        if self
+            .ttl
+            .map_or(false, |ttl| entry.created_at.elapsed() >= ttl)
+        {
+            cache.pop(key);
+            return None;
+        }
+        Some(entry.results.clone())
+    }
+
+    fn insert(&self, key: CacheKey, results: Arc<Vec<CachedQuery>>) {
+        let entry = CacheEntry {
+            results,
+            created_at: Instant::now(),
+        };
+        if self.cache.lock().unwrap().push(key, entry) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[async_trait]
+impl<H: CacheableQueryHandler> SimpleQueryHandler for QueryCache<H> {
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if !self.inner.is_read_only(query) {
+            self.invalidate();
+            let responses = self.inner.do_query(client, query).await;
+            // results cached while the statement was running may be stale
+            self.invalidate();
+            return responses;
+        }
+
+        let metadata = client.metadata();
+        let key = (
+            query.to_owned(),
+            metadata.get(METADATA_DATABASE).cloned(),
+            metadata.get(METADATA_USER).cloned(),
+        );
+        if let Some(cached) = self.lookup(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(cached.iter().map(CachedQuery::to_response).collect());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let responses = self.inner.do_query(client, query).await?;
+        if !responses.iter().all(|r| matches!(r, Response::Query(_))) {
+            // errors and anything other than rows are never cached
+            return Ok(responses);
+        }
+
+        let mut results = Vec::with_capacity(responses.len());
+        for resp in responses {
+            if let Response::Query(query_response) = resp {
+                let tag = query_response.tag().clone();
+                let row_schema = query_response.row_schema();
+                let data_rows = query_response
+                    .data_rows()
+                    .collect::<Vec<_>>()
+                    .await
+                    .into_iter()
+                    .collect::<PgWireResult<Vec<_>>>()?;
+                results.push(CachedQuery {
+                    tag,
+                    row_schema,
+                    data_rows,
+                });
+            }
+        }
+
+        let results = Arc::new(results);
+        self.insert(key, results.clone());
+        Ok(results.iter().map(CachedQuery::to_response).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::api::results::FieldFormat;
+    use crate::api::{DefaultClient, Type};
+
+    struct CountingHandler {
+        calls: AtomicU64,
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for CountingHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if !self.is_read_only(query) {
+                return Ok(vec![Response::Execution(Tag::new("INSERT 0 1"))]);
+            }
+
+            let schema = Arc::new(vec![FieldInfo::new(
+                "id".to_owned(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let rows = vec![Ok(DataRow::new(BytesMut::from("1"), 1))];
+            Ok(vec![Response::Query(QueryResponse::new(
+                schema,
+                stream::iter(rows),
+            ))])
+        }
+    }
+
+    impl CacheableQueryHandler for CountingHandler {
+        fn is_read_only(&self, query: &str) -> bool {
+            query
+                .get(..6)
+                .map_or(false, |s| s.eq_ignore_ascii_case("SELECT"))
+        }
+    }
+
+    /// Client with a sink that discards everything
+    struct TestClient {
+        info: DefaultClient<()>,
+    }
+
+    impl ClientInfo for TestClient {
+        fn socket_addr(&self) -> std::net::SocketAddr {
+            self.info.socket_addr()
+        }
+
+        fn is_secure(&self) -> bool {
+            self.info.is_secure()
+        }
+
+        fn state(&self) -> super::super::PgWireConnectionState {
+            self.info.state()
+        }
+
+        fn set_state(&mut self, new_state: super::super::PgWireConnectionState) {
+            self.info.set_state(new_state)
+        }
+
+        fn metadata(&self) -> &std::collections::HashMap<String, String> {
+            self.info.metadata()
+        }
+
+        fn metadata_mut(&mut self) -> &mut std::collections::HashMap<String, String> {
+            self.info.metadata_mut()
+        }
+    }
+
+    impl Sink<PgWireBackendMessage> for TestClient {
+        type Error = PgWireError;
+
+        fn poll_ready(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn start_send(
+            self: std::pin::Pin<&mut Self>,
+            _item: PgWireBackendMessage,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn query_rows(
+        cache: &QueryCache<CountingHandler>,
+        client: &mut TestClient,
+        query: &str,
+    ) -> usize {
+        let mut rows = 0;
+        for resp in cache.do_query(client, query).await.unwrap() {
+            if let Response::Query(results) = resp {
+                rows += results.data_rows().count().await;
+            }
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let handler = CountingHandler {
+            calls: AtomicU64::new(0),
+        };
+        let cache = QueryCache::new(handler, NonZeroUsize::new(1).unwrap());
+        let mut client = TestClient {
+            info: DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false),
+        };
+
+        assert_eq!(1, query_rows(&cache, &mut client, "SELECT 1").await);
+        assert_eq!(1, query_rows(&cache, &mut client, "SELECT 1").await);
+        assert_eq!(1, cache.inner().calls.load(Ordering::Relaxed));
+        assert_eq!(
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 0
+            },
+            cache.stats()
+        );
+
+        // evicts SELECT 1
+        query_rows(&cache, &mut client, "SELECT 2").await;
+        assert_eq!(1, cache.stats().evictions);
+
+        // ddl clears cache
+        query_rows(&cache, &mut client, "create table t (id int)").await;
+        query_rows(&cache, &mut client, "SELECT 2").await;
+        assert_eq!(4, cache.inner().calls.load(Ordering::Relaxed));
+        assert_eq!(3, cache.stats().misses);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_writes() {
+        let handler = CountingHandler {
+            calls: AtomicU64::new(0),
+        };
+        let cache = QueryCache::new(handler, NonZeroUsize::new(8).unwrap());
+        let mut client = TestClient {
+            info: DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false),
+        };
+
+        // writes always reach the handler
+        query_rows(&cache, &mut client, "INSERT INTO t VALUES (1)").await;
+        query_rows(&cache, &mut client, "INSERT INTO t VALUES (1)").await;
+        assert_eq!(2, cache.inner().calls.load(Ordering::Relaxed));
+
+        // and invalidate cached reads
+        query_rows(&cache, &mut client, "SELECT * FROM t").await;
+        query_rows(&cache, &mut client, "SELECT * FROM t").await;
+        query_rows(&cache, &mut client, "DELETE FROM t").await;
+        query_rows(&cache, &mut client, "SELECT * FROM t").await;
+        assert_eq!(5, cache.inner().calls.load(Ordering::Relaxed));
+        assert_eq!(1, cache.stats().hits);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_per_user() {
+        let handler = CountingHandler {
+            calls: AtomicU64::new(0),
+        };
+        let cache = QueryCache::new(handler, NonZeroUsize::new(8).unwrap());
+        let mut client = TestClient {
+            info: DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false),
+        };
+
+        for user in ["alice", "bob", "alice"] {
+            client
+                .metadata_mut()
+                .insert(METADATA_USER.to_owned(), user.to_owned());
+            query_rows(&cache, &mut client, "SELECT * FROM secrets").await;
+        }
+        assert_eq!(2, cache.inner().calls.load(Ordering::Relaxed));
+        assert_eq!(1, cache.stats().hits);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_ttl() {
+        let handler = CountingHandler {
+            calls: AtomicU64::new(0),
+        };
+        let cache =
+            QueryCache::new(handler, NonZeroUsize::new(8).unwrap()).with_ttl(Duration::ZERO);
+        let mut client = TestClient {
+            info: DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false),
+        };
+
+        query_rows(&cache, &mut client, "SELECT 1").await;
+        query_rows(&cache, &mut client, "SELECT 1").await;
+        assert_eq!(2, cache.inner().calls.load(Ordering::Relaxed));
+        assert_eq!(0, cache.stats().hits);
+    }
+}
@@ -0,0 +1,603 @@
+//! Emulation of `pg_catalog` tables that tools and ORMs query for schema
+//! introspection.
+//!
+//! [`CatalogHandler`] wraps a `SimpleQueryHandler` and answers simple
+//! queries of emulated tables, in the form of
+//! `SELECT columns FROM table [WHERE column = value [AND ...]]`, with content
+//! from a [`SchemaProvider`]. Other queries are passed to the wrapped handler.
+//!
+//! Emulated tables:
+//!
+//! - `pg_namespace`: `oid`, `nspname`, `nspowner`, `nspacl`
+//! - `pg_proc`: `oid`, `proname`, `pronamespace`, `prokind`, `proretset`,
+//!   `pronargs`, `prorettype`, `proargtypes`
+
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+use futures::stream;
+
+use super::lexer::{is_keyword, tokenize, Token};
+use super::query::SimpleQueryHandler;
+use super::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response};
+use super::{ClientInfo, Type};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::PgWireBackendMessage;
+
+/// Oid of `pg_catalog` schema
+pub const PG_CATALOG_NAMESPACE_OID: u32 = 11;
+/// Oid of `public` schema
+pub const PUBLIC_NAMESPACE_OID: u32 = 2200;
+/// Oid of `information_schema` schema, as created by `initdb` of postgres 15
+pub const INFORMATION_SCHEMA_NAMESPACE_OID: u32 = 13207;
+/// Oid of the bootstrap superuser, owner of builtin schemas
+pub const BOOTSTRAP_SUPERUSER_OID: u32 = 10;
+/// First oid of user defined objects, `FirstNormalObjectId` of postgres
+pub const FIRST_NORMAL_OBJECT_ID: u32 = 16384;
+
+/// Builtin schemas, always listed in `pg_namespace`
+const BUILTIN_SCHEMAS: [(&str, u32); 3] = [
+    ("pg_catalog", PG_CATALOG_NAMESPACE_OID),
+    ("public", PUBLIC_NAMESPACE_OID),
+    ("information_schema", INFORMATION_SCHEMA_NAMESPACE_OID),
+];
+
+/// Schema listed in emulated `pg_namespace`
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct SchemaInfo {
+    pub name: String,
+    /// name of the role owning the schema
+    pub owner: String,
+}
+
+/// Function listed in emulated `pg_proc`
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// schema of the function, like `public`
+    pub schema: String,
+    pub arg_types: Vec<Type>,
+    pub return_type: Type,
+    /// function returns a set of rows
+    #[new(default)]
+    pub returns_set: bool,
+}
+
+impl FunctionInfo {
+    /// Mark the function as set returning.
+    pub fn with_returns_set(mut self, returns_set: bool) -> Self {
+        self.returns_set = returns_set;
+        self
+    }
+}
+
+/// Source of database objects listed in emulated catalog tables.
+pub trait SchemaProvider: Send + Sync {
+    /// Schemas listed in `pg_namespace`, in addition to `pg_catalog`,
+    /// `public` and `information_schema`
+    fn list_schemas(&self) -> Vec<SchemaInfo> {
+        Vec::new()
+    }
+
+    /// Functions listed in `pg_proc`
+    fn list_functions(&self) -> Vec<FunctionInfo> {
+        Vec::new()
+    }
+}
+
+/// Schema provider without any object.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopSchemaProvider;
+
+impl SchemaProvider for NoopSchemaProvider {}
+
+/// Content of an emulated table, in text format
+#[derive(Debug)]
+struct CatalogTable {
+    columns: Vec<(&'static str, Type)>,
+    rows: Vec<Vec<Option<String>>>,
+}
+
+/// Oids of objects listed by a `SchemaProvider`.
+///
+/// Builtin schemas have the same oids as postgres. Other schemas, owners of
+/// schemas and then functions take oids from `FIRST_NORMAL_OBJECT_ID` in
+/// order, so oids are stable as long as the provider lists the same objects.
+#[derive(Debug)]
+struct CatalogOids {
+    schemas: Vec<String>,
+    roles: Vec<String>,
+}
+
+impl CatalogOids {
+    fn new(schemas: &[SchemaInfo], functions: &[FunctionInfo]) -> CatalogOids {
+        let custom_schemas = schemas
+            .iter()
+            .map(|s| s.name.as_str())
+            .chain(functions.iter().map(|f| f.schema.as_str()))
+            .filter(|s| Self::builtin_namespace_oid(s).is_none())
+            .collect::<BTreeSet<_>>();
+        let roles = schemas
+            .iter()
+            .map(|s| s.owner.as_str())
+            .collect::<BTreeSet<_>>();
+        CatalogOids {
+            schemas: custom_schemas.into_iter().map(str::to_owned).collect(),
+            roles: roles.into_iter().map(str::to_owned).collect(),
+        }
+    }
+
+    fn builtin_namespace_oid(schema: &str) -> Option<u32> {
+        BUILTIN_SCHEMAS
+            .iter()
+            .find(|(name, _)| *name == schema)
+            .map(|(_, oid)| *oid)
+    }
+
+    fn namespace_oid(&self, schema: &str) -> u32 {
+        Self::builtin_namespace_oid(schema).unwrap_or_else(|| {
+            let idx = self.schemas.iter().position(|s| s == schema).unwrap_or(0);
+            FIRST_NORMAL_OBJECT_ID + idx as u32
+        })
+    }
+
+    fn role_oid(&self, role: &str) -> u32 {
+        let idx = self.roles.iter().position(|r| r == role).unwrap_or(0);
+        FIRST_NORMAL_OBJECT_ID + (self.schemas.len() + idx) as u32
+    }
+
+    fn function_oid(&self, idx: usize) -> u32 {
+        FIRST_NORMAL_OBJECT_ID + (self.schemas.len() + self.roles.len() + idx) as u32
+    }
+}
+
+fn pg_namespace(
+    oids: &CatalogOids,
+    schemas: &[SchemaInfo],
+    _functions: &[FunctionInfo],
+) -> CatalogTable {
+    let builtin_rows = BUILTIN_SCHEMAS.iter().map(|(name, oid)| {
+        let owner = schemas
+            .iter()
+            .find(|s| s.name == *name)
+            .map_or(BOOTSTRAP_SUPERUSER_OID, |s| oids.role_oid(&s.owner));
+        vec![
+            Some(oid.to_string()),
+            Some((*name).to_owned()),
+            Some(owner.to_string()),
+            None,
+        ]
+    });
+    let custom_rows = schemas
+        .iter()
+        .filter(|s| CatalogOids::builtin_namespace_oid(&s.name).is_none())
+        .map(|s| {
+            vec![
+                Some(oids.namespace_oid(&s.name).to_string()),
+                Some(s.name.clone()),
+                Some(oids.role_oid(&s.owner).to_string()),
+                None,
+            ]
+        });
+
+    CatalogTable {
+        columns: vec![
+            ("oid", Type::OID),
+            ("nspname", Type::NAME),
+            ("nspowner", Type::OID),
+            ("nspacl", Type::ACLITEM_ARRAY),
+        ],
+        rows: builtin_rows.chain(custom_rows).collect(),
+    }
+}
+
+fn pg_proc(
+    oids: &CatalogOids,
+    _schemas: &[SchemaInfo],
+    functions: &[FunctionInfo],
+) -> CatalogTable {
+    let rows = functions
+        .iter()
+        .enumerate()
+        .map(|(idx, f)| {
+            let arg_types = f
+                .arg_types
+                .iter()
+                .map(|t| t.oid().to_string())
+                .collect::<Vec<_>>();
+            vec![
+                Some(oids.function_oid(idx).to_string()),
+                Some(f.name.clone()),
+                Some(oids.namespace_oid(&f.schema).to_string()),
+                Some("f".to_owned()),
+                Some(if f.returns_set { "t" } else { "f" }.to_owned()),
+                Some(f.arg_types.len().to_string()),
+                Some(f.return_type.oid().to_string()),
+                Some(arg_types.join(" ")),
+            ]
+        })
+        .collect();
+
+    CatalogTable {
+        columns: vec![
+            ("oid", Type::OID),
+            ("proname", Type::NAME),
+            ("pronamespace", Type::OID),
+            ("prokind", Type::CHAR),
+            ("proretset", Type::BOOL),
+            ("pronargs", Type::INT2),
+            ("prorettype", Type::OID),
+            ("proargtypes", Type::OID_VECTOR),
+        ],
+        rows,
+    }
+}
+
+/// `SELECT` of an emulated table
+#[derive(Debug, PartialEq, Eq)]
+struct CatalogQuery {
+    /// selected columns, `None` for `*`
+    columns: Option<Vec<String>>,
+    table: String,
+    /// `column = value` conditions of `WHERE`
+    filters: Vec<(String, String)>,
+}
+
+/// Column name, with table qualifier removed
+fn column_name(token: &Token) -> Option<String> {
+    match token {
+        Token::Word(w) => {
+            let name = w.rsplit('.').next()?.to_lowercase();
+            name.chars()
+                .all(|c| c.is_alphanumeric() || c == '_')
+                .then_some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Parse `SELECT columns FROM table [alias] [WHERE column = value [AND ...]]`.
+/// Returns `None` for any other query.
+fn parse_catalog_query(query: &str) -> Option<CatalogQuery> {
+    let mut tokens = tokenize(query)?;
+    while tokens.last() == Some(&Token::Punct(';')) {
+        tokens.pop();
+    }
+    let mut tokens = tokens.iter().peekable();
+    if !is_keyword(tokens.next(), "select") {
+        return None;
+    }
+
+    let columns = if matches!(tokens.peek(), Some(Token::Word(w)) if w == "*") {
+        tokens.next();
+        None
+    } else {
+        let mut columns = Vec::new();
+        loop {
+            columns.push(column_name(tokens.next()?)?);
+            if tokens.peek() != Some(&&Token::Punct(',')) {
+                break;
+            }
+            tokens.next();
+        }
+        Some(columns)
+    };
+
+    if !is_keyword(tokens.next(), "from") {
+        return None;
+    }
+    let table = match tokens.next()? {
+        Token::Word(w) => {
+            let w = w.to_lowercase();
+            w.strip_prefix("pg_catalog.")
+                .map(str::to_owned)
+                .unwrap_or(w)
+        }
+        _ => return None,
+    };
+    // table alias
+    if matches!(tokens.peek(), Some(Token::Word(w)) if !w.eq_ignore_ascii_case("where")) {
+        tokens.next();
+    }
+
+    let mut filters = Vec::new();
+    if tokens.peek().is_some() {
+        if !is_keyword(tokens.next(), "where") {
+            return None;
+        }
+        loop {
+            let column = column_name(tokens.next()?)?;
+            if tokens.next() != Some(&Token::Punct('=')) {
+                return None;
+            }
+            let value = match tokens.next()? {
+                Token::Literal(s) | Token::Word(s) => s.clone(),
+                _ => return None,
+            };
+            filters.push((column, value));
+            match tokens.next() {
+                None => break,
+                t if is_keyword(t, "and") => {}
+                _ => return None,
+            }
+        }
+    }
+
+    Some(CatalogQuery {
+        columns,
+        table,
+        filters,
+    })
+}
+
+impl CatalogQuery {
+    /// Select rows of `table`. Returns `None` if any column is unknown.
+    fn execute<'a>(&self, table: CatalogTable) -> Option<PgWireResult<Response<'a>>> {
+        let position = |name: &str| table.columns.iter().position(|(c, _)| *c == name);
+        let selected = match &self.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|c| position(c))
+                .collect::<Option<Vec<_>>>()?,
+            None => (0..table.columns.len()).collect(),
+        };
+        let filters = self
+            .filters
+            .iter()
+            .map(|(c, v)| Some((position(c)?, v)))
+            .collect::<Option<Vec<_>>>()?;
+
+        let schema = Arc::new(
+            selected
+                .iter()
+                .map(|&idx| {
+                    let (name, ty) = &table.columns[idx];
+                    FieldInfo::new(
+                        (*name).to_owned(),
+                        None,
+                        None,
+                        ty.clone(),
+                        FieldFormat::Text,
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        let data_rows = table
+            .rows
+            .into_iter()
+            .filter(|row| {
+                filters.iter().all(|(idx, value)| {
+                    row[*idx]
+                        .as_deref()
+                        .map_or(false, |v| v.eq_ignore_ascii_case(value))
+                })
+            })
+            .map(|row| {
+                let mut encoder = DataRowEncoder::new(schema.clone());
+                for &idx in &selected {
+                    encoder.encode_field(&row[idx])?;
+                }
+                encoder.finish()
+            })
+            .collect::<Vec<_>>();
+
+        Some(Ok(Response::Query(QueryResponse::new(
+            schema,
+            stream::iter(data_rows),
+        ))))
+    }
+}
+
+/// Simple query handler answering queries of emulated catalog tables, and
+/// passing other queries to the wrapped handler.
+#[derive(Debug, new)]
+pub struct CatalogHandler<H, P> {
+    handler: Arc<H>,
+    provider: Arc<P>,
+}
+
+impl<H, P: SchemaProvider> CatalogHandler<H, P> {
+    fn table(&self, name: &str) -> Option<CatalogTable> {
+        let table: fn(&CatalogOids, &[SchemaInfo], &[FunctionInfo]) -> CatalogTable = match name {
+            "pg_namespace" => pg_namespace,
+            "pg_proc" => pg_proc,
+            _ => return None,
+        };
+        let schemas = self.provider.list_schemas();
+        let functions = self.provider.list_functions();
+        let oids = CatalogOids::new(&schemas, &functions);
+        Some(table(&oids, &schemas, &functions))
+    }
+}
+
+#[async_trait]
+impl<H, P> SimpleQueryHandler for CatalogHandler<H, P>
+where
+    H: SimpleQueryHandler,
+    P: SchemaProvider,
+{
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let Some(catalog_query) = parse_catalog_query(query) {
+            if let Some(table) = self.table(&catalog_query.table) {
+                if let Some(response) = catalog_query.execute(table) {
+                    return Ok(vec![response?]);
+                }
+            }
+        }
+        self.handler.do_query(client, query).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_catalog_query() {
+        assert_eq!(
+            Some(CatalogQuery {
+                columns: Some(vec!["proname".to_owned(), "prorettype".to_owned()]),
+                table: "pg_proc".to_owned(),
+                filters: vec![],
+            }),
+            parse_catalog_query("SELECT proname, p.prorettype FROM pg_catalog.pg_proc p;")
+        );
+        assert_eq!(
+            Some(CatalogQuery {
+                columns: None,
+                table: "pg_proc".to_owned(),
+                filters: vec![
+                    ("proname".to_owned(), "add".to_owned()),
+                    ("pronargs".to_owned(), "2".to_owned())
+                ],
+            }),
+            parse_catalog_query("select * from PG_PROC where proname = 'add' and pronargs = 2")
+        );
+
+        assert_eq!(None, parse_catalog_query("SELECT count(*) FROM pg_proc"));
+        assert_eq!(None, parse_catalog_query("SELECT 1"));
+        assert_eq!(
+            None,
+            parse_catalog_query("SELECT proname FROM pg_proc WHERE pronargs > 1")
+        );
+        assert_eq!(
+            None,
+            parse_catalog_query("SELECT proname FROM pg_proc ORDER BY proname")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pg_proc() {
+        let functions = vec![
+            FunctionInfo::new(
+                "add".to_owned(),
+                "public".to_owned(),
+                vec![Type::INT4, Type::INT4],
+                Type::INT4,
+            ),
+            FunctionInfo::new(
+                "series".to_owned(),
+                "app".to_owned(),
+                vec![Type::INT8],
+                Type::INT8,
+            )
+            .with_returns_set(true),
+        ];
+        let oids = CatalogOids::new(&[], &functions);
+
+        let query = parse_catalog_query(
+            "SELECT proname, pronamespace, proargtypes, proretset FROM pg_proc",
+        )
+        .unwrap();
+        let Some(Ok(Response::Query(response))) = query.execute(pg_proc(&oids, &[], &functions))
+        else {
+            panic!("expect query response");
+        };
+        assert_eq!(4, response.row_schema().len());
+        assert_eq!(&Type::OID_VECTOR, response.row_schema()[2].datatype());
+
+        let rows = response.data_rows().collect::<Vec<_>>().await;
+        assert_eq!(2, rows.len());
+        let row = rows[0].as_ref().unwrap();
+        let mut expected = bytes::BytesMut::new();
+        for value in ["add", "2200", "23 23", "f"] {
+            bytes::BufMut::put_i32(&mut expected, value.len() as i32);
+            bytes::BufMut::put_slice(&mut expected, value.as_bytes());
+        }
+        assert_eq!(expected, row.data);
+
+        // custom schema takes the first user oid, before functions
+        let query =
+            parse_catalog_query("SELECT oid, pronamespace FROM pg_proc WHERE proname = 'series'")
+                .unwrap();
+        let Some(Ok(Response::Query(response))) = query.execute(pg_proc(&oids, &[], &functions))
+        else {
+            panic!("expect query response");
+        };
+        let rows = response.data_rows().collect::<Vec<_>>().await;
+        assert_eq!(1, rows.len());
+        let data = &rows[0].as_ref().unwrap().data;
+        assert_eq!(&b"16386"[..], &data[4..9]);
+        assert_eq!(&b"16384"[..], &data[13..18]);
+
+        // unknown column
+        let query = parse_catalog_query("SELECT proowner FROM pg_proc").unwrap();
+        assert!(query.execute(pg_proc(&oids, &[], &functions)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pg_namespace() {
+        let schemas = vec![
+            SchemaInfo::new("sales".to_owned(), "alice".to_owned()),
+            SchemaInfo::new("public".to_owned(), "bob".to_owned()),
+            SchemaInfo::new("app".to_owned(), "alice".to_owned()),
+        ];
+        let functions = vec![FunctionInfo::new(
+            "f".to_owned(),
+            "app".to_owned(),
+            vec![],
+            Type::INT4,
+        )];
+        let oids = CatalogOids::new(&schemas, &functions);
+
+        let query =
+            parse_catalog_query("SELECT n.oid, n.nspname, n.nspowner FROM pg_namespace n").unwrap();
+        let Some(Ok(Response::Query(response))) =
+            query.execute(pg_namespace(&oids, &schemas, &functions))
+        else {
+            panic!("expect query response");
+        };
+        let rows = response.data_rows().collect::<Vec<_>>().await;
+        let text_rows = rows
+            .into_iter()
+            .map(|row| {
+                let data = row.unwrap().data;
+                let mut values = Vec::new();
+                let mut buf = &data[..];
+                while !buf.is_empty() {
+                    let len = bytes::Buf::get_i32(&mut buf) as usize;
+                    values.push(String::from_utf8(buf[..len].to_vec()).unwrap());
+                    bytes::Buf::advance(&mut buf, len);
+                }
+                values.join(" ")
+            })
+            .collect::<Vec<_>>();
+        // custom schemas and then owners take user oids in name order
+        assert_eq!(
+            vec![
+                "11 pg_catalog 10",
+                "2200 public 16387",
+                "13207 information_schema 10",
+                "16385 sales 16386",
+                "16384 app 16386",
+            ],
+            text_rows
+        );
+        assert_eq!(16388, oids.function_oid(0));
+
+        let query =
+            parse_catalog_query("SELECT oid FROM pg_catalog.pg_namespace WHERE nspname = 'public'")
+                .unwrap();
+        let Some(Ok(Response::Query(response))) = query.execute(pg_namespace(&oids, &[], &[]))
+        else {
+            panic!("expect query response");
+        };
+        assert_eq!(1, response.data_rows().count().await);
+    }
+}
@@ -0,0 +1,66 @@
+//! Fastpath function calls, sent with `FunctionCall` message instead of a
+//! query.
+//!
+//! The sub-protocol is obsolete, but it's still used by large object API of
+//! some drivers. Without a handler, calls are rejected with an error.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::sink::{Sink, SinkExt};
+
+use super::{ClientInfo, PgWireConnectionState};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::fastpath::{FunctionCall, FunctionCallResponse};
+use crate::messages::response::ReadyForQuery;
+use crate::messages::PgWireBackendMessage;
+
+/// Handler of fastpath function calls
+#[async_trait]
+pub trait FastpathHandler: Send + Sync {
+    /// Call function `call.function_oid` with arguments of `call`. Returns
+    /// the result encoded in `call.result_format_code`, or `None` for NULL.
+    async fn call_function(
+        &self,
+        client: &(dyn ClientInfo + Send + Sync),
+        call: FunctionCall,
+    ) -> PgWireResult<Option<Bytes>>;
+}
+
+/// Respond `FunctionCall` with result of `handler`, followed by
+/// `ReadyForQuery`.
+pub(crate) async fn on_function_call<C>(
+    client: &mut C,
+    handler: Option<&dyn FastpathHandler>,
+    call: FunctionCall,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let Some(handler) = handler else {
+        return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            "fastpath function calls are not supported".to_owned(),
+        ))));
+    };
+
+    client.set_state(PgWireConnectionState::QueryInProgress);
+    let result = handler.call_function(client, call).await?;
+    client
+        .feed(PgWireBackendMessage::FunctionCallResponse(
+            FunctionCallResponse::new(result),
+        ))
+        .await?;
+    client
+        .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+            client.transaction_status().to_ready_status(),
+        )))
+        .await?;
+    client.flush().await?;
+    client.set_state(PgWireConnectionState::ReadyForQuery);
+    Ok(())
+}
@@ -0,0 +1,583 @@
+//! Emulation of postgres run-time parameters (GUC), so `SHOW`, `SET` and
+//! `RESET` of common parameters issued by drivers and tools work without
+//! support from query handler.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use futures::sink::{Sink, SinkExt};
+use futures::stream;
+
+use super::results::{FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use super::{ClientInfo, PgWireConnectionState, Type};
+use crate::api::lexer::{is_keyword, tokenize, Token};
+use crate::api::query::{send_pending_parameter_status, send_responses};
+use crate::api::results::DataRowEncoder;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::response::ReadyForQuery;
+use crate::messages::PgWireBackendMessage;
+
+/// Parameters reported to client with `ParameterStatus` when changed.
+const REPORTED_PARAMETERS: &[&str] = &[
+    "application_name",
+    "client_encoding",
+    "DateStyle",
+    "IntervalStyle",
+    "TimeZone",
+    "integer_datetimes",
+    "server_encoding",
+    "server_version",
+    "standard_conforming_strings",
+];
+
+/// Value of a run-time parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GucValue {
+    /// Value of current session
+    pub current: String,
+    /// Value restored by `RESET`
+    pub default: String,
+    /// Unit of numeric value, like `ms`
+    pub unit: Option<String>,
+    pub description: &'static str,
+}
+
+impl GucValue {
+    /// Create value with `default` as current value.
+    pub fn new(default: &str, unit: Option<&str>, description: &'static str) -> GucValue {
+        GucValue {
+            current: default.to_owned(),
+            default: default.to_owned(),
+            unit: unit.map(str::to_owned),
+            description,
+        }
+    }
+
+    /// Current value as shown by `SHOW`, with unit appended to plain numbers.
+    pub fn setting(&self) -> String {
+        match &self.unit {
+            Some(unit) if self.current != "0" && self.current.parse::<i64>().is_ok() => {
+                format!("{}{}", self.current, unit)
+            }
+            _ => self.current.clone(),
+        }
+    }
+}
+
+/// Run-time parameters of a session.
+///
+/// Names are case-insensitive. `Default` registers parameters commonly
+/// queried by psql, JDBC and other drivers, use `register` to add more.
+#[derive(Debug, Clone)]
+pub struct GucRegistry {
+    values: HashMap<String, GucValue>,
+}
+
+impl Default for GucRegistry {
+    fn default() -> GucRegistry {
+        let mut registry = GucRegistry::new();
+        for (name, default, unit, description) in [
+            (
+                "application_name",
+                "",
+                None,
+                "Sets the application name to be reported in statistics and logs.",
+            ),
+            (
+                "client_encoding",
+                "UTF8",
+                None,
+                "Sets the client's character set encoding.",
+            ),
+            (
+                "DateStyle",
+                "ISO YMD",
+                None,
+                "Sets the display format for date and time values.",
+            ),
+            (
+                "default_transaction_isolation",
+                "read committed",
+                None,
+                "Sets the transaction isolation level of each new transaction.",
+            ),
+            (
+                "default_transaction_read_only",
+                "off",
+                None,
+                "Sets the default read-only status of new transactions.",
+            ),
+            (
+                "extra_float_digits",
+                "1",
+                None,
+                "Sets the number of digits displayed for floating-point values.",
+            ),
+            (
+                "integer_datetimes",
+                "on",
+                None,
+                "Shows whether datetimes are integer based.",
+            ),
+            (
+                "IntervalStyle",
+                "postgres",
+                None,
+                "Sets the display format for interval values.",
+            ),
+            (
+                "lock_timeout",
+                "0",
+                Some("ms"),
+                "Sets the maximum allowed duration of any wait for a lock.",
+            ),
+            (
+                "max_identifier_length",
+                "63",
+                None,
+                "Shows the maximum identifier length.",
+            ),
+            (
+                "search_path",
+                "\"$user\", public",
+                None,
+                "Sets the schema search order for names that are not schema-qualified.",
+            ),
+            (
+                "server_encoding",
+                "UTF8",
+                None,
+                "Shows the server (database) character set encoding.",
+            ),
+            (
+                "server_version",
+                env!("CARGO_PKG_VERSION"),
+                None,
+                "Shows the server version.",
+            ),
+            (
+                "standard_conforming_strings",
+                "on",
+                None,
+                "Causes '...' strings to treat backslashes literally.",
+            ),
+            (
+                "statement_timeout",
+                "0",
+                Some("ms"),
+                "Sets the maximum allowed duration of any statement.",
+            ),
+            (
+                "TimeZone",
+                "UTC",
+                None,
+                "Sets the time zone for displaying and interpreting time stamps.",
+            ),
+            (
+                "transaction_isolation",
+                "read committed",
+                None,
+                "Sets the current transaction's isolation level.",
+            ),
+            (
+                "transaction_read_only",
+                "off",
+                None,
+                "Sets the current transaction's read-only status.",
+            ),
+        ] {
+            registry.register(name, GucValue::new(default, unit, description));
+        }
+        registry
+    }
+}
+
+impl GucRegistry {
+    /// Create registry without any parameter.
+    pub fn new() -> GucRegistry {
+        GucRegistry {
+            values: HashMap::new(),
+        }
+    }
+
+    /// Add or replace parameter `name`.
+    pub fn register(&mut self, name: &str, value: GucValue) {
+        self.values.insert(name.to_lowercase(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GucValue> {
+        self.values.get(&name.to_lowercase())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(&name.to_lowercase())
+    }
+
+    /// Set current value of parameter `name`. Returns `false` if the
+    /// parameter is not registered.
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        if let Some(guc) = self.values.get_mut(&name.to_lowercase()) {
+            guc.current = value.to_owned();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Restore default value of parameter `name`. Returns `false` if the
+    /// parameter is not registered.
+    pub fn reset(&mut self, name: &str) -> bool {
+        if let Some(guc) = self.values.get_mut(&name.to_lowercase()) {
+            guc.current = guc.default.clone();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Restore default values of all parameters.
+    pub fn reset_all(&mut self) {
+        for guc in self.values.values_mut() {
+            guc.current = guc.default.clone();
+        }
+    }
+
+    /// Parameters with lower-cased names, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &GucValue)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Use parameters sent by client in startup message as session defaults,
+    /// like postgres does. Unknown parameters are ignored.
+    pub fn apply_startup_parameters<'a, I>(&mut self, parameters: I)
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        for (name, value) in parameters {
+            if let Some(guc) = self.values.get_mut(&name.to_lowercase()) {
+                guc.current = value.clone();
+                guc.default = value.clone();
+            }
+        }
+    }
+}
+
+/// `SHOW`, `SET` or `RESET` statement
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum GucCommand {
+    Show(String),
+    ShowAll,
+    Set(String, String),
+    Reset(String),
+    ResetAll,
+}
+
+impl GucCommand {
+    /// Name of the parameter the command works on, `None` for `ALL`
+    fn name(&self) -> Option<&str> {
+        match self {
+            GucCommand::Show(name) | GucCommand::Set(name, _) | GucCommand::Reset(name) => {
+                Some(name)
+            }
+            GucCommand::ShowAll | GucCommand::ResetAll => None,
+        }
+    }
+}
+
+/// Parameter name, with special syntax of `TIME ZONE` and
+/// `TRANSACTION ISOLATION LEVEL`
+fn parse_name(tokens: &[Token]) -> Option<(String, &[Token])> {
+    if is_keyword(tokens.first(), "time") && is_keyword(tokens.get(1), "zone") {
+        return Some(("timezone".to_owned(), &tokens[2..]));
+    }
+    if is_keyword(tokens.first(), "transaction")
+        && is_keyword(tokens.get(1), "isolation")
+        && is_keyword(tokens.get(2), "level")
+    {
+        return Some(("transaction_isolation".to_owned(), &tokens[3..]));
+    }
+    match tokens.first()? {
+        Token::Word(name) => Some((name.to_lowercase(), &tokens[1..])),
+        Token::Quoted(name) => Some((name[1..name.len() - 1].replace("\"\"", "\""), &tokens[1..])),
+        _ => None,
+    }
+}
+
+/// Parse value list of `SET`. `None` stands for `DEFAULT`.
+fn parse_value(tokens: &[Token]) -> Option<Option<String>> {
+    if let [Token::Word(w)] = tokens {
+        if w.eq_ignore_ascii_case("default") {
+            return Some(None);
+        }
+    }
+
+    let mut items = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match (i % 2, token) {
+            (0, Token::Word(s) | Token::Literal(s) | Token::Quoted(s)) => items.push(s.as_str()),
+            (1, Token::Punct(',')) => {}
+            _ => return None,
+        }
+    }
+    if items.is_empty() || tokens.len() % 2 == 0 {
+        return None;
+    }
+    Some(Some(items.join(", ")))
+}
+
+/// Parse `SHOW`, `SET` and `RESET` statements. Returns `None` if the query is
+/// anything else, including multiple statements.
+pub(crate) fn parse_guc_command(query: &str) -> Option<GucCommand> {
+    let mut tokens = tokenize(query)?;
+    while tokens.last() == Some(&Token::Punct(';')) {
+        tokens.pop();
+    }
+    let (keyword, rest) = tokens.split_first()?;
+
+    let command = match keyword {
+        Token::Word(k) if k.eq_ignore_ascii_case("show") => {
+            if let [Token::Word(all)] = rest {
+                if all.eq_ignore_ascii_case("all") {
+                    return Some(GucCommand::ShowAll);
+                }
+            }
+            let (name, rest) = parse_name(rest)?;
+            rest.is_empty().then_some(GucCommand::Show(name))?
+        }
+        Token::Word(k) if k.eq_ignore_ascii_case("reset") => {
+            if let [Token::Word(all)] = rest {
+                if all.eq_ignore_ascii_case("all") {
+                    return Some(GucCommand::ResetAll);
+                }
+            }
+            let (name, rest) = parse_name(rest)?;
+            rest.is_empty().then_some(GucCommand::Reset(name))?
+        }
+        Token::Word(k) if k.eq_ignore_ascii_case("set") => {
+            let rest = if is_keyword(rest.first(), "session") || is_keyword(rest.first(), "local") {
+                &rest[1..]
+            } else {
+                rest
+            };
+            // `SET TIME ZONE value` has no `TO`
+            let (name, rest) =
+                if is_keyword(rest.first(), "time") && is_keyword(rest.get(1), "zone") {
+                    ("timezone".to_owned(), &rest[2..])
+                } else {
+                    let (name, rest) = parse_name(rest)?;
+                    if is_keyword(rest.first(), "to") || rest.first() == Some(&Token::Punct('=')) {
+                        (name, &rest[1..])
+                    } else {
+                        return None;
+                    }
+                };
+            match parse_value(rest)? {
+                Some(value) => GucCommand::Set(name, value),
+                None => GucCommand::Reset(name),
+            }
+        }
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Test if `query` is a `SHOW`, `SET` or `RESET` statement of a parameter
+/// known to the registry of `client`.
+pub(crate) fn match_guc_command<C>(client: &C, query: &str) -> Option<GucCommand>
+where
+    C: ClientInfo,
+{
+    let registry = client.guc_registry()?;
+    let command = parse_guc_command(query)?;
+    match command.name() {
+        Some(name) if !lock(&registry).contains(name) => None,
+        _ => Some(command),
+    }
+}
+
+fn lock(registry: &Mutex<GucRegistry>) -> std::sync::MutexGuard<'_, GucRegistry> {
+    registry
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn text_field(name: &str) -> FieldInfo {
+    FieldInfo::new(name.to_owned(), None, None, Type::TEXT, FieldFormat::Text)
+}
+
+fn show_response<'a>(columns: &[&str], rows: Vec<Vec<String>>) -> PgWireResult<Response<'a>> {
+    let schema = Arc::new(columns.iter().map(|c| text_field(c)).collect::<Vec<_>>());
+    let data_rows = rows
+        .into_iter()
+        .map(|row| {
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            for value in row {
+                encoder.encode_field(&value)?;
+            }
+            encoder.finish()
+        })
+        .collect::<Vec<_>>();
+    let response = QueryResponse::new(schema, stream::iter(data_rows)).with_tag(Tag::new("SHOW"));
+    Ok(Response::Query(response))
+}
+
+/// Name of parameter in `ParameterStatus`, if it's reported to client
+fn reported_name(name: &str) -> Option<&'static str> {
+    REPORTED_PARAMETERS
+        .iter()
+        .find(|p| p.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// Execute `SHOW`, `SET` or `RESET` against the registry of `client` and
+/// respond to the simple query. Changed parameters that postgres reports are
+/// pushed with `ClientInfo::push_parameter_status`, and sent to client with
+/// `ParameterStatus` after the result.
+pub(crate) async fn on_guc_command<C>(client: &mut C, command: GucCommand) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    client.set_state(PgWireConnectionState::QueryInProgress);
+    let registry = client.guc_registry().unwrap_or_default();
+
+    let mut reported = Vec::new();
+    let response = {
+        let mut registry = lock(&registry);
+        let mut report = |registry: &GucRegistry, name: &str| {
+            if let (Some(reported_name), Some(guc)) = (reported_name(name), registry.get(name)) {
+                reported.push((reported_name, guc.current.clone()));
+            }
+        };
+        match command {
+            GucCommand::Show(name) => {
+                let value = registry
+                    .get(&name)
+                    .map(GucValue::setting)
+                    .unwrap_or_default();
+                show_response(&[&name], vec![vec![value]])?
+            }
+            GucCommand::ShowAll => {
+                let mut rows = registry
+                    .iter()
+                    .map(|(name, guc)| {
+                        vec![name.to_owned(), guc.setting(), guc.description.to_owned()]
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort();
+                show_response(&["name", "setting", "description"], rows)?
+            }
+            GucCommand::Set(name, value) => {
+                registry.set(&name, &value);
+                report(&registry, &name);
+                Response::Execution(Tag::new("SET"))
+            }
+            GucCommand::Reset(name) => {
+                registry.reset(&name);
+                report(&registry, &name);
+                Response::Execution(Tag::new("RESET"))
+            }
+            GucCommand::ResetAll => {
+                registry.reset_all();
+                for name in REPORTED_PARAMETERS {
+                    report(&registry, name);
+                }
+                Response::Execution(Tag::new("RESET"))
+            }
+        }
+    };
+
+    send_responses(client, vec![response]).await?;
+    for (name, value) in reported {
+        client.push_parameter_status(name, &value);
+    }
+    send_pending_parameter_status(client).await?;
+    client
+        .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+            client.transaction_status().to_ready_status(),
+        )))
+        .await?;
+    client.flush().await?;
+    client.set_state(PgWireConnectionState::ReadyForQuery);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_guc_command() {
+        assert_eq!(
+            Some(GucCommand::Show("search_path".to_owned())),
+            parse_guc_command("SHOW search_path;")
+        );
+        assert_eq!(
+            Some(GucCommand::Show("transaction_isolation".to_owned())),
+            parse_guc_command("show transaction isolation level")
+        );
+        assert_eq!(Some(GucCommand::ShowAll), parse_guc_command("SHOW ALL"));
+        assert_eq!(
+            Some(GucCommand::Set(
+                "search_path".to_owned(),
+                "my_schema, \"$user\", public".to_owned()
+            )),
+            parse_guc_command("SET search_path TO my_schema, \"$user\", 'public'")
+        );
+        assert_eq!(
+            Some(GucCommand::Set(
+                "application_name".to_owned(),
+                "it's me".to_owned()
+            )),
+            parse_guc_command("set session Application_Name = 'it''s me';")
+        );
+        assert_eq!(
+            Some(GucCommand::Set("timezone".to_owned(), "UTC".to_owned())),
+            parse_guc_command("SET TIME ZONE 'UTC'")
+        );
+        assert_eq!(
+            Some(GucCommand::Set(
+                "extra_float_digits".to_owned(),
+                "-1".to_owned()
+            )),
+            parse_guc_command("SET extra_float_digits = -1")
+        );
+        assert_eq!(
+            Some(GucCommand::Reset("datestyle".to_owned())),
+            parse_guc_command("SET DateStyle TO DEFAULT")
+        );
+        assert_eq!(
+            Some(GucCommand::Reset("datestyle".to_owned())),
+            parse_guc_command("RESET DateStyle")
+        );
+        assert_eq!(Some(GucCommand::ResetAll), parse_guc_command("reset all;"));
+
+        assert_eq!(None, parse_guc_command("SELECT 1"));
+        assert_eq!(None, parse_guc_command("SET search_path TO a; SELECT 1"));
+        assert_eq!(None, parse_guc_command("SET search_path TO"));
+        assert_eq!(None, parse_guc_command("SET search_path TO a,"));
+        assert_eq!(None, parse_guc_command("SET search_path a"));
+        assert_eq!(None, parse_guc_command("SHOW application_name = 'x'"));
+        assert_eq!(None, parse_guc_command("SET application_name = 'x"));
+    }
+
+    #[test]
+    fn test_guc_registry() {
+        let mut registry = GucRegistry::default();
+        assert_eq!("ISO YMD", registry.get("datestyle").unwrap().current);
+
+        assert!(registry.set("DateStyle", "ISO, MDY"));
+        assert_eq!("ISO, MDY", registry.get("DATESTYLE").unwrap().current);
+        assert!(registry.reset("datestyle"));
+        assert_eq!("ISO YMD", registry.get("datestyle").unwrap().current);
+        assert!(!registry.set("no_such_parameter", "1"));
+
+        let startup = [("application_name".to_owned(), "psql".to_owned())];
+        registry.apply_startup_parameters(startup.iter().map(|(k, v)| (k, v)));
+        assert!(registry.set("application_name", "other"));
+        registry.reset_all();
+        assert_eq!("psql", registry.get("application_name").unwrap().current);
+    }
+}
@@ -0,0 +1,61 @@
+//! Minimal SQL tokenizer for statements that pgwire handles by itself, like
+//! `SET` and queries of emulated catalog tables.
+
+/// Token of a SQL statement
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    Word(String),
+    /// `"identifier"`, with quotes kept
+    Quoted(String),
+    /// `'string'`, with quotes removed
+    Literal(String),
+    Punct(char),
+}
+
+/// Split `query` into tokens. Returns `None` if a quoted string is not
+/// terminated.
+pub(crate) fn tokenize(query: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ',' | '=' | ';' => tokens.push(Token::Punct(c)),
+            '\'' | '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        q if q == c && chars.peek() == Some(&c) => {
+                            chars.next();
+                            s.push(c);
+                        }
+                        q if q == c => break,
+                        other => s.push(other),
+                    }
+                }
+                if c == '\'' {
+                    tokens.push(Token::Literal(s));
+                } else {
+                    tokens.push(Token::Quoted(format!("\"{}\"", s.replace('"', "\"\""))));
+                }
+            }
+            _ => {
+                let mut s = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, ',' | '=' | ';' | '\'' | '"') {
+                        break;
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// Test if `token` is the word `keyword`, case-insensitively
+pub(crate) fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+}
@@ -2,19 +2,49 @@
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 pub use postgres_types::Type;
 
+use crate::messages::response::NoticeResponse;
+use crate::messages::startup::{
+    BackendKeyData, ParameterStatus, ReplicationMode, StartupMode, REPLICATION_PARAMETER,
+};
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod auth;
+#[cfg(feature = "query-cache")]
+pub mod cache;
+pub mod catalog;
+pub mod fastpath;
+pub mod guc;
+pub(crate) mod lexer;
+pub mod negotiate;
+pub mod notice;
 pub mod portal;
+pub(crate) mod process;
+pub mod push;
 pub mod query;
+pub mod replication;
 pub mod results;
 pub mod stmt;
 pub mod store;
+pub mod terminate;
+pub(crate) mod trace;
+pub mod transaction;
 
 pub const DEFAULT_NAME: &str = "POSTGRESQL_DEFAULT_NAME";
+/// Name of the unnamed statement or portal for display
+pub(crate) const UNNAMED_DISPLAY_NAME: &str = "(unnamed)";
+
+/// Test if `name` refers to the unnamed statement or portal. Both empty name,
+/// as sent in protocol, and `DEFAULT_NAME` are unnamed.
+pub(crate) fn is_unnamed(name: &str) -> bool {
+    name.is_empty() || name == DEFAULT_NAME
+}
 
+#[non_exhaustive]
 #[derive(Debug, Clone, Copy, Default)]
 pub enum PgWireConnectionState {
     #[default]
@@ -25,6 +55,16 @@ pub enum PgWireConnectionState {
     AwaitingSync,
 }
 
+/// Credentials of client process connected over Unix domain socket, as
+/// reported by the operating system (`SO_PEERCRED` or `getpeereid`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not available on all platforms
+    pub pid: Option<i32>,
+}
+
 /// Describe a client information holder
 pub trait ClientInfo {
     fn socket_addr(&self) -> SocketAddr;
@@ -38,6 +78,109 @@ pub trait ClientInfo {
     fn metadata(&self) -> &HashMap<String, String>;
 
     fn metadata_mut(&mut self) -> &mut HashMap<String, String>;
+
+    /// Handle for sending notices to this client during a query.
+    ///
+    /// Returns `None` if the client doesn't support it.
+    fn notice_emitter(&self) -> Option<notice::NoticeEmitter> {
+        None
+    }
+
+    /// Take notices emitted with `notice_emitter` but not yet sent.
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        Vec::new()
+    }
+
+    /// Queue a `ParameterStatus` to inform client of changed run-time
+    /// parameter `name`. It's sent after the result of current query, before
+    /// `ReadyForQuery`.
+    ///
+    /// The default implementation discards it.
+    fn push_parameter_status(&mut self, _name: &str, _value: &str) {}
+
+    /// Take `ParameterStatus` queued with `push_parameter_status` but not yet
+    /// sent.
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        Vec::new()
+    }
+
+    /// `BackendKeyData` sent to this client during startup, which holds the
+    /// process id and cancel key of the session.
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        None
+    }
+
+    /// Store `BackendKeyData` sent to this client. The default implementation
+    /// discards it.
+    fn set_backend_key_data(&mut self, _backend_key_data: BackendKeyData) {}
+
+    /// Run-time parameters of the session, updated by `SET` and `RESET`.
+    ///
+    /// When available, `SHOW`, `SET` and `RESET` of registered parameters are
+    /// handled by pgwire instead of the query handler.
+    fn guc_registry(&self) -> Option<Arc<Mutex<guc::GucRegistry>>> {
+        None
+    }
+
+    /// Transaction status of the session, reported in `ReadyForQuery`.
+    fn transaction_status(&self) -> transaction::TransactionStatus {
+        transaction::TransactionStatus::Idle
+    }
+
+    /// Update transaction status of the session. The default implementation
+    /// discards it, so the session is always reported idle.
+    fn set_transaction_status(&mut self, _status: transaction::TransactionStatus) {}
+
+    /// Minor version of protocol 3 negotiated during startup.
+    fn protocol_minor_version(&self) -> u16 {
+        0
+    }
+
+    /// Store negotiated minor version of protocol. The default implementation
+    /// discards it, so the session uses protocol 3.0.
+    fn set_protocol_minor_version(&mut self, _minor_version: u16) {}
+
+    /// DER encoded certificate chain presented by client during TLS
+    /// handshake, end-entity certificate first. `None` if the connection is
+    /// not secure or client sent no certificate.
+    fn client_certificates(&self) -> Option<&[Vec<u8>]> {
+        None
+    }
+
+    /// Host name requested by client with SNI during TLS handshake. Servers
+    /// hosting multiple tenants may route the session by it, e.g. choose the
+    /// catalog or authentication realm.
+    fn server_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Application protocol negotiated with ALPN during TLS handshake.
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Credentials of client process, only available for connections over
+    /// Unix domain socket.
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        None
+    }
+
+    /// Principal of client authenticated when establishing GSSAPI transport
+    /// encryption. `None` if the connection is not GSSAPI encrypted.
+    fn gss_principal(&self) -> Option<&str> {
+        None
+    }
+
+    /// Mode requested by the `replication` startup parameter, which is saved
+    /// to metadata during startup.
+    fn startup_mode(&self) -> StartupMode {
+        ReplicationMode::from_parameter(
+            self.metadata()
+                .get(REPLICATION_PARAMETER)
+                .map(String::as_str),
+        )
+        .into()
+    }
 }
 
 /// Client Portal Store
@@ -58,6 +201,18 @@ pub struct DefaultClient<S> {
     pub state: PgWireConnectionState,
     pub metadata: HashMap<String, String>,
     pub portal_store: store::MemPortalStore<S>,
+    pub backend_key_data: Option<BackendKeyData>,
+    pub guc_registry: Arc<Mutex<guc::GucRegistry>>,
+    pub transaction_status: transaction::TransactionStatus,
+    pub protocol_minor_version: u16,
+    pub client_certificates: Option<Vec<Vec<u8>>>,
+    pub server_name: Option<String>,
+    pub alpn_protocol: Option<Vec<u8>>,
+    pub peer_credentials: Option<PeerCredentials>,
+    pub gss_principal: Option<String>,
+    pending_parameter_status: Vec<ParameterStatus>,
+    notice_emitter: notice::NoticeEmitter,
+    notice_receiver: notice::NoticeReceiver,
 }
 
 impl<S> ClientInfo for DefaultClient<S> {
@@ -84,16 +239,94 @@ impl<S> ClientInfo for DefaultClient<S> {
     fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
         &mut self.metadata
     }
+
+    fn notice_emitter(&self) -> Option<notice::NoticeEmitter> {
+        Some(self.notice_emitter.clone())
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.notice_receiver.drain()
+    }
+
+    fn push_parameter_status(&mut self, name: &str, value: &str) {
+        self.pending_parameter_status
+            .push(ParameterStatus::new(name.to_owned(), value.to_owned()));
+    }
+
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        std::mem::take(&mut self.pending_parameter_status)
+    }
+
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        self.backend_key_data.as_ref()
+    }
+
+    fn set_backend_key_data(&mut self, backend_key_data: BackendKeyData) {
+        self.backend_key_data = Some(backend_key_data);
+    }
+
+    fn guc_registry(&self) -> Option<Arc<Mutex<guc::GucRegistry>>> {
+        Some(self.guc_registry.clone())
+    }
+
+    fn transaction_status(&self) -> transaction::TransactionStatus {
+        self.transaction_status
+    }
+
+    fn set_transaction_status(&mut self, status: transaction::TransactionStatus) {
+        self.transaction_status = status;
+    }
+
+    fn protocol_minor_version(&self) -> u16 {
+        self.protocol_minor_version
+    }
+
+    fn set_protocol_minor_version(&mut self, minor_version: u16) {
+        self.protocol_minor_version = minor_version;
+    }
+
+    fn client_certificates(&self) -> Option<&[Vec<u8>]> {
+        self.client_certificates.as_deref()
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.peer_credentials
+    }
+
+    fn gss_principal(&self) -> Option<&str> {
+        self.gss_principal.as_deref()
+    }
 }
 
 impl<S> DefaultClient<S> {
     pub fn new(socket_addr: SocketAddr, is_secure: bool) -> DefaultClient<S> {
+        let (notice_emitter, notice_receiver) = notice::channel();
         DefaultClient {
             socket_addr,
             is_secure,
             state: PgWireConnectionState::default(),
             metadata: HashMap::new(),
             portal_store: store::MemPortalStore::new(),
+            backend_key_data: None,
+            guc_registry: Arc::default(),
+            transaction_status: transaction::TransactionStatus::default(),
+            protocol_minor_version: 0,
+            client_certificates: None,
+            server_name: None,
+            alpn_protocol: None,
+            peer_credentials: None,
+            gss_principal: None,
+            pending_parameter_status: Vec::new(),
+            notice_emitter,
+            notice_receiver,
         }
     }
 }
@@ -0,0 +1,181 @@
+//! Negotiation of protocol version and `_pq_.` prefixed protocol options
+//! requested in startup message.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use futures::sink::{Sink, SinkExt};
+
+use super::ClientInfo;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::startup::{NegotiateProtocolVersion, Startup, PROTOCOL_EXTENSION_PREFIX};
+use crate::messages::PgWireBackendMessage;
+
+/// Newest minor version of protocol 3 supported by pgwire. Protocol 3.2 has
+/// variable length secret key in `BackendKeyData` and `CancelRequest`.
+pub const NEWEST_MINOR_PROTOCOL_VERSION: u16 = 2;
+
+/// A protocol extension that clients request with `_pq_.{name}` startup
+/// parameter.
+pub trait ProtocolFeature: Send + Sync {
+    /// Name of the feature, without the `_pq_.` prefix
+    fn name(&self) -> &str;
+
+    /// Accept or decline the feature with `options` requested by client.
+    ///
+    /// Declined features, and features failed with error, are reported to
+    /// client as unsupported.
+    fn negotiate(&self, options: &str) -> PgWireResult<bool>;
+}
+
+/// Registry of protocol features supported by server.
+#[derive(Default, Clone)]
+pub struct ProtocolFeatureNegotiator {
+    features: BTreeMap<String, Arc<dyn ProtocolFeature>>,
+}
+
+impl Debug for ProtocolFeatureNegotiator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.features.keys()).finish()
+    }
+}
+
+impl ProtocolFeatureNegotiator {
+    pub fn new() -> ProtocolFeatureNegotiator {
+        ProtocolFeatureNegotiator::default()
+    }
+
+    /// Register `feature`, replacing any feature of the same name.
+    pub fn with_feature(mut self, feature: Arc<dyn ProtocolFeature>) -> Self {
+        self.features.insert(feature.name().to_owned(), feature);
+        self
+    }
+
+    /// Negotiate protocol version and options of `startup`.
+    ///
+    /// Options that are not accepted are removed from `startup`, and the
+    /// requested minor version is lowered to the supported one. Returns the
+    /// `NegotiateProtocolVersion` message to inform client, if anything
+    /// requested is not supported.
+    pub fn negotiate(&self, startup: &mut Startup) -> Option<NegotiateProtocolVersion> {
+        let mut unsupported_options = Vec::new();
+        startup.parameters.retain(|key, options| {
+            let Some(name) = key.strip_prefix(PROTOCOL_EXTENSION_PREFIX) else {
+                return true;
+            };
+            // unknown feature or error of negotiation is a decline
+            let accepted = matches!(
+                self.features.get(name).map(|f| f.negotiate(options)),
+                Some(Ok(true))
+            );
+            if !accepted {
+                unsupported_options.push(key.clone());
+            }
+            accepted
+        });
+
+        let minor_version_unsupported =
+            startup.protocol_number_minor > NEWEST_MINOR_PROTOCOL_VERSION;
+        if minor_version_unsupported {
+            startup.protocol_number_minor = NEWEST_MINOR_PROTOCOL_VERSION;
+        }
+
+        (minor_version_unsupported || !unsupported_options.is_empty()).then(|| {
+            NegotiateProtocolVersion::new(NEWEST_MINOR_PROTOCOL_VERSION as i32, unsupported_options)
+        })
+    }
+}
+
+/// Negotiate protocol with `negotiator`, or decline all protocol options if
+/// server provides none, and send `NegotiateProtocolVersion` when needed.
+pub(crate) async fn negotiate_protocol<C>(
+    client: &mut C,
+    startup: &mut Startup,
+    negotiator: Option<&ProtocolFeatureNegotiator>,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let response = match negotiator {
+        Some(negotiator) => negotiator.negotiate(startup),
+        None => ProtocolFeatureNegotiator::default().negotiate(startup),
+    };
+    client.set_protocol_minor_version(startup.protocol_number_minor);
+    if let Some(response) = response {
+        client
+            .feed(PgWireBackendMessage::NegotiateProtocolVersion(response))
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorInfo;
+
+    struct Compression;
+
+    impl ProtocolFeature for Compression {
+        fn name(&self) -> &str {
+            "compression"
+        }
+
+        fn negotiate(&self, options: &str) -> PgWireResult<bool> {
+            if options.is_empty() {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "08P01".to_owned(),
+                    "no compression algorithm".to_owned(),
+                ))));
+            }
+            Ok(options.split(',').any(|algorithm| algorithm == "lz4"))
+        }
+    }
+
+    #[test]
+    fn test_negotiate_protocol() {
+        let negotiator = ProtocolFeatureNegotiator::new().with_feature(Arc::new(Compression));
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        assert!(negotiator.negotiate(&mut startup).is_none());
+
+        startup
+            .parameters
+            .insert("_pq_.compression".to_owned(), "zstd,lz4".to_owned());
+        assert!(negotiator.negotiate(&mut startup).is_none());
+        assert!(startup.parameters.contains_key("_pq_.compression"));
+
+        startup
+            .parameters
+            .insert("_pq_.unknown".to_owned(), "on".to_owned());
+        startup.protocol_number_minor = 3;
+        let response = negotiator.negotiate(&mut startup).unwrap();
+        assert_eq!(2, response.newest_minor_version);
+        assert_eq!(
+            vec!["_pq_.unknown".to_owned()],
+            response.unsupported_options
+        );
+        assert_eq!(2, startup.protocol_number_minor);
+        assert!(!startup.parameters.contains_key("_pq_.unknown"));
+
+        // declined and failed features are reported
+        for options in ["zstd", ""] {
+            startup
+                .parameters
+                .insert("_pq_.compression".to_owned(), options.to_owned());
+            let response = negotiator.negotiate(&mut startup).unwrap();
+            assert_eq!(
+                vec!["_pq_.compression".to_owned()],
+                response.unsupported_options
+            );
+            assert_eq!(1, startup.parameters.len());
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use crate::error::ErrorInfo;
+use crate::messages::response::NoticeResponse;
+
+/// Handle for sending `NoticeResponse` to client while processing a query.
+///
+/// Get it from [`ClientInfo::notice_emitter`](super::ClientInfo::notice_emitter).
+/// The emitter can be cloned and moved into row streams or spawned tasks.
+/// Notices are sent to client in the order they are emitted, interleaved with
+/// result data.
+#[derive(Debug, Clone)]
+pub struct NoticeEmitter {
+    sender: UnboundedSender<NoticeResponse>,
+}
+
+impl NoticeEmitter {
+    /// Send a notice to client. The notice is dropped if the client is gone.
+    pub fn notice(&self, info: ErrorInfo) {
+        let _ = self.sender.unbounded_send(info.into());
+    }
+}
+
+/// Receiving end of notices, owned by the client
+#[derive(Debug)]
+pub(crate) struct NoticeReceiver {
+    receiver: UnboundedReceiver<NoticeResponse>,
+}
+
+impl NoticeReceiver {
+    /// Take notices emitted so far
+    pub(crate) fn drain(&mut self) -> Vec<NoticeResponse> {
+        let mut notices = Vec::new();
+        while let Ok(notice) = self.receiver.try_recv() {
+            notices.push(notice);
+        }
+        notices
+    }
+}
+
+pub(crate) fn channel() -> (NoticeEmitter, NoticeReceiver) {
+    let (sender, receiver) = unbounded();
+    (NoticeEmitter { sender }, NoticeReceiver { receiver })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_notice_channel() {
+        let (emitter, mut receiver) = channel();
+        assert!(receiver.drain().is_empty());
+
+        emitter.notice(ErrorInfo::new(
+            "NOTICE".to_owned(),
+            "00000".to_owned(),
+            "first".to_owned(),
+        ));
+        emitter.clone().notice(ErrorInfo::new(
+            "NOTICE".to_owned(),
+            "00000".to_owned(),
+            "second".to_owned(),
+        ));
+
+        let notices = receiver.drain();
+        assert_eq!(2, notices.len());
+        assert!(notices[0].fields.contains(&(b'M', "first".to_owned())));
+        assert!(notices[1].fields.contains(&(b'M', "second".to_owned())));
+        assert!(receiver.drain().is_empty());
+    }
+}
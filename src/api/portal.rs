@@ -1,29 +1,49 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Formatter};
 use std::sync::Arc;
 
 use bytes::Bytes;
+use futures::stream::BoxStream;
 use postgres_types::FromSqlOwned;
 
 use crate::{
     api::Type,
     error::{PgWireError, PgWireResult},
-    messages::{data::FORMAT_CODE_BINARY, extendedquery::Bind},
+    messages::{
+        data::{DataRow, FORMAT_CODE_BINARY},
+        extendedquery::Bind,
+    },
+    types::{DateStyleParser, FromDateStyleText, FromSqlText},
 };
 
-use super::{results::FieldFormat, stmt::StoredStatement, DEFAULT_NAME};
+use super::{
+    is_unnamed,
+    results::{Backpressure, FieldFormat, RowErrors, Tag},
+    stmt::StoredStatement,
+    DEFAULT_NAME, UNNAMED_DISPLAY_NAME,
+};
 
 /// Represent a prepared sql statement and its parameters bound by a `Bind`
 /// request.
 #[non_exhaustive]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Portal<S> {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub statement: Arc<StoredStatement<S>>,
     pub parameter_format: Format,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serde_util::base64_nullable_list::serialize")
+    )]
     pub parameters: Vec<Option<Bytes>>,
     pub result_column_format: Format,
 }
 
+#[non_exhaustive]
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Format {
     #[default]
     UnifiedText,
@@ -61,6 +81,15 @@ impl Format {
         self.format_for(idx) == FieldFormat::Binary
     }
 
+    /// Format codes to send in `Bind` message
+    pub(crate) fn to_codes(&self) -> Vec<i16> {
+        match self {
+            Format::UnifiedText => vec![],
+            Format::UnifiedBinary => vec![FORMAT_CODE_BINARY],
+            Format::Individual(ref fv) => fv.clone(),
+        }
+    }
+
     fn from_codes(codes: &[i16]) -> Self {
         if codes.is_empty() {
             Format::UnifiedText
@@ -74,7 +103,19 @@ impl Format {
 
 impl<S: Clone> Portal<S> {
     /// Try to create portal from bind command and current client state
+    ///
+    /// `statement` must be the one referenced by `bind`.
     pub fn try_new(bind: &Bind, statement: Arc<StoredStatement<S>>) -> PgWireResult<Self> {
+        let statement_name = bind.statement_name.as_deref().unwrap_or(DEFAULT_NAME);
+        let statement_matched = if is_unnamed(statement_name) {
+            !statement.is_named()
+        } else {
+            statement.id == statement_name
+        };
+        if !statement_matched {
+            return Err(PgWireError::StatementNotFound(statement_name.to_owned()));
+        }
+
         let portal_name = bind
             .portal_name
             .clone()
@@ -95,6 +136,20 @@ impl<S: Clone> Portal<S> {
         })
     }
 
+    /// Test if this is a named portal, rather than the unnamed one.
+    pub fn is_named(&self) -> bool {
+        !is_unnamed(&self.name)
+    }
+
+    /// Name of the portal for display, `(unnamed)` for the unnamed portal.
+    pub fn effective_name(&self) -> &str {
+        if self.is_named() {
+            &self.name
+        } else {
+            UNNAMED_DISPLAY_NAME
+        }
+    }
+
     /// Get number of parameters
     pub fn parameter_len(&self) -> usize {
         self.parameters.len()
@@ -102,9 +157,86 @@ impl<S: Clone> Portal<S> {
 
     /// Attempt to get parameter at given index as type `T`.
     ///
+    /// The parameter is decoded with `FromSql` or `FromSqlText` according to
+    /// its format code in `Bind`.
     pub fn parameter<T>(&self, idx: usize, pg_type: &Type) -> PgWireResult<Option<T>>
+    where
+        T: FromSqlOwned + FromSqlText,
+    {
+        self.decode_parameter(idx, pg_type, |param| T::from_sql_text(pg_type, param))
+    }
+
+    /// Attempt to get date/time parameter at given index as type `T`.
+    ///
+    /// Text format parameter is parsed with `date_style`, which can be created
+    /// from client session with `DateStyleParser::from_client`.
+    pub fn datetime_parameter<T>(
+        &self,
+        idx: usize,
+        pg_type: &Type,
+        date_style: &DateStyleParser,
+    ) -> PgWireResult<Option<T>>
+    where
+        T: FromSqlOwned + FromDateStyleText,
+    {
+        self.decode_parameter(idx, pg_type, |param| {
+            let text = std::str::from_utf8(param)?;
+            T::from_date_style_text(date_style, text)
+        })
+    }
+
+    /// Decode all parameters against types declared by the statement, like
+    /// `portal.parameters_as::<(i64, String, Option<f64>)>()`.
+    ///
+    /// Number of parameters must match the tuple, and `NULL` is only accepted
+    /// for `Option` fields. Parameters without declared type are treated as
+    /// `unknown`, which most rust types don't accept.
+    pub fn parameters_as<T: FromParameters>(&self) -> PgWireResult<T> {
+        T::from_portal(self)
+    }
+
+    /// Get parameter at `idx` as non-null `T`, with the type declared by the
+    /// statement and position in error messages.
+    fn declared_parameter<T>(&self, idx: usize) -> PgWireResult<T>
+    where
+        T: FromSqlOwned + FromSqlText,
+    {
+        let pg_type = self
+            .statement
+            .parameter_types
+            .get(idx)
+            .cloned()
+            .unwrap_or(Type::UNKNOWN);
+        let value = match self.parameter::<T>(idx, &pg_type) {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => <T as FromSqlText>::from_sql_null(&pg_type)
+                .map_err(PgWireError::FailedToParseParameter),
+            Err(e) => Err(e),
+        };
+
+        let position = idx + 1;
+        value.map_err(|e| match e {
+            PgWireError::InvalidRustTypeForParameter(ty) => {
+                PgWireError::InvalidRustTypeForParameter(format!("{ty} (parameter ${position})"))
+            }
+            PgWireError::FailedToParseParameter(source) => {
+                PgWireError::FailedToParseParameter(Box::new(ParameterError { position, source }))
+            }
+            e => e,
+        })
+    }
+
+    /// Decode non-null parameter with `from_text` if it's in text format, or
+    /// `FromSql` otherwise.
+    fn decode_parameter<T, F>(
+        &self,
+        idx: usize,
+        pg_type: &Type,
+        from_text: F,
+    ) -> PgWireResult<Option<T>>
     where
         T: FromSqlOwned,
+        F: FnOnce(&[u8]) -> Result<T, Box<dyn Error + Sync + Send>>,
     {
         if !T::accepts(pg_type) {
             return Err(PgWireError::InvalidRustTypeForParameter(
@@ -117,14 +249,13 @@ impl<S: Clone> Portal<S> {
             .get(idx)
             .ok_or_else(|| PgWireError::ParameterIndexOutOfBound(idx))?;
 
-        let _format = self.parameter_format.format_for(idx);
-
         if let Some(ref param) = param {
-            // TODO: from_sql only works with binary format
-            // here we need to check format code first and seek to support text
-            T::from_sql(pg_type, param)
-                .map(|v| Some(v))
-                .map_err(PgWireError::FailedToParseParameter)
+            let value = if self.parameter_format.is_text(idx) {
+                from_text(param)
+            } else {
+                T::from_sql(pg_type, param)
+            };
+            value.map(Some).map_err(PgWireError::FailedToParseParameter)
         } else {
             // Null
             Ok(None)
@@ -132,11 +263,232 @@ impl<S: Clone> Portal<S> {
     }
 }
 
+/// Types that can be decoded from the whole parameter list of a portal,
+/// implemented for tuples of `FromSqlOwned + FromSqlText` types up to 12
+/// fields.
+pub trait FromParameters: Sized {
+    fn from_portal<S: Clone>(portal: &Portal<S>) -> PgWireResult<Self>;
+}
+
+macro_rules! impl_from_parameters {
+    ($len:expr; $($idx:tt $t:ident),+) => {
+        impl<$($t),+> FromParameters for ($($t,)+)
+        where
+            $($t: FromSqlOwned + FromSqlText),+
+        {
+            fn from_portal<S: Clone>(portal: &Portal<S>) -> PgWireResult<Self> {
+                if portal.parameter_len() != $len {
+                    return Err(PgWireError::ParameterCountMismatch {
+                        expected: $len,
+                        got: portal.parameter_len(),
+                    });
+                }
+                Ok(($(portal.declared_parameter::<$t>($idx)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_parameters!(1; 0 T0);
+impl_from_parameters!(2; 0 T0, 1 T1);
+impl_from_parameters!(3; 0 T0, 1 T1, 2 T2);
+impl_from_parameters!(4; 0 T0, 1 T1, 2 T2, 3 T3);
+impl_from_parameters!(5; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4);
+impl_from_parameters!(6; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5);
+impl_from_parameters!(7; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6);
+impl_from_parameters!(8; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7);
+impl_from_parameters!(9; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7, 8 T8);
+impl_from_parameters!(10; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7, 8 T8, 9 T9);
+impl_from_parameters!(11; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7, 8 T8, 9 T9, 10 T10);
+impl_from_parameters!(12; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7, 8 T8, 9 T9, 10 T10, 11 T11);
+
+/// Failure of decoding the parameter at `position`, starting from 1.
+struct ParameterError {
+    position: usize,
+    source: Box<dyn Error + Sync + Send>,
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "parameter ${}: {}", self.position, self.source)
+    }
+}
+
+// `PgWireError` displays its source with `Debug`
+impl Debug for ParameterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "parameter ${}: {:?}", self.position, self.source)
+    }
+}
+
+impl Error for ParameterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// A portal whose execution is suspended because `max_rows` of `Execute` is
+/// reached, together with rows not yet sent to client.
+///
+/// Next `Execute` on the portal resumes from these rows instead of running
+/// the query again. Rows are pulled from the stream only when they are sent,
+/// so the stream of the response must be created by
+/// `QueryResponse::new_owned`. It's dropped when the portal is closed or
+/// bound again.
+#[non_exhaustive]
+pub struct SuspendedPortal<S> {
+    pub portal: Arc<Portal<S>>,
+    pub(crate) tag: Tag,
+    pub(crate) data_rows: BoxStream<'static, PgWireResult<DataRow>>,
+    pub(crate) backpressure: Option<Backpressure>,
+    pub(crate) row_errors: RowErrors,
+}
+
+impl<S> SuspendedPortal<S> {
+    pub(crate) fn new(
+        portal: Arc<Portal<S>>,
+        tag: Tag,
+        data_rows: BoxStream<'static, PgWireResult<DataRow>>,
+        backpressure: Option<Backpressure>,
+        row_errors: RowErrors,
+    ) -> SuspendedPortal<S> {
+        SuspendedPortal {
+            portal,
+            tag,
+            data_rows,
+            backpressure,
+            row_errors,
+        }
+    }
+
+    /// Command tag of the suspended query
+    pub fn command_tag(&self) -> &str {
+        self.tag.command()
+    }
+}
+
+impl<S: Debug> Debug for SuspendedPortal<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuspendedPortal")
+            .field("portal", &self.portal)
+            .field("tag", &self.tag)
+            .finish_non_exhaustive()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use chrono::NaiveDate;
     use postgres_types::FromSql;
 
     use super::*;
+    use crate::types::DateOrder;
+
+    #[test]
+    fn test_datetime_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![],
+            vec![Some(Bytes::from_static(b"01.02.2023")), None],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+        let date_style = DateStyleParser::new(DateOrder::Dmy);
+
+        assert_eq!(
+            Some(NaiveDate::from_ymd_opt(2023, 2, 1).unwrap()),
+            portal
+                .datetime_parameter::<NaiveDate>(0, &Type::DATE, &date_style)
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            portal
+                .datetime_parameter::<NaiveDate>(1, &Type::DATE, &date_style)
+                .unwrap()
+        );
+        assert!(portal
+            .datetime_parameter::<NaiveDate>(0, &Type::INT4, &date_style)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 1, 0, 0],
+            vec![
+                Some(Bytes::from_static(b"1234")),
+                Some(Bytes::from_static(&[0, 0, 4, 210])),
+                Some(Bytes::from_static(b"{t,f,NULL}")),
+                None,
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(Some(1234), portal.parameter::<i32>(0, &Type::INT4).unwrap());
+        assert_eq!(Some(1234), portal.parameter::<i32>(1, &Type::INT4).unwrap());
+        assert_eq!(
+            Some(vec![Some(true), Some(false), None]),
+            portal
+                .parameter::<Vec<Option<bool>>>(2, &Type::BOOL_ARRAY)
+                .unwrap()
+        );
+        assert_eq!(None, portal.parameter::<i64>(3, &Type::INT8).unwrap());
+        assert!(portal.parameter::<Vec<bool>>(2, &Type::BOOL_ARRAY).is_err());
+        assert!(portal.parameter::<String>(0, &Type::INT4).is_err());
+    }
+
+    #[test]
+    fn test_parameters_as() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![],
+            vec![
+                Some(Bytes::from_static(b"42")),
+                Some(Bytes::from_static(b"tom")),
+                None,
+            ],
+            vec![],
+        );
+        let statement = Arc::new(StoredStatement::new(
+            String::new(),
+            String::new(),
+            vec![Type::INT8, Type::VARCHAR, Type::FLOAT8],
+        ));
+        let portal = Portal::try_new(&bind, statement).unwrap();
+
+        assert_eq!(
+            (42, "tom".to_owned(), None),
+            portal
+                .parameters_as::<(i64, String, Option<f64>)>()
+                .unwrap()
+        );
+        assert!(matches!(
+            portal.parameters_as::<(i64, String)>(),
+            Err(PgWireError::ParameterCountMismatch {
+                expected: 2,
+                got: 3
+            })
+        ));
+
+        let err = portal
+            .parameters_as::<(i64, String, f64)>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("parameter $3"), "{err}");
+        let err = portal
+            .parameters_as::<(i64, i32, Option<f64>)>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("varchar (parameter $2)"), "{err}");
+    }
 
     #[test]
     fn test_from_sql() {
@@ -0,0 +1,205 @@
+//! Processing of frontend messages, shared by all server entry-points.
+//!
+//! Entry-points own the connection and read messages from it. Each message is
+//! dispatched to handlers by [`process_message`], and failures are reported to
+//! client by [`process_error`].
+
+use std::fmt::Debug;
+
+use futures::{Sink, SinkExt};
+use tracing::Instrument;
+
+use super::auth::StartupHandler;
+use super::fastpath::{on_function_call, FastpathHandler};
+use super::guc::{match_guc_command, on_guc_command};
+use super::negotiate::negotiate_protocol;
+use super::query::{on_deallocate, parse_deallocate, ExtendedQueryHandler, SimpleQueryHandler};
+use super::replication::{protocol_violation, ReplicationHandler};
+use super::store::PortalStore;
+use super::trace::startup_span;
+use super::transaction::update_transaction_status;
+use super::{ClientInfo, ClientPortalStore, PgWireConnectionState};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::response::{GssEncResponse, ReadyForQuery};
+use crate::messages::startup::StartupMode;
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+pub(crate) async fn process_message<C, A, Q, EQ, R>(
+    mut message: PgWireFrontendMessage,
+    socket: &mut C,
+    authenticator: &A,
+    query_handler: &Q,
+    extended_query_handler: &EQ,
+    replication_handler: Option<&R>,
+    fastpath_handler: Option<&dyn FastpathHandler>,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::PortalStore: PortalStore<Statement = EQ::Statement>,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    match socket.state() {
+        PgWireConnectionState::AwaitingStartup
+        | PgWireConnectionState::AuthenticationInProgress => {
+            if let PgWireFrontendMessage::CancelRequest(request) = message {
+                authenticator.on_cancel_request(request).await;
+                return Ok(());
+            }
+            if let PgWireFrontendMessage::GssEncRequest(_) = message {
+                // GSSAPI encryption is not supported, client may continue
+                // with SslRequest or Startup
+                socket
+                    .send(PgWireBackendMessage::GssEncResponse(GssEncResponse::Refuse))
+                    .await?;
+                return Ok(());
+            }
+            if let PgWireFrontendMessage::Startup(ref mut startup) = message {
+                negotiate_protocol(socket, startup, authenticator.protocol_negotiator()).await?;
+            }
+            let span = startup_span(socket, &message);
+            authenticator
+                .on_startup(socket, message)
+                .instrument(span)
+                .await?;
+        }
+        // From Postgres docs:
+        // When an error is detected while processing any extended-query
+        // message, the backend issues ErrorResponse, then reads and discards
+        // messages until a Sync is reached, then issues ReadyForQuery and
+        // returns to normal message processing.
+        PgWireConnectionState::AwaitingSync => {
+            if let PgWireFrontendMessage::Sync(sync) = message {
+                extended_query_handler.on_sync(socket, sync).await?;
+                socket.set_state(PgWireConnectionState::ReadyForQuery);
+            }
+        }
+        _ => {
+            // walsender connection, if replication is supported
+            let replication_handler = replication_handler
+                .filter(|_| matches!(socket.startup_mode(), StartupMode::Replication(_)));
+            // query or query in progress
+            match message {
+                PgWireFrontendMessage::Query(query) => {
+                    if let Some(replication_handler) = replication_handler {
+                        replication_handler
+                            .on_replication_command(socket, query)
+                            .await?;
+                    } else if let Some(target) = parse_deallocate(&query.query) {
+                        // prepared statements are managed by pgwire, so
+                        // `DEALLOCATE` is handled here instead of query handler
+                        on_deallocate(socket, extended_query_handler, target).await?;
+                    } else if let Some(command) = match_guc_command(socket, &query.query) {
+                        on_guc_command(socket, command).await?;
+                    } else {
+                        query_handler.on_query(socket, query).await?;
+                    }
+                }
+                PgWireFrontendMessage::Parse(_)
+                | PgWireFrontendMessage::Bind(_)
+                | PgWireFrontendMessage::Execute(_)
+                | PgWireFrontendMessage::Describe(_)
+                | PgWireFrontendMessage::Close(_)
+                    if replication_handler.is_some() =>
+                {
+                    return Err(protocol_violation(
+                        "extended query protocol is not supported in replication mode".to_owned(),
+                    ));
+                }
+                PgWireFrontendMessage::Parse(parse) => {
+                    extended_query_handler.on_parse(socket, parse).await?;
+                }
+                PgWireFrontendMessage::Bind(bind) => {
+                    extended_query_handler.on_bind(socket, bind).await?;
+                }
+                PgWireFrontendMessage::Execute(execute) => {
+                    extended_query_handler.on_execute(socket, execute).await?;
+                }
+                PgWireFrontendMessage::Describe(describe) => {
+                    extended_query_handler.on_describe(socket, describe).await?;
+                }
+                PgWireFrontendMessage::Sync(sync) => {
+                    extended_query_handler.on_sync(socket, sync).await?;
+                }
+                PgWireFrontendMessage::Close(close) => {
+                    extended_query_handler.on_close(socket, close).await?;
+                }
+                PgWireFrontendMessage::Flush(_) => {
+                    socket.flush().await?;
+                }
+                PgWireFrontendMessage::FunctionCall(call) => {
+                    on_function_call(socket, fastpath_handler, call).await?;
+                }
+                PgWireFrontendMessage::Startup(_)
+                | PgWireFrontendMessage::SslRequest(_)
+                | PgWireFrontendMessage::PasswordMessageFamily(_) => {
+                    return Err(PgWireError::unexpected_message("Query", &message));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn process_error<C>(
+    socket: &mut C,
+    error: PgWireError,
+    wait_for_sync: bool,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    match error {
+        PgWireError::UserError(error_info) => {
+            socket
+                .feed(PgWireBackendMessage::ErrorResponse((*error_info).into()))
+                .await?;
+        }
+        // the connection is still usable after these
+        PgWireError::ApiError(_) | PgWireError::PortalNotSuspendable(_) => {
+            let error_info = ErrorInfo::new(
+                "ERROR".to_owned(),
+                error.sqlstate().to_owned(),
+                error.to_string(),
+            );
+            socket
+                .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
+                .await?;
+        }
+        _ => {
+            // Internal error
+            let error_info = ErrorInfo::new(
+                "FATAL".to_owned(),
+                error.sqlstate().to_owned(),
+                error.to_string(),
+            );
+            socket
+                .send(PgWireBackendMessage::ErrorResponse(error_info.into()))
+                .await?;
+            socket.close().await?;
+            return Ok(());
+        }
+    }
+
+    // error aborts the transaction block
+    update_transaction_status(socket, None, false);
+    if wait_for_sync {
+        socket.set_state(PgWireConnectionState::AwaitingSync);
+    } else {
+        socket
+            .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                socket.transaction_status().to_ready_status(),
+            )))
+            .await?;
+    }
+    socket.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+
+use crate::error::PgWireResult;
+use crate::messages::response::{NoticeResponse, NotificationResponse};
+use crate::messages::PgWireBackendMessage;
+
+/// Messages that are allowed to be pushed to client outside of a query.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum ServerPushMessage {
+    Notification(NotificationResponse),
+    Notice(NoticeResponse),
+}
+
+impl From<ServerPushMessage> for PgWireBackendMessage {
+    fn from(msg: ServerPushMessage) -> PgWireBackendMessage {
+        match msg {
+            ServerPushMessage::Notification(n) => PgWireBackendMessage::NotificationResponse(n),
+            ServerPushMessage::Notice(n) => PgWireBackendMessage::NoticeResponse(n),
+        }
+    }
+}
+
+/// Source of messages that server sends to an idle client without being
+/// asked.
+///
+/// The connection loop waits for `push_messages` alongside the next client
+/// message, and sends returned messages as soon as they're ready. The future
+/// is dropped when a client message arrives first, so implementations must be
+/// cancel safe: no message should be lost if the future is dropped before it
+/// completes.
+#[async_trait]
+pub trait ServerPush: Send + Sync {
+    /// Wait for messages to push to the client.
+    ///
+    /// Return an empty `Vec` when this source is exhausted, it won't be polled
+    /// again for the connection.
+    async fn push_messages(&self) -> PgWireResult<Vec<ServerPushMessage>>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_into_backend_message() {
+        let msg: PgWireBackendMessage = ServerPushMessage::Notification(NotificationResponse::new(
+            1,
+            "channel".to_owned(),
+            "payload".to_owned(),
+        ))
+        .into();
+        assert!(matches!(msg, PgWireBackendMessage::NotificationResponse(_)));
+
+        let msg: PgWireBackendMessage =
+            ServerPushMessage::Notice(NoticeResponse::new(vec![])).into();
+        assert!(matches!(msg, PgWireBackendMessage::NoticeResponse(_)));
+    }
+}
@@ -3,23 +3,26 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
-use futures::stream::StreamExt;
+use futures::stream::{BoxStream, StreamExt};
+use tracing::Instrument;
 
-use super::portal::Portal;
-use super::results::{into_row_description, Tag};
+use super::portal::{Portal, SuspendedPortal};
+use super::results::{into_row_description, Backpressure, RowErrors, Tag};
 use super::stmt::{NoopQueryParser, QueryParser, StoredStatement};
 use super::store::PortalStore;
-use super::{ClientInfo, ClientPortalStore, DEFAULT_NAME};
+use super::trace::query_span;
+use super::transaction::{parse_transaction_command, update_transaction_status};
+use super::{is_unnamed, ClientInfo, ClientPortalStore, DEFAULT_NAME};
 use crate::api::results::{
     DescribePortalResponse, DescribeResponse, DescribeStatementResponse, QueryResponse, Response,
 };
-use crate::error::{PgWireError, PgWireResult};
-use crate::messages::data::{NoData, ParameterDescription};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::data::{DataRow, NoData, ParameterDescription};
 use crate::messages::extendedquery::{
     Bind, BindComplete, Close, CloseComplete, Describe, Execute, Parse, ParseComplete,
-    Sync as PgSync, TARGET_TYPE_BYTE_PORTAL, TARGET_TYPE_BYTE_STATEMENT,
+    PortalSuspended, Sync as PgSync, TARGET_TYPE_BYTE_PORTAL, TARGET_TYPE_BYTE_STATEMENT,
 };
-use crate::messages::response::{EmptyQueryResponse, ReadyForQuery, READY_STATUS_IDLE};
+use crate::messages::response::{EmptyQueryResponse, ReadyForQuery};
 use crate::messages::simplequery::Query;
 use crate::messages::PgWireBackendMessage;
 
@@ -28,6 +31,110 @@ fn is_empty_query(q: &str) -> bool {
     trimmed_query == ";" || trimmed_query.is_empty()
 }
 
+/// Target of a `DEALLOCATE` statement
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Deallocate {
+    All,
+    Name(String),
+}
+
+/// Parse `DEALLOCATE [PREPARE] { name | ALL }`. Returns `None` if the query is
+/// anything else, including multiple statements.
+pub(crate) fn parse_deallocate(query: &str) -> Option<Deallocate> {
+    let query = query.trim();
+    let query = query.strip_suffix(';').unwrap_or(query).trim_end();
+
+    let (keyword, rest) = query.split_once(char::is_whitespace)?;
+    if !keyword.eq_ignore_ascii_case("deallocate") {
+        return None;
+    }
+    let mut rest = rest.trim_start();
+    if let Some((keyword, name)) = rest.split_once(char::is_whitespace) {
+        if keyword.eq_ignore_ascii_case("prepare") {
+            rest = name.trim_start();
+        }
+    }
+
+    if let Some(quoted) = rest.strip_prefix('"') {
+        // quoted identifier, with `""` as escaped quote
+        let name = quoted.strip_suffix('"')?;
+        if name.is_empty() || name.replace("\"\"", "").contains('"') {
+            return None;
+        }
+        Some(Deallocate::Name(name.replace("\"\"", "\"")))
+    } else if rest.eq_ignore_ascii_case("all") {
+        Some(Deallocate::All)
+    } else {
+        let mut chars = rest.chars();
+        let first = chars.next()?;
+        if (first.is_alphabetic() || first == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            // unquoted identifier is case-insensitive
+            Some(Deallocate::Name(rest.to_lowercase()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Remove prepared statements for `DEALLOCATE` and respond to the simple
+/// query. The unnamed statement is kept by `DEALLOCATE ALL`.
+pub(crate) async fn on_deallocate<C, EQ>(
+    client: &mut C,
+    extended_query_handler: &EQ,
+    target: Deallocate,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::PortalStore: PortalStore<Statement = EQ::Statement>,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    EQ: ExtendedQueryHandler,
+{
+    client.set_state(super::PgWireConnectionState::QueryInProgress);
+    let (names, tag) = match target {
+        Deallocate::All => {
+            let names = client
+                .portal_store()
+                .list_statements()
+                .into_iter()
+                .map(|stmt| stmt.name)
+                .filter(|name| !is_unnamed(name))
+                .collect();
+            (names, Tag::new("DEALLOCATE ALL"))
+        }
+        Deallocate::Name(name) => {
+            if client.portal_store().get_statement(&name).is_none() {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "26000".to_owned(),
+                    format!("prepared statement \"{name}\" does not exist"),
+                ))));
+            }
+            (vec![name], Tag::new("DEALLOCATE"))
+        }
+    };
+    for name in names {
+        if let Some(stmt) = client.portal_store().get_statement(&name) {
+            client.portal_store().rm_statement(&name);
+            extended_query_handler
+                .do_close_statement(client, &stmt)
+                .await?;
+        }
+    }
+
+    send_execution_response(client, tag).await?;
+    client
+        .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+            client.transaction_status().to_ready_status(),
+        )))
+        .await?;
+    client.flush().await?;
+    client.set_state(super::PgWireConnectionState::ReadyForQuery);
+    Ok(())
+}
+
 /// handler for processing simple query.
 #[async_trait]
 pub trait SimpleQueryHandler: Send + Sync {
@@ -37,6 +144,10 @@ pub trait SimpleQueryHandler: Send + Sync {
     ///
     /// This handle checks empty query by default, if the query string is empty
     /// or `;`, it returns `EmptyQueryResponse` and does not call `self.do_query`.
+    ///
+    /// Transaction control statements, like `BEGIN` and `COMMIT`, are passed
+    /// to `self.do_query` as well, and followed to report transaction status
+    /// in `ReadyForQuery`.
     async fn on_query<C>(&self, client: &mut C, query: Query) -> PgWireResult<()>
     where
         C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
@@ -50,32 +161,22 @@ pub trait SimpleQueryHandler: Send + Sync {
                 .feed(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
                 .await?;
         } else {
-            let resp = self.do_query(client, &query_string).await?;
-            for r in resp {
-                match r {
-                    Response::EmptyQuery => {
-                        client
-                            .feed(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
-                            .await?;
-                    }
-                    Response::Query(results) => {
-                        send_query_response(client, results, true).await?;
-                    }
-                    Response::Execution(tag) => {
-                        send_execution_response(client, tag).await?;
-                    }
-                    Response::Error(e) => {
-                        client
-                            .feed(PgWireBackendMessage::ErrorResponse((*e).into()))
-                            .await?;
-                    }
-                }
-            }
+            let span = query_span(client, &query_string);
+            let command = parse_transaction_command(&query_string);
+            let resp = self
+                .do_query(client, &query_string)
+                .instrument(span)
+                .await?;
+            let succeeded = !resp.iter().any(|r| matches!(r, Response::Error(_)));
+            update_transaction_status(client, command, succeeded);
+            send_responses(client, resp).await?;
         }
 
+        send_pending_notices(client).await?;
+        send_pending_parameter_status(client).await?;
         client
             .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
+                client.transaction_status().to_ready_status(),
             )))
             .await?;
         client.flush().await?;
@@ -105,8 +206,8 @@ pub trait ExtendedQueryHandler: Send + Sync {
 
     /// Called when client sends `parse` command.
     ///
-    /// The default implementation parsed query with `Self::QueryParser` and
-    /// stores it in `Self::PortalStore`.
+    /// The default implementation parsed query with `Self::QueryParser`, calls
+    /// `self.do_parse` and stores it in `Self::PortalStore`.
     async fn on_parse<C>(&self, client: &mut C, message: Parse) -> PgWireResult<()>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
@@ -115,10 +216,11 @@ pub trait ExtendedQueryHandler: Send + Sync {
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
         let parser = self.query_parser();
-        let stmt = StoredStatement::parse(&message, parser).await?;
+        let mut stmt = StoredStatement::parse(&message, parser).await?;
+        self.do_parse(client, &mut stmt).await?;
         client.portal_store().put_statement(Arc::new(stmt));
         client
-            .send(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
+            .feed(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
             .await?;
 
         Ok(())
@@ -141,7 +243,7 @@ pub trait ExtendedQueryHandler: Send + Sync {
             let portal = Portal::try_new(&message, statement)?;
             client.portal_store().put_portal(Arc::new(portal));
             client
-                .send(PgWireBackendMessage::BindComplete(BindComplete::new()))
+                .feed(PgWireBackendMessage::BindComplete(BindComplete::new()))
                 .await?;
             Ok(())
         } else {
@@ -157,6 +259,11 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// Note that, different from `SimpleQueryHandler`, this implementation
     /// won't check empty query because it cannot understand parsed
     /// `Self::Statement`.
+    ///
+    /// When `max_rows` is reached, the portal is suspended and stored in
+    /// `Self::PortalStore` with its unconsumed row stream. Following
+    /// `Execute` on the same portal resumes from the stream without calling
+    /// `self::do_query`. See `send_portal_query_response`.
     async fn on_execute<C>(&self, client: &mut C, message: Execute) -> PgWireResult<()>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
@@ -165,26 +272,50 @@ pub trait ExtendedQueryHandler: Send + Sync {
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
         let portal_name = message.name.as_deref().unwrap_or(DEFAULT_NAME);
-        if let Some(portal) = client.portal_store().get_portal(portal_name) {
-            match self
-                .do_query(client, portal.as_ref(), message.max_rows as usize)
-                .await?
+        let max_rows = message.max_rows.max(0) as usize;
+        if let Some(suspended) = client.portal_store().take_suspended_portal(portal_name) {
+            if let Some(suspended) =
+                send_suspended_portal_response(client, suspended, max_rows).await?
             {
+                client.portal_store().put_suspended_portal(suspended);
+            }
+            return Ok(());
+        }
+
+        if let Some(portal) = client.portal_store().get_portal(portal_name) {
+            let span = query_span(client, &portal.statement.query);
+            let response = self
+                .do_query(client, portal.as_ref(), max_rows)
+                .instrument(span)
+                .await?;
+            update_transaction_status(
+                client,
+                parse_transaction_command(&portal.statement.query),
+                !matches!(response, Response::Error(_)),
+            );
+            match response {
                 Response::EmptyQuery => {
                     client
                         .feed(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
                         .await?;
                 }
                 Response::Query(results) => {
-                    send_query_response(client, results, false).await?;
+                    if let Some(suspended) =
+                        send_portal_query_response(client, &portal, results, max_rows).await?
+                    {
+                        client.portal_store().put_suspended_portal(suspended);
+                    }
                 }
                 Response::Execution(tag) => {
                     send_execution_response(client, tag).await?;
                 }
                 Response::Error(err) => {
                     client
-                        .send(PgWireBackendMessage::ErrorResponse((*err).into()))
+                        .feed(PgWireBackendMessage::ErrorResponse((*err).into()))
                         .await?;
+                    // like other errors, the rest of pipeline is skipped
+                    // until `Sync`
+                    client.set_state(super::PgWireConnectionState::AwaitingSync);
                 }
             }
 
@@ -238,9 +369,11 @@ pub trait ExtendedQueryHandler: Send + Sync {
         C::Error: Debug,
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
+        send_pending_notices(client).await?;
+        send_pending_parameter_status(client).await?;
         client
-            .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
+            .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                client.transaction_status().to_ready_status(),
             )))
             .await?;
         client.flush().await?;
@@ -260,42 +393,122 @@ pub trait ExtendedQueryHandler: Send + Sync {
         let name = message.name.as_deref().unwrap_or(DEFAULT_NAME);
         match message.target_type {
             TARGET_TYPE_BYTE_STATEMENT => {
-                client.portal_store().rm_statement(name);
+                if let Some(stmt) = client.portal_store().get_statement(name) {
+                    client.portal_store().rm_statement(name);
+                    self.do_close_statement(client, &stmt).await?;
+                }
             }
             TARGET_TYPE_BYTE_PORTAL => {
-                client.portal_store().rm_portal(name);
+                if let Some(portal) = client.portal_store().get_portal(name) {
+                    client.portal_store().rm_portal(name);
+                    self.do_close_portal(client, &portal).await?;
+                }
             }
             _ => {}
         }
         client
-            .send(PgWireBackendMessage::CloseComplete(CloseComplete))
+            .feed(PgWireBackendMessage::CloseComplete(CloseComplete))
             .await?;
         Ok(())
     }
 
+    /// Called with the parsed statement before it's stored. Override this to
+    /// analyze the query and update parameter types with
+    /// `StoredStatement::set_parameter_types`, so inferred types are returned
+    /// in `ParameterDescription`.
+    ///
+    /// The default implementation does nothing.
+    async fn do_parse<C>(
+        &self,
+        _client: &mut C,
+        _statement: &mut StoredStatement<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Ok(())
+    }
+
+    /// Called after a statement is closed by `Close` or `DEALLOCATE`, to free
+    /// resources tied to it, like query plans.
+    ///
+    /// It's not called for statements replaced by a new one of the same name,
+    /// or evicted from a bounded `PortalStore`. The default implementation
+    /// does nothing.
+    async fn do_close_statement<C>(
+        &self,
+        _client: &mut C,
+        _statement: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Ok(())
+    }
+
+    /// Called after a portal is closed by `Close`, to free resources tied to
+    /// it, like server-side cursors.
+    ///
+    /// It's not called for portals replaced by a new `Bind` of the same name,
+    /// or evicted from a bounded `PortalStore`. The default implementation
+    /// does nothing.
+    async fn do_close_portal<C>(
+        &self,
+        _client: &mut C,
+        _portal: &Portal<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Ok(())
+    }
+
     /// Return resultset metadata without actually executing statement
+    ///
+    /// The default implementation responds with parameter types of the
+    /// statement, including inferred ones, and no data.
     async fn do_describe_statement<C>(
         &self,
-        client: &mut C,
+        _client: &mut C,
         target: &StoredStatement<Self::Statement>,
     ) -> PgWireResult<DescribeStatementResponse>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
         C::PortalStore: PortalStore<Statement = Self::Statement>,
         C::Error: Debug,
-        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>;
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Ok(DescribeStatementResponse::new(
+            target.parameter_types.clone(),
+            vec![],
+        ))
+    }
 
     /// Return resultset metadata without actually executing portal
+    ///
+    /// The default implementation responds with no data.
     async fn do_describe_portal<C>(
         &self,
-        client: &mut C,
-        target: &Portal<Self::Statement>,
+        _client: &mut C,
+        _target: &Portal<Self::Statement>,
     ) -> PgWireResult<DescribePortalResponse>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
         C::PortalStore: PortalStore<Statement = Self::Statement>,
         C::Error: Debug,
-        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>;
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Ok(DescribePortalResponse::no_data())
+    }
 
     /// This is the main implementation for query execution. Context has
     /// been provided:
@@ -303,6 +516,15 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// - `client`: Information of the client sending the query
     /// - `portal`: Statement and parameters for the query
     /// - `max_rows`: Max requested rows of the query
+    ///
+    /// When `max_rows` is not `0`, like for JDBC `setFetchSize`, the result
+    /// may stop at `max_rows` and the portal is suspended with the remaining
+    /// row stream. Create the `QueryResponse` with `new_owned` to support
+    /// that, rows of `QueryResponse::new` may borrow `portal` and can't be
+    /// kept.
+    ///
+    /// Returning `Response::Error` fails the `Execute` like a returned `Err`
+    /// does: following messages of the pipeline are skipped until `Sync`.
     async fn do_query<'a, 'b: 'a, C>(
         &'b self,
         client: &mut C,
@@ -331,8 +553,10 @@ where
     C::Error: Debug,
     PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
 {
-    let command_tag = results.command_tag().to_owned();
+    let tag = results.tag().clone();
     let row_schema = results.row_schema();
+    let backpressure = results.backpressure();
+    let mut row_errors = RowErrors::new(results.error_policy());
     let mut data_rows = results.data_rows();
 
     // Simple query has row_schema in query response. For extended query,
@@ -340,22 +564,219 @@ where
     if send_describe {
         let row_desc = into_row_description(&row_schema);
         client
-            .send(PgWireBackendMessage::RowDescription(row_desc))
+            .feed(PgWireBackendMessage::RowDescription(row_desc))
             .await?;
     }
 
-    let mut rows = 0;
-    while let Some(row) = data_rows.next().await {
-        let row = row?;
-        rows += 1;
-        client.feed(PgWireBackendMessage::DataRow(row)).await?;
+    send_data_rows(
+        client,
+        &tag,
+        &mut data_rows,
+        backpressure.as_ref(),
+        &mut row_errors,
+        0,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Helper function to send `QueryResponse` of `Execute` on `portal`.
+///
+/// At most `max_rows` rows are sent, `0` for no limit. When the limit is
+/// reached, `PortalSuspended` is sent instead of `CommandComplete`, and the
+/// portal is returned with the unconsumed row stream so it can be resumed by
+/// `send_suspended_portal_response`.
+///
+/// Only rows of `QueryResponse::new_owned` can be kept after this call. If
+/// the limit is reached for rows that borrow the portal,
+/// `PgWireError::PortalNotSuspendable` is returned.
+pub async fn send_portal_query_response<'a, C, S>(
+    client: &mut C,
+    portal: &Arc<Portal<S>>,
+    results: QueryResponse<'a>,
+    max_rows: usize,
+) -> PgWireResult<Option<SuspendedPortal<S>>>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    S: Send + Sync,
+{
+    let tag = results.tag().clone();
+    let backpressure = results.backpressure();
+    let mut row_errors = RowErrors::new(results.error_policy());
+
+    match results.owned_data_rows() {
+        Ok(mut data_rows) => {
+            let suspended = send_data_rows(
+                client,
+                &tag,
+                &mut data_rows,
+                backpressure.as_ref(),
+                &mut row_errors,
+                max_rows,
+            )
+            .await?;
+            if !suspended {
+                return Ok(None);
+            }
+
+            send_portal_suspended(client).await?;
+            Ok(Some(SuspendedPortal::new(
+                portal.clone(),
+                tag,
+                data_rows,
+                backpressure,
+                row_errors,
+            )))
+        }
+        Err(mut data_rows) => {
+            let suspended = send_data_rows(
+                client,
+                &tag,
+                &mut data_rows,
+                backpressure.as_ref(),
+                &mut row_errors,
+                max_rows,
+            )
+            .await?;
+            if suspended {
+                Err(PgWireError::PortalNotSuspendable(portal.name.clone()))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Helper function to resume a suspended portal for `Execute`.
+///
+/// Sends at most `max_rows` of remaining rows, and returns the portal again
+/// if it's still suspended.
+pub async fn send_suspended_portal_response<C, S>(
+    client: &mut C,
+    mut suspended: SuspendedPortal<S>,
+    max_rows: usize,
+) -> PgWireResult<Option<SuspendedPortal<S>>>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    S: Send + Sync,
+{
+    let suspended_again = send_data_rows(
+        client,
+        &suspended.tag,
+        &mut suspended.data_rows,
+        suspended.backpressure.as_ref(),
+        &mut suspended.row_errors,
+        max_rows,
+    )
+    .await?;
+    if !suspended_again {
+        return Ok(None);
     }
 
-    let tag = Tag::new(&command_tag).with_rows(rows);
+    send_portal_suspended(client).await?;
+    Ok(Some(suspended))
+}
+
+async fn send_portal_suspended<C>(client: &mut C) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    send_pending_notices(client).await?;
     client
-        .send(PgWireBackendMessage::CommandComplete(tag.into()))
+        .feed(PgWireBackendMessage::PortalSuspended(PortalSuspended::new()))
         .await?;
+    Ok(())
+}
+
+/// Send at most `max_rows` data rows, followed by `CommandComplete` if rows
+/// are exhausted. Returns `true` if the limit is reached before that, and
+/// the caller decides how to suspend.
+///
+/// Failed rows are skipped or terminate the result according to policy of
+/// `row_errors`.
+async fn send_data_rows<C>(
+    client: &mut C,
+    tag: &Tag,
+    data_rows: &mut BoxStream<'_, PgWireResult<DataRow>>,
+    backpressure: Option<&Backpressure>,
+    row_errors: &mut RowErrors,
+    max_rows: usize,
+) -> PgWireResult<bool>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let mut rows = 0;
+    while max_rows == 0 || rows < max_rows {
+        let Some(row) = data_rows.next().await else {
+            let tag = tag.complete(rows);
+            send_pending_notices(client).await?;
+            if let Some(summary) = row_errors.summary() {
+                client
+                    .feed(PgWireBackendMessage::NoticeResponse(summary.into()))
+                    .await?;
+            }
+            client
+                .feed(PgWireBackendMessage::CommandComplete(tag.into()))
+                .await?;
+            return Ok(false);
+        };
+
+        let message = match row_errors.check(row)? {
+            Ok(row) => {
+                rows += 1;
+                PgWireBackendMessage::DataRow(row)
+            }
+            // skipped row
+            Err(notice) => PgWireBackendMessage::NoticeResponse(notice.into()),
+        };
+        send_pending_notices(client).await?;
+        client.feed(message).await?;
+
+        if let Some(backpressure) = backpressure.filter(|b| b.is_exhausted()) {
+            client.flush().await?;
+            backpressure.release();
+        }
+    }
 
+    Ok(true)
+}
+
+/// Send responses of a simple query, without `ReadyForQuery`.
+pub(crate) async fn send_responses<C>(client: &mut C, resp: Vec<Response<'_>>) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    for r in resp {
+        match r {
+            Response::EmptyQuery => {
+                client
+                    .feed(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
+                    .await?;
+            }
+            Response::Query(results) => {
+                send_query_response(client, results, true).await?;
+            }
+            Response::Execution(tag) => {
+                send_execution_response(client, tag).await?;
+            }
+            Response::Error(e) => {
+                client
+                    .feed(PgWireBackendMessage::ErrorResponse((*e).into()))
+                    .await?;
+            }
+        }
+    }
     Ok(())
 }
 
@@ -366,13 +787,53 @@ where
     C::Error: Debug,
     PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
 {
+    send_pending_notices(client).await?;
     client
-        .send(PgWireBackendMessage::CommandComplete(tag.into()))
+        .feed(PgWireBackendMessage::CommandComplete(tag.into()))
         .await?;
 
     Ok(())
 }
 
+/// Helper function to send notices emitted by handler with
+/// `ClientInfo::notice_emitter`.
+///
+/// Call this before sending other messages to keep notices in order with
+/// result data.
+pub async fn send_pending_notices<C>(client: &mut C) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    for notice in client.take_notices() {
+        client
+            .feed(PgWireBackendMessage::NoticeResponse(notice))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Helper function to send `ParameterStatus` queued by handler with
+/// `ClientInfo::push_parameter_status`.
+///
+/// Call this after the result of query, before `ReadyForQuery`.
+pub async fn send_pending_parameter_status<C>(client: &mut C) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    for status in client.take_parameter_status() {
+        client
+            .feed(PgWireBackendMessage::ParameterStatus(status))
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// Helper function to send response for `Describe`.
 pub async fn send_describe_response<C, DR>(
     client: &mut C,
@@ -387,17 +848,17 @@ where
     if let Some(parameter_types) = describe_response.parameters() {
         // parameter type inference
         client
-            .send(PgWireBackendMessage::ParameterDescription(
+            .feed(PgWireBackendMessage::ParameterDescription(
                 ParameterDescription::new(parameter_types.iter().map(|t| t.oid()).collect()),
             ))
             .await?;
     }
     if describe_response.is_no_data() {
-        client.send(PgWireBackendMessage::NoData(NoData)).await?;
+        client.feed(PgWireBackendMessage::NoData(NoData)).await?;
     } else {
         let row_desc = into_row_description(describe_response.fields());
         client
-            .send(PgWireBackendMessage::RowDescription(row_desc))
+            .feed(PgWireBackendMessage::RowDescription(row_desc))
             .await?;
     }
 
@@ -430,26 +891,42 @@ impl ExtendedQueryHandler for PlaceholderExtendedQueryHandler {
     {
         unimplemented!("Extended Query is not implemented on this server.")
     }
+}
 
-    async fn do_describe_statement<C>(
-        &self,
-        _client: &mut C,
-        _statement: &StoredStatement<Self::Statement>,
-    ) -> PgWireResult<DescribeStatementResponse>
-    where
-        C: ClientInfo + Unpin + Send + Sync,
-    {
-        unimplemented!("Extended Query is not implemented on this server.")
-    }
-
-    async fn do_describe_portal<C>(
-        &self,
-        _client: &mut C,
-        _portal: &Portal<Self::Statement>,
-    ) -> PgWireResult<DescribePortalResponse>
-    where
-        C: ClientInfo + Unpin + Send + Sync,
-    {
-        unimplemented!("Extended Query is not implemented on this server.")
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_deallocate() {
+        assert_eq!(
+            Some(Deallocate::Name("s1".to_owned())),
+            parse_deallocate("DEALLOCATE S1;")
+        );
+        assert_eq!(
+            Some(Deallocate::Name("S1".to_owned())),
+            parse_deallocate("deallocate prepare \"S1\"")
+        );
+        assert_eq!(
+            Some(Deallocate::Name("a\"b".to_owned())),
+            parse_deallocate("DEALLOCATE \"a\"\"b\"")
+        );
+        assert_eq!(
+            Some(Deallocate::Name("prepare".to_owned())),
+            parse_deallocate("DEALLOCATE prepare")
+        );
+        assert_eq!(
+            Some(Deallocate::All),
+            parse_deallocate("  DEALLOCATE ALL ; ")
+        );
+        assert_eq!(
+            Some(Deallocate::All),
+            parse_deallocate("DEALLOCATE PREPARE all")
+        );
+
+        assert_eq!(None, parse_deallocate("DEALLOCATE"));
+        assert_eq!(None, parse_deallocate("DEALLOCATE s1; SELECT 1"));
+        assert_eq!(None, parse_deallocate("DEALLOCATE \"a\"b\""));
+        assert_eq!(None, parse_deallocate("SELECT 'DEALLOCATE s1'"));
     }
 }
@@ -0,0 +1,172 @@
+//! Handler of walsender connections, requested by client with `replication`
+//! startup parameter.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use futures::sink::{Sink, SinkExt};
+use tracing::Instrument;
+
+use super::query::{send_pending_notices, send_responses};
+use super::results::Response;
+use super::trace::query_span;
+use super::{ClientInfo, PgWireConnectionState};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
+use crate::messages::simplequery::Query;
+use crate::messages::PgWireBackendMessage;
+
+/// Commands accepted by a connection in replication mode
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationCommand {
+    IdentifySystem,
+    StartReplication,
+    CreateReplicationSlot,
+    TimelineHistory,
+}
+
+impl ReplicationCommand {
+    /// Find replication command by leading keyword of `query`. Arguments of
+    /// the command are left to the handler.
+    pub fn parse(query: &str) -> Option<ReplicationCommand> {
+        let keyword = query
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .find(|s| !s.is_empty())?;
+        [
+            ReplicationCommand::IdentifySystem,
+            ReplicationCommand::StartReplication,
+            ReplicationCommand::CreateReplicationSlot,
+            ReplicationCommand::TimelineHistory,
+        ]
+        .into_iter()
+        .find(|cmd| keyword.eq_ignore_ascii_case(cmd.name()))
+    }
+
+    /// Keyword of the command
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReplicationCommand::IdentifySystem => "IDENTIFY_SYSTEM",
+            ReplicationCommand::StartReplication => "START_REPLICATION",
+            ReplicationCommand::CreateReplicationSlot => "CREATE_REPLICATION_SLOT",
+            ReplicationCommand::TimelineHistory => "TIMELINE_HISTORY",
+        }
+    }
+}
+
+/// Error for messages other than replication commands in replication mode.
+pub(crate) fn protocol_violation(message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "08P01".to_owned(),
+        message,
+    )))
+}
+
+#[async_trait]
+pub trait ReplicationHandler: Send + Sync {
+    /// Executed on `Query` request arrived in replication mode.
+    ///
+    /// Queries other than replication commands are rejected with SQLSTATE
+    /// `08P01`, otherwise `self.do_replication_command` is called and its
+    /// responses are sent to client.
+    async fn on_replication_command<C>(&self, client: &mut C, query: Query) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let query_string = query.query;
+        let command = ReplicationCommand::parse(&query_string).ok_or_else(|| {
+            protocol_violation(format!(
+                "only replication commands are accepted in replication mode: {query_string}"
+            ))
+        })?;
+
+        client.set_state(PgWireConnectionState::QueryInProgress);
+        let span = query_span(client, &query_string);
+        let resp = self
+            .do_replication_command(client, command, &query_string)
+            .instrument(span)
+            .await?;
+        send_responses(client, resp).await?;
+
+        send_pending_notices(client).await?;
+        client
+            .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                READY_STATUS_IDLE,
+            )))
+            .await?;
+        client.flush().await?;
+        client.set_state(PgWireConnectionState::ReadyForQuery);
+        Ok(())
+    }
+
+    /// Provide your implementation of `command`, `query` is the full command
+    /// string with arguments.
+    ///
+    /// Streaming of `START_REPLICATION` can be done by sending `CopyBoth`
+    /// messages to `client` directly, before returning the final responses.
+    async fn do_replication_command<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        command: ReplicationCommand,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>;
+}
+
+/// Replication handler that rejects all replication commands.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlaceholderReplicationHandler;
+
+#[async_trait]
+impl ReplicationHandler for PlaceholderReplicationHandler {
+    async fn do_replication_command<'a, 'b: 'a, C>(
+        &'b self,
+        _client: &mut C,
+        command: ReplicationCommand,
+        _query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            format!("{} is not supported on this server", command.name()),
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_replication_command() {
+        assert_eq!(
+            Some(ReplicationCommand::IdentifySystem),
+            ReplicationCommand::parse("IDENTIFY_SYSTEM;")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::StartReplication),
+            ReplicationCommand::parse(" start_replication SLOT s1 LOGICAL 0/0")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::CreateReplicationSlot),
+            ReplicationCommand::parse("CREATE_REPLICATION_SLOT s1 LOGICAL pgoutput")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::TimelineHistory),
+            ReplicationCommand::parse("TIMELINE_HISTORY 1")
+        );
+        assert_eq!(None, ReplicationCommand::parse("SELECT 1"));
+        assert_eq!(None, ReplicationCommand::parse(""));
+    }
+}
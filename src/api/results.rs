@@ -1,35 +1,71 @@
+use std::any::Any;
 use std::fmt::Debug;
 use std::sync::Arc;
 
 use bytes::{BufMut, BytesMut};
 use futures::{
-    stream::{BoxStream, StreamExt},
+    stream::{self, BoxStream, StreamExt},
     Stream,
 };
 use postgres_types::{IsNull, Oid, ToSql, Type};
+use tokio::sync::Semaphore;
 
 use crate::{
-    error::{ErrorInfo, PgWireResult},
+    error::{ErrorInfo, PgWireError, PgWireResult},
     messages::{
         data::{DataRow, FieldDescription, RowDescription, FORMAT_CODE_BINARY, FORMAT_CODE_TEXT},
         response::CommandComplete,
     },
-    types::ToSqlText,
+    types::{ExtensionRegistry, ToSqlText, TypeExtension},
 };
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Tag {
     command: String,
     oid: Option<Oid>,
     rows: Option<usize>,
+    /// rows are counted when the result is sent
+    rows_counted: bool,
 }
 
 impl Tag {
+    /// Create tag of `command` as is.
+    ///
+    /// Prefer [`CommandCompleteBuilder`] for commands reporting row count, and
+    /// [`Tag::ddl`] for others, which format the tag the way postgres does.
     pub fn new(command: &str) -> Tag {
         Tag {
             command: command.to_owned(),
             oid: None,
             rows: None,
+            rows_counted: false,
+        }
+    }
+
+    /// Tag of `SELECT` reporting number of rows, like `SELECT 5`, which is
+    /// counted when the `QueryResponse` is sent.
+    pub fn select_auto() -> Tag {
+        Tag::new("SELECT").with_rows_counted()
+    }
+
+    /// Report number of rows of `QueryResponse`, counted when it's sent. Row
+    /// count set by `with_rows` is replaced.
+    pub fn with_rows_counted(mut self) -> Tag {
+        self.rows_counted = true;
+        self
+    }
+
+    /// Command of the tag, like `SELECT`
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Tag completing a result of `rows` data rows
+    pub(crate) fn complete(&self, rows: usize) -> Tag {
+        if self.rows_counted {
+            self.clone().with_rows(rows)
+        } else {
+            self.clone()
         }
     }
 
@@ -42,11 +78,25 @@ impl Tag {
         self.oid = Some(oid);
         self
     }
+
+    /// Tag of a command without row count.
+    ///
+    /// Postgres reports the command name in tag, for example `CREATE TABLE`,
+    /// `DROP INDEX`, `ALTER TABLE`, `CREATE VIEW`, `TRUNCATE TABLE`, `BEGIN`,
+    /// `COMMIT`, `ROLLBACK` and `SET`. The command is upper-cased with words
+    /// separated by a single space, so `"create  table"` becomes
+    /// `CREATE TABLE`.
+    pub fn ddl(command: &str) -> Tag {
+        let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+        Tag::new(&command.to_ascii_uppercase())
+    }
 }
 
 impl From<Tag> for CommandComplete {
     fn from(tag: Tag) -> CommandComplete {
-        let tag_string = if let Some(rows) = tag.rows {
+        // counted tag not sent with a result has no rows
+        let rows = tag.rows.or(tag.rows_counted.then_some(0));
+        let tag_string = if let Some(rows) = rows {
             format!("{} {rows}", tag.command)
         } else {
             tag.command
@@ -55,7 +105,97 @@ impl From<Tag> for CommandComplete {
     }
 }
 
+/// Commands whose tag reports number of rows processed
+const COMMANDS_WITH_ROWS: &[&str] = &[
+    "INSERT", "SELECT", "UPDATE", "DELETE", "MERGE", "COPY", "FETCH", "MOVE",
+];
+
+/// Builder of `Tag` that formats it the way postgres does:
+///
+/// - `INSERT oid rows`, where oid is always 0 unless provided
+/// - `SELECT rows`, `UPDATE rows`, `DELETE rows`, `COPY rows` and etc.
+/// - `CREATE TABLE` and other commands without row count, see [`Tag::ddl`]
+///
+/// ```
+/// use pgwire::api::results::CommandCompleteBuilder;
+///
+/// let tag = CommandCompleteBuilder::new()
+///     .command("INSERT")
+///     .affected_rows(3)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct CommandCompleteBuilder {
+    command: Option<String>,
+    affected_rows: Option<u64>,
+    inserted_oid: Option<u32>,
+}
+
+impl CommandCompleteBuilder {
+    pub fn new() -> CommandCompleteBuilder {
+        CommandCompleteBuilder::default()
+    }
+
+    /// Command name, like `INSERT` or `CREATE TABLE`
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = Some(command.to_owned());
+        self
+    }
+
+    /// Number of rows inserted, updated, deleted, returned or copied
+    pub fn affected_rows(mut self, rows: u64) -> Self {
+        self.affected_rows = Some(rows);
+        self
+    }
+
+    /// Oid of inserted row, only valid for `INSERT`
+    pub fn inserted_oid(mut self, oid: Option<u32>) -> Self {
+        self.inserted_oid = oid;
+        self
+    }
+
+    /// Validate fields and build the `Tag`.
+    pub fn build(self) -> PgWireResult<Tag> {
+        let command = self
+            .command
+            .filter(|c| !c.trim().is_empty())
+            .ok_or_else(|| PgWireError::InvalidCommandTag("command is required".to_owned()))?;
+        let is_insert = command.eq_ignore_ascii_case("INSERT");
+        let with_rows = COMMANDS_WITH_ROWS
+            .iter()
+            .any(|c| command.eq_ignore_ascii_case(c));
+
+        if self.inserted_oid.is_some() && !is_insert {
+            return Err(PgWireError::InvalidCommandTag(format!(
+                "inserted oid is not allowed for {command}"
+            )));
+        }
+        if self.affected_rows.is_some() && !with_rows {
+            return Err(PgWireError::InvalidCommandTag(format!(
+                "row count is not allowed for {command}"
+            )));
+        }
+
+        // oid of `Tag` is not part of the tag string, so it's formatted into
+        // the command here
+        let mut tag = if is_insert {
+            let oid = self.inserted_oid.unwrap_or(0);
+            Tag::new(&format!("{command} {oid}")).with_oid(oid)
+        } else {
+            Tag::new(&command)
+        };
+        if let Some(rows) = self.affected_rows {
+            tag = tag.with_rows(rows as usize);
+        } else if with_rows {
+            tag = tag.with_rows(0);
+        }
+        Ok(tag)
+    }
+}
+
 /// Describe encoding of a data field.
+#[non_exhaustive]
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum FieldFormat {
     Text,
@@ -134,34 +274,300 @@ pub(crate) fn into_row_description(fields: &[FieldInfo]) -> RowDescription {
     RowDescription::new(fields.iter().map(Into::into).collect())
 }
 
+/// A column present in only one of the compared schemas
+#[non_exhaustive]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ColumnChange {
+    /// index of the column in its schema
+    pub position: usize,
+    pub field: FieldInfo,
+}
+
+/// A column present in both compared schemas, but with different definition
+/// or position
+#[non_exhaustive]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ColumnModification {
+    pub old_position: usize,
+    pub new_position: usize,
+    pub old: FieldInfo,
+    pub new: FieldInfo,
+}
+
+/// Difference between two result set schemas, returned by
+/// `RowDescription::diff`
+#[non_exhaustive]
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct SchemaDiff {
+    pub added: Vec<ColumnChange>,
+    pub removed: Vec<ColumnChange>,
+    pub modified: Vec<ColumnModification>,
+}
+
+impl RowDescription {
+    /// Compare two result set schemas.
+    ///
+    /// Columns are matched by name, in order of appearance when names are
+    /// duplicated. A matched column is modified if its type, format, source
+    /// table/column or position changed. Returns `None` if schemas are
+    /// identical.
+    pub fn diff(old: &[FieldInfo], new: &[FieldInfo]) -> Option<SchemaDiff> {
+        let mut diff = SchemaDiff::default();
+        let mut matched = vec![false; old.len()];
+
+        for (new_position, new_field) in new.iter().enumerate() {
+            let old_position = old
+                .iter()
+                .enumerate()
+                .position(|(i, f)| !matched[i] && f.name == new_field.name);
+
+            if let Some(old_position) = old_position {
+                matched[old_position] = true;
+                let old_field = &old[old_position];
+                if old_position != new_position || old_field != new_field {
+                    diff.modified.push(ColumnModification {
+                        old_position,
+                        new_position,
+                        old: old_field.clone(),
+                        new: new_field.clone(),
+                    });
+                }
+            } else {
+                diff.added.push(ColumnChange {
+                    position: new_position,
+                    field: new_field.clone(),
+                });
+            }
+        }
+
+        for (position, field) in old.iter().enumerate() {
+            if !matched[position] {
+                diff.removed.push(ColumnChange {
+                    position,
+                    field: field.clone(),
+                });
+            }
+        }
+
+        if diff == SchemaDiff::default() {
+            None
+        } else {
+            Some(diff)
+        }
+    }
+}
+
 pub struct QueryResponse<'a> {
-    command_tag: String,
+    tag: Tag,
     row_schema: Arc<Vec<FieldInfo>>,
-    data_rows: BoxStream<'a, PgWireResult<DataRow>>,
+    data_rows: DataRows<'a>,
+    backpressure: Option<Backpressure>,
+    error_policy: DataflowErrorPolicy,
+}
+
+/// Row stream of `QueryResponse`
+enum DataRows<'a> {
+    /// Rows that may borrow the portal or handler
+    Borrowed(BoxStream<'a, PgWireResult<DataRow>>),
+    /// Rows that borrow nothing, so they can be kept by a suspended portal
+    Owned(BoxStream<'static, PgWireResult<DataRow>>),
+}
+
+/// Take a permit of `semaphore` before pulling each row of `data_rows`
+fn with_permits(
+    data_rows: BoxStream<'_, PgWireResult<DataRow>>,
+    semaphore: Arc<Semaphore>,
+) -> BoxStream<'_, PgWireResult<DataRow>> {
+    stream::unfold(
+        (data_rows, semaphore),
+        |(mut data_rows, semaphore)| async move {
+            semaphore.acquire().await.ok()?.forget();
+            let row = data_rows.next().await?;
+            Some((row, (data_rows, semaphore)))
+        },
+    )
+    .boxed()
+}
+
+/// How rows that failed to produce, like `DataRowEncoder` errors, are handled
+/// when sending a `QueryResponse`.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataflowErrorPolicy {
+    /// Terminate the result with `ErrorResponse` of the first failed row
+    #[default]
+    FailFast,
+    /// Skip failed rows with a `NoticeResponse` telling their position,
+    /// until more than `max_errors` rows failed. The number of skipped rows is
+    /// reported in a notice before `CommandComplete`.
+    SkipAndContinue { max_errors: u64 },
+}
+
+/// Failed rows of a result, counted against `DataflowErrorPolicy`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RowErrors {
+    policy: DataflowErrorPolicy,
+    /// rows taken from the stream so far, including failed ones
+    position: u64,
+    skipped: u64,
+}
+
+impl RowErrors {
+    pub(crate) fn new(policy: DataflowErrorPolicy) -> RowErrors {
+        RowErrors {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Count the row. Returns the row if it's ok, the notice to send if the
+    /// failed row is skipped, or the error to terminate the result.
+    pub(crate) fn check(
+        &mut self,
+        row: PgWireResult<DataRow>,
+    ) -> PgWireResult<Result<DataRow, ErrorInfo>> {
+        self.position += 1;
+        let error = match row {
+            Ok(row) => return Ok(Ok(row)),
+            Err(e) => e,
+        };
+
+        match self.policy {
+            DataflowErrorPolicy::SkipAndContinue { max_errors } if self.skipped < max_errors => {
+                self.skipped += 1;
+                let message = match &error {
+                    PgWireError::UserError(info) => info.message.clone(),
+                    e => e.to_string(),
+                };
+                Ok(Err(ErrorInfo::new(
+                    "WARNING".to_owned(),
+                    error.sqlstate().to_owned(),
+                    format!("row {} skipped: {}", self.position, message),
+                )))
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Notice of skipped rows, if any
+    pub(crate) fn summary(&self) -> Option<ErrorInfo> {
+        (self.skipped > 0).then(|| {
+            ErrorInfo::new(
+                "WARNING".to_owned(),
+                "01000".to_owned(),
+                format!("{} rows skipped due to errors", self.skipped),
+            )
+        })
+    }
+}
+
+/// Permits for rows that are produced but not yet flushed to client
+#[derive(Debug, Clone)]
+pub(crate) struct Backpressure {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+}
+
+impl Backpressure {
+    /// All permits are taken by rows not yet flushed
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.semaphore.available_permits() == 0
+    }
+
+    /// Return taken permits. Caller must flush buffered rows to client first.
+    pub(crate) fn release(&self) {
+        self.semaphore
+            .add_permits(self.permits - self.semaphore.available_permits());
+    }
 }
 
 impl<'a> QueryResponse<'a> {
     /// Create `QueryResponse` from column schemas and stream of data row.
-    /// Sets "SELECT" as the command tag.
+    /// Sets `Tag::select_auto` as the command tag, so number of rows is
+    /// reported like `SELECT 5`.
     pub fn new<S>(field_defs: Arc<Vec<FieldInfo>>, row_stream: S) -> QueryResponse<'a>
     where
         S: Stream<Item = PgWireResult<DataRow>> + Send + Unpin + 'a,
     {
         QueryResponse {
-            command_tag: "SELECT".to_owned(),
+            tag: Tag::select_auto(),
             row_schema: field_defs,
-            data_rows: row_stream.boxed(),
+            data_rows: DataRows::Borrowed(row_stream.boxed()),
+            backpressure: None,
+            error_policy: DataflowErrorPolicy::FailFast,
         }
     }
 
+    /// Create `QueryResponse` from column schemas and stream of data row that
+    /// borrows nothing.
+    ///
+    /// Use this for results of `Execute` that may stop at `max_rows`: the
+    /// portal is suspended with the remaining stream, and resumed by next
+    /// `Execute`. Clone what the stream needs from the portal, like
+    /// parameters, instead of borrowing them. Rows of `QueryResponse::new`
+    /// can't outlive the `Execute`, and suspending them fails with
+    /// `PgWireError::PortalNotSuspendable`.
+    pub fn new_owned<S>(field_defs: Arc<Vec<FieldInfo>>, row_stream: S) -> QueryResponse<'a>
+    where
+        S: Stream<Item = PgWireResult<DataRow>> + Send + Unpin + 'static,
+    {
+        QueryResponse {
+            data_rows: DataRows::Owned(row_stream.boxed()),
+            ..QueryResponse::new(field_defs, stream::empty())
+        }
+    }
+
+    /// Bound the number of rows read ahead of the client.
+    ///
+    /// Each row pulled from the row stream takes one of `permits`. When all
+    /// permits are taken, the row stream is not polled until buffered rows
+    /// are flushed to the client, so a fast producer won't buffer the whole
+    /// result in memory when the client reads slowly.
+    pub fn with_backpressure(mut self, permits: usize) -> QueryResponse<'a> {
+        let semaphore = Arc::new(Semaphore::new(permits));
+        self.data_rows = match self.data_rows {
+            DataRows::Borrowed(data_rows) => {
+                DataRows::Borrowed(with_permits(data_rows, semaphore.clone()))
+            }
+            DataRows::Owned(data_rows) => {
+                DataRows::Owned(with_permits(data_rows, semaphore.clone()))
+            }
+        };
+        self.backpressure = Some(Backpressure { semaphore, permits });
+        self
+    }
+
+    /// Set how failed rows are handled, `FailFast` by default.
+    pub fn with_error_policy(mut self, policy: DataflowErrorPolicy) -> QueryResponse<'a> {
+        self.error_policy = policy;
+        self
+    }
+
+    pub fn error_policy(&self) -> DataflowErrorPolicy {
+        self.error_policy
+    }
+
     /// Get the command tag
     pub fn command_tag(&self) -> &str {
-        &self.command_tag
+        self.tag.command()
     }
 
-    /// Set the command tag
+    /// Set the command tag, which reports number of rows counted when the
+    /// response is sent.
     pub fn set_command_tag(&mut self, command_tag: &str) {
-        command_tag.clone_into(&mut self.command_tag);
+        self.tag = Tag::new(command_tag).with_rows_counted();
+    }
+
+    /// Get the tag sent in `CommandComplete`
+    pub fn tag(&self) -> &Tag {
+        &self.tag
+    }
+
+    /// Set the tag sent in `CommandComplete`. Use `Tag::with_rows_counted`
+    /// to report number of rows sent.
+    pub fn with_tag(mut self, tag: Tag) -> QueryResponse<'a> {
+        self.tag = tag;
+        self
     }
 
     /// Get schema of columns
@@ -169,9 +575,29 @@ impl<'a> QueryResponse<'a> {
         self.row_schema.clone()
     }
 
+    pub(crate) fn backpressure(&self) -> Option<Backpressure> {
+        self.backpressure.clone()
+    }
+
     /// Get owned `BoxStream` of data rows
     pub fn data_rows(self) -> BoxStream<'a, PgWireResult<DataRow>> {
-        self.data_rows
+        match self.data_rows {
+            DataRows::Borrowed(data_rows) => data_rows,
+            DataRows::Owned(data_rows) => data_rows,
+        }
+    }
+
+    /// Get data rows as `'static` stream if it's created by `new_owned`, or
+    /// the borrowed stream otherwise.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn owned_data_rows(
+        self,
+    ) -> Result<BoxStream<'static, PgWireResult<DataRow>>, BoxStream<'a, PgWireResult<DataRow>>>
+    {
+        match self.data_rows {
+            DataRows::Borrowed(data_rows) => Err(data_rows),
+            DataRows::Owned(data_rows) => Ok(data_rows),
+        }
     }
 }
 
@@ -179,6 +605,7 @@ pub struct DataRowEncoder {
     schema: Arc<Vec<FieldInfo>>,
     row_buffer: BytesMut,
     col_index: usize,
+    extensions: Option<Arc<ExtensionRegistry>>,
 }
 
 impl DataRowEncoder {
@@ -188,13 +615,28 @@ impl DataRowEncoder {
             schema: fields,
             row_buffer: BytesMut::with_capacity(128),
             col_index: 0,
+            extensions: None,
         }
     }
 
+    /// Use `ExtensionRegistry` for encoding extension types
+    ///
+    /// Fields of types with oid unknown to pgwire are encoded by the
+    /// extension registered for their type name, see
+    /// `encode_field_with_type_and_format`.
+    pub fn with_extensions(mut self, extensions: Arc<ExtensionRegistry>) -> DataRowEncoder {
+        self.extensions = Some(extensions);
+        self
+    }
+
     /// Encode value with custom type and format
     ///
     /// This encode function ignores data type and format information from
     /// schema of this encoder.
+    ///
+    /// If `data_type` is not a built-in type and an extension is registered
+    /// for its name, the value is handed to the extension as a `String` of
+    /// its text representation.
     pub fn encode_field_with_type_and_format<T>(
         &mut self,
         value: &T,
@@ -204,15 +646,35 @@ impl DataRowEncoder {
     where
         T: ToSql + ToSqlText + Sized,
     {
+        if let Some(extension) = self.extension_for(data_type) {
+            let mut text = BytesMut::new();
+            let value = match value.to_sql_text(data_type, &mut text)? {
+                IsNull::Yes => None,
+                IsNull::No => Some(
+                    String::from_utf8(text.to_vec())
+                        .map_err(|e| PgWireError::ApiError(Box::new(e)))?,
+                ),
+            };
+            let value = value.as_ref().map(|value| value as &dyn Any);
+            return self.encode_with_extension(extension.as_ref(), value, format);
+        }
+
         // remember the position of the 4-byte length field
         let prev_index = self.row_buffer.len();
         // write value length as -1 ahead of time
         self.row_buffer.put_i32(-1);
 
         let is_null = if format == FieldFormat::Text {
-            value.to_sql_text(data_type, &mut self.row_buffer)?
+            value.to_sql_text(data_type, &mut self.row_buffer)
         } else {
-            value.to_sql(data_type, &mut self.row_buffer)?
+            value.to_sql(data_type, &mut self.row_buffer)
+        };
+        let is_null = match is_null {
+            Ok(is_null) => is_null,
+            Err(e) => {
+                self.row_buffer.truncate(prev_index);
+                return Err(e.into());
+            }
         };
 
         if let IsNull::No = is_null {
@@ -239,6 +701,65 @@ impl DataRowEncoder {
         self.encode_field_with_type_and_format(value, &data_type, format)
     }
 
+    /// Encode value of an extension type, using the `TypeExtension` registered
+    /// for the type name defined by schema
+    ///
+    /// Panic when encoding more columns than provided as schema.
+    pub fn encode_extension_field(&mut self, value: &dyn Any) -> PgWireResult<()> {
+        let data_type = self.schema[self.col_index].datatype();
+        let format = self.schema[self.col_index].format();
+        let extension = self
+            .extensions
+            .as_ref()
+            .and_then(|registry| registry.get(data_type.name()))
+            .ok_or_else(|| PgWireError::UnknownTypeExtension(data_type.name().to_owned()))?
+            .clone();
+
+        self.encode_with_extension(extension.as_ref(), Some(value), format)
+    }
+
+    /// Registered extension for a type that is not built-in
+    fn extension_for(&self, data_type: &Type) -> Option<Arc<dyn TypeExtension>> {
+        if Type::from_oid(data_type.oid()).is_some() {
+            return None;
+        }
+        self.extensions
+            .as_ref()
+            .and_then(|registry| registry.get(data_type.name()))
+            .cloned()
+    }
+
+    fn encode_with_extension(
+        &mut self,
+        extension: &dyn TypeExtension,
+        value: Option<&dyn Any>,
+        format: FieldFormat,
+    ) -> PgWireResult<()> {
+        let prev_index = self.row_buffer.len();
+        self.row_buffer.put_i32(-1);
+
+        if let Some(value) = value {
+            let result = if format == FieldFormat::Text {
+                extension.encode_text(value, &mut self.row_buffer)
+            } else {
+                extension.encode_binary(value, &mut self.row_buffer)
+            };
+            if let Err(e) = result {
+                // drop the length placeholder and partially written value
+                self.row_buffer.truncate(prev_index);
+                return Err(e);
+            }
+
+            let value_length = self.row_buffer.len() - prev_index - 4;
+            let mut length_bytes = &mut self.row_buffer[prev_index..(prev_index + 4)];
+            length_bytes.put_i32(value_length as i32);
+        }
+
+        self.col_index += 1;
+
+        Ok(())
+    }
+
     pub fn finish(self) -> PgWireResult<DataRow> {
         Ok(DataRow::new(self.row_buffer, self.col_index as i16))
     }
@@ -284,9 +805,10 @@ impl DescribeResponse for DescribeStatementResponse {
         }
     }
 
-    /// Return true if the `DescribeStatementResponse` is empty/nodata
+    /// Return true if the statement returns no rows. Parameters are
+    /// described separately so they don't count.
     fn is_no_data(&self) -> bool {
-        self.parameters.is_empty() && self.fields.is_empty()
+        self.fields.is_empty()
     }
 }
 
@@ -323,6 +845,7 @@ impl DescribeResponse for DescribePortalResponse {
 /// * Query: the response contains data rows
 /// * Execution: response for ddl/dml execution
 /// * Error: error response
+#[non_exhaustive]
 pub enum Response<'a> {
     EmptyQuery,
     Query(QueryResponse<'a>),
@@ -334,8 +857,100 @@ pub enum Response<'a> {
 mod test {
     use std::time::SystemTime;
 
+    use bytes::Buf;
+    use futures::FutureExt;
+    use postgres_types::FromSql;
+
     use super::*;
 
+    fn tag_string(builder: CommandCompleteBuilder) -> String {
+        CommandComplete::from(builder.build().unwrap()).tag
+    }
+
+    #[test]
+    fn test_command_complete_builder() {
+        let builder = CommandCompleteBuilder::new;
+        assert_eq!(
+            "INSERT 0 3",
+            tag_string(builder().command("INSERT").affected_rows(3))
+        );
+        assert_eq!(
+            "INSERT 16384 1",
+            tag_string(
+                builder()
+                    .command("INSERT")
+                    .inserted_oid(Some(16384))
+                    .affected_rows(1)
+            )
+        );
+        assert_eq!(
+            "UPDATE 2",
+            tag_string(builder().command("UPDATE").affected_rows(2))
+        );
+        assert_eq!("SELECT 0", tag_string(builder().command("SELECT")));
+        assert_eq!(
+            "DELETE 5",
+            tag_string(builder().command("DELETE").affected_rows(5))
+        );
+        assert_eq!(
+            "COPY 10",
+            tag_string(builder().command("COPY").affected_rows(10))
+        );
+        assert_eq!(
+            "CREATE TABLE",
+            tag_string(builder().command("CREATE TABLE"))
+        );
+
+        assert!(matches!(
+            builder().command("UPDATE").inserted_oid(Some(1)).build(),
+            Err(PgWireError::InvalidCommandTag(_))
+        ));
+        assert!(matches!(
+            builder().command("DROP TABLE").affected_rows(1).build(),
+            Err(PgWireError::InvalidCommandTag(_))
+        ));
+        assert!(matches!(
+            builder().affected_rows(1).build(),
+            Err(PgWireError::InvalidCommandTag(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_response_backpressure() {
+        let rows = (0..5).map(|_| Ok(DataRow::new(BytesMut::new(), 0)));
+        let response =
+            QueryResponse::new(Arc::new(vec![]), stream::iter(rows)).with_backpressure(2);
+        let backpressure = response.backpressure().unwrap();
+        let mut data_rows = response.data_rows();
+
+        assert!(data_rows.next().now_or_never().flatten().is_some());
+        assert!(!backpressure.is_exhausted());
+        assert!(data_rows.next().now_or_never().flatten().is_some());
+        assert!(backpressure.is_exhausted());
+        // no permit left until rows are flushed
+        assert!(data_rows.next().now_or_never().is_none());
+
+        backpressure.release();
+        assert!(data_rows.next().now_or_never().flatten().is_some());
+        assert!(data_rows.next().now_or_never().flatten().is_some());
+        backpressure.release();
+        assert!(data_rows.next().now_or_never().flatten().is_some());
+        assert!(data_rows.next().now_or_never().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ddl_tag() {
+        for (command, expected) in [
+            ("CREATE TABLE", "CREATE TABLE"),
+            ("drop  index", "DROP INDEX"),
+            (" alter table\n", "ALTER TABLE"),
+            ("BEGIN", "BEGIN"),
+            ("rollback", "ROLLBACK"),
+        ] {
+            assert_eq!(expected, CommandComplete::from(Tag::ddl(command)).tag);
+        }
+    }
+
     #[test]
     fn test_command_complete() {
         let tag = Tag::new("INSERT").with_oid(0).with_rows(100);
@@ -344,6 +959,21 @@ mod test {
         assert_eq!(cc.tag, "INSERT 100");
     }
 
+    #[test]
+    fn test_counted_tag() {
+        let tag = Tag::select_auto();
+        assert_eq!("SELECT", tag.command());
+        assert_eq!("SELECT 5", CommandComplete::from(tag.complete(5)).tag);
+        assert_eq!("SELECT 0", CommandComplete::from(tag).tag);
+
+        let tag = Tag::new("FETCH").with_rows(10).with_rows_counted();
+        assert_eq!("FETCH 2", CommandComplete::from(tag.complete(2)).tag);
+
+        // tag without row count is sent as is
+        let tag = Tag::new("SHOW");
+        assert_eq!("SHOW", CommandComplete::from(tag.complete(1)).tag);
+    }
+
     #[test]
     fn test_data_row_encoder() {
         let schema = Arc::new(vec![
@@ -370,4 +1000,167 @@ mod test {
         let _ = now.to_sql_text(&Type::TIMESTAMP, &mut expected);
         assert_eq!(row.data, expected);
     }
+
+    #[test]
+    fn test_data_row_encoder_binary_array() {
+        let schema = Arc::new(vec![
+            FieldInfo::new(
+                "ids".into(),
+                None,
+                None,
+                Type::INT4_ARRAY,
+                FieldFormat::Binary,
+            ),
+            FieldInfo::new(
+                "tags".into(),
+                None,
+                None,
+                Type::TEXT_ARRAY,
+                FieldFormat::Binary,
+            ),
+        ]);
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&vec![1, 2, 3]).unwrap();
+        encoder.encode_field(&[Some("a"), None]).unwrap();
+        let row = encoder.finish().unwrap();
+
+        let mut data = &row.data[..];
+        let mut field = || {
+            let len = data.get_i32() as usize;
+            let (value, rest) = data.split_at(len);
+            data = rest;
+            value
+        };
+
+        let ids = field();
+        // 1 dimension, no null, element type, then 3 elements from index 1
+        assert_eq!(&[0, 0, 0, 1], &ids[..4]);
+        assert_eq!(&[0, 0, 0, 0], &ids[4..8]);
+        assert_eq!(&Type::INT4.oid().to_be_bytes(), &ids[8..12]);
+        assert_eq!(&[0, 0, 0, 3, 0, 0, 0, 1], &ids[12..20]);
+        assert_eq!(
+            vec![1, 2, 3],
+            Vec::<i32>::from_sql(&Type::INT4_ARRAY, ids).unwrap()
+        );
+
+        let tags = field();
+        // has null, null element is encoded with length -1
+        assert_eq!(&[0, 0, 0, 1], &tags[4..8]);
+        assert_eq!(&Type::TEXT.oid().to_be_bytes(), &tags[8..12]);
+        assert_eq!(&(-1i32).to_be_bytes(), &tags[tags.len() - 4..]);
+        assert_eq!(
+            vec![Some("a".to_owned()), None],
+            Vec::<Option<String>>::from_sql(&Type::TEXT_ARRAY, tags).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_schema_diff() {
+        let id = FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Text);
+        let name = FieldInfo::new("name".into(), None, None, Type::VARCHAR, FieldFormat::Text);
+        let ts = FieldInfo::new("ts".into(), None, None, Type::TIMESTAMP, FieldFormat::Text);
+        let id8 = FieldInfo::new("id".into(), None, None, Type::INT8, FieldFormat::Text);
+
+        let old = vec![id.clone(), name.clone()];
+        assert!(RowDescription::diff(&old, &old).is_none());
+
+        let diff = RowDescription::diff(&old, &[id8.clone(), ts.clone()]).unwrap();
+        assert_eq!(
+            vec![ColumnChange {
+                position: 1,
+                field: ts
+            }],
+            diff.added
+        );
+        assert_eq!(
+            vec![ColumnChange {
+                position: 1,
+                field: name.clone()
+            }],
+            diff.removed
+        );
+        assert_eq!(1, diff.modified.len());
+        assert_eq!(Type::INT8, *diff.modified[0].new.datatype());
+
+        // reordering is a modification
+        let diff = RowDescription::diff(&old, &[name, id]).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(2, diff.modified.len());
+    }
+
+    #[test]
+    fn test_data_row_encoder_extension() {
+        let ltree = Type::new(
+            "ltree".to_owned(),
+            16385,
+            postgres_types::Kind::Simple,
+            "public".to_owned(),
+        );
+        let schema = Arc::new(vec![
+            FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Binary),
+            FieldInfo::new("path".into(), None, None, ltree, FieldFormat::Binary),
+        ]);
+
+        let mut encoder = DataRowEncoder::new(schema.clone());
+        encoder.encode_field(&1i32).unwrap();
+        assert!(encoder.encode_extension_field(&"Top.Science").is_err());
+
+        let mut registry = ExtensionRegistry::new();
+        registry.register(Arc::new(crate::types::LtreeExtension::ltree()));
+        let registry = Arc::new(registry);
+        let mut encoder = DataRowEncoder::new(schema.clone()).with_extensions(registry.clone());
+        encoder.encode_field(&1i32).unwrap();
+        encoder.encode_extension_field(&"Top.Science").unwrap();
+        let row = encoder.finish().unwrap();
+
+        let mut expected = BytesMut::new();
+        expected.put_i32(4);
+        expected.put_i32(1);
+        expected.put_i32(12);
+        expected.put_slice(b"\x01Top.Science");
+        assert_eq!(row.data, expected);
+
+        // failed value leaves no bytes behind
+        let mut encoder = DataRowEncoder::new(schema.clone()).with_extensions(registry.clone());
+        encoder.encode_field(&1i32).unwrap();
+        assert!(encoder.encode_extension_field(&1i32).is_err());
+        encoder.encode_extension_field(&"Top.Science").unwrap();
+        assert_eq!(encoder.finish().unwrap().data, expected);
+
+        // types unknown to pgwire are dispatched to the registry
+        let mut encoder = DataRowEncoder::new(schema).with_extensions(registry);
+        encoder.encode_field(&1i32).unwrap();
+        encoder.encode_field(&"Top.Science").unwrap();
+        assert_eq!(encoder.finish().unwrap().data, expected);
+    }
+
+    #[test]
+    fn test_row_errors() {
+        let failed = || -> PgWireResult<DataRow> {
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "22P02".to_owned(),
+                "invalid input".to_owned(),
+            ))))
+        };
+
+        let mut errors = RowErrors::default();
+        assert!(errors.check(Ok(DataRow::default())).unwrap().is_ok());
+        assert!(errors.check(failed()).is_err());
+
+        let mut errors = RowErrors::new(DataflowErrorPolicy::SkipAndContinue { max_errors: 2 });
+        assert!(errors.check(Ok(DataRow::default())).unwrap().is_ok());
+        let notice = errors.check(failed()).unwrap().unwrap_err();
+        assert_eq!("22P02", notice.code);
+        assert_eq!("row 2 skipped: invalid input", notice.message);
+        assert!(errors.check(failed()).unwrap().is_err());
+        assert_eq!(
+            "2 rows skipped due to errors",
+            errors.summary().unwrap().message
+        );
+        // the third failed row exceeds max_errors
+        assert!(errors.check(failed()).is_err());
+
+        assert!(RowErrors::default().summary().is_none());
+    }
 }
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
 use postgres_types::Type;
@@ -6,10 +7,10 @@ use postgres_types::Type;
 use crate::error::PgWireResult;
 use crate::messages::extendedquery::Parse;
 
-use super::DEFAULT_NAME;
+use super::{is_unnamed, DEFAULT_NAME, UNNAMED_DISPLAY_NAME};
 
 #[non_exhaustive]
-#[derive(Debug, Default, new)]
+#[derive(Debug, new)]
 pub struct StoredStatement<S> {
     /// name of the statement
     pub id: String,
@@ -18,9 +19,47 @@ pub struct StoredStatement<S> {
     /// type ids of query parameters, can be empty if frontend asks backend for
     /// type inference
     pub parameter_types: Vec<Type>,
+    /// original query string
+    #[new(default)]
+    pub query: String,
+    /// time when the statement is parsed
+    #[new(value = "Instant::now()")]
+    pub created_at: Instant,
+}
+
+impl<S: Default> Default for StoredStatement<S> {
+    fn default() -> Self {
+        StoredStatement::new(String::default(), S::default(), Vec::default())
+    }
 }
 
 impl<S> StoredStatement<S> {
+    /// Test if this is a named statement, rather than the unnamed one.
+    pub fn is_named(&self) -> bool {
+        !is_unnamed(&self.id)
+    }
+
+    /// Name of the statement for display, `(unnamed)` for the unnamed
+    /// statement.
+    pub fn effective_name(&self) -> &str {
+        if self.is_named() {
+            &self.id
+        } else {
+            UNNAMED_DISPLAY_NAME
+        }
+    }
+
+    /// Replace parameter types, for example with types inferred from the
+    /// query when frontend didn't specify them in `Parse`.
+    pub fn set_parameter_types(&mut self, types: Vec<Type>) {
+        self.parameter_types = types;
+    }
+
+    /// Mutable access to parameter types.
+    pub fn parameter_types_mut(&mut self) -> &mut Vec<Type> {
+        &mut self.parameter_types
+    }
+
     pub(crate) async fn parse<Q>(parse: &Parse, parser: Q) -> PgWireResult<StoredStatement<S>>
     where
         Q: QueryParser<Statement = S>,
@@ -31,17 +70,36 @@ impl<S> StoredStatement<S> {
             .map(|oid| Type::from_oid(*oid).unwrap_or(Type::UNKNOWN))
             .collect::<Vec<Type>>();
         let statement = parser.parse_sql(&parse.query, &types).await?;
+        let inferred = parser.infer_parameter_types(&statement, &types)?;
         Ok(StoredStatement {
             id: parse
                 .name
                 .clone()
                 .unwrap_or_else(|| DEFAULT_NAME.to_owned()),
             statement,
-            parameter_types: types,
+            parameter_types: merge_parameter_types(&types, inferred),
+            query: parse.query.clone(),
+            created_at: Instant::now(),
         })
     }
 }
 
+/// Keep types specified by client, and fill the unspecified ones, which are
+/// omitted or `unknown`, with inferred types.
+fn merge_parameter_types(specified: &[Type], inferred: Vec<Type>) -> Vec<Type> {
+    let len = specified.len().max(inferred.len());
+    let mut inferred = inferred.into_iter();
+    (0..len)
+        .map(|idx| {
+            let inferred = inferred.next().unwrap_or(Type::UNKNOWN);
+            match specified.get(idx) {
+                Some(ty) if *ty != Type::UNKNOWN => ty.clone(),
+                _ => inferred,
+            }
+        })
+        .collect()
+}
+
 /// Trait for sql parser. The parser transforms string query into its statement
 /// type.
 #[async_trait]
@@ -49,6 +107,23 @@ pub trait QueryParser {
     type Statement;
 
     async fn parse_sql(&self, sql: &str, types: &[Type]) -> PgWireResult<Self::Statement>;
+
+    /// Infer types of all parameters in `statement`, which are returned to
+    /// client in `ParameterDescription`.
+    ///
+    /// `types` are from `Parse`, where unspecified ones are `unknown` and
+    /// trailing ones can be omitted. Types specified by client always take
+    /// precedence over the result. The default implementation infers nothing.
+    ///
+    /// Inference that needs the client session can be done in
+    /// `ExtendedQueryHandler::do_parse` instead.
+    fn infer_parameter_types(
+        &self,
+        _statement: &Self::Statement,
+        types: &[Type],
+    ) -> PgWireResult<Vec<Type>> {
+        Ok(types.to_vec())
+    }
 }
 
 #[async_trait]
@@ -61,6 +136,14 @@ where
     async fn parse_sql(&self, sql: &str, types: &[Type]) -> PgWireResult<Self::Statement> {
         (**self).parse_sql(sql, types).await
     }
+
+    fn infer_parameter_types(
+        &self,
+        statement: &Self::Statement,
+        types: &[Type],
+    ) -> PgWireResult<Vec<Type>> {
+        (**self).infer_parameter_types(statement, types)
+    }
 }
 
 /// A demo parser implementation. Never use it in serious application.
@@ -75,3 +158,49 @@ impl QueryParser for NoopQueryParser {
         Ok(sql.to_owned())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Infers `int4` for every `$n` placeholder
+    struct Int4QueryParser;
+
+    #[async_trait]
+    impl QueryParser for Int4QueryParser {
+        type Statement = String;
+
+        async fn parse_sql(&self, sql: &str, _types: &[Type]) -> PgWireResult<Self::Statement> {
+            Ok(sql.to_owned())
+        }
+
+        fn infer_parameter_types(
+            &self,
+            statement: &Self::Statement,
+            _types: &[Type],
+        ) -> PgWireResult<Vec<Type>> {
+            Ok(vec![Type::INT4; statement.matches('$').count()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_infer_parameter_types() {
+        let parse = Parse::new(
+            None,
+            "SELECT $1, $2, $3".to_owned(),
+            vec![0, Type::TEXT.oid()],
+        );
+        let stmt = StoredStatement::parse(&parse, Int4QueryParser)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![Type::INT4, Type::TEXT, Type::INT4],
+            stmt.parameter_types
+        );
+
+        let stmt = StoredStatement::parse(&parse, NoopQueryParser)
+            .await
+            .unwrap();
+        assert_eq!(vec![Type::UNKNOWN, Type::TEXT], stmt.parameter_types);
+    }
+}
@@ -1,9 +1,30 @@
 use std::collections::BTreeMap;
-use std::sync::{Arc, RwLock};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 
-use super::portal::Portal;
+use super::is_unnamed;
+use super::portal::{Portal, SuspendedPortal};
 use super::stmt::StoredStatement;
 
+/// Summary of a prepared statement in `PortalStore`, similar to a row of
+/// `pg_prepared_statements` in postgres.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct StatementSummary {
+    /// name of the statement
+    pub name: String,
+    /// query string of the statement
+    pub query: String,
+    /// names of parameter types
+    pub parameter_types: Vec<String>,
+    /// number of portals bound to this statement
+    pub portal_count: usize,
+    /// time when the statement is parsed
+    pub created_at: Instant,
+}
+
 pub trait PortalStore: Send + Sync {
     type Statement;
 
@@ -18,14 +39,157 @@ pub trait PortalStore: Send + Sync {
     fn rm_portal(&self, name: &str);
 
     fn get_portal(&self, name: &str) -> Option<Arc<Portal<Self::Statement>>>;
+
+    /// Keep rows left by an `Execute` with row limit, so the next `Execute`
+    /// on the portal resumes from them.
+    ///
+    /// The default implementation drops them, and the query is executed again
+    /// by next `Execute`.
+    fn put_suspended_portal(&self, _portal: SuspendedPortal<Self::Statement>) {}
+
+    /// Remove and return suspended state of portal `name`.
+    fn take_suspended_portal(&self, _name: &str) -> Option<SuspendedPortal<Self::Statement>> {
+        None
+    }
+
+    /// List statements currently stored, for inspection and debugging.
+    ///
+    /// The default implementation lists nothing.
+    fn list_statements(&self) -> Vec<StatementSummary> {
+        Vec::new()
+    }
+}
+
+/// Key of statement or portal `name`. The unnamed one is keyed by empty
+/// string, whether it's named empty or `DEFAULT_NAME`.
+fn store_key(name: &str) -> &str {
+    if is_unnamed(name) {
+        ""
+    } else {
+        name
+    }
+}
+
+/// Counters of `MemPortalStore`
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PortalStoreStats {
+    /// number of statements currently stored
+    pub statements: usize,
+    /// number of portals currently stored
+    pub portals: usize,
+    /// number of statements and portals evicted for capacity
+    pub evictions: u64,
+}
+
+/// Map of named entries that tracks recency of access, so the least recently
+/// used one can be evicted.
+#[derive(Debug)]
+struct LruMap<V> {
+    entries: BTreeMap<String, (V, AtomicU64)>,
+    tick: AtomicU64,
+}
+
+impl<V> Default for LruMap<V> {
+    fn default() -> Self {
+        LruMap {
+            entries: BTreeMap::new(),
+            tick: AtomicU64::new(0),
+        }
+    }
 }
 
+impl<V> LruMap<V> {
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn get(&self, key: &str) -> Option<&V> {
+        self.entries.get(key).map(|(v, t)| {
+            t.store(self.next_tick(), Ordering::Relaxed);
+            v
+        })
+    }
+
+    /// Insert value, returns the evicted entry if there are more than
+    /// `capacity` entries
+    fn insert(
+        &mut self,
+        key: String,
+        value: V,
+        capacity: Option<NonZeroUsize>,
+    ) -> Option<(String, V)> {
+        let tick = AtomicU64::new(self.next_tick());
+        self.entries.insert(key, (value, tick));
+
+        if self.entries.len() > capacity?.get() {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, t))| t.load(Ordering::Relaxed))
+                .map(|(k, _)| k.clone())?;
+            return self.remove(&lru).map(|v| (lru, v));
+        }
+        None
+    }
+
+    fn remove(&mut self, key: &str) -> Option<V> {
+        self.entries.remove(key).map(|(v, _)| v)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(v, _)| v)
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// In-memory `PortalStore` of a connection.
+///
+/// It's unbounded by default. With a capacity, the least recently used
+/// statement or portal is evicted when a new one exceeds the capacity, so a
+/// client that never closes them can't exhaust server memory. Evicted ones
+/// behave as if they were closed: using them is an error, while closing them
+/// again still succeeds.
 #[derive(Debug, Default, new)]
 pub struct MemPortalStore<S> {
     #[new(default)]
-    statements: RwLock<BTreeMap<String, Arc<StoredStatement<S>>>>,
+    statements: RwLock<LruMap<Arc<StoredStatement<S>>>>,
+    #[new(default)]
+    portals: RwLock<LruMap<Arc<Portal<S>>>>,
+    #[new(default)]
+    suspended_portals: Mutex<BTreeMap<String, SuspendedPortal<S>>>,
     #[new(default)]
-    portals: RwLock<BTreeMap<String, Arc<Portal<S>>>>,
+    capacity: Option<NonZeroUsize>,
+    #[new(default)]
+    evictions: AtomicU64,
+}
+
+impl<S> MemPortalStore<S> {
+    /// Create store holding at most `capacity` statements and `capacity`
+    /// portals
+    pub fn with_capacity(capacity: NonZeroUsize) -> MemPortalStore<S> {
+        MemPortalStore {
+            capacity: Some(capacity),
+            ..MemPortalStore::new()
+        }
+    }
+
+    /// Get current counters
+    pub fn stats(&self) -> PortalStoreStats {
+        PortalStoreStats {
+            statements: self.statements.read().unwrap().len(),
+            portals: self.portals.read().unwrap().len(),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_eviction(&self, kind: &str, name: &str) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(kind, name, "evicted least recently used entry");
+    }
 }
 
 impl<S: Clone + Send + Sync> PortalStore for MemPortalStore<S> {
@@ -33,31 +197,203 @@ impl<S: Clone + Send + Sync> PortalStore for MemPortalStore<S> {
 
     fn put_statement(&self, statement: Arc<StoredStatement<Self::Statement>>) {
         let mut guard = self.statements.write().unwrap();
-        guard.insert(statement.id.to_owned(), statement);
+        let key = store_key(&statement.id).to_owned();
+        if let Some((name, _)) = guard.insert(key, statement, self.capacity) {
+            self.record_eviction("statement", &name);
+        }
     }
 
     fn rm_statement(&self, name: &str) {
         let mut guard = self.statements.write().unwrap();
-        guard.remove(name);
+        guard.remove(store_key(name));
     }
 
     fn get_statement(&self, name: &str) -> Option<Arc<StoredStatement<Self::Statement>>> {
         let guard = self.statements.read().unwrap();
-        guard.get(name).cloned()
+        guard.get(store_key(name)).cloned()
     }
 
     fn put_portal(&self, portal: Arc<Portal<Self::Statement>>) {
+        let mut suspended_portals = self.suspended_portals.lock().unwrap();
+        suspended_portals.remove(store_key(&portal.name));
         let mut guard = self.portals.write().unwrap();
-        guard.insert(portal.name.to_owned(), portal);
+        let key = store_key(&portal.name).to_owned();
+        if let Some((name, _)) = guard.insert(key, portal, self.capacity) {
+            suspended_portals.remove(&name);
+            self.record_eviction("portal", &name);
+        }
     }
 
     fn rm_portal(&self, name: &str) {
+        self.suspended_portals
+            .lock()
+            .unwrap()
+            .remove(store_key(name));
         let mut guard = self.portals.write().unwrap();
-        guard.remove(name);
+        guard.remove(store_key(name));
     }
 
     fn get_portal(&self, name: &str) -> Option<Arc<Portal<Self::Statement>>> {
         let guard = self.portals.read().unwrap();
-        guard.get(name).cloned()
+        guard.get(store_key(name)).cloned()
+    }
+
+    fn put_suspended_portal(&self, portal: SuspendedPortal<Self::Statement>) {
+        let mut guard = self.suspended_portals.lock().unwrap();
+        guard.insert(store_key(&portal.portal.name).to_owned(), portal);
+    }
+
+    fn take_suspended_portal(&self, name: &str) -> Option<SuspendedPortal<Self::Statement>> {
+        let mut guard = self.suspended_portals.lock().unwrap();
+        guard.remove(store_key(name))
+    }
+
+    fn list_statements(&self) -> Vec<StatementSummary> {
+        let statements = self.statements.read().unwrap();
+        let portals = self.portals.read().unwrap();
+        statements
+            .values()
+            .map(|stmt| {
+                let portal_count = portals
+                    .values()
+                    .filter(|p| Arc::ptr_eq(&p.statement, stmt))
+                    .count();
+                StatementSummary::new(
+                    stmt.id.clone(),
+                    stmt.query.clone(),
+                    stmt.parameter_types
+                        .iter()
+                        .map(|t| t.name().to_owned())
+                        .collect(),
+                    portal_count,
+                    stmt.created_at,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use postgres_types::Type;
+
+    use super::*;
+    use crate::api::DEFAULT_NAME;
+    use crate::messages::data::FORMAT_CODE_TEXT;
+    use crate::messages::extendedquery::Bind;
+
+    #[test]
+    fn test_list_statements() {
+        let store = MemPortalStore::<String>::new();
+
+        let mut stmt = StoredStatement::new("s1".to_owned(), "".to_owned(), vec![Type::INT4]);
+        stmt.query = "SELECT $1".to_owned();
+        let stmt = Arc::new(stmt);
+        store.put_statement(stmt.clone());
+        store.put_statement(Arc::new(StoredStatement::new(
+            "s2".to_owned(),
+            "".to_owned(),
+            vec![],
+        )));
+
+        let bind = Bind::new(
+            Some("p1".to_owned()),
+            Some("s1".to_owned()),
+            vec![FORMAT_CODE_TEXT],
+            vec![None],
+            vec![],
+        );
+        store.put_portal(Arc::new(Portal::try_new(&bind, stmt).unwrap()));
+
+        let summaries = store.list_statements();
+        assert_eq!(2, summaries.len());
+        assert_eq!("s1", summaries[0].name);
+        assert_eq!("SELECT $1", summaries[0].query);
+        assert_eq!(vec!["int4".to_owned()], summaries[0].parameter_types);
+        assert_eq!(1, summaries[0].portal_count);
+        assert_eq!(0, summaries[1].portal_count);
+    }
+
+    #[test]
+    fn test_unnamed_statement() {
+        let store = MemPortalStore::<String>::new();
+        let stmt = Arc::new(StoredStatement::new(
+            DEFAULT_NAME.to_owned(),
+            "".to_owned(),
+            vec![],
+        ));
+        assert!(!stmt.is_named());
+        assert_eq!("(unnamed)", stmt.effective_name());
+        store.put_statement(stmt.clone());
+        assert!(store.get_statement("").is_some());
+        assert!(store.get_statement(DEFAULT_NAME).is_some());
+
+        let bind = Bind::new(None, None, vec![], vec![], vec![]);
+        store.put_portal(Arc::new(Portal::try_new(&bind, stmt).unwrap()));
+        let portal = store.get_portal("").unwrap();
+        assert!(!portal.is_named());
+        assert_eq!("(unnamed)", portal.effective_name());
+
+        store.rm_statement("");
+        assert!(store.get_statement(DEFAULT_NAME).is_none());
+
+        // bound statement must be the one named in bind
+        let s1 = Arc::new(StoredStatement::new("s1".to_owned(), "".to_owned(), vec![]));
+        assert_eq!("s1", s1.effective_name());
+        assert!(Portal::try_new(&bind, s1.clone()).is_err());
+        let bind = Bind::new(
+            Some("p1".to_owned()),
+            Some("s1".to_owned()),
+            vec![],
+            vec![],
+            vec![],
+        );
+        let portal = Portal::try_new(&bind, s1).unwrap();
+        assert!(portal.is_named());
+        assert_eq!("p1", portal.effective_name());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let store = MemPortalStore::<String>::with_capacity(NonZeroUsize::new(2).unwrap());
+        let statement =
+            |name: &str| Arc::new(StoredStatement::new(name.to_owned(), "".to_owned(), vec![]));
+        store.put_statement(statement("s1"));
+        store.put_statement(statement("s2"));
+        // s1 becomes the most recently used
+        assert!(store.get_statement("s1").is_some());
+        store.put_statement(statement("s3"));
+        assert!(store.get_statement("s2").is_none());
+        assert!(store.get_statement("s1").is_some());
+        assert!(store.get_statement("s3").is_some());
+
+        // replacing doesn't evict
+        store.put_statement(statement("s3"));
+        assert_eq!(
+            PortalStoreStats {
+                statements: 2,
+                portals: 0,
+                evictions: 1
+            },
+            store.stats()
+        );
+
+        // closing evicted one is fine
+        store.rm_statement("s2");
+
+        let stmt = store.get_statement("s1").unwrap();
+        for name in ["p1", "p2", "p3"] {
+            let bind = Bind::new(
+                Some(name.to_owned()),
+                Some("s1".to_owned()),
+                vec![],
+                vec![],
+                vec![],
+            );
+            store.put_portal(Arc::new(Portal::try_new(&bind, stmt.clone()).unwrap()));
+        }
+        assert!(store.get_portal("p1").is_none());
+        assert!(store.get_portal("p3").is_some());
+        assert_eq!(2, store.stats().evictions);
     }
 }
@@ -0,0 +1,27 @@
+//! Callback on the end of a client session.
+//!
+//! Engines may keep state tied to a connection, like locks, temporary tables
+//! or cursors. A [`TerminateHandler`] is notified when an authenticated
+//! session ends, so the state can be released.
+
+use async_trait::async_trait;
+
+use super::ClientInfo;
+
+/// Why a session has ended.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminateReason {
+    /// Client sent `Terminate`.
+    Terminate,
+    /// Connection was closed without `Terminate`, or failed with an error.
+    Disconnect,
+}
+
+/// Handler notified when a session ends.
+#[async_trait]
+pub trait TerminateHandler: Send + Sync {
+    /// Called once for each authenticated session, after the last message of
+    /// client is processed. Nothing can be sent to client at this point.
+    async fn on_terminate(&self, client: &(dyn ClientInfo + Send + Sync), reason: TerminateReason);
+}
@@ -0,0 +1,96 @@
+use tracing::field::Empty;
+use tracing::Span;
+
+use super::auth::LoginInfo;
+use super::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+use crate::messages::startup::{TraceParent, TRACEPARENT_PARAMETER};
+use crate::messages::PgWireFrontendMessage;
+
+const DB_SYSTEM: &str = "postgresql";
+
+/// First keyword of the query, as `db.operation`.
+fn operation(query: &str) -> String {
+    query
+        .split(|c: char| c.is_whitespace() || c == ';' || c == '(')
+        .find(|s| !s.is_empty())
+        .unwrap_or_default()
+        .to_ascii_uppercase()
+}
+
+/// Record trace context sent by client, so a tracing subscriber can link the
+/// span to the caller's trace.
+fn record_trace_parent(span: &Span, trace_parent: Option<TraceParent>) {
+    if let Some(trace_parent) = trace_parent {
+        span.record("trace_id", format!("{:032x}", trace_parent.trace_id));
+        span.record("parent_span_id", format!("{:016x}", trace_parent.parent_id));
+    }
+}
+
+fn client_trace_parent<C: ClientInfo>(client: &C) -> Option<TraceParent> {
+    client
+        .metadata()
+        .get(TRACEPARENT_PARAMETER)
+        .and_then(|v| TraceParent::parse(v))
+}
+
+/// Span for `StartupHandler::on_startup`.
+pub(crate) fn startup_span<C: ClientInfo>(client: &C, message: &PgWireFrontendMessage) -> Span {
+    let span = tracing::info_span!(
+        "pgwire.startup",
+        otel.kind = "server",
+        db.system = DB_SYSTEM,
+        net.peer.addr = %client.socket_addr(),
+        trace_id = Empty,
+        parent_span_id = Empty,
+    );
+    // trace context is not saved to metadata until startup is processed
+    let trace_parent = if let PgWireFrontendMessage::Startup(startup) = message {
+        startup.negotiate_protocol().trace_parent
+    } else {
+        client_trace_parent(client)
+    };
+    record_trace_parent(&span, trace_parent);
+    span
+}
+
+/// Span for `do_query` of query handlers, with `db.statement` and
+/// `db.operation` of OpenTelemetry semantic conventions.
+pub(crate) fn query_span<C: ClientInfo>(client: &C, query: &str) -> Span {
+    let metadata = client.metadata();
+    let span = tracing::info_span!(
+        "pgwire.query",
+        otel.kind = "server",
+        db.system = DB_SYSTEM,
+        db.user = metadata.get(METADATA_USER).map(String::as_str),
+        db.name = metadata.get(METADATA_DATABASE).map(String::as_str),
+        db.statement = query,
+        db.operation = operation(query),
+        trace_id = Empty,
+        parent_span_id = Empty,
+    );
+    record_trace_parent(&span, client_trace_parent(client));
+    span
+}
+
+/// Span for fetching or verifying password of `login`.
+pub(crate) fn auth_span(login: &LoginInfo) -> Span {
+    tracing::info_span!(
+        "pgwire.auth",
+        db.system = DB_SYSTEM,
+        db.user = login.user(),
+        net.peer.addr = login.host(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_operation() {
+        assert_eq!("SELECT", operation("  select 1"));
+        assert_eq!("BEGIN", operation("begin;"));
+        assert_eq!("WITH", operation("\nWITH(x) AS (SELECT 1) SELECT * FROM x"));
+        assert_eq!("", operation(" ; "));
+    }
+}
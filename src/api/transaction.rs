@@ -0,0 +1,215 @@
+//! Tracking of transaction status reported in `ReadyForQuery`.
+//!
+//! Transaction control statements, like `BEGIN` and `COMMIT`, are still
+//! executed by the query handler. pgwire only follows them, and errors in a
+//! transaction block, to report the status expected by clients.
+
+use super::lexer::{is_keyword, tokenize, Token};
+use super::ClientInfo;
+use crate::messages::response::{
+    READY_STATUS_FAILED_TRANSACTION_BLOCK, READY_STATUS_IDLE, READY_STATUS_TRANSACTION_BLOCK,
+};
+
+/// Transaction status of a session
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// not in a transaction block
+    #[default]
+    Idle,
+    /// in a transaction block
+    InTransaction,
+    /// in a failed transaction block, queries are rejected until the block
+    /// is ended
+    FailedTransaction,
+}
+
+impl TransactionStatus {
+    /// Status byte of `ReadyForQuery`
+    pub fn to_ready_status(self) -> u8 {
+        match self {
+            TransactionStatus::Idle => READY_STATUS_IDLE,
+            TransactionStatus::InTransaction => READY_STATUS_TRANSACTION_BLOCK,
+            TransactionStatus::FailedTransaction => READY_STATUS_FAILED_TRANSACTION_BLOCK,
+        }
+    }
+
+    /// Status after a statement is executed. `command` is `None` for
+    /// statements other than transaction control.
+    pub(crate) fn transition(
+        self,
+        command: Option<TransactionCommand>,
+        succeeded: bool,
+    ) -> TransactionStatus {
+        use TransactionCommand::*;
+        use TransactionStatus::*;
+
+        if !succeeded {
+            // any error aborts the transaction block
+            return if self == Idle {
+                Idle
+            } else {
+                FailedTransaction
+            };
+        }
+        match (self, command) {
+            (_, None) | (_, Some(Savepoint)) | (_, Some(ReleaseSavepoint)) => self,
+            (Idle, Some(Begin)) => InTransaction,
+            // commit or rollback outside of transaction block is only a warning
+            (Idle, _) => Idle,
+            (_, Some(Begin)) => self,
+            (_, Some(Commit { chain })) | (_, Some(Rollback { chain })) => {
+                if chain {
+                    InTransaction
+                } else {
+                    Idle
+                }
+            }
+            (_, Some(RollbackToSavepoint)) => InTransaction,
+        }
+    }
+}
+
+/// Transaction control statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TransactionCommand {
+    /// `BEGIN` or `START TRANSACTION`
+    Begin,
+    /// `COMMIT`, `END` or `PREPARE TRANSACTION`, with `AND CHAIN` to start a
+    /// new transaction
+    Commit {
+        chain: bool,
+    },
+    /// `ROLLBACK` or `ABORT`
+    Rollback {
+        chain: bool,
+    },
+    Savepoint,
+    ReleaseSavepoint,
+    RollbackToSavepoint,
+}
+
+/// Parse a transaction control statement. Returns `None` if the query is
+/// anything else, including multiple statements.
+pub(crate) fn parse_transaction_command(query: &str) -> Option<TransactionCommand> {
+    let mut tokens = tokenize(query)?;
+    while tokens.last() == Some(&Token::Punct(';')) {
+        tokens.pop();
+    }
+    if tokens.contains(&Token::Punct(';')) {
+        return None;
+    }
+
+    let keyword = |idx: usize, keyword: &str| is_keyword(tokens.get(idx), keyword);
+    // optional `WORK` or `TRANSACTION` after the first keyword
+    let noise = usize::from(keyword(1, "work") || keyword(1, "transaction"));
+    let chain = keyword(noise + 1, "and") && keyword(noise + 2, "chain");
+
+    if keyword(0, "begin") || (keyword(0, "start") && keyword(1, "transaction")) {
+        Some(TransactionCommand::Begin)
+    } else if (keyword(0, "commit") || keyword(0, "end")) && !keyword(1, "prepared") {
+        Some(TransactionCommand::Commit { chain })
+    } else if keyword(0, "prepare") && keyword(1, "transaction") {
+        Some(TransactionCommand::Commit { chain: false })
+    } else if keyword(0, "rollback") || keyword(0, "abort") {
+        if keyword(1, "prepared") {
+            None
+        } else if keyword(noise + 1, "to") {
+            Some(TransactionCommand::RollbackToSavepoint)
+        } else {
+            Some(TransactionCommand::Rollback { chain })
+        }
+    } else if keyword(0, "savepoint") {
+        Some(TransactionCommand::Savepoint)
+    } else if keyword(0, "release") {
+        Some(TransactionCommand::ReleaseSavepoint)
+    } else {
+        None
+    }
+}
+
+/// Update transaction status of `client` after a statement is executed,
+/// successfully or with an error responded.
+pub(crate) fn update_transaction_status<C: ClientInfo + ?Sized>(
+    client: &mut C,
+    command: Option<TransactionCommand>,
+    succeeded: bool,
+) {
+    let status = client.transaction_status().transition(command, succeeded);
+    client.set_transaction_status(status);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_transaction_command() {
+        use TransactionCommand::*;
+
+        for (query, expected) in [
+            ("BEGIN", Some(Begin)),
+            ("begin isolation level serializable;", Some(Begin)),
+            ("START TRANSACTION READ ONLY", Some(Begin)),
+            ("COMMIT", Some(Commit { chain: false })),
+            ("end work;", Some(Commit { chain: false })),
+            ("COMMIT AND CHAIN", Some(Commit { chain: true })),
+            (
+                "COMMIT TRANSACTION AND NO CHAIN",
+                Some(Commit { chain: false }),
+            ),
+            ("PREPARE TRANSACTION 'tx'", Some(Commit { chain: false })),
+            ("ROLLBACK", Some(Rollback { chain: false })),
+            ("ABORT AND CHAIN", Some(Rollback { chain: true })),
+            ("SAVEPOINT sp", Some(Savepoint)),
+            ("RELEASE SAVEPOINT sp", Some(ReleaseSavepoint)),
+            ("RELEASE sp", Some(ReleaseSavepoint)),
+            ("ROLLBACK TO SAVEPOINT sp", Some(RollbackToSavepoint)),
+            ("rollback work to sp", Some(RollbackToSavepoint)),
+            ("COMMIT PREPARED 'tx'", None),
+            ("ROLLBACK PREPARED 'tx'", None),
+            ("BEGIN; SELECT 1", None),
+            ("START 1", None),
+            ("SELECT 'BEGIN'", None),
+        ] {
+            assert_eq!(expected, parse_transaction_command(query), "{query}");
+        }
+    }
+
+    #[test]
+    fn test_transaction_status_transition() {
+        use TransactionCommand::*;
+        use TransactionStatus::*;
+
+        let status = Idle.transition(Some(Begin), true);
+        assert_eq!(InTransaction, status);
+        assert_eq!(b'T', status.to_ready_status());
+        assert_eq!(InTransaction, status.transition(None, true));
+        assert_eq!(InTransaction, status.transition(Some(Savepoint), true));
+        assert_eq!(Idle, status.transition(Some(Commit { chain: false }), true));
+        assert_eq!(
+            InTransaction,
+            status.transition(Some(Commit { chain: true }), true)
+        );
+
+        let status = status.transition(None, false);
+        assert_eq!(FailedTransaction, status);
+        assert_eq!(b'E', status.to_ready_status());
+        assert_eq!(FailedTransaction, status.transition(None, true));
+        assert_eq!(
+            InTransaction,
+            status.transition(Some(RollbackToSavepoint), true)
+        );
+        // commit of failed transaction is a rollback
+        assert_eq!(Idle, status.transition(Some(Commit { chain: false }), true));
+        assert_eq!(
+            Idle,
+            status.transition(Some(Rollback { chain: false }), true)
+        );
+
+        assert_eq!(Idle, Idle.transition(None, false));
+        assert_eq!(Idle, Idle.transition(Some(Begin), false));
+        assert_eq!(Idle, Idle.transition(Some(Rollback { chain: false }), true));
+        assert_eq!(b'I', Idle.to_ready_status());
+    }
+}
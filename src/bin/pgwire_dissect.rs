@@ -0,0 +1,337 @@
+//! Print postgres protocol trace of connections in a pcap capture file.
+//!
+//! ```text
+//! pgwire-dissect <capture.pcap> [--port 5432]
+//! ```
+//!
+//! Only the classic pcap format is supported. TCP segments are processed in
+//! capture order, retransmitted or reordered segments are not reassembled.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::ExitCode;
+
+use pgwire::messages::dissect::{Direction, WiresharkDissector};
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IPPROTO_TCP: u8 = 6;
+
+struct Segment<'a> {
+    src: SocketAddr,
+    dst: SocketAddr,
+    payload: &'a [u8],
+}
+
+struct PcapReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+    link_type: u32,
+}
+
+impl<'a> PcapReader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 24 {
+            return Err("file too short for pcap header".to_owned());
+        }
+        let magic = [data[0], data[1], data[2], data[3]];
+        let big_endian = match magic {
+            [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => true,
+            [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => false,
+            _ => return Err("not a pcap file (pcapng is not supported)".to_owned()),
+        };
+
+        let mut reader = PcapReader {
+            data,
+            pos: 0,
+            big_endian,
+            link_type: 0,
+        };
+        reader.link_type = reader.read_u32(20);
+        reader.pos = 24;
+        Ok(reader)
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let bytes = [
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+            self.data[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl<'a> Iterator for PcapReader<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos + 16 > self.data.len() {
+            return None;
+        }
+        let incl_len = self.read_u32(self.pos + 8) as usize;
+        let start = self.pos + 16;
+        let end = (start + incl_len).min(self.data.len());
+        self.pos = start + incl_len;
+        Some(&self.data[start..end])
+    }
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+/// Strip link layer header and return ethertype and network layer packet.
+fn link_payload(link_type: u32, frame: &[u8]) -> Option<(u16, &[u8])> {
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = be_u16(frame, 12)?;
+            let mut offset = 14;
+            while ethertype == ETHERTYPE_VLAN {
+                ethertype = be_u16(frame, offset + 2)?;
+                offset += 4;
+            }
+            Some((ethertype, frame.get(offset..)?))
+        }
+        LINKTYPE_NULL => {
+            let packet = frame.get(4..)?;
+            let version = packet.first()? >> 4;
+            let ethertype = if version == 6 {
+                ETHERTYPE_IPV6
+            } else {
+                ETHERTYPE_IPV4
+            };
+            Some((ethertype, packet))
+        }
+        LINKTYPE_RAW => {
+            let version = frame.first()? >> 4;
+            let ethertype = if version == 6 {
+                ETHERTYPE_IPV6
+            } else {
+                ETHERTYPE_IPV4
+            };
+            Some((ethertype, frame))
+        }
+        LINKTYPE_LINUX_SLL => Some((be_u16(frame, 14)?, frame.get(16..)?)),
+        LINKTYPE_LINUX_SLL2 => Some((be_u16(frame, 0)?, frame.get(20..)?)),
+        _ => None,
+    }
+}
+
+fn tcp_segment(link_type: u32, frame: &[u8]) -> Option<Segment<'_>> {
+    let (ethertype, packet) = link_payload(link_type, frame)?;
+    let (src_ip, dst_ip, segment) = match ethertype {
+        ETHERTYPE_IPV4 => {
+            let ihl = ((packet.first()? & 0x0f) as usize) * 4;
+            if *packet.get(9)? != IPPROTO_TCP {
+                return None;
+            }
+            let total_len = (be_u16(packet, 2)? as usize).min(packet.len());
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv4Addr::from(src)),
+                IpAddr::from(Ipv4Addr::from(dst)),
+                packet.get(ihl..total_len)?,
+            )
+        }
+        ETHERTYPE_IPV6 => {
+            if *packet.get(6)? != IPPROTO_TCP {
+                return None;
+            }
+            let payload_len = be_u16(packet, 4)? as usize;
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv6Addr::from(src)),
+                IpAddr::from(Ipv6Addr::from(dst)),
+                packet.get(40..(40 + payload_len).min(packet.len()))?,
+            )
+        }
+        _ => return None,
+    };
+
+    let src_port = be_u16(segment, 0)?;
+    let dst_port = be_u16(segment, 2)?;
+    let data_offset = ((segment.get(12)? >> 4) as usize) * 4;
+    Some(Segment {
+        src: SocketAddr::new(src_ip, src_port),
+        dst: SocketAddr::new(dst_ip, dst_port),
+        payload: segment.get(data_offset..)?,
+    })
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut port: u16 = 5432;
+    while let Some(arg) = args.next() {
+        if arg == "--port" {
+            match args.next().and_then(|p| p.parse().ok()) {
+                Some(p) => port = p,
+                None => {
+                    eprintln!("--port requires a port number");
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else {
+            path = Some(arg);
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("usage: pgwire-dissect <capture.pcap> [--port 5432]");
+        return ExitCode::FAILURE;
+    };
+
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("failed to read {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let reader = match PcapReader::new(&data) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let link_type = reader.link_type;
+    // dissector of each connection, keyed by client address. `None` marks
+    // connections that failed to decode.
+    let mut connections: HashMap<SocketAddr, Option<WiresharkDissector>> = HashMap::new();
+    for frame in reader {
+        let Some(segment) = tcp_segment(link_type, frame) else {
+            continue;
+        };
+        if segment.payload.is_empty() {
+            continue;
+        }
+
+        let (client, direction) = if segment.dst.port() == port {
+            (segment.src, Direction::Frontend)
+        } else if segment.src.port() == port {
+            (segment.dst, Direction::Backend)
+        } else {
+            continue;
+        };
+
+        let entry = connections
+            .entry(client)
+            .or_insert_with(|| Some(WiresharkDissector::new()));
+        if let Some(dissector) = entry {
+            match dissector.feed(direction, segment.payload) {
+                Ok(messages) => {
+                    for msg in messages {
+                        println!("[{client}] {msg}");
+                    }
+                }
+                Err(e) => {
+                    println!("[{client}] failed to decode: {e}");
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Build a little-endian pcap file from link layer frames.
+    fn pcap(link_type: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&65535u32.to_le_bytes());
+        data.extend_from_slice(&link_type.to_le_bytes());
+        for frame in frames {
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+        data
+    }
+
+    /// Ethernet frame of an IPv4 packet with `protocol` and TCP-like header.
+    fn ethernet_ipv4(protocol: u8, src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+        let total_len = (20 + 20 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, protocol, 0, 0]);
+        frame.extend_from_slice(&[127, 0, 0, 1]);
+        frame.extend_from_slice(&[127, 0, 0, 2]);
+
+        frame.extend_from_slice(&src_port.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&[0x50, 0x18, 0, 0, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_read_capture() {
+        let query = b"Q\0\0\0\x0dSELECT 1\0";
+        let data = pcap(
+            LINKTYPE_ETHERNET,
+            &[
+                ethernet_ipv4(IPPROTO_TCP, 40000, 5432, query),
+                // udp
+                ethernet_ipv4(17, 40000, 5432, query),
+            ],
+        );
+        let reader = PcapReader::new(&data).unwrap();
+        assert_eq!(LINKTYPE_ETHERNET, reader.link_type);
+
+        let frames = reader.collect::<Vec<_>>();
+        assert_eq!(2, frames.len());
+
+        let (ethertype, packet) = link_payload(LINKTYPE_ETHERNET, frames[0]).unwrap();
+        assert_eq!(ETHERTYPE_IPV4, ethertype);
+        assert_eq!(0x45, packet[0]);
+
+        let segment = tcp_segment(LINKTYPE_ETHERNET, frames[0]).unwrap();
+        assert_eq!("127.0.0.1:40000", segment.src.to_string());
+        assert_eq!("127.0.0.2:5432", segment.dst.to_string());
+        assert_eq!(&query[..], segment.payload);
+
+        assert!(tcp_segment(LINKTYPE_ETHERNET, frames[1]).is_none());
+        // truncated frame
+        assert!(tcp_segment(LINKTYPE_ETHERNET, &frames[0][..20]).is_none());
+    }
+
+    #[test]
+    fn test_invalid_capture() {
+        assert!(PcapReader::new(&[0; 10]).is_err());
+        // pcapng section header block
+        let mut data = vec![0x0a, 0x0d, 0x0d, 0x0a];
+        data.resize(24, 0);
+        assert!(PcapReader::new(&data).is_err());
+        assert!(link_payload(LINKTYPE_ETHERNET, &[0; 8]).is_none());
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::SinkExt;
+use postgres_types::Type;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Framed;
+
+use super::{
+    next_message, startup, ClientConfig, FromRow, Params, PgWireMessageClientCodec, Row, ServerInfo,
+};
+use crate::api::results::{FieldFormat, FieldInfo};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::data::FORMAT_CODE_BINARY;
+use crate::messages::extendedquery::{
+    Bind, Close, Describe, Execute, Parse, Sync as PgSync, TARGET_TYPE_BYTE_STATEMENT,
+};
+use crate::messages::terminate::Terminate;
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+#[derive(Debug)]
+struct PreparedStatement {
+    name: String,
+    parameter_types: Vec<Type>,
+    fields: Arc<Vec<FieldInfo>>,
+}
+
+/// A client that uses extended query protocol for all operations.
+///
+/// Statements are parsed on server on their first execution, and cached by
+/// query string. Later executions of the same query skip `Parse` and go
+/// directly to `Bind`. Parameters and results are always transferred in
+/// binary format.
+#[derive(Debug)]
+pub struct BinaryProtocolClient<S> {
+    socket: Framed<S, PgWireMessageClientCodec>,
+    server_info: ServerInfo,
+    statements: HashMap<String, PreparedStatement>,
+    next_statement_id: usize,
+}
+
+impl<S> BinaryProtocolClient<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Run startup and authentication on `stream` and create the client.
+    pub async fn connect(stream: S, config: &ClientConfig) -> PgWireResult<Self> {
+        let mut socket = Framed::new(stream, PgWireMessageClientCodec::new());
+        let server_info = startup(&mut socket, config).await?;
+
+        Ok(BinaryProtocolClient {
+            socket,
+            server_info,
+            statements: HashMap::new(),
+            next_statement_id: 0,
+        })
+    }
+
+    /// Information reported by server during startup.
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server_info
+    }
+
+    /// Execute `stmt` with `params` and convert each result row into `R`.
+    pub async fn execute<P, R>(&mut self, stmt: &str, params: P) -> PgWireResult<Vec<R>>
+    where
+        P: Params,
+        R: FromRow,
+    {
+        self.prepare(stmt).await?;
+        let statement = &self.statements[stmt];
+
+        let parameters = params.encode(&statement.parameter_types)?;
+        let fields = statement.fields.clone();
+        let bind = Bind::new(
+            None,
+            Some(statement.name.clone()),
+            vec![FORMAT_CODE_BINARY],
+            parameters,
+            vec![FORMAT_CODE_BINARY],
+        );
+
+        self.socket.feed(PgWireFrontendMessage::Bind(bind)).await?;
+        self.socket
+            .feed(PgWireFrontendMessage::Execute(Execute::new(None, 0)))
+            .await?;
+        self.socket
+            .send(PgWireFrontendMessage::Sync(PgSync::new()))
+            .await?;
+
+        let mut rows = Vec::new();
+        let mut error = None;
+        loop {
+            match self.next_message().await? {
+                PgWireBackendMessage::BindComplete(_)
+                | PgWireBackendMessage::CommandComplete(_)
+                | PgWireBackendMessage::EmptyQueryResponse(_) => {}
+                PgWireBackendMessage::DataRow(data_row) => {
+                    if error.is_none() {
+                        rows.push(Row::decode(fields.clone(), data_row)?);
+                    }
+                }
+                PgWireBackendMessage::ErrorResponse(e) => error = Some(e.into()),
+                PgWireBackendMessage::ReadyForQuery(_) => break,
+                other => {
+                    return Err(PgWireError::unexpected_backend_message("DataRow", &other));
+                }
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(PgWireError::UserError(Box::new(error)));
+        }
+
+        rows.iter().map(R::from_row).collect()
+    }
+
+    /// Close all cached statements on server, so the next execution of any
+    /// query will parse it again.
+    pub async fn clear_cache(&mut self) -> PgWireResult<()> {
+        if self.statements.is_empty() {
+            return Ok(());
+        }
+
+        for (_, statement) in self.statements.drain() {
+            self.socket
+                .feed(PgWireFrontendMessage::Close(Close::new(
+                    TARGET_TYPE_BYTE_STATEMENT,
+                    Some(statement.name),
+                )))
+                .await?;
+        }
+        self.socket
+            .send(PgWireFrontendMessage::Sync(PgSync::new()))
+            .await?;
+
+        self.wait_for_ready("CloseComplete", |message| {
+            matches!(message, PgWireBackendMessage::CloseComplete(_))
+        })
+        .await
+    }
+
+    /// Terminate the session.
+    pub async fn close(mut self) -> PgWireResult<()> {
+        self.socket
+            .send(PgWireFrontendMessage::Terminate(Terminate::new()))
+            .await?;
+        Ok(())
+    }
+
+    async fn prepare(&mut self, stmt: &str) -> PgWireResult<()> {
+        if self.statements.contains_key(stmt) {
+            return Ok(());
+        }
+
+        let name = format!("pgwire_stmt_{}", self.next_statement_id);
+        self.next_statement_id += 1;
+
+        self.socket
+            .feed(PgWireFrontendMessage::Parse(Parse::new(
+                Some(name.clone()),
+                stmt.to_owned(),
+                vec![],
+            )))
+            .await?;
+        self.socket
+            .feed(PgWireFrontendMessage::Describe(Describe::new(
+                TARGET_TYPE_BYTE_STATEMENT,
+                Some(name.clone()),
+            )))
+            .await?;
+        self.socket
+            .send(PgWireFrontendMessage::Sync(PgSync::new()))
+            .await?;
+
+        let mut parameter_types = Vec::new();
+        let mut fields = Vec::new();
+        let mut error: Option<ErrorInfo> = None;
+        loop {
+            match self.next_message().await? {
+                PgWireBackendMessage::ParseComplete(_) | PgWireBackendMessage::NoData(_) => {}
+                PgWireBackendMessage::ParameterDescription(desc) => {
+                    parameter_types = desc
+                        .types
+                        .into_iter()
+                        .map(|oid| Type::from_oid(oid).ok_or(PgWireError::UnknownTypeId(oid)))
+                        .collect::<PgWireResult<Vec<Type>>>()?;
+                }
+                PgWireBackendMessage::RowDescription(desc) => {
+                    fields = desc
+                        .fields
+                        .into_iter()
+                        .map(|f| {
+                            let datatype = Type::from_oid(f.type_id)
+                                .ok_or(PgWireError::UnknownTypeId(f.type_id))?;
+                            Ok(FieldInfo::new(
+                                f.name,
+                                Some(f.table_id),
+                                Some(f.column_id),
+                                datatype,
+                                FieldFormat::Binary,
+                            ))
+                        })
+                        .collect::<PgWireResult<Vec<FieldInfo>>>()?;
+                }
+                PgWireBackendMessage::ErrorResponse(e) => error = Some(e.into()),
+                PgWireBackendMessage::ReadyForQuery(_) => break,
+                other => {
+                    return Err(PgWireError::unexpected_backend_message(
+                        "ParseComplete",
+                        &other,
+                    ));
+                }
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(PgWireError::UserError(Box::new(error)));
+        }
+
+        self.statements.insert(
+            stmt.to_owned(),
+            PreparedStatement {
+                name,
+                parameter_types,
+                fields: Arc::new(fields),
+            },
+        );
+        Ok(())
+    }
+
+    async fn wait_for_ready<F>(&mut self, expected: &'static str, f: F) -> PgWireResult<()>
+    where
+        F: Fn(&PgWireBackendMessage) -> bool,
+    {
+        let mut error: Option<ErrorInfo> = None;
+        loop {
+            match self.next_message().await? {
+                PgWireBackendMessage::ReadyForQuery(_) => break,
+                PgWireBackendMessage::ErrorResponse(e) => error = Some(e.into()),
+                other if f(&other) => {}
+                other => return Err(PgWireError::unexpected_backend_message(expected, &other)),
+            }
+        }
+
+        if let Some(error) = error {
+            Err(PgWireError::UserError(Box::new(error)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read next message, handling asynchronous messages that may arrive at
+    /// any time.
+    async fn next_message(&mut self) -> PgWireResult<PgWireBackendMessage> {
+        loop {
+            match next_message(&mut self.socket).await? {
+                PgWireBackendMessage::ParameterStatus(status) => {
+                    self.server_info
+                        .parameters
+                        .insert(status.name, status.value);
+                }
+                PgWireBackendMessage::NoticeResponse(_)
+                | PgWireBackendMessage::NotificationResponse(_) => {}
+                message => return Ok(message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+
+    use super::*;
+    use crate::api::{ClientInfo, DefaultClient, PgWireConnectionState};
+    use crate::messages::data::{DataRow, FieldDescription, ParameterDescription, RowDescription};
+    use crate::messages::extendedquery::{BindComplete, CloseComplete, ParseComplete};
+    use crate::messages::response::{CommandComplete, ReadyForQuery, READY_STATUS_IDLE};
+    use crate::messages::startup::Authentication;
+    use crate::tokio::PgWireMessageServerCodec;
+
+    #[tokio::test]
+    async fn test_statement_cache() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let addr = "127.0.0.1:5432".parse().unwrap();
+            let mut socket = Framed::new(
+                server_stream,
+                PgWireMessageServerCodec::new(DefaultClient::<()>::new(addr, false)),
+            );
+
+            let mut parsed = 0;
+            let ready =
+                || PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(READY_STATUS_IDLE));
+            while let Some(Ok(message)) = socket.next().await {
+                match message {
+                    PgWireFrontendMessage::Startup(_) => {
+                        socket
+                            .codec_mut()
+                            .client_info
+                            .set_state(PgWireConnectionState::ReadyForQuery);
+                        socket
+                            .feed(PgWireBackendMessage::Authentication(Authentication::Ok))
+                            .await
+                            .unwrap();
+                        socket.send(ready()).await.unwrap();
+                    }
+                    PgWireFrontendMessage::Parse(_) => {
+                        parsed += 1;
+                        socket
+                            .feed(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
+                            .await
+                            .unwrap();
+                    }
+                    PgWireFrontendMessage::Describe(_) => {
+                        socket
+                            .feed(PgWireBackendMessage::ParameterDescription(
+                                ParameterDescription::new(vec![Type::INT4.oid()]),
+                            ))
+                            .await
+                            .unwrap();
+                        socket
+                            .feed(PgWireBackendMessage::RowDescription(RowDescription::new(
+                                vec![FieldDescription::new(
+                                    "id".to_owned(),
+                                    0,
+                                    0,
+                                    Type::INT4.oid(),
+                                    4,
+                                    -1,
+                                    FORMAT_CODE_BINARY,
+                                )],
+                            )))
+                            .await
+                            .unwrap();
+                    }
+                    PgWireFrontendMessage::Bind(bind) => {
+                        socket
+                            .feed(PgWireBackendMessage::BindComplete(BindComplete::new()))
+                            .await
+                            .unwrap();
+                        let mut data = BytesMut::new();
+                        let value = bind.parameters[0].clone().unwrap();
+                        data.put_i32(value.len() as i32);
+                        data.put_slice(&value);
+                        socket
+                            .feed(PgWireBackendMessage::DataRow(DataRow::new(data, 1)))
+                            .await
+                            .unwrap();
+                    }
+                    PgWireFrontendMessage::Execute(_) => {
+                        socket
+                            .feed(PgWireBackendMessage::CommandComplete(CommandComplete::new(
+                                "SELECT 1".to_owned(),
+                            )))
+                            .await
+                            .unwrap();
+                    }
+                    PgWireFrontendMessage::Close(_) => {
+                        socket
+                            .feed(PgWireBackendMessage::CloseComplete(CloseComplete::new()))
+                            .await
+                            .unwrap();
+                    }
+                    PgWireFrontendMessage::Sync(_) => {
+                        socket.send(ready()).await.unwrap();
+                    }
+                    PgWireFrontendMessage::Terminate(_) => break,
+                    _ => {}
+                }
+            }
+            parsed
+        });
+
+        let mut client =
+            BinaryProtocolClient::connect(client_stream, &ClientConfig::new("pgwire".to_owned()))
+                .await
+                .unwrap();
+
+        let rows: Vec<(i32,)> = client.execute("SELECT $1", (1i32,)).await.unwrap();
+        assert_eq!(vec![(1,)], rows);
+        let rows: Vec<(i32,)> = client.execute("SELECT $1", (2i32,)).await.unwrap();
+        assert_eq!(vec![(2,)], rows);
+
+        client.clear_cache().await.unwrap();
+        let rows: Vec<(i32,)> = client.execute("SELECT $1", (3i32,)).await.unwrap();
+        assert_eq!(vec![(3,)], rows);
+
+        client.close().await.unwrap();
+        assert_eq!(2, server.await.unwrap());
+    }
+}
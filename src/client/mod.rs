@@ -0,0 +1,160 @@
+//! Client side implementation of postgresql wire protocol.
+//!
+//! This module provides the building blocks for talking to a postgres
+//! compatible server: a codec for framing backend messages, the startup and
+//! authentication flow, typed row and parameter helpers, and clients built on
+//! them.
+
+use std::collections::BTreeMap;
+use std::io::Error as IOError;
+
+use bytes::{Bytes, BytesMut};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::api::auth::md5pass::hash_md5_password;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::startup::{Authentication, Password, PasswordMessageFamily, Startup};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+pub mod binary;
+mod row;
+pub mod simple;
+
+pub use binary::BinaryProtocolClient;
+pub use row::{FromRow, Params, Row};
+pub use simple::{SimpleClient, SimpleRow};
+
+/// Codec for client side connection, which decodes backend messages and
+/// encodes frontend messages.
+#[non_exhaustive]
+#[derive(Debug, Default, new)]
+pub struct PgWireMessageClientCodec;
+
+impl Decoder for PgWireMessageClientCodec {
+    type Item = PgWireBackendMessage;
+    type Error = PgWireError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        PgWireBackendMessage::decode(src)
+    }
+}
+
+impl Encoder<PgWireFrontendMessage> for PgWireMessageClientCodec {
+    type Error = IOError;
+
+    fn encode(
+        &mut self,
+        item: PgWireFrontendMessage,
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        item.encode(dst).map_err(Into::into)
+    }
+}
+
+/// Connection options used by client during startup.
+#[non_exhaustive]
+#[derive(Debug, Clone, new)]
+pub struct ClientConfig {
+    pub user: String,
+    #[new(default)]
+    pub database: Option<String>,
+    #[new(default)]
+    pub password: Option<String>,
+}
+
+/// Information reported by server during startup.
+#[derive(Debug, Clone, Default)]
+pub struct ServerInfo {
+    /// parameters sent by server with `ParameterStatus`
+    pub parameters: BTreeMap<String, String>,
+    /// process id from `BackendKeyData`
+    pub process_id: i32,
+    /// secret key from `BackendKeyData`, used for query cancellation
+    pub secret_key: Bytes,
+}
+
+/// Send startup message and run authentication until server is ready for
+/// query.
+pub(crate) async fn startup<S>(socket: &mut S, config: &ClientConfig) -> PgWireResult<ServerInfo>
+where
+    S: Sink<PgWireFrontendMessage, Error = IOError>
+        + Stream<Item = PgWireResult<PgWireBackendMessage>>
+        + Unpin,
+{
+    let mut startup = Startup::new();
+    startup
+        .parameters
+        .insert("user".to_owned(), config.user.clone());
+    if let Some(database) = &config.database {
+        startup
+            .parameters
+            .insert("database".to_owned(), database.clone());
+    }
+    socket.send(PgWireFrontendMessage::Startup(startup)).await?;
+
+    let mut server_info = ServerInfo::default();
+    loop {
+        let message = next_message(socket).await?;
+        match message {
+            PgWireBackendMessage::Authentication(Authentication::Ok) => {}
+            PgWireBackendMessage::Authentication(Authentication::CleartextPassword) => {
+                let password = config
+                    .password
+                    .clone()
+                    .ok_or(PgWireError::PasswordRequired)?;
+                socket
+                    .send(PgWireFrontendMessage::PasswordMessageFamily(
+                        PasswordMessageFamily::Password(Password::new(password)),
+                    ))
+                    .await?;
+            }
+            PgWireBackendMessage::Authentication(Authentication::MD5Password(salt)) => {
+                let password = config
+                    .password
+                    .as_ref()
+                    .ok_or(PgWireError::PasswordRequired)?;
+                let hashed = hash_md5_password(&config.user, password, &salt);
+                socket
+                    .send(PgWireFrontendMessage::PasswordMessageFamily(
+                        PasswordMessageFamily::Password(Password::new(hashed)),
+                    ))
+                    .await?;
+            }
+            PgWireBackendMessage::Authentication(_) => {
+                return Err(PgWireError::UnsupportedAuthenticationMethod);
+            }
+            PgWireBackendMessage::ParameterStatus(status) => {
+                server_info.parameters.insert(status.name, status.value);
+            }
+            PgWireBackendMessage::BackendKeyData(key_data) => {
+                server_info.process_id = key_data.pid;
+                server_info.secret_key = key_data.secret_key;
+            }
+            PgWireBackendMessage::NoticeResponse(_) => {}
+            PgWireBackendMessage::ReadyForQuery(_) => return Ok(server_info),
+            PgWireBackendMessage::ErrorResponse(error) => {
+                return Err(PgWireError::UserError(Box::new(error.into())));
+            }
+            other => {
+                return Err(PgWireError::unexpected_backend_message(
+                    "Authentication",
+                    &other,
+                ));
+            }
+        }
+    }
+}
+
+/// Read next message from server, treating end of stream as an error.
+pub(crate) async fn next_message<S>(socket: &mut S) -> PgWireResult<PgWireBackendMessage>
+where
+    S: Stream<Item = PgWireResult<PgWireBackendMessage>> + Unpin,
+{
+    socket.next().await.unwrap_or_else(|| {
+        Err(PgWireError::IoError(IOError::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "connection closed by server",
+        )))
+    })
+}
@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes, BytesMut};
+use postgres_types::{FromSql, FromSqlOwned, IsNull, ToSql, Type};
+
+use crate::api::results::FieldInfo;
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::data::DataRow;
+
+/// A row returned by server, with values kept in their wire format.
+#[derive(Debug, Clone)]
+pub struct Row {
+    fields: Arc<Vec<FieldInfo>>,
+    values: Vec<Option<Bytes>>,
+}
+
+impl Row {
+    pub(crate) fn decode(fields: Arc<Vec<FieldInfo>>, row: DataRow) -> PgWireResult<Row> {
+        let mut data = row.data;
+        let mut values = Vec::with_capacity(row.field_count as usize);
+        for _ in 0..row.field_count {
+            if data.remaining() < 4 {
+                return Err(PgWireError::InvalidDataRow);
+            }
+            let len = data.get_i32();
+            if len < 0 {
+                values.push(None);
+            } else {
+                let len = len as usize;
+                if data.remaining() < len {
+                    return Err(PgWireError::InvalidDataRow);
+                }
+                values.push(Some(data.split_to(len).freeze()));
+            }
+        }
+
+        Ok(Row { fields, values })
+    }
+
+    /// Field descriptions of this row
+    pub fn fields(&self) -> &[FieldInfo] {
+        &self.fields
+    }
+
+    /// Number of columns in this row
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if the row has no column
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Get raw value of the column at index `idx`, `None` for `NULL`
+    pub fn raw(&self, idx: usize) -> PgWireResult<Option<&[u8]>> {
+        self.values
+            .get(idx)
+            .map(|v| v.as_deref())
+            .ok_or(PgWireError::ColumnIndexOutOfBound(idx))
+    }
+
+    /// Get value of the column at index `idx` and decode it as `T`.
+    ///
+    /// Values are decoded from binary format, using the type reported by
+    /// server in `RowDescription`.
+    pub fn get<'a, T: FromSql<'a>>(&'a self, idx: usize) -> PgWireResult<T> {
+        let raw = self.raw(idx)?;
+        let field = self
+            .fields
+            .get(idx)
+            .ok_or(PgWireError::ColumnIndexOutOfBound(idx))?;
+
+        if !T::accepts(field.datatype()) {
+            return Err(PgWireError::InvalidRustTypeForParameter(
+                field.datatype().name().to_owned(),
+            ));
+        }
+
+        T::from_sql_nullable(field.datatype(), raw).map_err(PgWireError::FailedToParseColumn)
+    }
+}
+
+/// Conversion from a [`Row`] into a rust type.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> PgWireResult<Self>;
+}
+
+impl FromRow for Row {
+    fn from_row(row: &Row) -> PgWireResult<Self> {
+        Ok(row.clone())
+    }
+}
+
+macro_rules! from_row_tuple {
+    ($($t:ident : $idx:tt),+) => {
+        impl<$($t: FromSqlOwned),+> FromRow for ($($t,)+) {
+            fn from_row(row: &Row) -> PgWireResult<Self> {
+                Ok(($(row.get::<$t>($idx)?,)+))
+            }
+        }
+    };
+}
+
+from_row_tuple!(A: 0);
+from_row_tuple!(A: 0, B: 1);
+from_row_tuple!(A: 0, B: 1, C: 2);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+/// Parameters of a statement, encoded in binary format.
+pub trait Params {
+    /// Encode parameters with given types, which are reported by server in
+    /// `ParameterDescription`.
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>>;
+}
+
+fn encode_params(
+    params: &[&(dyn ToSql + Sync)],
+    types: &[Type],
+) -> PgWireResult<Vec<Option<Bytes>>> {
+    if params.len() != types.len() {
+        return Err(PgWireError::ParameterCountMismatch {
+            expected: types.len(),
+            got: params.len(),
+        });
+    }
+
+    params
+        .iter()
+        .zip(types)
+        .map(|(param, ty)| {
+            let mut buf = BytesMut::new();
+            match param
+                .to_sql_checked(ty, &mut buf)
+                .map_err(PgWireError::FailedToEncodeParameter)?
+            {
+                IsNull::Yes => Ok(None),
+                IsNull::No => Ok(Some(buf.freeze())),
+            }
+        })
+        .collect()
+}
+
+impl Params for () {
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+        encode_params(&[], types)
+    }
+}
+
+impl Params for [&(dyn ToSql + Sync)] {
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+        encode_params(self, types)
+    }
+}
+
+impl<const N: usize> Params for [&(dyn ToSql + Sync); N] {
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+        encode_params(self, types)
+    }
+}
+
+impl Params for Vec<&(dyn ToSql + Sync)> {
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+        encode_params(self, types)
+    }
+}
+
+impl<P: Params + ?Sized> Params for &P {
+    fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+        (**self).encode(types)
+    }
+}
+
+macro_rules! params_tuple {
+    ($($t:ident : $idx:tt),+) => {
+        impl<$($t: ToSql + Sync),+> Params for ($($t,)+) {
+            fn encode(&self, types: &[Type]) -> PgWireResult<Vec<Option<Bytes>>> {
+                encode_params(&[$(&self.$idx),+], types)
+            }
+        }
+    };
+}
+
+params_tuple!(A: 0);
+params_tuple!(A: 0, B: 1);
+params_tuple!(A: 0, B: 1, C: 2);
+params_tuple!(A: 0, B: 1, C: 2, D: 3);
+params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+#[cfg(test)]
+mod test {
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::api::results::FieldFormat;
+
+    #[test]
+    fn test_decode_row() {
+        let fields = Arc::new(vec![
+            FieldInfo::new("id".to_owned(), None, None, Type::INT4, FieldFormat::Binary),
+            FieldInfo::new(
+                "name".to_owned(),
+                None,
+                None,
+                Type::VARCHAR,
+                FieldFormat::Binary,
+            ),
+        ]);
+
+        let mut data = BytesMut::new();
+        data.put_i32(4);
+        data.put_i32(42);
+        data.put_i32(-1);
+
+        let row = Row::decode(fields, DataRow::new(data, 2)).unwrap();
+        assert_eq!(2, row.len());
+        assert_eq!(42, row.get::<i32>(0).unwrap());
+        assert_eq!(None, row.get::<Option<String>>(1).unwrap());
+        assert!(row.get::<String>(0).is_err());
+        assert!(row.get::<i32>(2).is_err());
+
+        let (id, name) = <(i32, Option<String>)>::from_row(&row).unwrap();
+        assert_eq!(42, id);
+        assert_eq!(None, name);
+    }
+
+    #[test]
+    fn test_encode_params() {
+        let encoded = (1i32, None::<String>)
+            .encode(&[Type::INT4, Type::VARCHAR])
+            .unwrap();
+        assert_eq!(vec![Some(Bytes::from_static(&[0, 0, 0, 1])), None], encoded);
+
+        assert!(().encode(&[Type::INT4]).is_err());
+        assert!((1i32,).encode(&[Type::VARCHAR]).is_err());
+    }
+}
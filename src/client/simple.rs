@@ -0,0 +1,265 @@
+use std::net::SocketAddr;
+
+use bytes::{Buf, Bytes};
+use futures::SinkExt;
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+use super::{next_message, startup, ClientConfig, PgWireMessageClientCodec, ServerInfo};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::data::{DataRow, FORMAT_CODE_TEXT};
+use crate::messages::extendedquery::{
+    Bind, Describe, Execute, Parse, Sync as PgSync, TARGET_TYPE_BYTE_STATEMENT,
+};
+use crate::messages::simplequery::Query;
+use crate::messages::terminate::Terminate;
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// A row in text format, `None` for `NULL` values.
+pub type SimpleRow = Vec<Option<String>>;
+
+/// A minimal client for exercising pgwire servers in tests.
+///
+/// Values are always transferred in text format, so results can be compared
+/// with plain strings.
+#[derive(Debug)]
+pub struct SimpleClient {
+    socket: Framed<TcpStream, PgWireMessageClientCodec>,
+    server_info: ServerInfo,
+}
+
+impl SimpleClient {
+    /// Connect to server at `addr` and authenticate with cleartext or md5
+    /// password.
+    pub async fn connect(
+        addr: SocketAddr,
+        user: &str,
+        database: Option<&str>,
+        password: Option<&str>,
+    ) -> PgWireResult<SimpleClient> {
+        let stream = TcpStream::connect(addr).await?;
+        let mut socket = Framed::new(stream, PgWireMessageClientCodec::new());
+
+        let mut config = ClientConfig::new(user.to_owned());
+        config.database = database.map(ToOwned::to_owned);
+        config.password = password.map(ToOwned::to_owned);
+        let server_info = startup(&mut socket, &config).await?;
+
+        Ok(SimpleClient {
+            socket,
+            server_info,
+        })
+    }
+
+    /// Information reported by server during startup.
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server_info
+    }
+
+    /// Run `query` with simple query protocol, and return rows of all result
+    /// sets.
+    pub async fn simple_query(&mut self, query: &str) -> PgWireResult<Vec<SimpleRow>> {
+        self.socket
+            .send(PgWireFrontendMessage::Query(Query::new(query.to_owned())))
+            .await?;
+        self.collect_rows().await
+    }
+
+    /// Parse `query` as prepared statement `name`.
+    pub async fn prepare(&mut self, name: &str, query: &str) -> PgWireResult<()> {
+        self.socket
+            .feed(PgWireFrontendMessage::Parse(Parse::new(
+                Some(name.to_owned()),
+                query.to_owned(),
+                vec![],
+            )))
+            .await?;
+        self.socket
+            .feed(PgWireFrontendMessage::Describe(Describe::new(
+                TARGET_TYPE_BYTE_STATEMENT,
+                Some(name.to_owned()),
+            )))
+            .await?;
+        self.socket
+            .send(PgWireFrontendMessage::Sync(PgSync::new()))
+            .await?;
+
+        self.collect_rows().await.map(|_| ())
+    }
+
+    /// Execute prepared statement `name` with parameters in text format.
+    pub async fn execute_prepared(
+        &mut self,
+        name: &str,
+        params: &[Option<&str>],
+    ) -> PgWireResult<Vec<SimpleRow>> {
+        let parameters = params
+            .iter()
+            .map(|p| p.map(|v| Bytes::copy_from_slice(v.as_bytes())))
+            .collect();
+        self.socket
+            .feed(PgWireFrontendMessage::Bind(Bind::new(
+                None,
+                Some(name.to_owned()),
+                vec![FORMAT_CODE_TEXT],
+                parameters,
+                vec![FORMAT_CODE_TEXT],
+            )))
+            .await?;
+        self.socket
+            .feed(PgWireFrontendMessage::Execute(Execute::new(None, 0)))
+            .await?;
+        self.socket
+            .send(PgWireFrontendMessage::Sync(PgSync::new()))
+            .await?;
+
+        self.collect_rows().await
+    }
+
+    /// Terminate the session.
+    pub async fn close(mut self) -> PgWireResult<()> {
+        self.socket
+            .send(PgWireFrontendMessage::Terminate(Terminate::new()))
+            .await?;
+        Ok(())
+    }
+
+    /// Read responses until `ReadyForQuery`, collecting data rows. The first
+    /// error received is returned after that.
+    async fn collect_rows(&mut self) -> PgWireResult<Vec<SimpleRow>> {
+        let mut rows = Vec::new();
+        let mut error: Option<ErrorInfo> = None;
+        loop {
+            match next_message(&mut self.socket).await? {
+                PgWireBackendMessage::DataRow(row) => rows.push(decode_text_row(row)?),
+                PgWireBackendMessage::ErrorResponse(e) if error.is_none() => {
+                    error = Some(e.into());
+                }
+                PgWireBackendMessage::ParameterStatus(status) => {
+                    self.server_info
+                        .parameters
+                        .insert(status.name, status.value);
+                }
+                PgWireBackendMessage::ReadyForQuery(_) => break,
+                other @ (PgWireBackendMessage::CopyInResponse(_)
+                | PgWireBackendMessage::CopyBothResponse(_)) => {
+                    // copy from client is not supported
+                    return Err(PgWireError::unexpected_backend_message(
+                        "ReadyForQuery",
+                        &other,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(error) = error {
+            Err(PgWireError::UserError(Box::new(error)))
+        } else {
+            Ok(rows)
+        }
+    }
+}
+
+fn decode_text_row(row: DataRow) -> PgWireResult<SimpleRow> {
+    let mut data = row.data;
+    let mut values = Vec::with_capacity(row.field_count.max(0) as usize);
+    for _ in 0..row.field_count {
+        if data.remaining() < 4 {
+            return Err(PgWireError::InvalidDataRow);
+        }
+        let len = data.get_i32();
+        if len < 0 {
+            values.push(None);
+        } else {
+            let len = len as usize;
+            if data.remaining() < len {
+                return Err(PgWireError::InvalidDataRow);
+            }
+            let value = data.split_to(len);
+            values.push(Some(String::from_utf8_lossy(&value).into_owned()));
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use futures::{stream, Sink};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::api::auth::noop::NoopStartupHandler;
+    use crate::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+    use crate::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response};
+    use crate::api::{ClientInfo, Type};
+    use crate::tokio::process_socket;
+
+    struct EchoQueryHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for EchoQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let schema = Arc::new(vec![
+                FieldInfo::new("q".to_owned(), None, None, Type::VARCHAR, FieldFormat::Text),
+                FieldInfo::new("n".to_owned(), None, None, Type::INT4, FieldFormat::Text),
+            ]);
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            encoder.encode_field(&query)?;
+            encoder.encode_field(&None::<i32>)?;
+            let row = encoder.finish();
+            Ok(vec![Response::Query(QueryResponse::new(
+                schema,
+                stream::iter(vec![row]),
+            ))])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_query() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(EchoQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+            )
+            .await
+        });
+
+        let mut client = SimpleClient::connect(addr, "pgwire", Some("test"), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some("UTF8"),
+            client
+                .server_info()
+                .parameters
+                .get("client_encoding")
+                .map(String::as_str)
+        );
+
+        let rows = client.simple_query("SELECT 1").await.unwrap();
+        assert_eq!(vec![vec![Some("SELECT 1".to_owned()), None]], rows);
+
+        client.close().await.unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
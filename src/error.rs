@@ -4,37 +4,86 @@ use postgres_types::Oid;
 use thiserror::Error;
 
 use crate::messages::response::{ErrorResponse, NoticeResponse};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
+#[non_exhaustive]
 #[derive(Error, Debug)]
 pub enum PgWireError {
     #[error("Invalid protocol version, received {0}")]
     InvalidProtocolVersion(i32),
     #[error("Invalid message recevied, received {0}")]
     InvalidMessageType(u8),
+    #[error("expected {expected} message, got {got}")]
+    UnexpectedMessage {
+        expected: &'static str,
+        got: &'static str,
+    },
+    #[error("Invalid multiplexed message frame")]
+    InvalidMuxFrame,
     #[error("Invalid target type, received {0}")]
     InvalidTargetType(u8),
     #[error("Invalid startup message")]
     InvalidStartupMessage,
+    // forward display and source to the original error, so it's not repeated
+    // in error chain
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("Portal not found for name: {0:?}")]
     PortalNotFound(String),
     #[error("Statement not found for name: {0:?}")]
     StatementNotFound(String),
+    /// `max_rows` of `Execute` is reached, but rows of the response borrow
+    /// the portal, see `QueryResponse::new_owned`
+    #[error("Portal {0:?} cannot be suspended, its rows are not owned")]
+    PortalNotSuspendable(String),
     #[error("Unknown type: {0:?}")]
     UnknownTypeId(Oid),
     #[error("Parameter index out of bound: {0:?}")]
     ParameterIndexOutOfBound(usize),
     #[error("Cannot convert postgre type {0:?} to given rust type")]
     InvalidRustTypeForParameter(String),
+    #[error("No extension registered for type: {0}")]
+    UnknownTypeExtension(String),
+    #[error("Cannot convert given rust type to extension type {0}")]
+    InvalidRustTypeForExtension(String),
     #[error("Failed to parse parameter: {0:?}")]
-    FailedToParseParameter(Box<dyn std::error::Error + Send + Sync>),
+    FailedToParseParameter(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to encode parameter: {0:?}")]
+    FailedToEncodeParameter(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Parameter count mismatch, expected {expected}, got {got}")]
+    ParameterCountMismatch { expected: usize, got: usize },
+    #[error("Too many parameters: {0}")]
+    TooManyParameters(usize),
+    #[error("Invalid data row message")]
+    InvalidDataRow,
+    #[error("Insufficient bytes in message, need {needed}, remaining {remaining}")]
+    InsufficientBytes { needed: usize, remaining: usize },
+    #[error("Column index out of bound: {0:?}")]
+    ColumnIndexOutOfBound(usize),
+    #[error("Failed to parse column value: {0:?}")]
+    FailedToParseColumn(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("Failed to parse scram message: {0}")]
     InvalidScramMessage(String),
+    #[error(
+        "Invalid pepper length {0}, expected {}",
+        crate::api::auth::scram::PEPPER_LENGTH
+    )]
+    InvalidPepperLength(usize),
+    #[error("Invalid SCRAM verifier: {0}")]
+    InvalidScramVerifier(String),
     #[error("Certificate algorithm is not supported")]
     UnsupportedCertificateSignatureAlgorithm,
     #[error("Username is required")]
     UserNameRequired,
+    #[error("Password is required")]
+    PasswordRequired,
+    #[error("Authentication method is not supported")]
+    UnsupportedAuthenticationMethod,
+    #[error("Invalid command tag: {0}")]
+    InvalidCommandTag(String),
+    /// Returned by startup handlers that limit number of connections
+    #[error("Too many connections")]
+    TooManyConnections,
 
     #[error(transparent)]
     ApiError(#[from] Box<dyn std::error::Error + 'static + Send + Sync>),
@@ -43,6 +92,83 @@ pub enum PgWireError {
     UserError(Box<ErrorInfo>),
 }
 
+impl PgWireError {
+    /// SQLSTATE code that best describes this error, used as `C` field of
+    /// `ErrorResponse` sent to client.
+    ///
+    /// See <https://www.postgresql.org/docs/current/errcodes-appendix.html>
+    pub fn sqlstate(&self) -> &str {
+        match self {
+            // protocol_violation
+            PgWireError::InvalidProtocolVersion(_)
+            | PgWireError::InvalidMessageType(_)
+            | PgWireError::UnexpectedMessage { .. }
+            | PgWireError::InvalidMuxFrame
+            | PgWireError::InvalidTargetType(_)
+            | PgWireError::InvalidStartupMessage
+            | PgWireError::ParameterIndexOutOfBound(_)
+            | PgWireError::ParameterCountMismatch { .. }
+            | PgWireError::InvalidDataRow
+            | PgWireError::InsufficientBytes { .. }
+            | PgWireError::InvalidScramMessage(_) => "08P01",
+            // connection_failure
+            PgWireError::IoError(_) => "08006",
+            // invalid_cursor_name
+            PgWireError::PortalNotFound(_) => "34000",
+            // invalid_sql_statement_name
+            PgWireError::StatementNotFound(_) => "26000",
+            // undefined_object
+            PgWireError::UnknownTypeId(_) | PgWireError::UnknownTypeExtension(_) => "42704",
+            // datatype_mismatch
+            PgWireError::InvalidRustTypeForParameter(_)
+            | PgWireError::InvalidRustTypeForExtension(_) => "42804",
+            // invalid_text_representation
+            PgWireError::FailedToParseParameter(_) | PgWireError::FailedToParseColumn(_) => "22P02",
+            // data_exception
+            PgWireError::FailedToEncodeParameter(_) => "22000",
+            // too_many_arguments
+            PgWireError::TooManyParameters(_) => "54023",
+            // invalid_column_reference
+            PgWireError::ColumnIndexOutOfBound(_) => "42P10",
+            // invalid_authorization_specification
+            PgWireError::UnsupportedCertificateSignatureAlgorithm
+            | PgWireError::UserNameRequired
+            | PgWireError::UnsupportedAuthenticationMethod => "28000",
+            // invalid_password
+            PgWireError::PasswordRequired => "28P01",
+            // too_many_connections
+            PgWireError::TooManyConnections => "53300",
+            // feature_not_supported
+            PgWireError::PortalNotSuspendable(_) => "0A000",
+            // internal_error
+            PgWireError::InvalidCommandTag(_)
+            | PgWireError::InvalidPepperLength(_)
+            | PgWireError::InvalidScramVerifier(_)
+            | PgWireError::ApiError(_) => "XX000",
+            PgWireError::UserError(info) => &info.code,
+        }
+    }
+
+    /// Create `UnexpectedMessage` error from received frontend message.
+    pub fn unexpected_message(expected: &'static str, got: &PgWireFrontendMessage) -> PgWireError {
+        PgWireError::UnexpectedMessage {
+            expected,
+            got: got.message_name(),
+        }
+    }
+
+    /// Create `UnexpectedMessage` error from received backend message.
+    pub fn unexpected_backend_message(
+        expected: &'static str,
+        got: &PgWireBackendMessage,
+    ) -> PgWireError {
+        PgWireError::UnexpectedMessage {
+            expected,
+            got: got.message_name(),
+        }
+    }
+}
+
 impl From<PgWireError> for IOError {
     fn from(e: PgWireError) -> Self {
         IOError::new(ErrorKind::Other, e)
@@ -56,6 +182,7 @@ pub type PgWireResult<T> = Result<T, PgWireError>;
 // https://www.postgresql.org/docs/8.2/protocol-error-fields.html
 #[non_exhaustive]
 #[derive(new, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ErrorInfo {
     // severity can be one of `ERROR`, `FATAL`, or `PANIC` (in an error
     // message), or `WARNING`, `NOTICE`, `DEBUG`, `INFO`, or `LOG` (in a notice
@@ -139,6 +266,42 @@ impl ErrorInfo {
     }
 }
 
+impl ErrorInfo {
+    fn from_fields(fields: Vec<(u8, String)>) -> ErrorInfo {
+        let mut info = ErrorInfo::new(String::new(), String::new(), String::new());
+        for (code, value) in fields {
+            match code {
+                b'S' => info.severity = value,
+                b'C' => info.code = value,
+                b'M' => info.message = value,
+                b'D' => info.detail = Some(value),
+                b'H' => info.hint = Some(value),
+                b'P' => info.position = Some(value),
+                b'p' => info.internal_position = Some(value),
+                b'q' => info.internal_query = Some(value),
+                b'W' => info.where_context = Some(value),
+                b'F' => info.file_name = Some(value),
+                b'L' => info.line = value.parse().ok(),
+                b'R' => info.routine = Some(value),
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+impl From<ErrorResponse> for ErrorInfo {
+    fn from(resp: ErrorResponse) -> ErrorInfo {
+        ErrorInfo::from_fields(resp.fields)
+    }
+}
+
+impl From<NoticeResponse> for ErrorInfo {
+    fn from(resp: NoticeResponse) -> ErrorInfo {
+        ErrorInfo::from_fields(resp.fields)
+    }
+}
+
 impl From<ErrorInfo> for ErrorResponse {
     fn from(ei: ErrorInfo) -> ErrorResponse {
         ErrorResponse::new(ei.into_fields())
@@ -154,6 +317,97 @@ impl From<ErrorInfo> for NoticeResponse {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::messages::simplequery::Query;
+    use crate::messages::startup::SslRequest;
+
+    #[test]
+    fn test_sqlstate() {
+        let cases = vec![
+            ("08P01", PgWireError::InvalidProtocolVersion(2)),
+            ("08P01", PgWireError::InvalidMessageType(b'?')),
+            (
+                "08P01",
+                PgWireError::UnexpectedMessage {
+                    expected: "Query",
+                    got: "Terminate",
+                },
+            ),
+            ("08P01", PgWireError::InvalidMuxFrame),
+            ("08P01", PgWireError::InvalidTargetType(b'?')),
+            ("08P01", PgWireError::InvalidStartupMessage),
+            (
+                "08006",
+                PgWireError::IoError(IOError::from(std::io::ErrorKind::BrokenPipe)),
+            ),
+            ("34000", PgWireError::PortalNotFound("p1".to_owned())),
+            ("26000", PgWireError::StatementNotFound("s1".to_owned())),
+            ("0A000", PgWireError::PortalNotSuspendable("p1".to_owned())),
+            ("42704", PgWireError::UnknownTypeId(0)),
+            ("08P01", PgWireError::ParameterIndexOutOfBound(1)),
+            (
+                "42804",
+                PgWireError::InvalidRustTypeForParameter("int4".to_owned()),
+            ),
+            (
+                "42704",
+                PgWireError::UnknownTypeExtension("vector".to_owned()),
+            ),
+            (
+                "42804",
+                PgWireError::InvalidRustTypeForExtension("vector".to_owned()),
+            ),
+            (
+                "22P02",
+                PgWireError::FailedToParseParameter("invalid".into()),
+            ),
+            (
+                "22000",
+                PgWireError::FailedToEncodeParameter("invalid".into()),
+            ),
+            (
+                "08P01",
+                PgWireError::ParameterCountMismatch {
+                    expected: 1,
+                    got: 2,
+                },
+            ),
+            ("54023", PgWireError::TooManyParameters(70000)),
+            ("08P01", PgWireError::InvalidDataRow),
+            (
+                "08P01",
+                PgWireError::InsufficientBytes {
+                    needed: 4,
+                    remaining: 2,
+                },
+            ),
+            ("42P10", PgWireError::ColumnIndexOutOfBound(1)),
+            ("22P02", PgWireError::FailedToParseColumn("invalid".into())),
+            ("08P01", PgWireError::InvalidScramMessage("n,,".to_owned())),
+            ("XX000", PgWireError::InvalidPepperLength(16)),
+            ("XX000", PgWireError::InvalidScramVerifier("".to_owned())),
+            (
+                "28000",
+                PgWireError::UnsupportedCertificateSignatureAlgorithm,
+            ),
+            ("28000", PgWireError::UserNameRequired),
+            ("28P01", PgWireError::PasswordRequired),
+            ("28000", PgWireError::UnsupportedAuthenticationMethod),
+            ("XX000", PgWireError::InvalidCommandTag("".to_owned())),
+            ("53300", PgWireError::TooManyConnections),
+            ("XX000", PgWireError::ApiError("oops".into())),
+            (
+                "42P01",
+                PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42P01".to_owned(),
+                    "relation does not exist".to_owned(),
+                ))),
+            ),
+        ];
+        for (sqlstate, error) in cases {
+            assert_eq!(sqlstate, error.sqlstate(), "{error:?}");
+        }
+    }
 
     #[test]
     fn test_error_notice_info() {
@@ -167,4 +421,53 @@ mod test {
         assert_eq!("Password authentication failed", error_info.message);
         assert!(error_info.file_name.is_none());
     }
+
+    #[test]
+    fn test_unexpected_message() {
+        let error = PgWireError::unexpected_message(
+            "Sync",
+            &PgWireFrontendMessage::Query(Query::new("SELECT 1".to_owned())),
+        );
+        assert_eq!("expected Sync message, got Query", error.to_string());
+
+        // startup messages have no type code
+        let error = PgWireError::unexpected_message(
+            "PasswordMessage",
+            &PgWireFrontendMessage::SslRequest(SslRequest::new()),
+        );
+        assert_eq!(
+            "expected PasswordMessage message, got SslRequest",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        fn parse(v: &str) -> PgWireResult<i32> {
+            let v = v
+                .parse::<i32>()
+                .map_err(|e| PgWireError::FailedToParseParameter(Box::new(e)))?;
+            Ok(v)
+        }
+        let error = parse("abc").unwrap_err();
+        let source = error.source().unwrap();
+        assert!(source.is::<std::num::ParseIntError>());
+
+        let io_error = IOError::new(ErrorKind::TimedOut, "read timeout");
+        let error = PgWireError::from(io_error);
+        assert_eq!("read timeout", error.to_string());
+        assert!(error.source().is_none());
+        assert!(matches!(&error, PgWireError::IoError(e) if e.kind() == ErrorKind::TimedOut));
+
+        fn handler() -> PgWireResult<()> {
+            let boxed: Box<dyn Error + Send + Sync> = "table not found".into();
+            Err(boxed)?
+        }
+        let error = handler().unwrap_err();
+        assert_eq!("table not found", error.to_string());
+        assert!(error.source().is_none());
+        assert!(matches!(&error, PgWireError::ApiError(e) if e.to_string() == "table not found"));
+    }
 }
@@ -0,0 +1,424 @@
+//! GSSAPI transport encryption, requested by clients with `gssencmode`.
+//!
+//! After `GSSENCRequest` is accepted, client establishes a security context
+//! by exchanging tokens in packets framed as `length: u32` followed by
+//! `length` bytes. The rest of protocol is carried in packets of the same
+//! framing, each holding data wrapped by the context.
+//!
+//! Set [`GssEncAcceptor`] as
+//! [`ProcessSocketOptions::gss_encryption`](crate::tokio::ProcessSocketOptions::gss_encryption)
+//! to accept `GSSENCRequest`, otherwise it's refused.
+
+use std::io::{Error as IOError, ErrorKind};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_util::io::poll_read_buf;
+
+use crate::api::auth::gss::{GssAcceptStep, GssContext, GssProvider, GssWrap};
+
+/// Max size of a packet, including the length field, same as Postgres.
+pub const MAX_PACKET_SIZE: usize = 16384;
+
+const LENGTH_SIZE: usize = 4;
+
+/// Stream carrying data in packets wrapped by an established security
+/// context.
+pub struct GssEncStream<S> {
+    inner: S,
+    context: Box<dyn GssWrap>,
+    /// Max length of data wrapped in a packet
+    max_chunk: usize,
+    /// Received packets not unwrapped yet
+    read_buf: BytesMut,
+    /// Unwrapped data not read yet
+    plain: BytesMut,
+    /// Wrapped packets not written yet
+    write_buf: BytesMut,
+}
+
+impl<S> GssEncStream<S> {
+    pub fn new(inner: S, context: Box<dyn GssWrap>) -> Self {
+        let max_chunk = context.wrap_size_limit(MAX_PACKET_SIZE - LENGTH_SIZE);
+        GssEncStream {
+            inner,
+            context,
+            max_chunk,
+            read_buf: BytesMut::new(),
+            plain: BytesMut::new(),
+            write_buf: BytesMut::new(),
+        }
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> std::fmt::Debug for GssEncStream<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GssEncStream")
+            .field("max_chunk", &self.max_chunk)
+            .finish()
+    }
+}
+
+fn invalid_packet(message: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Take a complete packet from `buf`, if any.
+fn split_packet(buf: &mut BytesMut) -> Result<Option<BytesMut>, IOError> {
+    if buf.len() < LENGTH_SIZE {
+        return Ok(None);
+    }
+    let len = (&buf[..LENGTH_SIZE]).get_u32() as usize;
+    if len > MAX_PACKET_SIZE - LENGTH_SIZE {
+        return Err(invalid_packet("oversize GSSAPI packet"));
+    }
+    if buf.len() < LENGTH_SIZE + len {
+        return Ok(None);
+    }
+    buf.advance(LENGTH_SIZE);
+    Ok(Some(buf.split_to(len)))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for GssEncStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), IOError>> {
+        let this = self.get_mut();
+        loop {
+            if !this.plain.is_empty() {
+                let len = this.plain.len().min(buf.remaining());
+                buf.put_slice(&this.plain.split_to(len));
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(token) = split_packet(&mut this.read_buf)? {
+                let data = this.context.unwrap(&token)?;
+                this.plain.extend_from_slice(&data);
+                continue;
+            }
+
+            if std::task::ready!(poll_read_buf(
+                Pin::new(&mut this.inner),
+                cx,
+                &mut this.read_buf
+            ))? == 0
+            {
+                return if this.read_buf.is_empty() {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Ready(Err(ErrorKind::UnexpectedEof.into()))
+                };
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> GssEncStream<S> {
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IOError>> {
+        while !self.write_buf.is_empty() {
+            let written =
+                std::task::ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_buf))?;
+            if written == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.advance(written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for GssEncStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IOError>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_write_buf(cx))?;
+
+        let data = &buf[..buf.len().min(this.max_chunk)];
+        let token = this.context.wrap(data)?;
+        if token.len() > MAX_PACKET_SIZE - LENGTH_SIZE {
+            return Poll::Ready(Err(invalid_packet("oversize GSSAPI token")));
+        }
+        this.write_buf.put_u32(token.len() as u32);
+        this.write_buf.extend_from_slice(&token);
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IOError>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IOError>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Establishes GSSAPI encryption on a socket whose `GSSENCRequest` is
+/// accepted.
+#[async_trait]
+pub trait GssEncAccept: Send + Sync {
+    /// Exchange tokens until the security context is established. Returns
+    /// the encrypted stream, and principal of client.
+    async fn accept(&self, socket: TcpStream)
+        -> Result<(GssEncStream<TcpStream>, String), IOError>;
+}
+
+/// [`GssEncAccept`] with contexts created by a [`GssProvider`].
+#[derive(Debug, new)]
+pub struct GssEncAcceptor<G> {
+    provider: Arc<G>,
+}
+
+async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, IOError> {
+    let len = stream.read_u32().await? as usize;
+    if len > MAX_PACKET_SIZE - LENGTH_SIZE {
+        return Err(invalid_packet("oversize GSSAPI packet"));
+    }
+    let mut token = vec![0u8; len];
+    stream.read_exact(&mut token).await?;
+    Ok(token)
+}
+
+async fn write_packet<S: AsyncWrite + Unpin>(stream: &mut S, token: &[u8]) -> Result<(), IOError> {
+    if token.len() > MAX_PACKET_SIZE - LENGTH_SIZE {
+        return Err(invalid_packet("oversize GSSAPI token"));
+    }
+    let mut buf = BytesMut::with_capacity(LENGTH_SIZE + token.len());
+    buf.put_u32(token.len() as u32);
+    buf.extend_from_slice(token);
+    stream.write_all(&buf).await?;
+    stream.flush().await
+}
+
+#[async_trait]
+impl<G> GssEncAccept for GssEncAcceptor<G>
+where
+    G: GssProvider,
+    G::Context: GssWrap + 'static,
+{
+    async fn accept(
+        &self,
+        mut socket: TcpStream,
+    ) -> Result<(GssEncStream<TcpStream>, String), IOError> {
+        let mut context = self.provider.new_context()?;
+        loop {
+            let token = read_packet(&mut socket).await?;
+            match context.step(&token)? {
+                GssAcceptStep::Continue(token) => write_packet(&mut socket, &token).await?,
+                GssAcceptStep::Complete { token, principal } => {
+                    if let Some(token) = token {
+                        write_packet(&mut socket, &token).await?;
+                    }
+                    return Ok((GssEncStream::new(socket, Box::new(context)), principal));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use futures::Sink;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::api::auth::noop::NoopStartupHandler;
+    use crate::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+    use crate::api::results::Response;
+    use crate::api::ClientInfo;
+    use crate::error::{PgWireError, PgWireResult};
+    use crate::messages::simplequery::Query;
+    use crate::messages::startup::{GssEncRequest, Startup};
+    use crate::messages::{Message, PgWireBackendMessage};
+    use crate::tokio::{process_socket_with_options, ProcessSocketOptions};
+
+    /// Accepts `hello` as the only token, and wraps data by XOR.
+    struct XorContext;
+
+    impl GssContext for XorContext {
+        fn step(&mut self, token: &[u8]) -> PgWireResult<GssAcceptStep> {
+            if token == b"hello" {
+                Ok(GssAcceptStep::Complete {
+                    token: Some(Bytes::from_static(b"welcome")),
+                    principal: "alice@EXAMPLE.COM".to_owned(),
+                })
+            } else {
+                Err(PgWireError::UnsupportedAuthenticationMethod)
+            }
+        }
+    }
+
+    impl GssWrap for XorContext {
+        fn wrap(&mut self, data: &[u8]) -> PgWireResult<Vec<u8>> {
+            Ok(data.iter().map(|b| b ^ 0x5a).collect())
+        }
+
+        fn unwrap(&mut self, token: &[u8]) -> PgWireResult<Vec<u8>> {
+            self.wrap(token)
+        }
+
+        fn wrap_size_limit(&self, _max_token: usize) -> usize {
+            10
+        }
+    }
+
+    struct XorProvider;
+
+    impl GssProvider for XorProvider {
+        type Context = XorContext;
+
+        fn new_context(&self) -> PgWireResult<Self::Context> {
+            Ok(XorContext)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gss_enc_stream() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = GssEncStream::new(server, Box::new(XorContext));
+        let mut client = GssEncStream::new(client, Box::new(XorContext));
+
+        // split into packets of 10 bytes
+        let data = b"GSSAPI encrypted packets".to_vec();
+        server.write_all(&data).await.unwrap();
+        server.flush().await.unwrap();
+        let mut received = vec![0u8; data.len()];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(data, received);
+
+        client.write_all(b"reply").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(b"reply".to_vec(), received);
+    }
+
+    #[test]
+    fn test_split_packet() {
+        let mut buf = BytesMut::from(&b"\0\0\0\x03ab"[..]);
+        assert!(split_packet(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"cd");
+        assert_eq!(&b"abc"[..], split_packet(&mut buf).unwrap().unwrap());
+        assert_eq!(&b"d"[..], buf);
+
+        let mut buf = BytesMut::from(&b"\0\x01\0\0"[..]);
+        assert!(split_packet(&mut buf).is_err());
+    }
+
+    /// Records GSSAPI principal of client seen by the handler
+    #[derive(Default)]
+    struct PrincipalQueryHandler(Mutex<Option<String>>);
+
+    #[async_trait]
+    impl SimpleQueryHandler for PrincipalQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            *self.0.lock().unwrap() = client.gss_principal().map(str::to_owned);
+            Ok(vec![])
+        }
+    }
+
+    /// Read messages until `ReadyForQuery`
+    async fn receive<S: AsyncRead + Unpin>(stream: &mut S) -> Vec<PgWireBackendMessage> {
+        let mut buf = BytesMut::new();
+        let mut received = Vec::new();
+        loop {
+            while let Some(msg) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+                let ready = matches!(msg, PgWireBackendMessage::ReadyForQuery(_));
+                received.push(msg);
+                if ready {
+                    return received;
+                }
+            }
+            assert!(stream.read_buf(&mut buf).await.unwrap() > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gss_encryption() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(PrincipalQueryHandler::default());
+
+        let query_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                gss_encryption: Some(Arc::new(GssEncAcceptor::new(Arc::new(XorProvider)))),
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                query_handler,
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        GssEncRequest::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(b'G', client.read_u8().await.unwrap());
+
+        write_packet(&mut client, b"hello").await.unwrap();
+        assert_eq!(b"welcome".to_vec(), read_packet(&mut client).await.unwrap());
+        let mut client = GssEncStream::new(client, Box::new(XorContext));
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "alice".to_owned());
+        let mut buf = BytesMut::new();
+        startup.encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        client.flush().await.unwrap();
+        receive(&mut client).await;
+
+        let mut buf = BytesMut::new();
+        Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        client.flush().await.unwrap();
+        receive(&mut client).await;
+        drop(client);
+        server.await.unwrap().unwrap();
+
+        assert_eq!(
+            Some("alice@EXAMPLE.COM".to_owned()),
+            *handler.0.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,474 @@
+//! Server entry-point on io_uring.
+//!
+//! [`process_socket_uring`] serves a connection of [`tokio_uring`] runtime,
+//! which submits socket reads and writes to io_uring instead of waiting for
+//! readiness with epoll. Messages are handled by the same handlers as
+//! [`process_socket`](crate::tokio::process_socket).
+//!
+//! Messages received in one read are processed as a batch, and all responses
+//! of the batch are written back with a single `write`. With a
+//! [`FixedBufPool`] registered to the runtime, reads go to the registered
+//! buffers so the kernel doesn't have to map user memory on every call.
+//!
+//! Compared to `process_socket`, TLS, multiplexing, replication and server
+//! push are not supported: `SslRequest` is always refused.
+//!
+//! To compare the two backends, run `pgbench` against the `server` and
+//! `uring_server` examples:
+//!
+//! ```text
+//! pgbench -h 127.0.0.1 -p 5432 -S -n -c 32 -T 30 -M simple
+//! ```
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Error as IOError;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::BytesMut;
+use futures::{Sink, SinkExt};
+use tokio::time::{timeout_at, Instant};
+use tokio_uring::buf::fixed::FixedBufPool;
+use tokio_uring::buf::BoundedBuf;
+use tokio_uring::net::TcpStream;
+use tokio_util::codec::Decoder;
+
+use crate::api::auth::StartupHandler;
+use crate::api::guc::GucRegistry;
+use crate::api::notice::NoticeEmitter;
+use crate::api::process::{process_error, process_message};
+use crate::api::query::{ExtendedQueryHandler, SimpleQueryHandler};
+use crate::api::replication::PlaceholderReplicationHandler;
+use crate::api::transaction::TransactionStatus;
+use crate::api::{ClientInfo, ClientPortalStore, DefaultClient, PgWireConnectionState};
+use crate::messages::response::{NoticeResponse, SslResponse};
+use crate::messages::startup::{BackendKeyData, ParameterStatus};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+use crate::tokio::{
+    is_authenticating, send_auth_timeout, PgWireMessageServerCodec, DEFAULT_AUTH_TIMEOUT,
+};
+
+/// Initial capacity of read and write buffers of a connection.
+const BUFFER_SIZE: usize = 8192;
+
+/// Client of an io_uring connection.
+///
+/// Backend messages are encoded into an outgoing buffer, which is written to
+/// the socket once all messages of current batch are processed.
+#[derive(Debug)]
+struct UringClient<S> {
+    codec: PgWireMessageServerCodec<S>,
+    outgoing: BytesMut,
+    closed: bool,
+}
+
+impl<S> ClientInfo for UringClient<S> {
+    fn socket_addr(&self) -> SocketAddr {
+        self.codec.client_info.socket_addr
+    }
+
+    fn is_secure(&self) -> bool {
+        self.codec.client_info.is_secure
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.codec.client_info.state
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.codec.client_info.set_state(new_state);
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        self.codec.client_info.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        self.codec.client_info.metadata_mut()
+    }
+
+    fn notice_emitter(&self) -> Option<NoticeEmitter> {
+        self.codec.client_info.notice_emitter()
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.codec.client_info.take_notices()
+    }
+
+    fn push_parameter_status(&mut self, name: &str, value: &str) {
+        self.codec.client_info.push_parameter_status(name, value);
+    }
+
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        self.codec.client_info.take_parameter_status()
+    }
+
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        self.codec.client_info.backend_key_data()
+    }
+
+    fn set_backend_key_data(&mut self, backend_key_data: BackendKeyData) {
+        self.codec
+            .client_info
+            .set_backend_key_data(backend_key_data);
+    }
+
+    fn guc_registry(&self) -> Option<Arc<Mutex<GucRegistry>>> {
+        self.codec.client_info.guc_registry()
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.codec.client_info.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.codec.client_info.set_transaction_status(status);
+    }
+
+    fn protocol_minor_version(&self) -> u16 {
+        self.codec.client_info.protocol_minor_version()
+    }
+
+    fn set_protocol_minor_version(&mut self, minor_version: u16) {
+        self.codec
+            .client_info
+            .set_protocol_minor_version(minor_version);
+    }
+}
+
+impl<S> ClientPortalStore for UringClient<S> {
+    type PortalStore = <DefaultClient<S> as ClientPortalStore>::PortalStore;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        self.codec.client_info.portal_store()
+    }
+}
+
+impl<S> Sink<PgWireBackendMessage> for UringClient<S> {
+    type Error = IOError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: PgWireBackendMessage) -> Result<(), Self::Error> {
+        item.encode(&mut self.get_mut().outgoing)
+            .map_err(Into::into)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // buffered messages are written by the connection loop
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Options for [`process_socket_uring_with_options`].
+#[non_exhaustive]
+#[derive(Clone)]
+pub struct UringSocketOptions {
+    /// Max time from accepting the connection to finishing authentication.
+    pub auth_timeout: Duration,
+    /// Registered buffers to read messages into.
+    ///
+    /// The pool has to be registered to current runtime with
+    /// [`FixedBufPool::register`]. Connections fall back to ordinary buffers
+    /// when all buffers of the pool are in use.
+    pub buffer_pool: Option<FixedBufPool<Vec<u8>>>,
+}
+
+impl Default for UringSocketOptions {
+    fn default() -> Self {
+        Self {
+            auth_timeout: DEFAULT_AUTH_TIMEOUT,
+            buffer_pool: None,
+        }
+    }
+}
+
+impl Debug for UringSocketOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UringSocketOptions")
+            .field("auth_timeout", &self.auth_timeout)
+            .field("buffer_pool", &self.buffer_pool.is_some())
+            .finish()
+    }
+}
+
+/// Read available bytes from `socket` and append them to `read_buf`.
+///
+/// Returns number of bytes read, `0` means end of stream.
+async fn read_into(
+    socket: &TcpStream,
+    read_buf: &mut BytesMut,
+    buffer_pool: Option<&FixedBufPool<Vec<u8>>>,
+) -> Result<usize, IOError> {
+    if let Some(fixed) = buffer_pool.and_then(|pool| pool.try_next(BUFFER_SIZE)) {
+        let (res, fixed) = socket.read_fixed(fixed).await;
+        let n = res?;
+        read_buf.extend_from_slice(&fixed[..n]);
+        return Ok(n);
+    }
+
+    read_buf.reserve(BUFFER_SIZE);
+    let len = read_buf.len();
+    let buf = std::mem::take(read_buf);
+    let (res, slice) = socket.read(buf.slice(len..)).await;
+    *read_buf = slice.into_inner();
+    res
+}
+
+/// Write buffered responses of `client` to `socket`.
+async fn write_outgoing<S>(socket: &TcpStream, client: &mut UringClient<S>) -> Result<(), IOError> {
+    if client.outgoing.is_empty() {
+        return Ok(());
+    }
+
+    let buf = std::mem::take(&mut client.outgoing);
+    let (res, mut buf) = socket.write_all(buf).await;
+    res?;
+    // keep the allocation for next batch
+    buf.clear();
+    client.outgoing = buf;
+    Ok(())
+}
+
+pub async fn process_socket_uring<A, Q, EQ>(
+    socket: TcpStream,
+    addr: SocketAddr,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+{
+    process_socket_uring_with_options(
+        socket,
+        addr,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        UringSocketOptions::default(),
+    )
+    .await
+}
+
+/// Same as `process_socket_uring`, with behaviour customized by `options`.
+pub async fn process_socket_uring_with_options<A, Q, EQ>(
+    socket: TcpStream,
+    addr: SocketAddr,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    options: UringSocketOptions,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+{
+    let auth_deadline = Instant::now() + options.auth_timeout;
+    socket.set_nodelay(true)?;
+
+    let mut client = UringClient {
+        codec: PgWireMessageServerCodec::new(DefaultClient::new(addr, false)),
+        outgoing: BytesMut::with_capacity(BUFFER_SIZE),
+        closed: false,
+    };
+    let mut read_buf = BytesMut::with_capacity(BUFFER_SIZE);
+
+    loop {
+        // process all complete messages received so far
+        loop {
+            let msg = match client.codec.decode(&mut read_buf) {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(_) => {
+                    write_outgoing(&socket, &mut client).await?;
+                    return Ok(());
+                }
+            };
+
+            match msg {
+                PgWireFrontendMessage::SslRequest(_) => {
+                    client
+                        .feed(PgWireBackendMessage::SslResponse(SslResponse::Refuse))
+                        .await?;
+                    continue;
+                }
+                PgWireFrontendMessage::Terminate(_) => {
+                    write_outgoing(&socket, &mut client).await?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+
+            let is_extended_query = msg.is_extended_query();
+            let is_cancel_request = matches!(msg, PgWireFrontendMessage::CancelRequest(_));
+            if let Err(e) = process_message(
+                msg,
+                &mut client,
+                startup_handler.as_ref(),
+                query_handler.as_ref(),
+                extended_query_handler.as_ref(),
+                None::<&PlaceholderReplicationHandler>,
+                None,
+            )
+            .await
+            {
+                process_error(&mut client, e, is_extended_query).await?;
+            }
+            for notice in client.take_notices() {
+                client
+                    .feed(PgWireBackendMessage::NoticeResponse(notice))
+                    .await?;
+            }
+
+            if client.closed || is_cancel_request {
+                write_outgoing(&socket, &mut client).await?;
+                return Ok(());
+            }
+        }
+
+        write_outgoing(&socket, &mut client).await?;
+
+        let read = read_into(&socket, &mut read_buf, options.buffer_pool.as_ref());
+        let n = if is_authenticating(&client) {
+            match timeout_at(auth_deadline, read).await {
+                Ok(n) => n?,
+                Err(_) => {
+                    send_auth_timeout(&mut client).await?;
+                    return write_outgoing(&socket, &mut client).await;
+                }
+            }
+        } else {
+            read.await?
+        };
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use async_trait::async_trait;
+    use tokio_uring::net::TcpListener;
+
+    use super::*;
+    use crate::api::auth::noop::NoopStartupHandler;
+    use crate::api::query::PlaceholderExtendedQueryHandler;
+    use crate::api::results::{Response, Tag};
+    use crate::error::PgWireResult;
+    use crate::messages::simplequery::Query;
+    use crate::messages::startup::Startup;
+    use crate::messages::terminate::Terminate;
+    use crate::messages::Message;
+
+    struct DummyQueryHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for DummyQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            Ok(vec![Response::Execution(Tag::new("OK").with_rows(1))])
+        }
+    }
+
+    /// Read backend messages until `ReadyForQuery`, return their types.
+    fn read_until_ready(stream: &mut std::net::TcpStream) -> Vec<u8> {
+        let mut types = Vec::new();
+        loop {
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).unwrap();
+            let len = i32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+            let mut body = vec![0u8; len as usize - 4];
+            stream.read_exact(&mut body).unwrap();
+            types.push(header[0]);
+            if header[0] == b'Z' {
+                return types;
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_socket_uring() {
+        tokio_uring::start(async {
+            let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let client = std::thread::spawn(move || {
+                let mut stream = std::net::TcpStream::connect(server_addr).unwrap();
+
+                // ssl is refused
+                stream.write_all(&[0, 0, 0, 8, 4, 210, 22, 47]).unwrap();
+                let mut resp = [0u8; 1];
+                stream.read_exact(&mut resp).unwrap();
+                assert_eq!(SslResponse::BYTE_REFUSE, resp[0]);
+
+                let mut buf = BytesMut::new();
+                let mut startup = Startup::new();
+                startup
+                    .parameters
+                    .insert("user".to_owned(), "tomcat".to_owned());
+                startup.encode(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+                let types = read_until_ready(&mut stream);
+                assert_eq!(Some(&b'R'), types.first());
+
+                // pipelined queries
+                buf.clear();
+                Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+                Query::new("SELECT 2".to_owned()).encode(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+                assert_eq!(vec![b'C', b'Z'], read_until_ready(&mut stream));
+                assert_eq!(vec![b'C', b'Z'], read_until_ready(&mut stream));
+
+                buf.clear();
+                Terminate::new().encode(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+                // server closes the connection
+                assert_eq!(0, stream.read(&mut resp).unwrap());
+            });
+
+            let buffer_pool = FixedBufPool::new((0..4).map(|_| Vec::with_capacity(BUFFER_SIZE)));
+            buffer_pool.register().unwrap();
+            let options = UringSocketOptions {
+                buffer_pool: Some(buffer_pool),
+                ..Default::default()
+            };
+
+            let (socket, addr) = listener.accept().await.unwrap();
+            process_socket_uring_with_options(
+                socket,
+                addr,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+            .unwrap();
+
+            client.join().unwrap();
+        });
+    }
+}
@@ -13,10 +13,10 @@
 //!
 //! - Startup: client-server handshake and authentication.
 //! - Simple Query: The legacy query protocol of postgresql. Query are provided
-//! as string, and server is allowed to stream data in response.
+//!   as string, and server is allowed to stream data in response.
 //! - Extended Query: A new sub-protocol for query which has ability to cache
-//! the query on server-side and reuse it with new parameters. The response part
-//! is identical to Simple Query.
+//!   the query on server-side and reuse it with new parameters. The response part
+//!   is identical to Simple Query.
 //!
 //! Also note that Postgres Wire Protocol has no semantics about SQL, so
 //! literally you can use any query language, data formats or even natural
@@ -37,7 +37,7 @@
 //! application from any level of abstraction. They are:
 //!
 //! - Protocol layer: Just use message definitions and codecs in `messages`
-//! module.
+//!   module.
 //! - Message handler layer: Implement `on_` prefixed methods in traits:
 //!   - `StartupHandler`
 //!   - `SimpleQueryHandler`
@@ -58,10 +58,37 @@ extern crate derive_new;
 
 /// handler layer and high-level API layer.
 pub mod api;
+/// client side connection and query APIs.
+#[cfg(feature = "tokio")]
+pub mod client;
 /// error types.
 pub mod error;
+/// GSSAPI transport encryption.
+#[cfg(all(feature = "gss", feature = "tokio"))]
+pub mod gssenc;
+/// server entry-point on io_uring, using `tokio-uring` runtime.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod io_uring;
 /// the protocol layer.
 pub mod messages;
+/// multiplexing of logical sessions over a connection.
+#[cfg(feature = "tokio")]
+pub mod mux;
+/// oid constants of standard types.
+pub mod oid_constants;
+/// PROXY protocol header sent by load balancers.
+#[cfg(feature = "tokio")]
+pub mod proxy;
+/// limit of errors from the same source address.
+#[cfg(feature = "tokio")]
+pub mod ratelimit;
+/// fixed size read buffer for reassembling messages without copying.
+#[cfg(feature = "tokio")]
+pub mod ringbuf;
+#[cfg(feature = "serde")]
+mod serde_util;
+/// blocking server entry-point without async runtime.
+pub mod sync;
 /// server entry-point for tokio based application.
 #[cfg(feature = "tokio")]
 pub mod tokio;
@@ -2,41 +2,62 @@ use std::str;
 
 use bytes::{Buf, BufMut, BytesMut};
 
-use crate::error::PgWireResult;
+use crate::error::{PgWireError, PgWireResult};
 
-/// Get null-terminated string, returns None when empty cstring read.
-///
-/// Note that this implementation will also advance cursor by 1 after reading
-/// empty cstring. This behaviour works for how postgres wire protocol handling
-/// key-value pairs, which is ended by a single `\0`
-pub(crate) fn get_cstring(buf: &mut BytesMut) -> Option<String> {
-    let mut i = 0;
+/// Result of reading a null-terminated string
+#[derive(Debug, PartialEq, Eq)]
+pub enum CStringResult {
+    /// a non-empty string
+    Value(String),
+    /// a single `\0`
+    Empty,
+    /// no `\0` in the buffer, nothing is consumed
+    Incomplete,
+}
 
-    // with bound check to prevent invalid format
-    while i < buf.remaining() && buf[i] != b'\0' {
-        i += 1;
-    }
+/// Get null-terminated string, distinguishing empty cstring from missing
+/// `\0`. The cursor is advanced past the `\0` unless the string is
+/// incomplete.
+pub fn get_cstring_checked(buf: &mut BytesMut) -> CStringResult {
+    let Some(i) = buf.iter().position(|b| *b == b'\0') else {
+        return CStringResult::Incomplete;
+    };
 
     // i+1: include the '\0'
     // move cursor to the end of cstring
     let string_buf = buf.split_to(i + 1);
 
     if i == 0 {
-        None
+        CStringResult::Empty
     } else {
-        Some(String::from_utf8_lossy(&string_buf[..i]).into_owned())
+        CStringResult::Value(String::from_utf8_lossy(&string_buf[..i]).into_owned())
+    }
+}
+
+/// Get null-terminated string, returns None when empty cstring read.
+///
+/// Note that this implementation will also advance cursor by 1 after reading
+/// empty cstring. This behaviour works for how postgres wire protocol handling
+/// key-value pairs, which is ended by a single `\0`. None is also returned,
+/// without advancing cursor, when there is no `\0` in the buffer. Use
+/// [`get_cstring_checked`] to tell these cases apart.
+pub fn get_cstring(buf: &mut BytesMut) -> Option<String> {
+    match get_cstring_checked(buf) {
+        CStringResult::Value(s) => Some(s),
+        CStringResult::Empty | CStringResult::Incomplete => None,
     }
 }
 
 /// Put null-termianted string
 ///
 /// You can put empty string by giving `""` as input.
-pub(crate) fn put_cstring(buf: &mut BytesMut, input: &str) {
+pub fn put_cstring(buf: &mut BytesMut, input: &str) {
     buf.put_slice(input.as_bytes());
     buf.put_u8(b'\0');
 }
 
-pub(crate) fn put_option_cstring(buf: &mut BytesMut, input: &Option<String>) {
+/// Put optional string as cstring, `None` is written as empty string.
+pub fn put_option_cstring(buf: &mut BytesMut, input: &Option<String>) {
     if let Some(input) = input {
         put_cstring(buf, input);
     } else {
@@ -83,6 +104,93 @@ where
 //     Ok(())
 // }
 
-pub(crate) fn option_string_len(s: &Option<String>) -> usize {
-    1 + s.as_ref().map(|s| s.as_bytes().len()).unwrap_or(0)
+/// Encoded length of optional cstring, including the `\0`
+pub fn option_string_len(s: &Option<String>) -> usize {
+    1 + s.as_ref().map(|s| s.len()).unwrap_or(0)
+}
+
+fn ensure_remaining(buf: &BytesMut, needed: usize) -> PgWireResult<()> {
+    if buf.remaining() < needed {
+        Err(PgWireError::InsufficientBytes {
+            needed,
+            remaining: buf.remaining(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Read big-endian `i16`, returns error instead of panic if buffer is too
+/// short.
+pub fn get_i16_checked(buf: &mut BytesMut) -> PgWireResult<i16> {
+    ensure_remaining(buf, 2)?;
+    Ok(buf.get_i16())
+}
+
+/// Read big-endian `i32`, returns error instead of panic if buffer is too
+/// short.
+pub fn get_i32_checked(buf: &mut BytesMut) -> PgWireResult<i32> {
+    ensure_remaining(buf, 4)?;
+    Ok(buf.get_i32())
+}
+
+/// Read big-endian `u32`, returns error instead of panic if buffer is too
+/// short.
+pub fn get_u32_checked(buf: &mut BytesMut) -> PgWireResult<u32> {
+    ensure_remaining(buf, 4)?;
+    Ok(buf.get_u32())
+}
+
+/// Split next `n` bytes from buffer, returns error instead of panic if buffer
+/// is too short.
+pub fn get_slice_checked(buf: &mut BytesMut, n: usize) -> PgWireResult<BytesMut> {
+    ensure_remaining(buf, n)?;
+    Ok(buf.split_to(n))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cstring() {
+        let mut buf = BytesMut::new();
+        put_cstring(&mut buf, "pgwire");
+        put_option_cstring(&mut buf, &None);
+        assert_eq!(8, buf.len());
+
+        assert_eq!(Some("pgwire".to_owned()), get_cstring(&mut buf));
+        assert_eq!(None, get_cstring(&mut buf));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_cstring_incomplete() {
+        let mut buf = BytesMut::from(&b"user\0\0post"[..]);
+        assert_eq!(
+            CStringResult::Value("user".to_owned()),
+            get_cstring_checked(&mut buf)
+        );
+        assert_eq!(CStringResult::Empty, get_cstring_checked(&mut buf));
+        assert_eq!(CStringResult::Incomplete, get_cstring_checked(&mut buf));
+        assert_eq!(None, get_cstring(&mut buf));
+        assert_eq!(&b"post"[..], &buf[..]);
+    }
+
+    #[test]
+    fn test_checked_read() {
+        let mut buf = BytesMut::from(&[0u8, 0, 0, 42, 0xff, 0xff, 0xff, 0xff, 1, 2][..]);
+        assert_eq!(42, get_i32_checked(&mut buf).unwrap());
+        assert_eq!(u32::MAX, get_u32_checked(&mut buf).unwrap());
+        assert!(matches!(
+            get_i32_checked(&mut buf),
+            Err(PgWireError::InsufficientBytes {
+                needed: 4,
+                remaining: 2
+            })
+        ));
+        assert!(get_slice_checked(&mut buf, 3).is_err());
+        assert_eq!(&[1, 2], &get_slice_checked(&mut buf, 2).unwrap()[..]);
+        assert!(buf.is_empty());
+    }
 }
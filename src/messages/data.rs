@@ -1,5 +1,6 @@
 use bytes::{Buf, BufMut, BytesMut};
 use postgres_types::Oid;
+use ring::digest;
 
 use super::codec;
 use super::Message;
@@ -35,6 +36,23 @@ pub struct RowDescription {
 
 pub const MESSAGE_TYPE_BYTE_ROW_DESCRITION: u8 = b'T';
 
+impl RowDescription {
+    /// Hex encoded SHA-256 hash of field names, type oids and type modifiers.
+    ///
+    /// The value only changes when the schema of result changes, so it can be
+    /// used as an ETag or sent to clients for detecting schema changes.
+    pub fn etag(&self) -> String {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        for field in &self.fields {
+            ctx.update(field.name.as_bytes());
+            ctx.update(&[0]);
+            ctx.update(&field.type_id.to_be_bytes());
+            ctx.update(&field.type_modifier.to_be_bytes());
+        }
+        hex::encode(ctx.finish())
+    }
+}
+
 impl Message for RowDescription {
     fn message_type() -> Option<u8> {
         Some(MESSAGE_TYPE_BYTE_ROW_DESCRITION)
@@ -45,7 +63,7 @@ impl Message for RowDescription {
             + self
                 .fields
                 .iter()
-                .map(|f| f.name.as_bytes().len() + 1 + 4 + 2 + 4 + 2 + 4 + 2)
+                .map(|f| f.name.len() + 1 + 4 + 2 + 4 + 2 + 4 + 2)
                 .sum::<usize>()
     }
 
@@ -133,8 +151,10 @@ impl Message for ParameterDescription {
 /// Data can be represented as text or binary format as specified by format
 /// codes from previous `RowDescription` message.
 #[non_exhaustive]
-#[derive(PartialEq, Eq, Debug, Default, new, Clone)]
+#[derive(PartialEq, Eq, Default, new, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataRow {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::base64_bytes_mut"))]
     pub data: BytesMut,
     pub field_count: i16,
 }
@@ -0,0 +1,621 @@
+use std::fmt::{self, Debug, Display, Formatter};
+
+use bytes::Buf;
+
+use super::copy::*;
+use super::data::*;
+use super::extendedquery::*;
+use super::fastpath::*;
+use super::response::*;
+use super::simplequery::*;
+use super::startup::*;
+use super::terminate::*;
+use super::{Message, PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Bytes shown as string if they're valid UTF-8, or as `<binary N bytes>`
+struct Text<'a>(&'a [u8]);
+
+impl Debug for Text<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Ok(s) = std::str::from_utf8(self.0) {
+            write!(f, "{s:?}")
+        } else {
+            write!(f, "<binary {} bytes>", self.0.len())
+        }
+    }
+}
+
+/// Bytes shown as list of hex values
+struct Hex<'a>(&'a [u8]);
+
+impl Debug for Hex<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{b:#04x}")?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// Byte shown as ascii char
+struct Char(u8);
+
+impl Debug for Char {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", char::from(self.0))
+    }
+}
+
+/// Redacted secret
+struct Redacted;
+
+impl Debug for Redacted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Wrapper for showing a message with its type code and length, the way
+/// protocol analyzers show it. For example:
+///
+/// ```text
+/// 'Z' len=5 ReadyForQuery { status: 'I' }
+/// ```
+pub struct PgWireMessageDisplay<'a, M>(pub &'a M);
+
+impl<M: Message + Display> Display for PgWireMessageDisplay<'_, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(t) = M::message_type() {
+            write!(f, "{:?} ", Char(t))?;
+        }
+        write!(f, "len={} {}", self.0.message_length(), self.0)
+    }
+}
+
+macro_rules! display_unit {
+    ($($t:ty),*) => {
+        $(
+            impl Display for $t {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                    f.write_str(stringify!($t))
+                }
+            }
+        )*
+    };
+}
+
+display_unit!(
+    SslRequest,
+    GssEncRequest,
+    ParseComplete,
+    CloseComplete,
+    BindComplete,
+    Flush,
+    Sync,
+    PortalSuspended,
+    EmptyQueryResponse,
+    NoData,
+    Terminate,
+    CopyDone
+);
+
+impl Display for Startup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Startup")
+            .field(
+                "protocol",
+                &format_args!(
+                    "{}.{}",
+                    self.protocol_number_major, self.protocol_number_minor
+                ),
+            )
+            .field("parameters", &self.parameters)
+            .finish()
+    }
+}
+
+impl Display for Authentication {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Authentication::Ok => f.write_str("Authentication::Ok"),
+            Authentication::CleartextPassword => f.write_str("Authentication::CleartextPassword"),
+            Authentication::KerberosV5 => f.write_str("Authentication::KerberosV5"),
+            Authentication::MD5Password(salt) => f
+                .debug_struct("Authentication::MD5Password")
+                .field("salt", &Hex(salt))
+                .finish(),
+            Authentication::SCMCredential => f.write_str("Authentication::SCMCredential"),
+            Authentication::GSS => f.write_str("Authentication::GSS"),
+            Authentication::GSSContinue(data) => f
+                .debug_struct("Authentication::GSSContinue")
+                .field("data", &Hex(data))
+                .finish(),
+            Authentication::SSPI => f.write_str("Authentication::SSPI"),
+            Authentication::SASL(mechanisms) => f
+                .debug_struct("Authentication::SASL")
+                .field("mechanisms", mechanisms)
+                .finish(),
+            Authentication::SASLContinue(data) => f
+                .debug_struct("Authentication::SASLContinue")
+                .field("data", &Text(data))
+                .finish(),
+            Authentication::SASLFinal(data) => f
+                .debug_struct("Authentication::SASLFinal")
+                .field("data", &Text(data))
+                .finish(),
+        }
+    }
+}
+
+impl Display for PasswordMessageFamily {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordMessageFamily::Raw(data) => f
+                .debug_struct("PasswordMessage")
+                .field("data", &Text(data))
+                .finish(),
+            PasswordMessageFamily::Password(inner) => Display::fmt(inner, f),
+            PasswordMessageFamily::SASLInitialResponse(inner) => Display::fmt(inner, f),
+            PasswordMessageFamily::SASLResponse(inner) => Display::fmt(inner, f),
+            PasswordMessageFamily::GSSResponse(inner) => Display::fmt(inner, f),
+        }
+    }
+}
+
+impl Display for Password {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Password")
+            .field("password", &Redacted)
+            .finish()
+    }
+}
+
+impl Display for SASLInitialResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SASLInitialResponse")
+            .field("auth_method", &self.auth_method)
+            .field("data", &self.data.as_deref().map(Text))
+            .finish()
+    }
+}
+
+impl Display for SASLResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SASLResponse")
+            .field("data", &Text(&self.data))
+            .finish()
+    }
+}
+
+impl Display for GSSResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GSSResponse")
+            .field("data", &Text(&self.data))
+            .finish()
+    }
+}
+
+impl Display for ParameterStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParameterStatus")
+            .field("name", &self.name)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+impl Display for NegotiateProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiateProtocolVersion")
+            .field("newest_minor_version", &self.newest_minor_version)
+            .field("unsupported_options", &self.unsupported_options)
+            .finish()
+    }
+}
+
+impl Display for BackendKeyData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackendKeyData")
+            .field("pid", &self.pid)
+            .field("secret_key", &Hex(&self.secret_key))
+            .finish()
+    }
+}
+
+impl Display for CancelRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelRequest")
+            .field("pid", &self.pid)
+            .field("secret_key", &Hex(&self.secret_key))
+            .finish()
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Query").field("query", &self.query).finish()
+    }
+}
+
+impl Display for Parse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parse")
+            .field("name", &self.name)
+            .field("query", &self.query)
+            .field("type_oids", &self.type_oids)
+            .finish()
+    }
+}
+
+impl Display for Close {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Close")
+            .field("target_type", &Char(self.target_type))
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Display for Bind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bind")
+            .field("portal_name", &self.portal_name)
+            .field("statement_name", &self.statement_name)
+            .field("parameter_format_codes", &self.parameter_format_codes)
+            .field(
+                "parameters",
+                &self
+                    .parameters
+                    .iter()
+                    .map(|p| p.as_deref().map(Text))
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "result_column_format_codes",
+                &self.result_column_format_codes,
+            )
+            .finish()
+    }
+}
+
+impl Display for Describe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Describe")
+            .field("target_type", &Char(self.target_type))
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Display for Execute {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Execute")
+            .field("name", &self.name)
+            .field("max_rows", &self.max_rows)
+            .finish()
+    }
+}
+
+impl Display for CommandComplete {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandComplete")
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
+
+impl Display for ReadyForQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadyForQuery")
+            .field("status", &Char(self.status))
+            .finish()
+    }
+}
+
+fn fmt_error_fields(name: &str, fields: &[(u8, String)], f: &mut Formatter<'_>) -> fmt::Result {
+    let mut s = f.debug_struct(name);
+    for (code, value) in fields {
+        s.field(char::from(*code).encode_utf8(&mut [0; 4]), value);
+    }
+    s.finish()
+}
+
+impl Display for ErrorResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt_error_fields("ErrorResponse", &self.fields, f)
+    }
+}
+
+impl Display for NoticeResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt_error_fields("NoticeResponse", &self.fields, f)
+    }
+}
+
+impl Display for SslResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SslResponse::Accept => f.write_str("SslResponse::Accept"),
+            SslResponse::Refuse => f.write_str("SslResponse::Refuse"),
+        }
+    }
+}
+
+impl Display for GssEncResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GssEncResponse::Accept => f.write_str("GssEncResponse::Accept"),
+            GssEncResponse::Refuse => f.write_str("GssEncResponse::Refuse"),
+        }
+    }
+}
+
+impl Display for NotificationResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotificationResponse")
+            .field("pid", &self.pid)
+            .field("channel", &self.channel)
+            .field("payload", &self.payload)
+            .finish()
+    }
+}
+
+impl Display for FieldDescription {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldDescription")
+            .field("name", &self.name)
+            .field("table_id", &self.table_id)
+            .field("column_id", &self.column_id)
+            .field("type_id", &self.type_id)
+            .field("type_size", &self.type_size)
+            .field("type_modifier", &self.type_modifier)
+            .field("format_code", &self.format_code)
+            .finish()
+    }
+}
+
+impl Display for RowDescription {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        struct Field<'a>(&'a FieldDescription);
+
+        impl Debug for Field<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                Display::fmt(self.0, f)
+            }
+        }
+
+        f.debug_struct("RowDescription")
+            .field("fields", &self.fields.iter().map(Field).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Display for ParameterDescription {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParameterDescription")
+            .field("types", &self.types)
+            .finish()
+    }
+}
+
+impl DataRow {
+    fn columns(&self) -> Vec<Option<Text<'_>>> {
+        let mut columns = Vec::with_capacity(self.field_count.max(0) as usize);
+        let mut data = &self.data[..];
+        while data.remaining() >= 4 {
+            let len = data.get_i32();
+            if len < 0 {
+                columns.push(None);
+            } else {
+                let len = (len as usize).min(data.len());
+                columns.push(Some(Text(&data[..len])));
+                data.advance(len);
+            }
+        }
+        columns
+    }
+}
+
+impl Display for DataRow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataRow")
+            .field("columns", &self.columns())
+            .finish()
+    }
+}
+
+impl Debug for DataRow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataRow")
+            .field("field_count", &self.field_count)
+            .field("columns", &self.columns())
+            .finish()
+    }
+}
+
+impl Display for CopyData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CopyData")
+            .field("data", &Text(&self.data))
+            .finish()
+    }
+}
+
+impl Display for CopyFail {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CopyFail")
+            .field("message", &self.message)
+            .finish()
+    }
+}
+
+macro_rules! display_copy_response {
+    ($($t:ty),*) => {
+        $(
+            impl Display for $t {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                    f.debug_struct(stringify!($t))
+                        .field("format", &self.format)
+                        .field("columns", &self.columns)
+                        .field("column_formats", &self.column_formats)
+                        .finish()
+                }
+            }
+        )*
+    };
+}
+
+display_copy_response!(CopyInResponse, CopyOutResponse, CopyBothResponse);
+
+impl Display for FunctionCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionCall")
+            .field("function_oid", &self.function_oid)
+            .field("argument_format_codes", &self.argument_format_codes)
+            .field(
+                "arguments",
+                &self
+                    .arguments
+                    .iter()
+                    .map(|a| a.as_deref().map(Text))
+                    .collect::<Vec<_>>(),
+            )
+            .field("result_format_code", &self.result_format_code)
+            .finish()
+    }
+}
+
+impl Display for FunctionCallResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionCallResponse")
+            .field("result", &self.result.as_deref().map(Text))
+            .finish()
+    }
+}
+
+impl Display for PgWireFrontendMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Startup(msg) => Display::fmt(msg, f),
+            Self::SslRequest(msg) => Display::fmt(msg, f),
+            Self::GssEncRequest(msg) => Display::fmt(msg, f),
+            Self::CancelRequest(msg) => Display::fmt(msg, f),
+            Self::PasswordMessageFamily(msg) => Display::fmt(msg, f),
+
+            Self::Query(msg) => Display::fmt(msg, f),
+
+            Self::Parse(msg) => Display::fmt(msg, f),
+            Self::Bind(msg) => Display::fmt(msg, f),
+            Self::Close(msg) => Display::fmt(msg, f),
+            Self::Describe(msg) => Display::fmt(msg, f),
+            Self::Execute(msg) => Display::fmt(msg, f),
+            Self::Flush(msg) => Display::fmt(msg, f),
+            Self::Sync(msg) => Display::fmt(msg, f),
+
+            Self::Terminate(msg) => Display::fmt(msg, f),
+
+            Self::CopyData(msg) => Display::fmt(msg, f),
+            Self::CopyFail(msg) => Display::fmt(msg, f),
+            Self::CopyDone(msg) => Display::fmt(msg, f),
+
+            Self::FunctionCall(msg) => Display::fmt(msg, f),
+        }
+    }
+}
+
+impl Display for PgWireBackendMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Authentication(msg) => Display::fmt(msg, f),
+            Self::ParameterStatus(msg) => Display::fmt(msg, f),
+            Self::BackendKeyData(msg) => Display::fmt(msg, f),
+            Self::NegotiateProtocolVersion(msg) => Display::fmt(msg, f),
+
+            Self::ParseComplete(msg) => Display::fmt(msg, f),
+            Self::BindComplete(msg) => Display::fmt(msg, f),
+            Self::CloseComplete(msg) => Display::fmt(msg, f),
+            Self::PortalSuspended(msg) => Display::fmt(msg, f),
+
+            Self::CommandComplete(msg) => Display::fmt(msg, f),
+            Self::EmptyQueryResponse(msg) => Display::fmt(msg, f),
+            Self::ReadyForQuery(msg) => Display::fmt(msg, f),
+            Self::ErrorResponse(msg) => Display::fmt(msg, f),
+            Self::NoticeResponse(msg) => Display::fmt(msg, f),
+            Self::SslResponse(msg) => Display::fmt(msg, f),
+            Self::GssEncResponse(msg) => Display::fmt(msg, f),
+            Self::NotificationResponse(msg) => Display::fmt(msg, f),
+
+            Self::ParameterDescription(msg) => Display::fmt(msg, f),
+            Self::RowDescription(msg) => Display::fmt(msg, f),
+            Self::DataRow(msg) => Display::fmt(msg, f),
+            Self::NoData(msg) => Display::fmt(msg, f),
+
+            Self::CopyData(msg) => Display::fmt(msg, f),
+            Self::CopyFail(msg) => Display::fmt(msg, f),
+            Self::CopyDone(msg) => Display::fmt(msg, f),
+            Self::CopyInResponse(msg) => Display::fmt(msg, f),
+            Self::CopyOutResponse(msg) => Display::fmt(msg, f),
+            Self::CopyBothResponse(msg) => Display::fmt(msg, f),
+
+            Self::FunctionCallResponse(msg) => Display::fmt(msg, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+
+    use super::*;
+
+    #[test]
+    fn test_data_row_display() {
+        let mut data = BytesMut::new();
+        data.put_i32(5);
+        data.put_slice(b"hello");
+        data.put_i32(-1);
+        data.put_i32(2);
+        data.put_slice(b"42");
+        data.put_i32(2);
+        data.put_slice(&[0xff, 0xfe]);
+        let row = DataRow::new(data, 4);
+
+        assert_eq!(
+            "DataRow { columns: [Some(\"hello\"), None, Some(\"42\"), Some(<binary 2 bytes>)] }",
+            row.to_string()
+        );
+    }
+
+    #[test]
+    fn test_message_display() {
+        let auth = Authentication::MD5Password(vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            "Authentication::MD5Password { salt: [0xde, 0xad, 0xbe, 0xef] }",
+            auth.to_string()
+        );
+
+        let password = Password::new("secret".to_owned());
+        assert_eq!(
+            "Password { password: <redacted> }",
+            PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Password(password))
+                .to_string()
+        );
+
+        let ready = ReadyForQuery::new(READY_STATUS_IDLE);
+        assert_eq!(
+            "'Z' len=5 ReadyForQuery { status: 'I' }",
+            PgWireMessageDisplay(&ready).to_string()
+        );
+
+        let error =
+            ErrorResponse::new(vec![(b'S', "ERROR".to_owned()), (b'C', "XX000".to_owned())]);
+        assert_eq!(
+            "ErrorResponse { S: \"ERROR\", C: \"XX000\" }",
+            error.to_string()
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt::{self, Display, Formatter};
+
+use bytes::{Bytes, BytesMut};
+use postgres_types::{Oid, Type};
+
+use super::data::{ParameterDescription, RowDescription};
+use super::response::{GssEncResponse, SslResponse};
+use super::simplequery::Query;
+use super::startup::{CancelRequest, GssEncRequest, SslRequest, Startup};
+use super::{Message, PgWireBackendMessage, PgWireFrontendMessage};
+use crate::error::PgWireResult;
+
+/// The side that sent a chunk of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Frontend,
+    Backend,
+}
+
+impl Direction {
+    fn arrow(&self) -> &'static str {
+        match self {
+            Direction::Frontend => "→",
+            Direction::Backend => "←",
+        }
+    }
+}
+
+/// A decoded message, with the side that sent it.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum DissectedMessage {
+    Frontend(PgWireFrontendMessage),
+    Backend(PgWireBackendMessage),
+    /// Traffic after TLS is accepted, which cannot be decoded.
+    Encrypted(Direction, usize),
+}
+
+impl Display for DissectedMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DissectedMessage::Frontend(msg) => {
+                write!(f, "{} ", Direction::Frontend.arrow())?;
+                match msg {
+                    PgWireFrontendMessage::Query(query) => fmt_query(query, f),
+                    _ => Display::fmt(msg, f),
+                }
+            }
+            DissectedMessage::Backend(msg) => {
+                write!(f, "{} ", Direction::Backend.arrow())?;
+                match msg {
+                    PgWireBackendMessage::RowDescription(desc) => fmt_row_description(desc, f),
+                    PgWireBackendMessage::ParameterDescription(desc) => {
+                        fmt_parameter_description(desc, f)
+                    }
+                    _ => Display::fmt(msg, f),
+                }
+            }
+            DissectedMessage::Encrypted(direction, len) => {
+                write!(f, "{} <encrypted {} bytes>", direction.arrow(), len)
+            }
+        }
+    }
+}
+
+fn type_name(oid: Oid) -> String {
+    Type::from_oid(oid)
+        .map(|t| t.name().to_owned())
+        .unwrap_or_else(|| oid.to_string())
+}
+
+fn fmt_query(query: &Query, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "Query({:?})", query.query)
+}
+
+fn fmt_row_description(desc: &RowDescription, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str("RowDescription([")?;
+    for (i, field) in desc.fields.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(
+            f,
+            "{{name: {:?}, type: {}}}",
+            field.name,
+            type_name(field.type_id)
+        )?;
+    }
+    f.write_str("])")
+}
+
+fn fmt_parameter_description(desc: &ParameterDescription, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str("ParameterDescription([")?;
+    for (i, oid) in desc.types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        f.write_str(&type_name(*oid))?;
+    }
+    f.write_str("])")
+}
+
+/// Decode captured traffic of a single connection into a readable protocol
+/// trace, in the style of Wireshark's PostgreSQL dissector:
+///
+/// ```text
+/// → Query("SELECT 1")
+/// ← RowDescription([{name: "?column?", type: int4}])
+/// ```
+///
+/// Bytes can be fed in arbitrary chunks, incomplete messages are kept until
+/// the rest arrives.
+#[derive(Debug, Default)]
+pub struct WiresharkDissector {
+    frontend_buf: BytesMut,
+    backend_buf: BytesMut,
+    startup_done: bool,
+    ssl_requested: bool,
+    gss_requested: bool,
+    encrypted: bool,
+}
+
+impl WiresharkDissector {
+    pub fn new() -> WiresharkDissector {
+        WiresharkDissector::default()
+    }
+
+    /// Feed bytes sent by `direction` and return messages completed by them.
+    pub fn feed(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+    ) -> PgWireResult<Vec<DissectedMessage>> {
+        if self.encrypted {
+            return Ok(vec![DissectedMessage::Encrypted(direction, data.len())]);
+        }
+
+        let mut messages = Vec::new();
+        match direction {
+            Direction::Frontend => {
+                self.frontend_buf.extend_from_slice(data);
+                while let Some(msg) = self.decode_frontend()? {
+                    messages.push(DissectedMessage::Frontend(msg));
+                }
+            }
+            Direction::Backend => {
+                self.backend_buf.extend_from_slice(data);
+                while let Some(msg) = self.decode_backend()? {
+                    messages.push(DissectedMessage::Backend(msg));
+                    if self.encrypted {
+                        let len = self.backend_buf.split().len();
+                        if len > 0 {
+                            messages.push(DissectedMessage::Encrypted(direction, len));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Dissect a complete capture and return the trace lines.
+    pub fn dissect<I>(packets: I) -> PgWireResult<Vec<String>>
+    where
+        I: IntoIterator<Item = (Direction, Bytes)>,
+    {
+        let mut dissector = WiresharkDissector::new();
+        let mut lines = Vec::new();
+        for (direction, data) in packets {
+            for msg in dissector.feed(direction, &data)? {
+                lines.push(msg.to_string());
+            }
+        }
+        Ok(lines)
+    }
+
+    fn decode_frontend(&mut self) -> PgWireResult<Option<PgWireFrontendMessage>> {
+        if self.startup_done {
+            return PgWireFrontendMessage::decode(&mut self.frontend_buf);
+        }
+
+        if let Some(request) = SslRequest::decode(&mut self.frontend_buf)? {
+            self.ssl_requested = true;
+            return Ok(Some(PgWireFrontendMessage::SslRequest(request)));
+        }
+        if let Some(request) = GssEncRequest::decode(&mut self.frontend_buf)? {
+            self.gss_requested = true;
+            return Ok(Some(PgWireFrontendMessage::GssEncRequest(request)));
+        }
+        if let Some(request) = CancelRequest::decode(&mut self.frontend_buf)? {
+            self.startup_done = true;
+            return Ok(Some(PgWireFrontendMessage::CancelRequest(request)));
+        }
+        if let Some(startup) = Startup::decode(&mut self.frontend_buf)? {
+            self.startup_done = true;
+            return Ok(Some(PgWireFrontendMessage::Startup(startup)));
+        }
+        Ok(None)
+    }
+
+    fn decode_backend(&mut self) -> PgWireResult<Option<PgWireBackendMessage>> {
+        if self.ssl_requested {
+            if let Some(response) = SslResponse::decode(&mut self.backend_buf)? {
+                self.ssl_requested = false;
+                self.encrypted = matches!(response, SslResponse::Accept);
+                return Ok(Some(PgWireBackendMessage::SslResponse(response)));
+            }
+            return Ok(None);
+        }
+        if self.gss_requested {
+            if let Some(response) = GssEncResponse::decode(&mut self.backend_buf)? {
+                self.gss_requested = false;
+                self.encrypted = matches!(response, GssEncResponse::Accept);
+                return Ok(Some(PgWireBackendMessage::GssEncResponse(response)));
+            }
+            return Ok(None);
+        }
+        PgWireBackendMessage::decode(&mut self.backend_buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::messages::data::{DataRow, FieldDescription};
+    use crate::messages::response::{CommandComplete, ReadyForQuery, READY_STATUS_IDLE};
+
+    fn encode<M: Message>(msg: &M) -> Bytes {
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        buf.freeze()
+    }
+
+    #[test]
+    fn test_dissect() {
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+
+        let mut backend = BytesMut::new();
+        RowDescription::new(vec![FieldDescription::new(
+            "?column?".to_owned(),
+            0,
+            0,
+            Type::INT4.oid(),
+            4,
+            -1,
+            0,
+        )])
+        .encode(&mut backend)
+        .unwrap();
+        DataRow::new(BytesMut::from(&b"\0\0\0\x011"[..]), 1)
+            .encode(&mut backend)
+            .unwrap();
+        CommandComplete::new("SELECT 1".to_owned())
+            .encode(&mut backend)
+            .unwrap();
+        ReadyForQuery::new(READY_STATUS_IDLE)
+            .encode(&mut backend)
+            .unwrap();
+        let backend = backend.freeze();
+
+        let query = encode(&Query::new("SELECT 1".to_owned()));
+        let lines = WiresharkDissector::dissect(vec![
+            (Direction::Frontend, encode(&SslRequest::new())),
+            (Direction::Backend, Bytes::from_static(b"N")),
+            (Direction::Frontend, encode(&startup)),
+            (Direction::Frontend, query.slice(..3)),
+            (Direction::Frontend, query.slice(3..)),
+            (Direction::Backend, backend.slice(..10)),
+            (Direction::Backend, backend.slice(10..)),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "→ SslRequest",
+                "← SslResponse::Refuse",
+                "→ Startup { protocol: 3.0, parameters: {\"user\": \"pgwire\"} }",
+                "→ Query(\"SELECT 1\")",
+                "← RowDescription([{name: \"?column?\", type: int4}])",
+                "← DataRow { columns: [Some(\"1\")] }",
+                "← CommandComplete { tag: \"SELECT 1\" }",
+                "← ReadyForQuery { status: 'I' }",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_dissect_encrypted() {
+        let lines = WiresharkDissector::dissect(vec![
+            (Direction::Frontend, encode(&SslRequest::new())),
+            (Direction::Backend, Bytes::from_static(b"S\x16\x03")),
+            (Direction::Frontend, Bytes::from_static(b"\x16\x03\x01")),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "→ SslRequest",
+                "← SslResponse::Accept",
+                "← <encrypted 2 bytes>",
+                "→ <encrypted 3 bytes>",
+            ],
+            lines
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use bytes::{Buf, BufMut, Bytes};
-use postgres_types::Oid;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use postgres_types::{IsNull, Oid, ToSql, Type};
 
+use super::data::FORMAT_CODE_BINARY;
 use super::{codec, Message};
-use crate::error::PgWireResult;
+use crate::api::portal::Format;
+use crate::error::{PgWireError, PgWireResult};
 
 /// Request from frontend to parse a prepared query string
 #[non_exhaustive]
@@ -23,7 +25,7 @@ impl Message for Parse {
 
     fn message_length(&self) -> usize {
         4 + codec::option_string_len(&self.name) // name
-            + (1 + self.query.as_bytes().len()) // query
+            + (1 + self.query.len()) // query
             + (4 * self.type_oids.len()) // type oids
     }
 
@@ -253,6 +255,77 @@ impl Message for Bind {
     }
 }
 
+/// Builder of `Bind` message with parameters encoded in binary format.
+///
+/// ```
+/// use pgwire::api::portal::Format;
+/// use pgwire::api::Type;
+/// use pgwire::messages::extendedquery::BindParameterBuilder;
+///
+/// let mut builder = BindParameterBuilder::new();
+/// builder.add(&42i32, &Type::INT4).unwrap();
+/// builder.add_null();
+/// let bind = builder.build("find-user", "", Format::UnifiedBinary).unwrap();
+/// assert_eq!(vec![1], bind.parameter_format_codes);
+/// assert_eq!(2, bind.parameters.len());
+/// ```
+#[derive(Debug, Default)]
+pub struct BindParameterBuilder {
+    parameters: Vec<Option<Bytes>>,
+}
+
+impl BindParameterBuilder {
+    pub fn new() -> BindParameterBuilder {
+        BindParameterBuilder::default()
+    }
+
+    /// Encode `value` as type `ty` and append it to parameters.
+    pub fn add<T>(&mut self, value: &T, ty: &Type) -> PgWireResult<&mut Self>
+    where
+        T: ToSql + ?Sized,
+    {
+        let mut buf = BytesMut::new();
+        let parameter = match value
+            .to_sql_checked(ty, &mut buf)
+            .map_err(PgWireError::FailedToEncodeParameter)?
+        {
+            IsNull::Yes => None,
+            IsNull::No => Some(buf.freeze()),
+        };
+        self.parameters.push(parameter);
+        Ok(self)
+    }
+
+    /// Append a `NULL` parameter.
+    pub fn add_null(&mut self) -> &mut Self {
+        self.parameters.push(None);
+        self
+    }
+
+    /// Create `Bind` of given statement and portal, empty names refer to
+    /// the unnamed statement or portal.
+    pub fn build(
+        self,
+        statement_name: &str,
+        portal_name: &str,
+        result_format: Format,
+    ) -> PgWireResult<Bind> {
+        if self.parameters.len() > i16::MAX as usize {
+            return Err(PgWireError::TooManyParameters(self.parameters.len()));
+        }
+
+        let name = |s: &str| (!s.is_empty()).then(|| s.to_owned());
+        Ok(Bind::new(
+            name(portal_name),
+            name(statement_name),
+            // a single code applies to all parameters
+            vec![FORMAT_CODE_BINARY],
+            self.parameters,
+            result_format.to_codes(),
+        ))
+    }
+}
+
 /// Success response for `Bind`
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
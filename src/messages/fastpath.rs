@@ -0,0 +1,136 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use postgres_types::Oid;
+
+use super::codec;
+use super::Message;
+use crate::error::PgWireResult;
+
+/// Fastpath function call, sent from frontend to call a function by oid
+/// without a query. It's still used by large object API of some drivers.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Default, new)]
+pub struct FunctionCall {
+    pub function_oid: Oid,
+    pub argument_format_codes: Vec<i16>,
+    // None for Null argument
+    pub arguments: Vec<Option<Bytes>>,
+    pub result_format_code: i16,
+}
+
+pub const MESSAGE_TYPE_BYTE_FUNCTION_CALL: u8 = b'F';
+
+impl Message for FunctionCall {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_FUNCTION_CALL)
+    }
+
+    fn message_length(&self) -> usize {
+        4 + 4 // function oid
+            + 2 + (2 * self.argument_format_codes.len())
+            + 2 + self.arguments.iter().map(|a| 4 + a.as_ref().map(|data| data.len()).unwrap_or(0)).sum::<usize>()
+            + 2 // result format code
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_u32(self.function_oid);
+
+        buf.put_i16(self.argument_format_codes.len() as i16);
+        for c in &self.argument_format_codes {
+            buf.put_i16(*c);
+        }
+
+        buf.put_i16(self.arguments.len() as i16);
+        for v in &self.arguments {
+            if let Some(v) = v {
+                buf.put_i32(v.len() as i32);
+                buf.put_slice(v.as_ref());
+            } else {
+                buf.put_i32(-1);
+            }
+        }
+
+        buf.put_i16(self.result_format_code);
+
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, len: usize) -> PgWireResult<Self> {
+        // read within the message only
+        let mut buf = buf.split_to(len.saturating_sub(4));
+
+        let function_oid = codec::get_u32_checked(&mut buf)?;
+
+        let format_code_len = codec::get_i16_checked(&mut buf)?;
+        let mut argument_format_codes = Vec::with_capacity(format_code_len.max(0) as usize);
+        for _ in 0..format_code_len {
+            argument_format_codes.push(codec::get_i16_checked(&mut buf)?);
+        }
+
+        let argument_len = codec::get_i16_checked(&mut buf)?;
+        let mut arguments = Vec::with_capacity(argument_len.max(0) as usize);
+        for _ in 0..argument_len {
+            let data_len = codec::get_i32_checked(&mut buf)?;
+            if data_len >= 0 {
+                arguments.push(Some(
+                    codec::get_slice_checked(&mut buf, data_len as usize)?.freeze(),
+                ));
+            } else {
+                arguments.push(None);
+            }
+        }
+
+        let result_format_code = codec::get_i16_checked(&mut buf)?;
+
+        Ok(FunctionCall {
+            function_oid,
+            argument_format_codes,
+            arguments,
+            result_format_code,
+        })
+    }
+}
+
+/// Result of fastpath function call
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Default, new)]
+pub struct FunctionCallResponse {
+    // None for Null result
+    pub result: Option<Bytes>,
+}
+
+pub const MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE: u8 = b'V';
+
+impl Message for FunctionCallResponse {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE)
+    }
+
+    fn message_length(&self) -> usize {
+        4 + 4 + self.result.as_ref().map(|data| data.len()).unwrap_or(0)
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        if let Some(result) = &self.result {
+            buf.put_i32(result.len() as i32);
+            buf.put_slice(result.as_ref());
+        } else {
+            buf.put_i32(-1);
+        }
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, len: usize) -> PgWireResult<Self> {
+        let mut buf = buf.split_to(len.saturating_sub(4));
+
+        let data_len = codec::get_i32_checked(&mut buf)?;
+        let result = if data_len >= 0 {
+            Some(codec::get_slice_checked(&mut buf, data_len as usize)?.freeze())
+        } else {
+            None
+        };
+
+        Ok(FunctionCallResponse { result })
+    }
+}
@@ -54,13 +54,19 @@ pub trait Message: Sized {
     }
 }
 
-mod codec;
+/// Helpers for reading and writing message fields, for custom message types
+pub mod codec;
 /// Copy messages
 pub mod copy;
 /// Data related messages
 pub mod data;
+mod display;
+/// Readable protocol trace of captured traffic, for debugging
+pub mod dissect;
 /// Extended query messages, including request/response for parse, bind and etc.
 pub mod extendedquery;
+/// Fastpath function call messages
+pub mod fastpath;
 /// General response messages
 pub mod response;
 /// Simple query messages, including descriptions
@@ -70,11 +76,16 @@ pub mod startup;
 /// Termination messages
 pub mod terminate;
 
+pub use display::PgWireMessageDisplay;
+
 /// Messages sent from Frontend
+#[non_exhaustive]
 #[derive(Debug)]
 pub enum PgWireFrontendMessage {
     Startup(startup::Startup),
     SslRequest(startup::SslRequest),
+    GssEncRequest(startup::GssEncRequest),
+    CancelRequest(startup::CancelRequest),
     PasswordMessageFamily(startup::PasswordMessageFamily),
 
     Query(simplequery::Query),
@@ -92,6 +103,8 @@ pub enum PgWireFrontendMessage {
     CopyData(copy::CopyData),
     CopyFail(copy::CopyFail),
     CopyDone(copy::CopyDone),
+
+    FunctionCall(fastpath::FunctionCall),
 }
 
 impl PgWireFrontendMessage {
@@ -108,10 +121,75 @@ impl PgWireFrontendMessage {
         )
     }
 
+    /// Return the type code of the message. `Startup`, `SslRequest`,
+    /// `GssEncRequest` and `CancelRequest` have no message type, and `None` is
+    /// returned for them.
+    pub fn message_type(&self) -> Option<u8> {
+        match self {
+            Self::Startup(_) => startup::Startup::message_type(),
+            Self::SslRequest(_) => startup::SslRequest::message_type(),
+            Self::GssEncRequest(_) => startup::GssEncRequest::message_type(),
+            Self::CancelRequest(_) => startup::CancelRequest::message_type(),
+            Self::PasswordMessageFamily(_) => {
+                Some(startup::MESSAGE_TYPE_BYTE_PASWORD_MESSAGE_FAMILY)
+            }
+
+            Self::Query(_) => simplequery::Query::message_type(),
+
+            Self::Parse(_) => extendedquery::Parse::message_type(),
+            Self::Bind(_) => extendedquery::Bind::message_type(),
+            Self::Close(_) => extendedquery::Close::message_type(),
+            Self::Describe(_) => extendedquery::Describe::message_type(),
+            Self::Execute(_) => extendedquery::Execute::message_type(),
+            Self::Flush(_) => extendedquery::Flush::message_type(),
+            Self::Sync(_) => extendedquery::Sync::message_type(),
+
+            Self::Terminate(_) => terminate::Terminate::message_type(),
+
+            Self::CopyData(_) => copy::CopyData::message_type(),
+            Self::CopyFail(_) => copy::CopyFail::message_type(),
+            Self::CopyDone(_) => copy::CopyDone::message_type(),
+
+            Self::FunctionCall(_) => fastpath::FunctionCall::message_type(),
+        }
+    }
+
+    /// Return the name of the message, which is also available for messages
+    /// without a type code.
+    pub fn message_name(&self) -> &'static str {
+        match self {
+            Self::Startup(_) => "Startup",
+            Self::SslRequest(_) => "SslRequest",
+            Self::GssEncRequest(_) => "GssEncRequest",
+            Self::CancelRequest(_) => "CancelRequest",
+            Self::PasswordMessageFamily(_) => "PasswordMessage",
+
+            Self::Query(_) => "Query",
+
+            Self::Parse(_) => "Parse",
+            Self::Bind(_) => "Bind",
+            Self::Close(_) => "Close",
+            Self::Describe(_) => "Describe",
+            Self::Execute(_) => "Execute",
+            Self::Flush(_) => "Flush",
+            Self::Sync(_) => "Sync",
+
+            Self::Terminate(_) => "Terminate",
+
+            Self::CopyData(_) => "CopyData",
+            Self::CopyFail(_) => "CopyFail",
+            Self::CopyDone(_) => "CopyDone",
+
+            Self::FunctionCall(_) => "FunctionCall",
+        }
+    }
+
     pub fn encode(&self, buf: &mut BytesMut) -> PgWireResult<()> {
         match self {
             Self::Startup(msg) => msg.encode(buf),
             Self::SslRequest(msg) => msg.encode(buf),
+            Self::GssEncRequest(msg) => msg.encode(buf),
+            Self::CancelRequest(msg) => msg.encode(buf),
             Self::PasswordMessageFamily(msg) => msg.encode(buf),
 
             Self::Query(msg) => msg.encode(buf),
@@ -129,9 +207,29 @@ impl PgWireFrontendMessage {
             Self::CopyData(msg) => msg.encode(buf),
             Self::CopyFail(msg) => msg.encode(buf),
             Self::CopyDone(msg) => msg.encode(buf),
+
+            Self::FunctionCall(msg) => msg.encode(buf),
         }
     }
 
+    /// Type code of the message at the start of `buf`, without consuming it.
+    /// Returns `None` if `buf` is empty.
+    ///
+    /// `Startup`, `SslRequest`, `GssEncRequest` and `CancelRequest` have no
+    /// type code, and the
+    /// first byte of their length is returned instead.
+    pub fn peek_type(buf: &[u8]) -> Option<u8> {
+        buf.first().copied()
+    }
+
+    /// Length of the typed message at the start of `buf`, excluding the type
+    /// code but including the length itself, without consuming it. Returns
+    /// `None` if the length is not fully received.
+    pub fn peek_length(buf: &[u8]) -> Option<u32> {
+        let len = buf.get(1..5)?;
+        Some(u32::from_be_bytes([len[0], len[1], len[2], len[3]]))
+    }
+
     pub fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
         if buf.remaining() > 1 {
             let first_byte = buf[0];
@@ -182,6 +280,10 @@ impl PgWireFrontendMessage {
                 copy::MESSAGE_TYPE_BYTE_COPY_DONE => {
                     copy::CopyDone::decode(buf).map(|v| v.map(Self::CopyDone))
                 }
+
+                fastpath::MESSAGE_TYPE_BYTE_FUNCTION_CALL => {
+                    fastpath::FunctionCall::decode(buf).map(|v| v.map(Self::FunctionCall))
+                }
                 _ => Err(PgWireError::InvalidMessageType(first_byte)),
             }
         } else {
@@ -191,12 +293,14 @@ impl PgWireFrontendMessage {
 }
 
 /// Messages sent from Backend
+#[non_exhaustive]
 #[derive(Debug)]
 pub enum PgWireBackendMessage {
     // startup
     Authentication(startup::Authentication),
     ParameterStatus(startup::ParameterStatus),
     BackendKeyData(startup::BackendKeyData),
+    NegotiateProtocolVersion(startup::NegotiateProtocolVersion),
 
     // extended query
     ParseComplete(extendedquery::ParseComplete),
@@ -211,6 +315,7 @@ pub enum PgWireBackendMessage {
     ErrorResponse(response::ErrorResponse),
     NoticeResponse(response::NoticeResponse),
     SslResponse(response::SslResponse),
+    GssEncResponse(response::GssEncResponse),
     NotificationResponse(response::NotificationResponse),
 
     // data
@@ -226,14 +331,95 @@ pub enum PgWireBackendMessage {
     CopyInResponse(copy::CopyInResponse),
     CopyOutResponse(copy::CopyOutResponse),
     CopyBothResponse(copy::CopyBothResponse),
+
+    FunctionCallResponse(fastpath::FunctionCallResponse),
 }
 
 impl PgWireBackendMessage {
+    /// Return the type code of the message. `SslResponse` and
+    /// `GssEncResponse` have no message type, and `None` is returned for them.
+    pub fn message_type(&self) -> Option<u8> {
+        match self {
+            Self::Authentication(_) => startup::Authentication::message_type(),
+            Self::ParameterStatus(_) => startup::ParameterStatus::message_type(),
+            Self::BackendKeyData(_) => startup::BackendKeyData::message_type(),
+            Self::NegotiateProtocolVersion(_) => startup::NegotiateProtocolVersion::message_type(),
+
+            Self::ParseComplete(_) => extendedquery::ParseComplete::message_type(),
+            Self::BindComplete(_) => extendedquery::BindComplete::message_type(),
+            Self::CloseComplete(_) => extendedquery::CloseComplete::message_type(),
+            Self::PortalSuspended(_) => extendedquery::PortalSuspended::message_type(),
+
+            Self::CommandComplete(_) => response::CommandComplete::message_type(),
+            Self::EmptyQueryResponse(_) => response::EmptyQueryResponse::message_type(),
+            Self::ReadyForQuery(_) => response::ReadyForQuery::message_type(),
+            Self::ErrorResponse(_) => response::ErrorResponse::message_type(),
+            Self::NoticeResponse(_) => response::NoticeResponse::message_type(),
+            Self::SslResponse(_) => response::SslResponse::message_type(),
+            Self::GssEncResponse(_) => response::GssEncResponse::message_type(),
+            Self::NotificationResponse(_) => response::NotificationResponse::message_type(),
+
+            Self::ParameterDescription(_) => data::ParameterDescription::message_type(),
+            Self::RowDescription(_) => data::RowDescription::message_type(),
+            Self::DataRow(_) => data::DataRow::message_type(),
+            Self::NoData(_) => data::NoData::message_type(),
+
+            Self::CopyData(_) => copy::CopyData::message_type(),
+            Self::CopyFail(_) => copy::CopyFail::message_type(),
+            Self::CopyDone(_) => copy::CopyDone::message_type(),
+            Self::CopyInResponse(_) => copy::CopyInResponse::message_type(),
+            Self::CopyOutResponse(_) => copy::CopyOutResponse::message_type(),
+            Self::CopyBothResponse(_) => copy::CopyBothResponse::message_type(),
+
+            Self::FunctionCallResponse(_) => fastpath::FunctionCallResponse::message_type(),
+        }
+    }
+
+    /// Return the name of the message, which is also available for messages
+    /// without a type code.
+    pub fn message_name(&self) -> &'static str {
+        match self {
+            Self::Authentication(_) => "Authentication",
+            Self::ParameterStatus(_) => "ParameterStatus",
+            Self::BackendKeyData(_) => "BackendKeyData",
+            Self::NegotiateProtocolVersion(_) => "NegotiateProtocolVersion",
+
+            Self::ParseComplete(_) => "ParseComplete",
+            Self::BindComplete(_) => "BindComplete",
+            Self::CloseComplete(_) => "CloseComplete",
+            Self::PortalSuspended(_) => "PortalSuspended",
+
+            Self::CommandComplete(_) => "CommandComplete",
+            Self::EmptyQueryResponse(_) => "EmptyQueryResponse",
+            Self::ReadyForQuery(_) => "ReadyForQuery",
+            Self::ErrorResponse(_) => "ErrorResponse",
+            Self::NoticeResponse(_) => "NoticeResponse",
+            Self::SslResponse(_) => "SslResponse",
+            Self::GssEncResponse(_) => "GssEncResponse",
+            Self::NotificationResponse(_) => "NotificationResponse",
+
+            Self::ParameterDescription(_) => "ParameterDescription",
+            Self::RowDescription(_) => "RowDescription",
+            Self::DataRow(_) => "DataRow",
+            Self::NoData(_) => "NoData",
+
+            Self::CopyData(_) => "CopyData",
+            Self::CopyFail(_) => "CopyFail",
+            Self::CopyDone(_) => "CopyDone",
+            Self::CopyInResponse(_) => "CopyInResponse",
+            Self::CopyOutResponse(_) => "CopyOutResponse",
+            Self::CopyBothResponse(_) => "CopyBothResponse",
+
+            Self::FunctionCallResponse(_) => "FunctionCallResponse",
+        }
+    }
+
     pub fn encode(&self, buf: &mut BytesMut) -> PgWireResult<()> {
         match self {
             Self::Authentication(msg) => msg.encode(buf),
             Self::ParameterStatus(msg) => msg.encode(buf),
             Self::BackendKeyData(msg) => msg.encode(buf),
+            Self::NegotiateProtocolVersion(msg) => msg.encode(buf),
 
             Self::ParseComplete(msg) => msg.encode(buf),
             Self::BindComplete(msg) => msg.encode(buf),
@@ -246,6 +432,7 @@ impl PgWireBackendMessage {
             Self::ErrorResponse(msg) => msg.encode(buf),
             Self::NoticeResponse(msg) => msg.encode(buf),
             Self::SslResponse(msg) => msg.encode(buf),
+            Self::GssEncResponse(msg) => msg.encode(buf),
             Self::NotificationResponse(msg) => msg.encode(buf),
 
             Self::ParameterDescription(msg) => msg.encode(buf),
@@ -259,6 +446,8 @@ impl PgWireBackendMessage {
             Self::CopyInResponse(msg) => msg.encode(buf),
             Self::CopyOutResponse(msg) => msg.encode(buf),
             Self::CopyBothResponse(msg) => msg.encode(buf),
+
+            Self::FunctionCallResponse(msg) => msg.encode(buf),
         }
     }
 
@@ -275,6 +464,10 @@ impl PgWireBackendMessage {
                 startup::MESSAGE_TYPE_BYTE_BACKEND_KEY_DATA => {
                     startup::BackendKeyData::decode(buf).map(|v| v.map(Self::BackendKeyData))
                 }
+                startup::MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION => {
+                    startup::NegotiateProtocolVersion::decode(buf)
+                        .map(|v| v.map(Self::NegotiateProtocolVersion))
+                }
 
                 extendedquery::MESSAGE_TYPE_BYTE_PARSE_COMPLETE => {
                     extendedquery::ParseComplete::decode(buf).map(|v| v.map(Self::ParseComplete))
@@ -343,6 +536,11 @@ impl PgWireBackendMessage {
                 copy::MESSAGE_TYPE_BYTE_COPY_BOTH_RESPONSE => {
                     copy::CopyBothResponse::decode(buf).map(|v| v.map(Self::CopyBothResponse))
                 }
+
+                fastpath::MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE => {
+                    fastpath::FunctionCallResponse::decode(buf)
+                        .map(|v| v.map(Self::FunctionCallResponse))
+                }
                 _ => Err(PgWireError::InvalidMessageType(first_byte)),
             }
         } else {
@@ -356,11 +554,13 @@ mod test {
     use super::copy::*;
     use super::data::*;
     use super::extendedquery::*;
+    use super::fastpath::*;
     use super::response::*;
     use super::simplequery::*;
     use super::startup::*;
     use super::terminate::*;
-    use super::Message;
+    use super::{Message, PgWireFrontendMessage};
+    use crate::error::PgWireError;
     use bytes::{Buf, BufMut, Bytes, BytesMut};
 
     macro_rules! roundtrip {
@@ -387,21 +587,109 @@ mod test {
         roundtrip!(s, Startup);
     }
 
+    #[test]
+    fn test_cancel_request() {
+        let cancel = CancelRequest::new(42, Bytes::from_static(&[0, 0, 4, 210]));
+        roundtrip!(cancel, CancelRequest);
+        // variable length secret key of protocol 3.2
+        let cancel = CancelRequest::new(42, Bytes::from(vec![7; 32]));
+        roundtrip!(cancel, CancelRequest);
+
+        let mut buf = BytesMut::new();
+        CancelRequest::new(42, Bytes::from(vec![7; MAX_SECRET_KEY_LENGTH + 1]))
+            .encode(&mut buf)
+            .unwrap();
+        assert!(matches!(
+            CancelRequest::decode(&mut buf),
+            Err(PgWireError::InvalidStartupMessage)
+        ));
+
+        // startup packets are not cancel requests
+        let mut buf = BytesMut::new();
+        Startup::default().encode(&mut buf).unwrap();
+        assert!(CancelRequest::decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_backend_key_data() {
+        let key_data = BackendKeyData::new(42, Bytes::from_static(&[1, 2, 3, 4]));
+        roundtrip!(key_data, BackendKeyData);
+        let key_data = BackendKeyData::new(42, Bytes::from(vec![7; 32]));
+        roundtrip!(key_data, BackendKeyData);
+    }
+
+    #[test]
+    fn test_truncated_startup() {
+        // length covers "user\0tomcat" without the terminating \0s, followed
+        // by bytes of another message
+        let mut buf = BytesMut::new();
+        buf.put_i32(19);
+        buf.put_i32(196608);
+        buf.put_slice(b"user\0tomcat");
+        buf.put_slice(b"Q\0\0\0");
+        assert!(matches!(
+            Startup::decode(&mut buf),
+            Err(PgWireError::InvalidStartupMessage)
+        ));
+
+        let mut buf = BytesMut::new();
+        buf.put_i32(13);
+        buf.put_i32(196608);
+        buf.put_slice(b"user\0");
+        assert!(matches!(
+            Startup::decode(&mut buf),
+            Err(PgWireError::InvalidStartupMessage)
+        ));
+    }
+
     #[test]
     fn test_authentication() {
         let ss = vec![
             Authentication::Ok,
             Authentication::CleartextPassword,
             Authentication::KerberosV5,
+            Authentication::SCMCredential,
+            Authentication::GSS,
+            Authentication::GSSContinue(Bytes::from_static(&[0x60, 0x82, 0x01])),
+            Authentication::SSPI,
+            Authentication::SASL(vec![
+                "SCRAM-SHA-256".to_owned(),
+                "SCRAM-SHA-256-PLUS".to_owned(),
+            ]),
+            Authentication::SASLContinue(Bytes::from_static(b"r=nonce,s=salt,i=4096")),
+            Authentication::SASLFinal(Bytes::from_static(b"v=signature")),
         ];
         for s in ss {
             roundtrip!(s, Authentication);
         }
 
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        buf.put_i32(8);
+        buf.put_i32(4);
+        assert!(matches!(
+            Authentication::decode(&mut buf),
+            Err(PgWireError::UnsupportedAuthenticationMethod)
+        ));
+
         let md5pass = Authentication::MD5Password(vec![b'p', b's', b't', b'g']);
         roundtrip!(md5pass, Authentication);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let md5pass = Authentication::MD5Password(vec![0xde, 0xad, 0xbe, 0xef]);
+        let json = serde_json::to_string(&md5pass).unwrap();
+        assert_eq!(r#"{"MD5Password":"deadbeef"}"#, json);
+        assert_eq!(md5pass, serde_json::from_str(&json).unwrap());
+
+        let row = DataRow::new(BytesMut::from(&b"\x00\x00\x00\x01a"[..]), 1);
+        let json = serde_json::to_string(&row).unwrap();
+        assert_eq!(r#"{"data":"AAAAAWE=","field_count":1}"#, json);
+        assert_eq!(row, serde_json::from_str(&json).unwrap());
+    }
+
     #[test]
     fn test_password() {
         let s = Password::new("pgwire".to_owned());
@@ -414,6 +702,12 @@ mod test {
         roundtrip!(pps, ParameterStatus);
     }
 
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let npv = NegotiateProtocolVersion::new(0, vec!["_pq_.compression".to_owned()]);
+        roundtrip!(npv, NegotiateProtocolVersion);
+    }
+
     #[test]
     fn test_query() {
         let query = Query::new("SELECT 1".to_owned());
@@ -451,6 +745,84 @@ mod test {
     }
 
     #[test]
+    fn test_negotiate_protocol() {
+        let startup = Startup::new();
+        let capabilities = startup.negotiate_protocol();
+        assert_eq!((3, 0), capabilities.protocol_version);
+        assert_eq!(ReplicationMode::None, capabilities.replication);
+        assert_eq!(StartupMode::Normal, capabilities.startup_mode());
+        assert!(!capabilities.pipeline_mode);
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("replication".to_owned(), "database".to_owned());
+        startup
+            .parameters
+            .insert("application_name".to_owned(), "psql".to_owned());
+        startup
+            .parameters
+            .insert("_pq_.pipeline_mode".to_owned(), "on".to_owned());
+        let capabilities = startup.negotiate_protocol();
+        assert_eq!(ReplicationMode::Database, capabilities.replication);
+        assert_eq!(
+            StartupMode::Replication(ReplicationMode::Database),
+            capabilities.startup_mode()
+        );
+        assert_eq!(Some("psql"), capabilities.application_name.as_deref());
+        assert!(capabilities.pipeline_mode);
+        assert!(!capabilities.binary_parameter_support);
+        assert_eq!(
+            Some("on"),
+            capabilities
+                .protocol_extensions
+                .get("pipeline_mode")
+                .map(String::as_str)
+        );
+
+        startup
+            .parameters
+            .insert("replication".to_owned(), "true".to_owned());
+        assert_eq!(
+            ReplicationMode::True,
+            startup.negotiate_protocol().replication
+        );
+        assert!(startup.negotiate_protocol().trace_parent.is_none());
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        startup
+            .parameters
+            .insert(TRACEPARENT_PARAMETER.to_owned(), traceparent.to_owned());
+        let trace_parent = startup.negotiate_protocol().trace_parent.unwrap();
+        assert_eq!(0x4bf92f3577b34da6a3ce929d0e0e4736, trace_parent.trace_id);
+        assert_eq!(0x00f067aa0ba902b7, trace_parent.parent_id);
+        assert!(trace_parent.is_sampled());
+        assert_eq!(traceparent, trace_parent.to_string());
+    }
+
+    #[test]
+    fn test_trace_parent() {
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-+0f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceParent::parse(invalid).is_none(), "{invalid}");
+        }
+
+        let trace_parent =
+            TraceParent::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra")
+                .unwrap();
+        assert_eq!(1, trace_parent.version);
+        assert!(!trace_parent.is_sampled());
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_row_description() {
         let mut row_description = RowDescription::default();
 
@@ -477,6 +849,27 @@ mod test {
         roundtrip!(row_description, RowDescription);
     }
 
+    #[test]
+    fn test_row_description_etag() {
+        let desc = |type_id| {
+            RowDescription::new(vec![FieldDescription::new(
+                "id".into(),
+                1001,
+                10001,
+                type_id,
+                4,
+                -1,
+                FORMAT_CODE_TEXT,
+            )])
+        };
+
+        let etag = desc(23).etag();
+        assert_eq!(64, etag.len());
+        assert_eq!(etag, desc(23).etag());
+        assert_ne!(etag, desc(20).etag());
+        assert_ne!(etag, RowDescription::default().etag());
+    }
+
     #[test]
     fn test_data_row() {
         let mut row0 = DataRow::default();
@@ -532,6 +925,54 @@ mod test {
         roundtrip!(bind, Bind);
     }
 
+    #[test]
+    fn test_bind_parameter_builder() {
+        use crate::api::portal::{Format, Portal};
+        use crate::api::stmt::StoredStatement;
+        use postgres_types::Type;
+        use std::sync::Arc;
+
+        let mut builder = BindParameterBuilder::new();
+        builder
+            .add(&1234i32, &Type::INT4)
+            .unwrap()
+            .add(&"tom", &Type::VARCHAR)
+            .unwrap()
+            .add_null()
+            .add(&None::<i64>, &Type::INT8)
+            .unwrap();
+        assert!(builder.add(&1i32, &Type::TEXT).is_err());
+
+        let bind = builder
+            .build("find-user", "", Format::UnifiedBinary)
+            .unwrap();
+        assert_eq!(None, bind.portal_name);
+        assert_eq!(Some("find-user".to_owned()), bind.statement_name);
+        assert_eq!(vec![1], bind.parameter_format_codes);
+        assert_eq!(vec![1], bind.result_column_format_codes);
+        assert_eq!(
+            vec![
+                Some(Bytes::from_static(&[0, 0, 4, 210])),
+                Some(Bytes::from_static(b"tom")),
+                None,
+                None
+            ],
+            bind.parameters
+        );
+
+        let statement = Arc::new(StoredStatement::new(
+            "find-user".to_owned(),
+            (),
+            vec![Type::INT4, Type::VARCHAR, Type::INT4, Type::INT8],
+        ));
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        assert!(portal.parameter_format.is_binary(0));
+        assert_eq!(Some(1234), portal.parameter::<i32>(0, &Type::INT4).unwrap());
+        assert_eq!(None, portal.parameter::<i64>(3, &Type::INT8).unwrap());
+
+        roundtrip!(bind, Bind);
+    }
+
     #[test]
     fn test_execute() {
         let exec = Execute::new(Some("find-user-by-id-0".to_owned()), 100);
@@ -542,6 +983,8 @@ mod test {
     fn test_sslrequest() {
         let sslreq = SslRequest::new();
         roundtrip!(sslreq, SslRequest);
+        let gssreq = GssEncRequest::new();
+        roundtrip!(gssreq, GssEncRequest);
     }
 
     #[test]
@@ -550,6 +993,10 @@ mod test {
         roundtrip!(sslaccept, SslResponse);
         let sslrefuse = SslResponse::Refuse;
         roundtrip!(sslrefuse, SslResponse);
+        let gssaccept = GssEncResponse::Accept;
+        roundtrip!(gssaccept, GssEncResponse);
+        let gssrefuse = GssEncResponse::Refuse;
+        roundtrip!(gssrefuse, GssEncResponse);
     }
 
     #[test]
@@ -562,6 +1009,12 @@ mod test {
         roundtrip!(saslresp, SASLResponse);
     }
 
+    #[test]
+    fn test_gssresponse() {
+        let gssresp = GSSResponse::new(Bytes::from_static(b"abc"));
+        roundtrip!(gssresp, GSSResponse);
+    }
+
     #[test]
     fn test_parameter_description() {
         let param_desc = ParameterDescription::new(vec![100, 200]);
@@ -615,6 +1068,22 @@ mod test {
         roundtrip!(copyfail, CopyFail);
     }
 
+    #[test]
+    fn test_function_call() {
+        let call = FunctionCall::new(
+            764,
+            vec![1],
+            vec![Some(Bytes::from_static(&[0, 0, 0, 1])), None],
+            1,
+        );
+        roundtrip!(call, FunctionCall);
+
+        let response = FunctionCallResponse::new(Some(Bytes::from_static(b"42")));
+        roundtrip!(response, FunctionCallResponse);
+        let response = FunctionCallResponse::new(None);
+        roundtrip!(response, FunctionCallResponse);
+    }
+
     #[test]
     fn test_copy_response() {
         let copyresponse = CopyInResponse::new(0, 3, vec![0, 0, 0]);
@@ -633,4 +1102,23 @@ mod test {
             NotificationResponse::new(10087, "channel".to_owned(), "payload".to_owned());
         roundtrip!(notification_response, NotificationResponse);
     }
+
+    #[test]
+    fn test_peek_frontend_message() {
+        let mut buf = BytesMut::new();
+        assert_eq!(None, PgWireFrontendMessage::peek_type(&buf));
+        assert_eq!(None, PgWireFrontendMessage::peek_length(&buf));
+
+        Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+        assert_eq!(Some(b'Q'), PgWireFrontendMessage::peek_type(&buf[..1]));
+        assert_eq!(None, PgWireFrontendMessage::peek_length(&buf[..4]));
+        assert_eq!(Some(13), PgWireFrontendMessage::peek_length(&buf[..5]));
+        assert_eq!(
+            buf.len() as u32 - 1,
+            PgWireFrontendMessage::peek_length(&buf).unwrap()
+        );
+
+        // nothing is consumed
+        assert!(PgWireFrontendMessage::decode(&mut buf).unwrap().is_some());
+    }
 }
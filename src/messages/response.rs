@@ -19,7 +19,7 @@ impl Message for CommandComplete {
     }
 
     fn message_length(&self) -> usize {
-        5 + self.tag.as_bytes().len()
+        5 + self.tag.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -97,6 +97,7 @@ impl Message for ReadyForQuery {
 /// postgres error response, sent from backend to frontend
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Default, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ErrorResponse {
     pub fields: Vec<(u8, String)>,
 }
@@ -110,12 +111,7 @@ impl Message for ErrorResponse {
     }
 
     fn message_length(&self) -> usize {
-        4 + self
-            .fields
-            .iter()
-            .map(|f| 1 + f.1.as_bytes().len() + 1)
-            .sum::<usize>()
-            + 1
+        4 + self.fields.iter().map(|f| 1 + f.1.len() + 1).sum::<usize>() + 1
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -147,6 +143,7 @@ impl Message for ErrorResponse {
 /// postgres error response, sent from backend to frontend
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Default, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoticeResponse {
     pub fields: Vec<(u8, String)>,
 }
@@ -160,12 +157,7 @@ impl Message for NoticeResponse {
     }
 
     fn message_length(&self) -> usize {
-        4 + self
-            .fields
-            .iter()
-            .map(|f| 1 + f.1.as_bytes().len() + 1)
-            .sum::<usize>()
-            + 1
+        4 + self.fields.iter().map(|f| 1 + f.1.len() + 1).sum::<usize>() + 1
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -253,6 +245,62 @@ impl Message for SslResponse {
     }
 }
 
+/// Response to GSSENCRequest, a single byte 'G' or 'N' indicating that the
+/// backend is willing or unwilling to perform GSSAPI encryption.
+#[non_exhaustive]
+#[derive(Debug, PartialEq)]
+pub enum GssEncResponse {
+    Accept,
+    Refuse,
+}
+
+impl GssEncResponse {
+    pub const BYTE_ACCEPT: u8 = b'G';
+    pub const BYTE_REFUSE: u8 = b'N';
+    // The whole message takes only one byte and has no size field.
+    pub const MESSAGE_LENGTH: usize = 1;
+}
+
+impl Message for GssEncResponse {
+    fn message_length(&self) -> usize {
+        Self::MESSAGE_LENGTH
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        match self {
+            Self::Accept => buf.put_u8(Self::BYTE_ACCEPT),
+            Self::Refuse => buf.put_u8(Self::BYTE_REFUSE),
+        }
+        Ok(())
+    }
+
+    fn encode(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        self.encode_body(buf)
+    }
+
+    fn decode_body(_: &mut BytesMut, _: usize) -> PgWireResult<Self> {
+        unreachable!()
+    }
+
+    fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
+        if buf.remaining() >= Self::MESSAGE_LENGTH {
+            match buf[0] {
+                Self::BYTE_ACCEPT => {
+                    buf.advance(Self::MESSAGE_LENGTH);
+                    Ok(Some(GssEncResponse::Accept))
+                }
+                Self::BYTE_REFUSE => {
+                    buf.advance(Self::MESSAGE_LENGTH);
+                    Ok(Some(GssEncResponse::Refuse))
+                }
+                _ => Ok(None),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 /// NotificationResponse
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Default, new)]
@@ -271,7 +319,7 @@ impl Message for NotificationResponse {
     }
 
     fn message_length(&self) -> usize {
-        8 + self.channel.as_bytes().len() + 1 + self.payload.as_bytes().len() + 1
+        8 + self.channel.len() + 1 + self.payload.len() + 1
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -1,8 +1,9 @@
 use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-use super::codec;
+use super::codec::{self, CStringResult};
 use super::Message;
 use crate::error::{PgWireError, PgWireResult};
 
@@ -13,6 +14,7 @@ use crate::error::{PgWireError, PgWireResult};
 ///
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Startup {
     #[new(value = "3")]
     pub protocol_number_major: u16,
@@ -31,9 +33,201 @@ impl Default for Startup {
 impl Startup {
     const MINIMUM_STARTUP_MESSAGE_LEN: usize = 8;
 
+    /// Any minor version of protocol 3 is accepted, newer minor versions are
+    /// negotiated down during startup.
     fn is_protocol_version_supported(version: i32) -> bool {
-        version == 196608
+        version >> 16 == 3
     }
+
+    /// Interpret startup parameters into client capabilities.
+    pub fn negotiate_protocol(&self) -> ProtocolCapabilities {
+        let bool_param = |key: &str| {
+            self.parameters
+                .get(key)
+                .and_then(|v| parse_bool(v))
+                .unwrap_or(false)
+        };
+
+        let replication = ReplicationMode::from_parameter(
+            self.parameters
+                .get(REPLICATION_PARAMETER)
+                .map(|v| v.as_str()),
+        );
+
+        let protocol_extensions = self
+            .parameters
+            .iter()
+            .filter_map(|(k, v)| {
+                k.strip_prefix(PROTOCOL_EXTENSION_PREFIX)
+                    .map(|k| (k.to_owned(), v.clone()))
+            })
+            .collect();
+
+        ProtocolCapabilities {
+            protocol_version: (self.protocol_number_major, self.protocol_number_minor),
+            replication,
+            pipeline_mode: bool_param("_pq_.pipeline_mode"),
+            binary_parameter_support: bool_param("_pq_.binary_parameters"),
+            application_name: self.parameters.get("application_name").cloned(),
+            protocol_extensions,
+            trace_parent: self
+                .parameters
+                .get(TRACEPARENT_PARAMETER)
+                .and_then(|v| TraceParent::parse(v)),
+        }
+    }
+}
+
+impl ProtocolCapabilities {
+    /// Mode of the connection, `Replication` if a walsender connection is
+    /// requested.
+    pub fn startup_mode(&self) -> StartupMode {
+        self.replication.into()
+    }
+}
+
+/// Startup parameter requesting a replication connection
+pub const REPLICATION_PARAMETER: &str = "replication";
+
+/// Prefix of startup parameters that request protocol extensions
+pub const PROTOCOL_EXTENSION_PREFIX: &str = "_pq_.";
+
+/// Startup parameter carrying W3C trace context of client, in the format of
+/// `traceparent` http header.
+pub const TRACEPARENT_PARAMETER: &str = "traceparent";
+
+/// W3C trace context, `{version}-{trace-id}-{parent-id}-{trace-flags}` in
+/// lowercase hex.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TraceParent {
+    pub version: u8,
+    pub trace_id: u128,
+    pub parent_id: u64,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Parse `traceparent` value. `None` is returned for malformed value or
+    /// all-zero ids, which are invalid according to the spec.
+    pub fn parse(value: &str) -> Option<TraceParent> {
+        fn hex(part: &str, len: usize) -> Option<u128> {
+            if part.len() == len && part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                u128::from_str_radix(part, 16).ok()
+            } else {
+                None
+            }
+        }
+
+        let mut parts = value.trim().split('-');
+        let version = hex(parts.next()?, 2)? as u8;
+        let trace_id = hex(parts.next()?, 32)?;
+        let parent_id = hex(parts.next()?, 16)? as u64;
+        let flags = hex(parts.next()?, 2)? as u8;
+        // version 00 has exactly 4 fields, later versions may append more
+        if version == 0xff || (version == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == 0 || parent_id == 0 {
+            return None;
+        }
+
+        Some(TraceParent {
+            version,
+            trace_id,
+            parent_id,
+            flags,
+        })
+    }
+
+    /// Whether the caller may have recorded the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+}
+
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}-{:032x}-{:016x}-{:02x}",
+            self.version, self.trace_id, self.parent_id, self.flags
+        )
+    }
+}
+
+/// Parse boolean parameter value the way postgres does.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" | "t" | "y" => Some(true),
+        "off" | "false" | "no" | "0" | "f" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+/// Value of the `replication` startup parameter
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ReplicationMode {
+    /// Normal connection
+    #[default]
+    None,
+    /// Logical replication connection to the database
+    Database,
+    /// Physical replication connection
+    True,
+}
+
+impl ReplicationMode {
+    /// Interpret value of the `replication` startup parameter.
+    pub fn from_parameter(value: Option<&str>) -> ReplicationMode {
+        match value {
+            Some(v) if v.eq_ignore_ascii_case("database") => ReplicationMode::Database,
+            Some(v) if parse_bool(v) == Some(true) => ReplicationMode::True,
+            _ => ReplicationMode::None,
+        }
+    }
+}
+
+/// Mode of a connection, decided by startup parameters
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum StartupMode {
+    /// Regular connection for queries
+    #[default]
+    Normal,
+    /// Walsender connection, which accepts only replication commands
+    Replication(ReplicationMode),
+}
+
+impl From<ReplicationMode> for StartupMode {
+    fn from(mode: ReplicationMode) -> StartupMode {
+        match mode {
+            ReplicationMode::None => StartupMode::Normal,
+            mode => StartupMode::Replication(mode),
+        }
+    }
+}
+
+/// Client capabilities interpreted from startup parameters, see
+/// `Startup::negotiate_protocol`.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct ProtocolCapabilities {
+    /// protocol version requested by client
+    pub protocol_version: (u16, u16),
+    /// requested replication mode
+    pub replication: ReplicationMode,
+    /// client pipelines queries, requested with `_pq_.pipeline_mode`
+    pub pipeline_mode: bool,
+    /// client sends binary parameters, requested with `_pq_.binary_parameters`
+    pub binary_parameter_support: bool,
+    /// `application_name` reported by client
+    pub application_name: Option<String>,
+    /// all `_pq_.` prefixed parameters, with the prefix stripped
+    pub protocol_extensions: BTreeMap<String, String>,
+    /// trace context of client, sent with `traceparent`
+    pub trace_parent: Option<TraceParent>,
 }
 
 impl Message for Startup {
@@ -41,7 +235,7 @@ impl Message for Startup {
         let param_length = self
             .parameters
             .iter()
-            .map(|(k, v)| k.as_bytes().len() + v.as_bytes().len() + 2)
+            .map(|(k, v)| k.len() + v.len() + 2)
             .sum::<usize>();
         // length:4 + protocol_number:4 + param.len + nullbyte:1
         9 + param_length
@@ -89,11 +283,27 @@ impl Message for Startup {
         let protocol_number_major = buf.get_u16();
         let protocol_number_minor = buf.get_u16();
 
+        // parameters are read within the packet only, a truncated packet
+        // without the last \0 is rejected instead of reading the next one
+        let mut body = buf.split_to(msg_len - Self::MINIMUM_STARTUP_MESSAGE_LEN);
+
         // end by reading the last \0
         let mut parameters = BTreeMap::new();
-        while let Some(key) = codec::get_cstring(buf) {
-            let value = codec::get_cstring(buf).unwrap_or_else(|| "".to_owned());
-            parameters.insert(key, value);
+        loop {
+            match codec::get_cstring_checked(&mut body) {
+                CStringResult::Value(key) => {
+                    let value = match codec::get_cstring_checked(&mut body) {
+                        CStringResult::Value(value) => value,
+                        CStringResult::Empty => "".to_owned(),
+                        CStringResult::Incomplete => {
+                            return Err(PgWireError::InvalidStartupMessage)
+                        }
+                    };
+                    parameters.insert(key, value);
+                }
+                CStringResult::Empty => break,
+                CStringResult::Incomplete => return Err(PgWireError::InvalidStartupMessage),
+            }
         }
 
         Ok(Startup {
@@ -107,22 +317,24 @@ impl Message for Startup {
 /// authentication response family, sent by backend
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Authentication {
-    Ok,                   // code 0
-    CleartextPassword,    // code 3
-    KerberosV5,           // code 2
+    Ok,                // code 0
+    CleartextPassword, // code 3
+    KerberosV5,        // code 2
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::hex_bytes"))]
     MD5Password(Vec<u8>), // code 5, with 4 bytes of md5 salt
-
-    SASL(Vec<String>),   // code 10, with server supported sasl mechanisms
+    SCMCredential,     // code 6, peer credential over unix domain socket
+    GSS,               // code 7
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::base64_bytes"))]
+    GSSContinue(Bytes), // code 8, with GSSAPI or SSPI authentication data
+    SSPI,              // code 9
+
+    SASL(Vec<String>), // code 10, with server supported sasl mechanisms
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::base64_bytes"))]
     SASLContinue(Bytes), // code 11, with authentication data
-    SASLFinal(Bytes),    // code 12, with additional authentication data
-
-                         // TODO: more types
-                         // AuthenticationSCMCredential
-                         //
-                         // AuthenticationGSS
-                         // AuthenticationGSSContinue
-                         // AuthenticationSSPI
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::base64_bytes"))]
+    SASLFinal(Bytes), // code 12, with additional authentication data
 }
 
 pub const MESSAGE_TYPE_BYTE_AUTHENTICATION: u8 = b'R';
@@ -136,10 +348,14 @@ impl Message for Authentication {
     #[inline]
     fn message_length(&self) -> usize {
         match self {
-            Authentication::Ok | Authentication::CleartextPassword | Authentication::KerberosV5 => {
-                8
-            }
+            Authentication::Ok
+            | Authentication::CleartextPassword
+            | Authentication::KerberosV5
+            | Authentication::SCMCredential
+            | Authentication::GSS
+            | Authentication::SSPI => 8,
             Authentication::MD5Password(_) => 12,
+            Authentication::GSSContinue(data) => 8 + data.len(),
             Authentication::SASL(methods) => {
                 8 + methods.iter().map(|v| v.len() + 1).sum::<usize>() + 1
             }
@@ -157,6 +373,13 @@ impl Message for Authentication {
                 buf.put_i32(5);
                 buf.put_slice(salt.as_ref());
             }
+            Authentication::SCMCredential => buf.put_i32(6),
+            Authentication::GSS => buf.put_i32(7),
+            Authentication::GSSContinue(data) => {
+                buf.put_i32(8);
+                buf.put_slice(data.as_ref());
+            }
+            Authentication::SSPI => buf.put_i32(9),
             Authentication::SASL(methods) => {
                 buf.put_i32(10);
                 for method in methods {
@@ -187,6 +410,13 @@ impl Message for Authentication {
                 buf.copy_to_slice(&mut salt_vec);
                 Authentication::MD5Password(salt_vec)
             }
+            6 => Authentication::SCMCredential,
+            7 => Authentication::GSS,
+            8 => {
+                let data = buf.split_to(msg_len - 8).freeze();
+                Authentication::GSSContinue(data)
+            }
+            9 => Authentication::SSPI,
             10 => {
                 let mut methods = Vec::new();
                 while let Some(method) = codec::get_cstring(buf) {
@@ -195,14 +425,14 @@ impl Message for Authentication {
                 Authentication::SASL(methods)
             }
             11 => {
-                let data = buf.split_to(msg_len - 4).freeze();
+                let data = buf.split_to(msg_len - 8).freeze();
                 Authentication::SASLContinue(data)
             }
             12 => {
-                let data = buf.split_to(msg_len - 4).freeze();
+                let data = buf.split_to(msg_len - 8).freeze();
                 Authentication::SASLFinal(data)
             }
-            _ => unreachable!(),
+            _ => return Err(PgWireError::UnsupportedAuthenticationMethod),
         };
 
         Ok(msg)
@@ -236,6 +466,8 @@ pub enum PasswordMessageFamily {
     SASLInitialResponse(SASLInitialResponse),
     /// SASLResponse
     SASLResponse(SASLResponse),
+    /// GSSResponse
+    GSSResponse(GSSResponse),
 }
 
 impl Message for PasswordMessageFamily {
@@ -249,6 +481,7 @@ impl Message for PasswordMessageFamily {
             PasswordMessageFamily::Password(inner) => inner.message_length(),
             PasswordMessageFamily::SASLInitialResponse(inner) => inner.message_length(),
             PasswordMessageFamily::SASLResponse(inner) => inner.message_length(),
+            PasswordMessageFamily::GSSResponse(inner) => inner.message_length(),
         }
     }
 
@@ -261,6 +494,7 @@ impl Message for PasswordMessageFamily {
             PasswordMessageFamily::Password(inner) => inner.encode_body(buf),
             PasswordMessageFamily::SASLInitialResponse(inner) => inner.encode_body(buf),
             PasswordMessageFamily::SASLResponse(inner) => inner.encode_body(buf),
+            PasswordMessageFamily::GSSResponse(inner) => inner.encode_body(buf),
         }
     }
 
@@ -321,11 +555,29 @@ impl PasswordMessageFamily {
             )
         }
     }
+
+    /// Coerce the raw message into `GSSResponse`
+    ///
+    /// # Panics
+    ///
+    /// Panic when the message is already coerced into concrete type.
+    pub fn into_gss_response(self) -> PgWireResult<GSSResponse> {
+        if let PasswordMessageFamily::Raw(mut body) = self {
+            let len = body.len() + 4;
+            GSSResponse::decode_body(&mut body, len)
+        } else {
+            unreachable!(
+                "Do not coerce password message when it has a concrete type {:?}",
+                self
+            )
+        }
+    }
 }
 
 /// password packet sent from frontend
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Password {
     pub password: String,
 }
@@ -337,7 +589,7 @@ impl Message for Password {
     }
 
     fn message_length(&self) -> usize {
-        5 + self.password.as_bytes().len()
+        5 + self.password.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -356,6 +608,7 @@ impl Message for Password {
 /// parameter ack sent from backend after authentication success
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterStatus {
     pub name: String,
     pub value: String,
@@ -370,7 +623,7 @@ impl Message for ParameterStatus {
     }
 
     fn message_length(&self) -> usize {
-        4 + 2 + self.name.as_bytes().len() + self.value.as_bytes().len()
+        4 + 2 + self.name.len() + self.value.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -388,13 +641,75 @@ impl Message for ParameterStatus {
     }
 }
 
+/// Sent from backend during startup when the client requested a newer minor
+/// protocol version, or protocol options prefixed with `_pq_.` that are not
+/// supported.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NegotiateProtocolVersion {
+    /// newest minor protocol version supported by server
+    pub newest_minor_version: i32,
+    /// options not recognized by server, with the `_pq_.` prefix
+    pub unsupported_options: Vec<String>,
+}
+
+pub const MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION: u8 = b'v';
+
+impl Message for NegotiateProtocolVersion {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION)
+    }
+
+    fn message_length(&self) -> usize {
+        4 + 4
+            + 4
+            + self
+                .unsupported_options
+                .iter()
+                .map(|o| o.len() + 1)
+                .sum::<usize>()
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(self.newest_minor_version);
+        buf.put_i32(self.unsupported_options.len() as i32);
+        for option in &self.unsupported_options {
+            codec::put_cstring(buf, option);
+        }
+
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, _: usize) -> PgWireResult<Self> {
+        let newest_minor_version = buf.get_i32();
+        let count = buf.get_i32();
+        let mut unsupported_options = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            unsupported_options.push(codec::get_cstring(buf).unwrap_or_else(|| "".to_owned()));
+        }
+
+        Ok(NegotiateProtocolVersion {
+            newest_minor_version,
+            unsupported_options,
+        })
+    }
+}
+
+/// Max length of secret key in `BackendKeyData` and `CancelRequest`. The key
+/// is 4 bytes in protocol 3.0, and variable length since protocol 3.2.
+pub const MAX_SECRET_KEY_LENGTH: usize = 256;
+
 /// `BackendKeyData` message, sent from backend to frontend for issuing
 /// `CancelRequestMessage`
 #[non_exhaustive]
-#[derive(PartialEq, Eq, Debug, new)]
+#[derive(PartialEq, Eq, Debug, Clone, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BackendKeyData {
     pub pid: i32,
-    pub secret_key: i32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::base64_bytes"))]
+    pub secret_key: Bytes,
 }
 
 pub const MESSAGE_TYPE_BYTE_BACKEND_KEY_DATA: u8 = b'K';
@@ -407,19 +722,20 @@ impl Message for BackendKeyData {
 
     #[inline]
     fn message_length(&self) -> usize {
-        12
+        8 + self.secret_key.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
         buf.put_i32(self.pid);
-        buf.put_i32(self.secret_key);
+        buf.put_slice(&self.secret_key);
 
         Ok(())
     }
 
-    fn decode_body(buf: &mut BytesMut, _: usize) -> PgWireResult<Self> {
-        let pid = buf.get_i32();
-        let secret_key = buf.get_i32();
+    fn decode_body(buf: &mut BytesMut, len: usize) -> PgWireResult<Self> {
+        let pid = codec::get_i32_checked(buf)?;
+        // rest of the message
+        let secret_key = codec::get_slice_checked(buf, len.saturating_sub(8))?.freeze();
 
         Ok(BackendKeyData { pid, secret_key })
     }
@@ -472,6 +788,111 @@ impl Message for SslRequest {
     }
 }
 
+/// `GssEncRequest` sent from frontend to check if the backend supports GSSAPI
+/// encryption. Like `SslRequest`, the packet contains only a length(4) and an
+/// i32 value.
+///
+/// The backend sends a single byte 'G' or 'N' to indicate its support. Upon
+/// 'N' the frontend may continue with `SslRequest` or `Startup`.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct GssEncRequest;
+
+impl GssEncRequest {
+    pub const BODY_MAGIC_NUMBER: i32 = 80877104;
+    pub const BODY_SIZE: usize = 8;
+}
+
+impl Message for GssEncRequest {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    fn message_length(&self) -> usize {
+        Self::BODY_SIZE
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(Self::BODY_MAGIC_NUMBER);
+        Ok(())
+    }
+
+    fn decode_body(_buf: &mut BytesMut, _full_len: usize) -> PgWireResult<Self> {
+        unreachable!();
+    }
+
+    /// Try to decode and check if the packet is a `GssEncRequest`.
+    fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
+        if buf.remaining() >= 8 && (&buf[4..8]).get_i32() == Self::BODY_MAGIC_NUMBER {
+            buf.advance(8);
+            Ok(Some(GssEncRequest))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// `CancelRequest` sent from frontend on a new connection, to cancel the query
+/// running in the session identified by `BackendKeyData`. Like `SslRequest`,
+/// the packet has no message type.
+///
+/// The backend sends no response and closes the connection.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, new)]
+pub struct CancelRequest {
+    pub pid: i32,
+    pub secret_key: Bytes,
+}
+
+impl CancelRequest {
+    pub const BODY_MAGIC_NUMBER: i32 = 80877102;
+    /// Size of request with 4 bytes secret key of protocol 3.0
+    pub const MIN_BODY_SIZE: usize = 16;
+    pub const MAX_BODY_SIZE: usize = 12 + MAX_SECRET_KEY_LENGTH;
+}
+
+impl Message for CancelRequest {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    fn message_length(&self) -> usize {
+        12 + self.secret_key.len()
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(Self::BODY_MAGIC_NUMBER);
+        buf.put_i32(self.pid);
+        buf.put_slice(&self.secret_key);
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, full_len: usize) -> PgWireResult<Self> {
+        buf.advance(4);
+        let pid = buf.get_i32();
+        let secret_key = buf.split_to(full_len - 12).freeze();
+
+        Ok(CancelRequest { pid, secret_key })
+    }
+
+    /// Try to decode and check if the packet is a `CancelRequest`.
+    fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
+        if buf.remaining() >= 8 && (&buf[4..8]).get_i32() == Self::BODY_MAGIC_NUMBER {
+            let len = (&buf[0..4]).get_i32() as usize;
+            if !(Self::MIN_BODY_SIZE..=Self::MAX_BODY_SIZE).contains(&len) {
+                return Err(PgWireError::InvalidStartupMessage);
+            }
+            codec::decode_packet(buf, 0, Self::decode_body)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
 pub struct SASLInitialResponse {
@@ -487,10 +908,7 @@ impl Message for SASLInitialResponse {
 
     #[inline]
     fn message_length(&self) -> usize {
-        4 + self.auth_method.as_bytes().len()
-            + 1
-            + 4
-            + self.data.as_ref().map(|b| b.len()).unwrap_or(0)
+        4 + self.auth_method.len() + 1 + 4 + self.data.as_ref().map(|b| b.len()).unwrap_or(0)
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -544,3 +962,32 @@ impl Message for SASLResponse {
         Ok(SASLResponse { data })
     }
 }
+
+/// GSSAPI or SSPI authentication data sent from frontend
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct GSSResponse {
+    pub data: Bytes,
+}
+
+impl Message for GSSResponse {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_PASWORD_MESSAGE_FAMILY)
+    }
+
+    #[inline]
+    fn message_length(&self) -> usize {
+        4 + self.data.len()
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_slice(self.data.as_ref());
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, full_len: usize) -> PgWireResult<Self> {
+        let data = buf.split_to(full_len - 4).freeze();
+        Ok(GSSResponse { data })
+    }
+}
@@ -0,0 +1,391 @@
+//! Multiplexing of logical sessions over a single connection.
+//!
+//! A client asks for multiplexing by adding startup parameter
+//! `_pgwire_mux_version=1`. When the server supports it, it confirms by sending
+//! `ParameterStatus` with the same name and value once the connection is
+//! authenticated. From then on, every message in both directions is prefixed
+//! with a 4-byte session id. Each session id has its own connection state and
+//! portal store, just like an individual connection. A `Terminate` message
+//! ends only the session it's sent to.
+//!
+//! Sessions share authentication and startup parameters of the underlying
+//! connection. Clients that don't send the startup parameter are served in
+//! regular single-session mode.
+//!
+//! Messages are processed one at a time in the order they arrive, whatever
+//! session they belong to. A slow query of one session delays all other
+//! sessions of the connection.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::Sink;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::api::guc::GucRegistry;
+use crate::api::notice::NoticeEmitter;
+use crate::api::transaction::TransactionStatus;
+use crate::api::{ClientInfo, ClientPortalStore, DefaultClient, PgWireConnectionState};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::response::NoticeResponse;
+use crate::messages::startup::{BackendKeyData, ParameterStatus};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Startup parameter for negotiating multiplexing
+pub const MUX_VERSION_PARAMETER: &str = "_pgwire_mux_version";
+/// Supported version of multiplexing
+pub const MUX_VERSION: &str = "1";
+/// Default max number of sessions on a connection
+pub const DEFAULT_MAX_MUX_SESSIONS: usize = 64;
+
+/// Test if client asked for multiplexing in its startup parameters
+pub fn is_mux_requested<C: ClientInfo>(client: &C) -> bool {
+    client
+        .metadata()
+        .get(MUX_VERSION_PARAMETER)
+        .map(|v| v == MUX_VERSION)
+        .unwrap_or(false)
+}
+
+/// Codec for multiplexed connection. Messages are prefixed with 4-byte session
+/// id.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MuxCodec;
+
+impl Decoder for MuxCodec {
+    type Item = (u32, PgWireFrontendMessage);
+    type Error = PgWireError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // session id:4 + message type:1 + length:4
+        if src.remaining() < 9 {
+            return Ok(None);
+        }
+        // length of message includes itself
+        let msg_len = (&src[5..9]).get_i32();
+        if msg_len < 4 {
+            return Err(PgWireError::InvalidMuxFrame);
+        }
+        if src.remaining() < 5 + msg_len as usize {
+            return Ok(None);
+        }
+
+        let session_id = src.get_u32();
+        let message = PgWireFrontendMessage::decode(src)?.ok_or(PgWireError::InvalidMuxFrame)?;
+        Ok(Some((session_id, message)))
+    }
+}
+
+impl Encoder<(u32, PgWireBackendMessage)> for MuxCodec {
+    type Error = std::io::Error;
+
+    fn encode(
+        &mut self,
+        (session_id, item): (u32, PgWireBackendMessage),
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        dst.put_u32(session_id);
+        item.encode(dst).map_err(Into::into)
+    }
+}
+
+/// A logical session in multiplexed connection.
+///
+/// Messages sent to the session are buffered, and written to the connection
+/// by `MuxLayer` owner with session id prefixed.
+#[derive(Debug)]
+pub struct MuxSession<S> {
+    id: u32,
+    client_info: DefaultClient<S>,
+    outgoing: Vec<PgWireBackendMessage>,
+    closed: bool,
+}
+
+impl<S> MuxSession<S> {
+    /// Session id
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Test if the session has been closed by handler
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Take buffered messages of this session
+    pub fn take_outgoing(&mut self) -> Vec<PgWireBackendMessage> {
+        std::mem::take(&mut self.outgoing)
+    }
+}
+
+impl<S> ClientInfo for MuxSession<S> {
+    fn socket_addr(&self) -> SocketAddr {
+        self.client_info.socket_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        self.client_info.is_secure()
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.client_info.state()
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.client_info.set_state(new_state);
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        self.client_info.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        self.client_info.metadata_mut()
+    }
+
+    fn notice_emitter(&self) -> Option<NoticeEmitter> {
+        self.client_info.notice_emitter()
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.client_info.take_notices()
+    }
+
+    fn push_parameter_status(&mut self, name: &str, value: &str) {
+        self.client_info.push_parameter_status(name, value);
+    }
+
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        self.client_info.take_parameter_status()
+    }
+
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        self.client_info.backend_key_data()
+    }
+
+    fn set_backend_key_data(&mut self, backend_key_data: BackendKeyData) {
+        self.client_info.set_backend_key_data(backend_key_data);
+    }
+
+    fn guc_registry(&self) -> Option<Arc<Mutex<GucRegistry>>> {
+        self.client_info.guc_registry()
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.client_info.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.client_info.set_transaction_status(status);
+    }
+
+    fn protocol_minor_version(&self) -> u16 {
+        self.client_info.protocol_minor_version()
+    }
+
+    fn set_protocol_minor_version(&mut self, minor_version: u16) {
+        self.client_info.set_protocol_minor_version(minor_version);
+    }
+}
+
+impl<S> ClientPortalStore for MuxSession<S> {
+    type PortalStore = <DefaultClient<S> as ClientPortalStore>::PortalStore;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        self.client_info.portal_store()
+    }
+}
+
+impl<S> Sink<PgWireBackendMessage> for MuxSession<S> {
+    type Error = PgWireError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: PgWireBackendMessage) -> PgWireResult<()> {
+        self.get_mut().outgoing.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        self.get_mut().closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Demultiplexer of sessions on a connection
+///
+/// Sessions are created on their first message, up to `max_sessions`. The
+/// layer doesn't run handlers itself: its owner processes each message to
+/// completion before reading the next one, so sessions are not served
+/// concurrently and one slow query stalls every session of the connection.
+#[derive(Debug)]
+pub struct MuxLayer<S> {
+    socket_addr: SocketAddr,
+    is_secure: bool,
+    metadata: HashMap<String, String>,
+    sessions: HashMap<u32, MuxSession<S>>,
+    max_sessions: usize,
+}
+
+impl<S> MuxLayer<S> {
+    /// Create `MuxLayer` from the authenticated connection. Sessions inherit
+    /// its address and metadata.
+    pub fn new<C: ClientInfo>(client: &C) -> MuxLayer<S> {
+        MuxLayer {
+            socket_addr: client.socket_addr(),
+            is_secure: client.is_secure(),
+            metadata: client.metadata().clone(),
+            sessions: HashMap::new(),
+            max_sessions: DEFAULT_MAX_MUX_SESSIONS,
+        }
+    }
+
+    /// Limit number of active sessions, `DEFAULT_MAX_MUX_SESSIONS` by
+    /// default.
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> MuxLayer<S> {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// Max number of active sessions
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    /// Get session by id, a new session is created if it doesn't exist.
+    ///
+    /// Returns `PgWireError::InvalidMuxFrame` if a new session would exceed
+    /// `max_sessions`.
+    pub fn session_mut(&mut self, id: u32) -> PgWireResult<&mut MuxSession<S>> {
+        if !self.sessions.contains_key(&id) && self.sessions.len() >= self.max_sessions {
+            return Err(PgWireError::InvalidMuxFrame);
+        }
+        Ok(self.sessions.entry(id).or_insert_with(|| {
+            let mut client_info = DefaultClient::new(self.socket_addr, self.is_secure);
+            client_info.metadata = self.metadata.clone();
+            client_info.state = PgWireConnectionState::ReadyForQuery;
+            MuxSession {
+                id,
+                client_info,
+                outgoing: Vec::new(),
+                closed: false,
+            }
+        }))
+    }
+
+    /// Remove session by id
+    pub fn remove(&mut self, id: u32) -> Option<MuxSession<S>> {
+        self.sessions.remove(&id)
+    }
+
+    /// Ids of active sessions
+    pub fn session_ids(&self) -> Vec<u32> {
+        self.sessions.keys().copied().collect()
+    }
+
+    /// Number of active sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Test if there is no active session
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::SinkExt;
+
+    use super::*;
+    use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
+    use crate::messages::simplequery::Query;
+    use crate::messages::Message;
+
+    #[test]
+    fn test_mux_codec() {
+        let mut buf = BytesMut::new();
+        buf.put_u32(7);
+        Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+
+        let mut partial = buf.split_to(6);
+        assert!(MuxCodec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+
+        let (id, msg) = MuxCodec.decode(&mut partial).unwrap().unwrap();
+        assert_eq!(7, id);
+        assert!(matches!(msg, PgWireFrontendMessage::Query(q) if q.query == "SELECT 1"));
+        assert!(partial.is_empty());
+
+        // negative length
+        let mut buf = BytesMut::new();
+        buf.put_u32(7);
+        buf.put_u8(b'Q');
+        buf.put_i32(-1);
+        assert!(matches!(
+            MuxCodec.decode(&mut buf),
+            Err(PgWireError::InvalidMuxFrame)
+        ));
+
+        let mut out = BytesMut::new();
+        MuxCodec
+            .encode(
+                (
+                    7,
+                    PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(READY_STATUS_IDLE)),
+                ),
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(&[0, 0, 0, 7, b'Z', 0, 0, 0, 5, b'I'], out.as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_mux_layer() {
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        client
+            .metadata
+            .insert(MUX_VERSION_PARAMETER.to_owned(), MUX_VERSION.to_owned());
+        assert!(is_mux_requested(&client));
+
+        let mut layer = MuxLayer::<String>::new(&client).with_max_sessions(2);
+        let session = layer.session_mut(1).unwrap();
+        assert!(matches!(
+            session.state(),
+            PgWireConnectionState::ReadyForQuery
+        ));
+        session
+            .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                READY_STATUS_IDLE,
+            )))
+            .await
+            .unwrap();
+        assert_eq!(1, session.take_outgoing().len());
+        assert!(session.take_outgoing().is_empty());
+
+        layer.session_mut(2).unwrap();
+        assert_eq!(2, layer.len());
+        // existing sessions are still available at the limit
+        assert!(layer.session_mut(1).is_ok());
+        assert!(matches!(
+            layer.session_mut(3),
+            Err(PgWireError::InvalidMuxFrame)
+        ));
+        assert_eq!(2, layer.len());
+
+        assert!(layer.remove(1).is_some());
+        assert_eq!(1, layer.len());
+        assert!(layer.session_mut(3).is_ok());
+    }
+}
@@ -0,0 +1,83 @@
+//! OID constants of standard types, generated from the `pg_type` catalog of
+//! PostgreSQL 15.
+//!
+//! Array types are named after their element type, e.g. `INT4_ARRAY_OID` for
+//! `_int4`.
+
+/// Kind of a type, from `typtype` and `typcategory` of `pg_type`
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeCategory {
+    Base,
+    Array,
+    Composite,
+    Range,
+    Multirange,
+    Pseudo,
+}
+
+/// Information of a standard type
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgTypeInfo {
+    pub oid: u32,
+    /// `typname` in `pg_type`
+    pub name: &'static str,
+    pub category: TypeCategory,
+    /// variable length type, whose `typlen` is -1
+    pub varlena: bool,
+    /// oid of the array type with this type as element
+    pub array_oid: Option<u32>,
+}
+
+include!(concat!(env!("OUT_DIR"), "/oid_constants.rs"));
+
+/// Find standard type by oid.
+pub fn type_info(oid: u32) -> Option<&'static PgTypeInfo> {
+    PG_TYPES
+        .binary_search_by_key(&oid, |t| t.oid)
+        .ok()
+        .map(|idx| &PG_TYPES[idx])
+}
+
+#[cfg(test)]
+mod test {
+    use postgres_types::Type;
+
+    use super::*;
+
+    #[test]
+    fn test_oid_constants() {
+        assert_eq!(Type::BOOL.oid(), BOOL_OID);
+        assert_eq!(Type::INT4.oid(), INT4_OID);
+        assert_eq!(Type::INT4_ARRAY.oid(), INT4_ARRAY_OID);
+        assert_eq!(Type::JSONB.oid(), JSONB_OID);
+
+        let int4 = type_info(INT4_OID).unwrap();
+        assert_eq!("int4", int4.name);
+        assert_eq!(TypeCategory::Base, int4.category);
+        assert!(!int4.varlena);
+        assert_eq!(Some(INT4_ARRAY_OID), int4.array_oid);
+
+        let text_array = type_info(TEXT_ARRAY_OID).unwrap();
+        assert_eq!("_text", text_array.name);
+        assert_eq!(TypeCategory::Array, text_array.category);
+        assert!(text_array.varlena);
+
+        assert_eq!(
+            TypeCategory::Range,
+            type_info(INT4RANGE_OID).unwrap().category
+        );
+        assert_eq!(TypeCategory::Pseudo, type_info(ANY_OID).unwrap().category);
+        assert!(type_info(0).is_none());
+    }
+
+    #[test]
+    fn test_matches_postgres_types() {
+        for info in PG_TYPES {
+            if let Some(ty) = Type::from_oid(info.oid) {
+                assert_eq!(ty.name(), info.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! PROXY protocol header sent by load balancers, like HAProxy, before the
+//! client data.
+//!
+//! Both the text format of version 1 and the binary format of version 2 are
+//! accepted. The header carries the address of the original client, which is
+//! otherwise lost behind the load balancer. Only enable it when all
+//! connections come from a trusted proxy, since the header is not
+//! authenticated.
+
+use std::io::{Error as IOError, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature of version 2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Prefix of version 1 header
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Max length of version 1 header, including CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+fn invalid_header(message: &str) -> IOError {
+    IOError::new(
+        ErrorKind::InvalidData,
+        format!("invalid PROXY protocol header: {message}"),
+    )
+}
+
+/// Read the PROXY protocol header from `stream`, and nothing after it.
+///
+/// Returns the source address of original connection, or `None` if the
+/// header doesn't carry one, e.g. for health checks of the proxy itself.
+/// Missing header is an error.
+pub async fn read_proxy_header<S>(stream: &mut S) -> Result<Option<SocketAddr>, IOError>
+where
+    S: AsyncRead + Unpin,
+{
+    // both versions are longer than the signature
+    let mut header = [0u8; V2_SIGNATURE.len()];
+    stream.read_exact(&mut header).await?;
+
+    if header == V2_SIGNATURE {
+        let mut fixed = [0u8; 4];
+        stream.read_exact(&mut fixed).await?;
+        let len = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut addresses = vec![0u8; len];
+        stream.read_exact(&mut addresses).await?;
+        parse_v2(fixed[0], fixed[1], &addresses)
+    } else if header.starts_with(V1_PREFIX) {
+        let mut line = header.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LENGTH {
+                return Err(invalid_header("line too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        let line = std::str::from_utf8(&line[..line.len() - 2])
+            .map_err(|_| invalid_header("not ASCII"))?;
+        parse_v1(line)
+    } else {
+        Err(invalid_header("missing"))
+    }
+}
+
+/// Parse version 1 line without CRLF, like
+/// `PROXY TCP4 192.168.0.1 192.168.0.11 56324 5432`.
+fn parse_v1(line: &str) -> Result<Option<SocketAddr>, IOError> {
+    let mut fields = line.split(' ').skip(1);
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid_header("unsupported protocol")),
+    }
+
+    let src_ip = fields.next().and_then(|ip| ip.parse::<IpAddr>().ok());
+    let dst_ip = fields.next().and_then(|ip| ip.parse::<IpAddr>().ok());
+    let src_port = fields.next().and_then(|port| port.parse::<u16>().ok());
+    let dst_port = fields.next().and_then(|port| port.parse::<u16>().ok());
+    match (src_ip, dst_ip, src_port, dst_port, fields.next()) {
+        (Some(ip), Some(_), Some(port), Some(_), None) => Ok(Some(SocketAddr::new(ip, port))),
+        _ => Err(invalid_header("malformed addresses")),
+    }
+}
+
+/// Parse version 2 header after signature, TLVs after addresses are ignored.
+fn parse_v2(
+    version_command: u8,
+    family_protocol: u8,
+    addresses: &[u8],
+) -> Result<Option<SocketAddr>, IOError> {
+    if version_command >> 4 != 2 {
+        return Err(invalid_header("unsupported version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL, connection made by the proxy itself
+        0 => return Ok(None),
+        // PROXY
+        1 => {}
+        _ => return Err(invalid_header("unsupported command")),
+    }
+
+    let port = |offset: usize| u16::from_be_bytes([addresses[offset], addresses[offset + 1]]);
+    match family_protocol >> 4 {
+        // AF_INET
+        1 => {
+            if addresses.len() < 12 {
+                return Err(invalid_header("truncated addresses"));
+            }
+            let mut ip = [0u8; 4];
+            ip.copy_from_slice(&addresses[..4]);
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port(8))))
+        }
+        // AF_INET6
+        2 => {
+            if addresses.len() < 36 {
+                return Err(invalid_header("truncated addresses"));
+            }
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port(32))))
+        }
+        // AF_UNSPEC or AF_UNIX, no IP address
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn read(header: &[u8]) -> Result<Option<SocketAddr>, IOError> {
+        let mut stream = header;
+        let result = read_proxy_header(&mut stream).await;
+        // startup message after the header is left unread
+        if result.is_ok() {
+            assert_eq!(b"startup", stream);
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_v1() {
+        assert_eq!(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            read(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 5432\r\nstartup")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 5432\r\nstartup")
+                .await
+                .unwrap()
+        );
+        assert_eq!(None, read(b"PROXY UNKNOWN\r\nstartup").await.unwrap());
+
+        assert!(read(b"PROXY TCP4 192.168.0.1\r\nstartup").await.is_err());
+        assert!(read(b"PROXY UDP4 192.168.0.1 192.168.0.11 56324 5432\r\n")
+            .await
+            .is_err());
+        assert!(read(&[b'P'; 200]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let mut header = V2_SIGNATURE.to_vec();
+        // PROXY over TCP4, with a NOOP TLV
+        header.extend_from_slice(&[0x21, 0x11, 0, 16]);
+        header.extend_from_slice(&[192, 168, 0, 1, 192, 168, 0, 11, 0xdc, 0x04, 0x15, 0x38]);
+        header.extend_from_slice(&[0x04, 0, 1, 0]);
+        header.extend_from_slice(b"startup");
+        assert_eq!(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            read(&header).await.unwrap()
+        );
+
+        let mut header = V2_SIGNATURE.to_vec();
+        // PROXY over TCP6
+        header.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        header.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&[0xdc, 0x04, 0x15, 0x38]);
+        header.extend_from_slice(b"startup");
+        assert_eq!(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            read(&header).await.unwrap()
+        );
+
+        let mut header = V2_SIGNATURE.to_vec();
+        // LOCAL
+        header.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        header.extend_from_slice(b"startup");
+        assert_eq!(None, read(&header).await.unwrap());
+
+        let mut header = V2_SIGNATURE.to_vec();
+        // truncated TCP4 addresses
+        header.extend_from_slice(&[0x21, 0x11, 0, 4, 192, 168, 0, 1]);
+        assert!(read(&header).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_header() {
+        assert!(read(b"\0\0\0\x08\x04\xd2\x16\x2fstartup").await.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Limit of errors from the same source address, to slow down clients caught
+//! in a reconnect loop.
+//!
+//! Errors before authentication is finished, like a wrong password, are
+//! counted per source IP in a fixed time window. Once a source exceeds the
+//! limit, its error responses are delayed with exponential backoff, or its
+//! new connections are rejected.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default value of [`ErrorRateLimit::base_delay`].
+pub const DEFAULT_ERROR_DELAY: Duration = Duration::from_millis(100);
+/// Default value of [`ErrorRateLimit::max_delay`].
+pub const DEFAULT_MAX_ERROR_DELAY: Duration = Duration::from_secs(10);
+
+/// Number of sources kept before expired windows are cleaned up
+const MAX_IDLE_SOURCES: usize = 1024;
+
+/// Allowed rate of errors from a source IP
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct ErrorRateLimit {
+    /// Errors allowed in a window before responses are delayed
+    pub max_errors: u32,
+    pub window: Duration,
+    /// Delay of the first error over limit, doubled for each following error
+    #[new(value = "DEFAULT_ERROR_DELAY")]
+    pub base_delay: Duration,
+    #[new(value = "DEFAULT_MAX_ERROR_DELAY")]
+    pub max_delay: Duration,
+    /// Reject new connections from sources over limit, instead of delaying
+    /// their errors
+    #[new(default)]
+    pub reject: bool,
+}
+
+impl ErrorRateLimit {
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn with_reject(mut self, reject: bool) -> Self {
+        self.reject = reject;
+        self
+    }
+}
+
+/// Errors of a source in current window
+#[derive(Debug)]
+struct ErrorBucket {
+    window_start: Instant,
+    errors: u32,
+}
+
+/// Error counters of all sources, shared by connections of a server.
+#[derive(Debug)]
+pub struct ErrorRateLimiter {
+    limit: ErrorRateLimit,
+    buckets: Mutex<HashMap<IpAddr, ErrorBucket>>,
+}
+
+impl ErrorRateLimiter {
+    pub fn new(limit: ErrorRateLimit) -> ErrorRateLimiter {
+        ErrorRateLimiter {
+            limit,
+            buckets: Mutex::default(),
+        }
+    }
+
+    pub fn limit(&self) -> &ErrorRateLimit {
+        &self.limit
+    }
+
+    /// Count an error of `ip`. Returns the delay before responding the error,
+    /// which is zero while the source is within limit.
+    pub fn record_error(&self, ip: IpAddr) -> Duration {
+        self.record_error_at(ip, Instant::now())
+    }
+
+    /// Test if new connections of `ip` should be rejected.
+    pub fn is_rejected(&self, ip: IpAddr) -> bool {
+        self.is_rejected_at(ip, Instant::now())
+    }
+
+    fn record_error_at(&self, ip: IpAddr, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_IDLE_SOURCES {
+            buckets.retain(|_, bucket| !self.is_expired(bucket, now));
+        }
+
+        let bucket = buckets.entry(ip).or_insert(ErrorBucket {
+            window_start: now,
+            errors: 0,
+        });
+        if self.is_expired(bucket, now) {
+            bucket.window_start = now;
+            bucket.errors = 0;
+        }
+        bucket.errors = bucket.errors.saturating_add(1);
+
+        let excess = bucket.errors.saturating_sub(self.limit.max_errors);
+        if excess == 0 || self.limit.reject {
+            return Duration::ZERO;
+        }
+        let factor = 1u32.checked_shl(excess - 1).unwrap_or(u32::MAX);
+        self.limit
+            .base_delay
+            .saturating_mul(factor)
+            .min(self.limit.max_delay)
+    }
+
+    fn is_rejected_at(&self, ip: IpAddr, now: Instant) -> bool {
+        if !self.limit.reject {
+            return false;
+        }
+        let buckets = self.buckets.lock().unwrap();
+        buckets.get(&ip).map_or(false, |bucket| {
+            !self.is_expired(bucket, now) && bucket.errors > self.limit.max_errors
+        })
+    }
+
+    fn is_expired(&self, bucket: &ErrorBucket, now: Instant) -> bool {
+        now.duration_since(bucket.window_start) >= self.limit.window
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_rate_limiter() {
+        let limiter = ErrorRateLimiter::new(
+            ErrorRateLimit::new(2, Duration::from_secs(60)).with_max_delay(Duration::from_secs(1)),
+        );
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(Duration::ZERO, limiter.record_error_at(ip, now));
+        assert_eq!(Duration::ZERO, limiter.record_error_at(ip, now));
+        assert_eq!(Duration::from_millis(100), limiter.record_error_at(ip, now));
+        assert_eq!(Duration::from_millis(200), limiter.record_error_at(ip, now));
+        for _ in 0..40 {
+            limiter.record_error_at(ip, now);
+        }
+        assert_eq!(Duration::from_secs(1), limiter.record_error_at(ip, now));
+        // delaying limiter doesn't reject
+        assert!(!limiter.is_rejected_at(ip, now));
+
+        // sources are counted separately
+        assert_eq!(Duration::ZERO, limiter.record_error_at(other, now));
+
+        // counter is reset in next window
+        let later = now + Duration::from_secs(60);
+        assert_eq!(Duration::ZERO, limiter.record_error_at(ip, later));
+    }
+
+    #[test]
+    fn test_reject_over_limit() {
+        let limiter = ErrorRateLimiter::new(
+            ErrorRateLimit::new(1, Duration::from_secs(60)).with_reject(true),
+        );
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        let now = Instant::now();
+
+        limiter.record_error_at(ip, now);
+        assert!(!limiter.is_rejected_at(ip, now));
+        // errors are not delayed when connections are rejected
+        assert_eq!(Duration::ZERO, limiter.record_error_at(ip, now));
+        assert!(limiter.is_rejected_at(ip, now));
+        assert!(!limiter.is_rejected_at(ip, now + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{Error as IOError, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Fixed size read buffer for reassembling typed messages from a socket.
+///
+/// Bytes are read from the socket straight into a pre-allocated buffer, and
+/// complete messages are handed out as slices of it with
+/// [`peek_message`](Self::peek_message), so no per-message allocation or copy
+/// is needed. When the write position reaches the end of the buffer, the
+/// trailing incomplete message is moved to the front to make room, which
+/// keeps every message contiguous.
+///
+/// Only messages with a leading type byte are supported, `Startup` and
+/// `SslRequest` have to be handled before switching to this buffer. A single
+/// message must fit into `N` bytes.
+#[derive(Debug)]
+pub struct PgWireRingBuffer<const N: usize> {
+    buf: Box<[u8; N]>,
+    read_pos: usize,
+    write_pos: usize,
+}
+
+impl<const N: usize> Default for PgWireRingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PgWireRingBuffer<N> {
+    pub fn new() -> PgWireRingBuffer<N> {
+        // allocate on heap directly, large arrays may overflow the stack
+        let buf = vec![0u8; N]
+            .into_boxed_slice()
+            .try_into()
+            .expect("buffer length equals N");
+        PgWireRingBuffer {
+            buf,
+            read_pos: 0,
+            write_pos: 0,
+        }
+    }
+
+    /// Total size of the buffer.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of bytes received but not consumed yet.
+    pub fn len(&self) -> usize {
+        self.write_pos - self.read_pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read_pos == self.write_pos
+    }
+
+    /// Read available bytes from `reader` into free space of the buffer.
+    ///
+    /// Returns number of bytes read, `0` means end of stream. An
+    /// `InvalidData` error is returned when the pending message has an
+    /// invalid length or cannot fit into the buffer.
+    pub async fn fill_from<R>(&mut self, reader: &mut R) -> std::io::Result<usize>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        if let Some(frame_len) = self.frame_len() {
+            if frame_len < 5 {
+                return Err(IOError::new(
+                    ErrorKind::InvalidData,
+                    "invalid message length",
+                ));
+            }
+            if frame_len > N {
+                return Err(IOError::new(
+                    ErrorKind::InvalidData,
+                    format!("message of {frame_len} bytes exceeds buffer capacity {N}"),
+                ));
+            }
+        }
+
+        if self.write_pos == N {
+            self.compact();
+        }
+        if self.write_pos == N {
+            // buffer is full of complete messages, caller has to consume
+            // them first
+            return Err(IOError::new(
+                ErrorKind::InvalidData,
+                "buffer is full, consume pending messages first",
+            ));
+        }
+
+        let n = reader.read(&mut self.buf[self.write_pos..]).await?;
+        self.write_pos += n;
+        Ok(n)
+    }
+
+    /// Return type byte and body of the next complete message, or `None` if
+    /// it is not fully received yet.
+    pub fn peek_message(&self) -> Option<(u8, &[u8])> {
+        let frame_len = self.frame_len()?;
+        if frame_len < 5 || frame_len > self.len() {
+            return None;
+        }
+
+        let start = self.read_pos;
+        Some((self.buf[start], &self.buf[start + 5..start + frame_len]))
+    }
+
+    /// Drop the message returned by last [`peek_message`](Self::peek_message).
+    ///
+    /// Returns `false` if there is no complete message to consume.
+    pub fn consume_message(&mut self) -> bool {
+        match self.frame_len() {
+            Some(frame_len) if frame_len >= 5 && frame_len <= self.len() => {
+                self.read_pos += frame_len;
+                if self.read_pos == self.write_pos {
+                    self.read_pos = 0;
+                    self.write_pos = 0;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Length of next message including type byte, if its header is
+    /// received.
+    fn frame_len(&self) -> Option<usize> {
+        if self.len() < 5 {
+            return None;
+        }
+
+        let start = self.read_pos;
+        let len = i32::from_be_bytes([
+            self.buf[start + 1],
+            self.buf[start + 2],
+            self.buf[start + 3],
+            self.buf[start + 4],
+        ]);
+        Some(usize::try_from(len).map_or(0, |len| len.saturating_add(1)))
+    }
+
+    /// Move unconsumed bytes to the front of the buffer.
+    fn compact(&mut self) {
+        if self.read_pos > 0 {
+            self.buf.copy_within(self.read_pos..self.write_pos, 0);
+            self.write_pos -= self.read_pos;
+            self.read_pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::messages::simplequery::Query;
+    use crate::messages::Message;
+
+    fn encode_queries(queries: &[&str]) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        for q in queries {
+            Query::new((*q).to_owned()).encode(&mut buf).unwrap();
+        }
+        buf.to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_reassemble_messages() {
+        let queries = ["SELECT 1", "SELECT 2", "SELECT name FROM users", "BEGIN"];
+        let data = encode_queries(&queries);
+
+        let (mut client, mut server) = tokio::io::duplex(7);
+        let writer = tokio::spawn(async move {
+            tokio::io::AsyncWriteExt::write_all(&mut client, &data)
+                .await
+                .unwrap();
+        });
+
+        let mut ring = PgWireRingBuffer::<40>::new();
+        let mut received = Vec::new();
+        loop {
+            while let Some((msg_type, body)) = ring.peek_message() {
+                assert_eq!(b'Q', msg_type);
+                received.push(String::from_utf8(body[..body.len() - 1].to_vec()).unwrap());
+                assert!(ring.consume_message());
+            }
+            if ring.fill_from(&mut server).await.unwrap() == 0 {
+                break;
+            }
+        }
+        writer.await.unwrap();
+
+        assert_eq!(queries.to_vec(), received);
+        assert!(ring.is_empty());
+        assert!(!ring.consume_message());
+    }
+
+    #[tokio::test]
+    async fn test_message_too_large() {
+        let data = encode_queries(&["SELECT * FROM a_table_with_long_name"]);
+        let mut reader = &data[..];
+
+        let mut ring = PgWireRingBuffer::<16>::new();
+        assert_eq!(16, ring.fill_from(&mut reader).await.unwrap());
+        assert!(ring.peek_message().is_none());
+
+        let err = ring.fill_from(&mut reader).await.unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+}
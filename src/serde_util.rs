@@ -0,0 +1,65 @@
+//! Custom serde representations for binary fields.
+
+/// Serialize bytes as hex string
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&hex::encode(v))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        hex::decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serialize bytes as base64 string
+pub(crate) mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&STANDARD.encode(v))
+    }
+
+    pub(crate) fn decode<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Bytes, D::Error> {
+        decode(d).map(Bytes::from)
+    }
+}
+
+/// Serialize `BytesMut` as base64 string
+pub(crate) mod base64_bytes_mut {
+    use bytes::BytesMut;
+    use serde::{Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        super::base64_bytes::serialize(v, s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<BytesMut, D::Error> {
+        super::base64_bytes::decode(d).map(|v| BytesMut::from(&v[..]))
+    }
+}
+
+/// Serialize list of nullable bytes as list of nullable base64 strings
+pub(crate) mod base64_nullable_list {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use bytes::Bytes;
+    use serde::{Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(v: &[Option<Bytes>], s: S) -> Result<S::Ok, S::Error> {
+        v.iter()
+            .map(|b| b.as_ref().map(|b| STANDARD.encode(b)))
+            .collect::<Vec<_>>()
+            .serialize(s)
+    }
+}
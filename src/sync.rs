@@ -0,0 +1,1029 @@
+//! Blocking server entry-point for applications without an async runtime.
+//!
+//! The sync module mirrors the tokio based API with blocking handler traits:
+//! [`SimpleSyncQueryHandler`] and [`ExtendedSyncQueryHandler`]. Messages are
+//! read from and written to a `std::io` stream directly. Startup is still
+//! handled by [`StartupHandler`] implementations, which are driven to
+//! completion on current thread so existing authentication mechanisms can be
+//! reused as is.
+//!
+//! Blocking handlers are adapted to the async handler traits, and messages are
+//! processed by the same logic as [`crate::tokio::process_socket`]. The futures
+//! are driven by `futures::executor::block_on`, with no async runtime around.
+//! Handlers, including the `StartupHandler`, must not call APIs that require
+//! one: for example, tokio's IO, timers or `spawn` panic when invoked within
+//! `block_on`. Authentication timeout is not available for the same reason.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{Error as IOError, Read, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::executor::block_on;
+use futures::Sink;
+
+use crate::api::auth::StartupHandler;
+use crate::api::guc::GucRegistry;
+use crate::api::portal::Portal;
+use crate::api::process::{process_error, process_message};
+use crate::api::query::{ExtendedQueryHandler, SimpleQueryHandler};
+use crate::api::replication::PlaceholderReplicationHandler;
+use crate::api::results::{
+    DescribePortalResponse, DescribeResponse, DescribeStatementResponse, Response,
+};
+use crate::api::stmt::{QueryParser, StoredStatement};
+use crate::api::store::{MemPortalStore, PortalStore};
+use crate::api::transaction::TransactionStatus;
+use crate::api::{ClientInfo, ClientPortalStore, DefaultClient, PgWireConnectionState};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::response::{NoticeResponse, SslResponse};
+use crate::messages::startup::{
+    BackendKeyData, CancelRequest, GssEncRequest, ParameterStatus, SslRequest, Startup,
+};
+use crate::messages::{Message, PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Blocking handler for processing simple query.
+pub trait SimpleSyncQueryHandler: Send + Sync {
+    /// Provide your query implementation using the incoming query string.
+    fn do_query<'a, C>(&self, client: &mut C, query: &'a str) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo;
+}
+
+/// Blocking handler for processing extended query.
+pub trait ExtendedSyncQueryHandler: Send + Sync {
+    type Statement: Clone + Send + Sync;
+    type QueryParser: QueryParser<Statement = Self::Statement> + Send + Sync;
+
+    /// Get a reference to associated `QueryParser` implementation
+    fn query_parser(&self) -> Arc<Self::QueryParser>;
+
+    /// Called with the parsed statement before it's stored, see
+    /// `ExtendedQueryHandler::do_parse`.
+    fn do_parse<C>(
+        &self,
+        _client: &mut C,
+        _statement: &mut StoredStatement<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+    {
+        Ok(())
+    }
+
+    /// Return resultset metadata without actually executing statement
+    ///
+    /// The default implementation responds with no data.
+    fn do_describe_statement<C>(
+        &self,
+        _client: &mut C,
+        _target: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<DescribeStatementResponse>
+    where
+        C: ClientInfo + ClientPortalStore,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+    {
+        Ok(DescribeStatementResponse::no_data())
+    }
+
+    /// Return resultset metadata without actually executing portal
+    ///
+    /// The default implementation responds with no data.
+    fn do_describe_portal<C>(
+        &self,
+        _client: &mut C,
+        _target: &Portal<Self::Statement>,
+    ) -> PgWireResult<DescribePortalResponse>
+    where
+        C: ClientInfo + ClientPortalStore,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+    {
+        Ok(DescribePortalResponse::no_data())
+    }
+
+    /// This is the main implementation for query execution, see
+    /// `ExtendedQueryHandler::do_query`.
+    fn do_query<'a, C>(
+        &self,
+        client: &mut C,
+        portal: &'a Portal<Self::Statement>,
+        max_rows: usize,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + ClientPortalStore,
+        C::PortalStore: PortalStore<Statement = Self::Statement>;
+}
+
+/// A client connection over a blocking `std::io` stream.
+///
+/// It implements `Sink<PgWireBackendMessage>` by writing to the stream
+/// synchronously, so it can be passed to `StartupHandler` and the response
+/// helpers in `api::query`.
+#[derive(Debug)]
+pub struct SyncClient<S, ST> {
+    stream: S,
+    info: DefaultClient<ST>,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    closed: bool,
+}
+
+impl<S, ST> SyncClient<S, ST>
+where
+    S: Read + Write,
+{
+    /// Create client on `stream`. As generic streams have no peer address,
+    /// the unspecified address is reported by `ClientInfo::socket_addr`.
+    pub fn new(stream: S) -> SyncClient<S, ST> {
+        Self::with_socket_addr(stream, SocketAddr::from(([0, 0, 0, 0], 0)))
+    }
+
+    /// Create client on `stream` with known peer address.
+    pub fn with_socket_addr(stream: S, socket_addr: SocketAddr) -> SyncClient<S, ST> {
+        SyncClient {
+            stream,
+            info: DefaultClient::new(socket_addr, false),
+            read_buf: BytesMut::with_capacity(8192),
+            write_buf: BytesMut::with_capacity(8192),
+            closed: false,
+        }
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Block until next frontend message arrived. `None` is returned when the
+    /// stream is closed.
+    pub fn read_message(&mut self) -> PgWireResult<Option<PgWireFrontendMessage>> {
+        let mut buf = [0u8; 8192];
+        loop {
+            if let Some(message) = self.decode()? {
+                return Ok(Some(message));
+            }
+
+            let n = self.stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.read_buf.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn decode(&mut self) -> PgWireResult<Option<PgWireFrontendMessage>> {
+        match self.info.state() {
+            PgWireConnectionState::AwaitingStartup => {
+                if let Some(request) = SslRequest::decode(&mut self.read_buf)? {
+                    return Ok(Some(PgWireFrontendMessage::SslRequest(request)));
+                }
+
+                if let Some(request) = GssEncRequest::decode(&mut self.read_buf)? {
+                    return Ok(Some(PgWireFrontendMessage::GssEncRequest(request)));
+                }
+
+                if let Some(request) = CancelRequest::decode(&mut self.read_buf)? {
+                    return Ok(Some(PgWireFrontendMessage::CancelRequest(request)));
+                }
+
+                if let Some(startup) = Startup::decode(&mut self.read_buf)? {
+                    return Ok(Some(PgWireFrontendMessage::Startup(startup)));
+                }
+
+                Ok(None)
+            }
+            _ => PgWireFrontendMessage::decode(&mut self.read_buf),
+        }
+    }
+
+    /// Buffer message to be written on next flush.
+    pub fn feed(&mut self, message: PgWireBackendMessage) -> PgWireResult<()> {
+        message.encode(&mut self.write_buf)
+    }
+
+    /// Write all buffered messages to the stream.
+    pub fn flush(&mut self) -> Result<(), IOError> {
+        if !self.write_buf.is_empty() {
+            self.stream.write_all(&self.write_buf)?;
+            self.write_buf.clear();
+        }
+        self.stream.flush()
+    }
+
+    /// Write message to the stream immediately.
+    pub fn send(&mut self, message: PgWireBackendMessage) -> PgWireResult<()> {
+        self.feed(message)?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+impl<S, ST> ClientInfo for SyncClient<S, ST> {
+    fn socket_addr(&self) -> SocketAddr {
+        self.info.socket_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        self.info.is_secure()
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.info.state()
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.info.set_state(new_state);
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        self.info.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        self.info.metadata_mut()
+    }
+
+    fn notice_emitter(&self) -> Option<crate::api::notice::NoticeEmitter> {
+        self.info.notice_emitter()
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.info.take_notices()
+    }
+
+    fn push_parameter_status(&mut self, name: &str, value: &str) {
+        self.info.push_parameter_status(name, value);
+    }
+
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        self.info.take_parameter_status()
+    }
+
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        self.info.backend_key_data()
+    }
+
+    fn set_backend_key_data(&mut self, backend_key_data: BackendKeyData) {
+        self.info.set_backend_key_data(backend_key_data);
+    }
+
+    fn guc_registry(&self) -> Option<Arc<Mutex<GucRegistry>>> {
+        self.info.guc_registry()
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.info.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.info.set_transaction_status(status);
+    }
+
+    fn protocol_minor_version(&self) -> u16 {
+        self.info.protocol_minor_version()
+    }
+
+    fn set_protocol_minor_version(&mut self, minor_version: u16) {
+        self.info.set_protocol_minor_version(minor_version);
+    }
+}
+
+impl<S, ST> ClientPortalStore for SyncClient<S, ST> {
+    type PortalStore = MemPortalStore<ST>;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        &self.info.portal_store
+    }
+}
+
+impl<S, ST> Sink<PgWireBackendMessage> for SyncClient<S, ST>
+where
+    S: Read + Write + Unpin,
+{
+    type Error = PgWireError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: PgWireBackendMessage) -> Result<(), Self::Error> {
+        self.get_mut().feed(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(self.get_mut().flush().map_err(Into::into))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.closed = true;
+        Poll::Ready(this.flush().map_err(Into::into))
+    }
+}
+
+/// Runs blocking query handlers as async handlers, so messages of blocking
+/// connections are processed by the same logic as other entry-points.
+struct SyncQueryHandlerAdapter<'h, Q, EQ> {
+    query_handler: &'h Q,
+    extended_query_handler: &'h EQ,
+}
+
+#[async_trait]
+impl<Q, EQ> SimpleQueryHandler for SyncQueryHandlerAdapter<'_, Q, EQ>
+where
+    Q: SimpleSyncQueryHandler,
+    EQ: Send + Sync,
+{
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.query_handler.do_query(client, query)
+    }
+}
+
+#[async_trait]
+impl<Q, EQ> ExtendedQueryHandler for SyncQueryHandlerAdapter<'_, Q, EQ>
+where
+    Q: Send + Sync,
+    EQ: ExtendedSyncQueryHandler,
+{
+    type Statement = EQ::Statement;
+    type QueryParser = EQ::QueryParser;
+
+    fn query_parser(&self) -> Arc<Self::QueryParser> {
+        self.extended_query_handler.query_parser()
+    }
+
+    async fn do_parse<C>(
+        &self,
+        client: &mut C,
+        statement: &mut StoredStatement<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.extended_query_handler.do_parse(client, statement)
+    }
+
+    async fn do_describe_statement<C>(
+        &self,
+        client: &mut C,
+        target: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<DescribeStatementResponse>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.extended_query_handler
+            .do_describe_statement(client, target)
+    }
+
+    async fn do_describe_portal<C>(
+        &self,
+        client: &mut C,
+        target: &Portal<Self::Statement>,
+    ) -> PgWireResult<DescribePortalResponse>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.extended_query_handler
+            .do_describe_portal(client, target)
+    }
+
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        portal: &'a Portal<Self::Statement>,
+        max_rows: usize,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        self.extended_query_handler
+            .do_query(client, portal, max_rows)
+    }
+}
+
+/// Process a blocking client connection until it's closed.
+///
+/// This is the blocking counterpart of `tokio::process_socket`. TLS and
+/// multiplexing are not supported.
+pub fn process_socket_sync<S, A, Q, EQ>(
+    stream: S,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+) -> Result<(), IOError>
+where
+    S: Read + Write + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleSyncQueryHandler,
+    EQ: ExtendedSyncQueryHandler,
+{
+    let mut client = SyncClient::<S, EQ::Statement>::new(stream);
+    process_client_sync(
+        &mut client,
+        startup_handler.as_ref(),
+        query_handler.as_ref(),
+        extended_query_handler.as_ref(),
+    )
+}
+
+/// Process messages on a prepared `SyncClient` until it's closed.
+pub fn process_client_sync<S, A, Q, EQ>(
+    client: &mut SyncClient<S, EQ::Statement>,
+    startup_handler: &A,
+    query_handler: &Q,
+    extended_query_handler: &EQ,
+) -> Result<(), IOError>
+where
+    S: Read + Write + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleSyncQueryHandler,
+    EQ: ExtendedSyncQueryHandler,
+{
+    let handler = SyncQueryHandlerAdapter {
+        query_handler,
+        extended_query_handler,
+    };
+    while let Some(message) = client.read_message()? {
+        match message {
+            PgWireFrontendMessage::Terminate(_) => break,
+            PgWireFrontendMessage::SslRequest(_) => {
+                // TLS is not supported on blocking streams
+                client.send(PgWireBackendMessage::SslResponse(SslResponse::Refuse))?;
+                continue;
+            }
+            _ => {}
+        }
+
+        let is_extended_query = message.is_extended_query();
+        let is_cancel_request = matches!(message, PgWireFrontendMessage::CancelRequest(_));
+        if let Err(e) = block_on(process_message(
+            message,
+            client,
+            startup_handler,
+            &handler,
+            &handler,
+            None::<&PlaceholderReplicationHandler>,
+            None,
+        )) {
+            block_on(process_error(client, e, is_extended_query))?;
+        }
+        // the connection of cancel request is closed without response
+        if is_cancel_request || client.closed {
+            break;
+        }
+
+        let notices = client.take_notices();
+        if !notices.is_empty() {
+            for notice in notices {
+                client.feed(PgWireBackendMessage::NoticeResponse(notice))?;
+            }
+            client.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use bytes::{Bytes, BytesMut};
+    use futures::stream;
+
+    use super::*;
+    use crate::api::auth::noop::NoopStartupHandler;
+    use crate::api::auth::{finish_authentication, DefaultServerParameterProvider};
+    use crate::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse};
+    use crate::api::stmt::NoopQueryParser;
+    use crate::api::Type;
+    use crate::error::ErrorInfo;
+    use crate::messages::extendedquery::{
+        Bind, Close, Execute, Parse, Sync as PgSync, TARGET_TYPE_BYTE_PORTAL,
+    };
+    use crate::messages::response::GssEncResponse;
+    use crate::messages::simplequery::Query;
+
+    struct SyncHandler;
+
+    impl SimpleSyncQueryHandler for SyncHandler {
+        fn do_query<'a, C>(
+            &self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "q".to_owned(),
+                None,
+                None,
+                Type::VARCHAR,
+                FieldFormat::Text,
+            )]);
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            encoder.encode_field(&query)?;
+            let row = encoder.finish();
+            Ok(vec![Response::Query(QueryResponse::new(
+                schema,
+                stream::iter(vec![row]),
+            ))])
+        }
+    }
+
+    impl ExtendedSyncQueryHandler for SyncHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser)
+        }
+
+        fn do_query<'a, C>(
+            &self,
+            _client: &mut C,
+            _portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + ClientPortalStore,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "n".to_owned(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let rows = (1..=3)
+                .map(|n| {
+                    let mut encoder = DataRowEncoder::new(schema.clone());
+                    encoder.encode_field(&n)?;
+                    encoder.finish()
+                })
+                .collect::<Vec<_>>();
+            Ok(Response::Query(QueryResponse::new_owned(
+                schema,
+                stream::iter(rows),
+            )))
+        }
+    }
+
+    fn read_backend_messages(stream: &mut TcpStream, until: u8) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        let mut types = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            while let Some(msg) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+                let t = msg.message_type().unwrap();
+                types.push(t);
+                if t == until {
+                    return types;
+                }
+            }
+            let n = stream.read(&mut chunk).unwrap();
+            assert!(n > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    fn process(
+        client: &mut SyncClient<std::io::Cursor<Vec<u8>>, String>,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()> {
+        let handler = SyncQueryHandlerAdapter {
+            query_handler: &SyncHandler,
+            extended_query_handler: &SyncHandler,
+        };
+        block_on(process_message(
+            message,
+            client,
+            &NoopStartupHandler,
+            &handler,
+            &handler,
+            None::<&PlaceholderReplicationHandler>,
+            None,
+        ))
+    }
+
+    fn query(client: &mut SyncClient<std::io::Cursor<Vec<u8>>, String>, query: &str) {
+        process(
+            client,
+            PgWireFrontendMessage::Query(Query::new(query.to_owned())),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_backend_key_data() {
+        let mut client = SyncClient::<_, String>::new(std::io::Cursor::new(Vec::new()));
+        assert!(client.backend_key_data().is_none());
+
+        block_on(finish_authentication(
+            &mut client,
+            &DefaultServerParameterProvider::default(),
+        ));
+        let key_data = client.backend_key_data().unwrap();
+        assert_eq!(std::process::id() as i32, key_data.pid);
+        assert_eq!(4, key_data.secret_key.len());
+
+        // longer secret key since protocol 3.2
+        client.set_protocol_minor_version(2);
+        block_on(finish_authentication(
+            &mut client,
+            &DefaultServerParameterProvider::default(),
+        ));
+        assert_eq!(32, client.backend_key_data().unwrap().secret_key.len());
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        let mut client = SyncClient::<_, String>::new(std::io::Cursor::new(Vec::new()));
+        client.set_state(PgWireConnectionState::ReadyForQuery);
+        let ready_status = |client: &SyncClient<std::io::Cursor<Vec<u8>>, String>| {
+            *client.stream.get_ref().last().unwrap()
+        };
+
+        query(&mut client, "BEGIN");
+        assert_eq!(
+            TransactionStatus::InTransaction,
+            client.transaction_status()
+        );
+        assert_eq!(b'T', ready_status(&client));
+
+        query(&mut client, "SAVEPOINT sp");
+        assert_eq!(b'T', ready_status(&client));
+
+        block_on(process_error(
+            &mut client,
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "42P01".to_owned(),
+                "relation does not exist".to_owned(),
+            ))),
+            false,
+        ))
+        .unwrap();
+        assert_eq!(b'E', ready_status(&client));
+        query(&mut client, "SELECT 1");
+        assert_eq!(b'E', ready_status(&client));
+
+        query(&mut client, "ROLLBACK TO SAVEPOINT sp");
+        assert_eq!(b'T', ready_status(&client));
+        query(&mut client, "COMMIT;");
+        assert_eq!(TransactionStatus::Idle, client.transaction_status());
+        assert_eq!(b'I', ready_status(&client));
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        #[derive(Default)]
+        struct CancelHandler {
+            requests: std::sync::Mutex<Vec<CancelRequest>>,
+        }
+
+        #[async_trait::async_trait]
+        impl StartupHandler for CancelHandler {
+            async fn on_startup<C>(
+                &self,
+                _client: &mut C,
+                message: PgWireFrontendMessage,
+            ) -> PgWireResult<()>
+            where
+                C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+                C::Error: std::fmt::Debug,
+                PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+            {
+                Err(PgWireError::unexpected_message("CancelRequest", &message))
+            }
+
+            async fn on_cancel_request(&self, request: CancelRequest) {
+                self.requests.lock().unwrap().push(request);
+            }
+        }
+
+        let mut input = BytesMut::new();
+        CancelRequest::new(42, Bytes::from_static(&[0, 0, 4, 210]))
+            .encode(&mut input)
+            .unwrap();
+        Startup::default().encode(&mut input).unwrap();
+        let len = input.len();
+
+        let handler = CancelHandler::default();
+        let mut client = SyncClient::<_, String>::new(std::io::Cursor::new(input.to_vec()));
+        process_client_sync(&mut client, &handler, &SyncHandler, &SyncHandler).unwrap();
+
+        assert_eq!(
+            vec![CancelRequest::new(42, Bytes::from_static(&[0, 0, 4, 210]))],
+            *handler.requests.lock().unwrap()
+        );
+        // closed without response, and without reading following startup
+        assert_eq!(len, client.stream.get_ref().len());
+        assert!(matches!(
+            client.state(),
+            PgWireConnectionState::AwaitingStartup
+        ));
+    }
+
+    #[test]
+    fn test_push_parameter_status() {
+        let mut client = SyncClient::<_, String>::new(std::io::Cursor::new(Vec::new()));
+        client.push_parameter_status("TimeZone", "UTC");
+        client.push_parameter_status("client_encoding", "UTF8");
+
+        client.set_state(PgWireConnectionState::ReadyForQuery);
+        process(&mut client, PgWireFrontendMessage::Sync(PgSync::new())).unwrap();
+        assert!(client.take_parameter_status().is_empty());
+
+        let mut buf = BytesMut::from(&client.stream.get_ref()[..]);
+        let mut messages = Vec::new();
+        while let Some(msg) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+            messages.push(msg);
+        }
+        assert_eq!(3, messages.len());
+        assert!(matches!(
+            &messages[0],
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "TimeZone" && status.value == "UTC"
+        ));
+        assert!(matches!(
+            &messages[1],
+            PgWireBackendMessage::ParameterStatus(status) if status.name == "client_encoding"
+        ));
+        assert!(matches!(
+            &messages[2],
+            PgWireBackendMessage::ReadyForQuery(_)
+        ));
+    }
+
+    #[test]
+    fn test_suspended_portal() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            process_socket_sync(
+                stream,
+                Arc::new(NoopStartupHandler),
+                Arc::new(SyncHandler),
+                Arc::new(SyncHandler),
+            )
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = BytesMut::new();
+        GssEncRequest::new().encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        let mut response = [0u8; 1];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(GssEncResponse::BYTE_REFUSE, response[0]);
+
+        buf.clear();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        startup.encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        read_backend_messages(&mut stream, b'Z');
+
+        let messages = vec![
+            PgWireFrontendMessage::Parse(Parse::new(None, "SELECT n".to_owned(), vec![])),
+            PgWireFrontendMessage::Bind(Bind::new(None, None, vec![], vec![], vec![])),
+            PgWireFrontendMessage::Execute(Execute::new(None, 2)),
+            PgWireFrontendMessage::Execute(Execute::new(None, 2)),
+            PgWireFrontendMessage::Execute(Execute::new(None, 0)),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ];
+        buf.clear();
+        for msg in messages {
+            msg.encode(&mut buf).unwrap();
+        }
+        stream.write_all(&buf).unwrap();
+        let types = read_backend_messages(&mut stream, b'Z');
+        // the third execute runs query again because the portal is completed
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b's', b'D', b'C', b'D', b'D', b'D', b'C', b'Z'],
+            types
+        );
+
+        // closing a suspended portal drops remaining rows
+        let messages = vec![
+            PgWireFrontendMessage::Bind(Bind::new(None, None, vec![], vec![], vec![])),
+            PgWireFrontendMessage::Execute(Execute::new(None, 1)),
+            PgWireFrontendMessage::Close(Close::new(TARGET_TYPE_BYTE_PORTAL, None)),
+            PgWireFrontendMessage::Bind(Bind::new(None, None, vec![], vec![], vec![])),
+            PgWireFrontendMessage::Execute(Execute::new(None, 0)),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ];
+        buf.clear();
+        for msg in messages {
+            msg.encode(&mut buf).unwrap();
+        }
+        stream.write_all(&buf).unwrap();
+        let types = read_backend_messages(&mut stream, b'Z');
+        assert_eq!(
+            vec![b'2', b'D', b's', b'3', b'2', b'D', b'D', b'D', b'C', b'Z'],
+            types
+        );
+
+        buf.clear();
+        PgWireFrontendMessage::Terminate(Default::default())
+            .encode(&mut buf)
+            .unwrap();
+        stream.write_all(&buf).unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_deallocate() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            process_socket_sync(
+                stream,
+                Arc::new(NoopStartupHandler),
+                Arc::new(SyncHandler),
+                Arc::new(SyncHandler),
+            )
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = BytesMut::new();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        startup.encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        read_backend_messages(&mut stream, b'Z');
+
+        let mut send = |messages: Vec<PgWireFrontendMessage>| {
+            buf.clear();
+            for msg in messages {
+                msg.encode(&mut buf).unwrap();
+            }
+            stream.write_all(&buf).unwrap();
+            read_backend_messages(&mut stream, b'Z')
+        };
+
+        let parse = |name: &str| {
+            PgWireFrontendMessage::Parse(Parse::new(
+                Some(name.to_owned()),
+                "SELECT n".to_owned(),
+                vec![],
+            ))
+        };
+        let query = |q: &str| PgWireFrontendMessage::Query(Query::new(q.to_owned()));
+
+        let types = send(vec![
+            parse("s1"),
+            parse("s2"),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ]);
+        assert_eq!(vec![b'1', b'1', b'Z'], types);
+
+        assert_eq!(vec![b'C', b'Z'], send(vec![query("DEALLOCATE s1;")]));
+        assert_eq!(vec![b'E', b'Z'], send(vec![query("DEALLOCATE s1")]));
+        assert_eq!(
+            vec![b'C', b'Z'],
+            send(vec![query("deallocate prepare all")])
+        );
+        assert_eq!(vec![b'E', b'Z'], send(vec![query("DEALLOCATE s2")]));
+
+        buf.clear();
+        PgWireFrontendMessage::Terminate(Default::default())
+            .encode(&mut buf)
+            .unwrap();
+        stream.write_all(&buf).unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_guc_commands() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            process_socket_sync(
+                stream,
+                Arc::new(NoopStartupHandler),
+                Arc::new(SyncHandler),
+                Arc::new(SyncHandler),
+            )
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = BytesMut::new();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        startup
+            .parameters
+            .insert("application_name".to_owned(), "psql".to_owned());
+        startup.encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        read_backend_messages(&mut stream, b'Z');
+
+        let mut send = |q: &str| {
+            buf.clear();
+            PgWireFrontendMessage::Query(Query::new(q.to_owned()))
+                .encode(&mut buf)
+                .unwrap();
+            stream.write_all(&buf).unwrap();
+            read_backend_messages(&mut stream, b'Z')
+        };
+
+        assert_eq!(vec![b'T', b'D', b'C', b'Z'], send("SHOW search_path"));
+        assert_eq!(
+            vec![b'C', b'Z'],
+            send("SET search_path TO my_schema, public")
+        );
+        // reported parameter
+        assert_eq!(vec![b'C', b'S', b'Z'], send("SET application_name = 'app'"));
+        assert_eq!(vec![b'C', b'S', b'Z'], send("RESET application_name"));
+
+        buf.clear();
+        PgWireFrontendMessage::Terminate(Default::default())
+            .encode(&mut buf)
+            .unwrap();
+        stream.write_all(&buf).unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_process_socket_sync() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            process_socket_sync(
+                stream,
+                Arc::new(NoopStartupHandler),
+                Arc::new(SyncHandler),
+                Arc::new(SyncHandler),
+            )
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = BytesMut::new();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        startup.encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        let types = read_backend_messages(&mut stream, b'Z');
+        assert_eq!(Some(&b'R'), types.first());
+
+        buf.clear();
+        Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+        let types = read_backend_messages(&mut stream, b'Z');
+        assert_eq!(vec![b'T', b'D', b'C', b'Z'], types);
+
+        buf.clear();
+        PgWireFrontendMessage::Terminate(Default::default())
+            .encode(&mut buf)
+            .unwrap();
+        stream.write_all(&buf).unwrap();
+        server.join().unwrap().unwrap();
+    }
+}
@@ -1,23 +1,51 @@
-use std::io::Error as IOError;
-use std::sync::Arc;
+use std::fmt::Debug;
+use std::io::{Error as IOError, IoSlice};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
 
-use bytes::BytesMut;
-use futures::future::poll_fn;
-use futures::{SinkExt, StreamExt};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::future::{poll_fn, select, Either};
+use futures::{Sink, SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
-use tokio_rustls::TlsAcceptor;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::time::{sleep, timeout_at, Instant};
 use tokio_util::codec::{Decoder, Encoder, Framed};
 
 use crate::api::auth::StartupHandler;
-use crate::api::query::ExtendedQueryHandler;
-use crate::api::query::SimpleQueryHandler;
-use crate::api::{ClientInfo, ClientPortalStore, DefaultClient, PgWireConnectionState};
+use crate::api::fastpath::FastpathHandler;
+use crate::api::guc::GucRegistry;
+use crate::api::notice::NoticeEmitter;
+use crate::api::process::{process_error, process_message};
+use crate::api::push::{ServerPush, ServerPushMessage};
+use crate::api::query::{ExtendedQueryHandler, SimpleQueryHandler};
+use crate::api::replication::{PlaceholderReplicationHandler, ReplicationHandler};
+use crate::api::store::MemPortalStore;
+use crate::api::terminate::{TerminateHandler, TerminateReason};
+use crate::api::transaction::TransactionStatus;
+use crate::api::{
+    ClientInfo, ClientPortalStore, DefaultClient, PeerCredentials, PgWireConnectionState,
+};
 use crate::error::{ErrorInfo, PgWireError, PgWireResult};
-use crate::messages::response::ReadyForQuery;
-use crate::messages::response::{SslResponse, READY_STATUS_IDLE};
-use crate::messages::startup::{SslRequest, Startup};
+#[cfg(feature = "gss")]
+use crate::gssenc::GssEncAccept;
+use crate::messages::data::MESSAGE_TYPE_BYTE_DATA_ROW;
+use crate::messages::response::NoticeResponse;
+use crate::messages::response::{GssEncResponse, SslResponse};
+use crate::messages::startup::{
+    BackendKeyData, CancelRequest, GssEncRequest, ParameterStatus, SslRequest, Startup,
+};
 use crate::messages::{Message, PgWireBackendMessage, PgWireFrontendMessage};
+use crate::mux::{self, MuxCodec, MuxLayer};
+use crate::proxy::read_proxy_header;
+use crate::ratelimit::ErrorRateLimiter;
+
+pub use tokio_rustls::rustls;
+pub use tokio_rustls::TlsAcceptor;
 
 #[non_exhaustive]
 #[derive(Debug, new)]
@@ -36,6 +64,14 @@ impl<S> Decoder for PgWireMessageServerCodec<S> {
                     return Ok(Some(PgWireFrontendMessage::SslRequest(request)));
                 }
 
+                if let Some(request) = GssEncRequest::decode(src)? {
+                    return Ok(Some(PgWireFrontendMessage::GssEncRequest(request)));
+                }
+
+                if let Some(request) = CancelRequest::decode(src)? {
+                    return Ok(Some(PgWireFrontendMessage::CancelRequest(request)));
+                }
+
                 if let Some(startup) = Startup::decode(src)? {
                     return Ok(Some(PgWireFrontendMessage::Startup(startup)));
                 }
@@ -83,6 +119,76 @@ impl<T, S> ClientInfo for Framed<T, PgWireMessageServerCodec<S>> {
     fn metadata_mut(&mut self) -> &mut std::collections::HashMap<String, String> {
         self.codec_mut().client_info.metadata_mut()
     }
+
+    fn notice_emitter(&self) -> Option<NoticeEmitter> {
+        self.codec().client_info.notice_emitter()
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.codec_mut().client_info.take_notices()
+    }
+
+    fn push_parameter_status(&mut self, name: &str, value: &str) {
+        self.codec_mut()
+            .client_info
+            .push_parameter_status(name, value);
+    }
+
+    fn take_parameter_status(&mut self) -> Vec<ParameterStatus> {
+        self.codec_mut().client_info.take_parameter_status()
+    }
+
+    fn backend_key_data(&self) -> Option<&BackendKeyData> {
+        self.codec().client_info.backend_key_data()
+    }
+
+    fn set_backend_key_data(&mut self, backend_key_data: BackendKeyData) {
+        self.codec_mut()
+            .client_info
+            .set_backend_key_data(backend_key_data);
+    }
+
+    fn guc_registry(&self) -> Option<Arc<Mutex<GucRegistry>>> {
+        self.codec().client_info.guc_registry()
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.codec().client_info.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.codec_mut().client_info.set_transaction_status(status);
+    }
+
+    fn protocol_minor_version(&self) -> u16 {
+        self.codec().client_info.protocol_minor_version()
+    }
+
+    fn set_protocol_minor_version(&mut self, minor_version: u16) {
+        self.codec_mut()
+            .client_info
+            .set_protocol_minor_version(minor_version);
+    }
+
+    fn client_certificates(&self) -> Option<&[Vec<u8>]> {
+        self.codec().client_info.client_certificates()
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        self.codec().client_info.server_name()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.codec().client_info.alpn_protocol()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.codec().client_info.peer_credentials()
+    }
+
+    fn gss_principal(&self) -> Option<&str> {
+        self.codec().client_info.gss_principal()
+    }
 }
 
 impl<T, S> ClientPortalStore for Framed<T, PgWireMessageServerCodec<S>> {
@@ -93,210 +199,3042 @@ impl<T, S> ClientPortalStore for Framed<T, PgWireMessageServerCodec<S>> {
     }
 }
 
-async fn process_message<S, A, Q, EQ>(
-    message: PgWireFrontendMessage,
-    socket: &mut Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
-    authenticator: Arc<A>,
-    query_handler: Arc<Q>,
-    extended_query_handler: Arc<EQ>,
-) -> PgWireResult<()>
-where
-    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
-    A: StartupHandler,
-    Q: SimpleQueryHandler,
-    EQ: ExtendedQueryHandler,
-{
-    match socket.codec().client_info.state() {
-        PgWireConnectionState::AwaitingStartup
-        | PgWireConnectionState::AuthenticationInProgress => {
-            authenticator.on_startup(socket, message).await?;
-        }
-        // From Postgres docs:
-        // When an error is detected while processing any extended-query
-        // message, the backend issues ErrorResponse, then reads and discards
-        // messages until a Sync is reached, then issues ReadyForQuery and
-        // returns to normal message processing.
-        PgWireConnectionState::AwaitingSync => {
-            if let PgWireFrontendMessage::Sync(sync) = message {
-                extended_query_handler.on_sync(socket, sync).await?;
-                socket.set_state(PgWireConnectionState::ReadyForQuery);
+/// A batch of backend messages written to the client in one call.
+///
+/// Messages are encoded into a single buffer as they are added, so the client
+/// receives a pipeline batch, usually ended with `ReadyForQuery`, as a whole
+/// instead of seeing a partial response.
+///
+/// `process_socket` has the same behaviour built in: responses of extended
+/// query messages are buffered until `Sync` or `Flush`.
+#[derive(Debug, Default)]
+pub struct PipelineResponse {
+    buf: BytesMut,
+}
+
+impl PipelineResponse {
+    pub fn new() -> PipelineResponse {
+        PipelineResponse::default()
+    }
+
+    /// Encode the message and append it to the batch.
+    pub fn add(&mut self, msg: impl Into<PgWireBackendMessage>) -> PgWireResult<()> {
+        msg.into().encode(&mut self.buf)
+    }
+
+    /// Size of encoded messages in bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Write the whole batch to `writer`.
+    pub async fn flush<W>(&self, writer: &mut W) -> PgWireResult<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        writer.write_all(&self.buf).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Maximum number of slices passed to one `write_vectored` call, which is
+/// `IOV_MAX` of Linux and most other platforms.
+const MAX_IO_SLICES: usize = 1024;
+
+/// Part of an encoded response
+#[derive(Debug)]
+enum ResponseSegment {
+    /// Range of the shared buffer of `VectoredResponseEncoder`
+    Inline(Range<usize>),
+    /// Buffer taken from a message without copy
+    Shared(Bytes),
+}
+
+/// A batch of backend messages written to the client with scatter-gather
+/// I/O.
+///
+/// Unlike `PipelineResponse`, row data of `DataRow` and bodies added with
+/// `add_raw` are not copied into the batch. Message type and length, and
+/// small messages, are encoded into a shared buffer, then all parts are
+/// submitted with `write_vectored`.
+///
+/// Column values and their length prefixes are stored contiguously in
+/// `DataRow`, so each row takes a single slice, no matter how many columns
+/// it has.
+#[derive(Debug, Default)]
+pub struct VectoredResponseEncoder {
+    buf: BytesMut,
+    segments: Vec<ResponseSegment>,
+}
+
+impl VectoredResponseEncoder {
+    pub fn new() -> VectoredResponseEncoder {
+        VectoredResponseEncoder::default()
+    }
+
+    /// Append the message to the batch. Data of `DataRow` is kept as is.
+    pub fn add(&mut self, msg: impl Into<PgWireBackendMessage>) -> PgWireResult<()> {
+        match msg.into() {
+            PgWireBackendMessage::DataRow(row) => {
+                let start = self.buf.len();
+                self.buf.put_u8(MESSAGE_TYPE_BYTE_DATA_ROW);
+                self.buf.put_i32(row.message_length() as i32);
+                self.buf.put_i16(row.field_count);
+                self.push_inline(start);
+                self.push_shared(row.data.freeze());
             }
-        }
-        _ => {
-            // query or query in progress
-            match message {
-                PgWireFrontendMessage::Query(query) => {
-                    query_handler.on_query(socket, query).await?;
-                }
-                PgWireFrontendMessage::Parse(parse) => {
-                    extended_query_handler.on_parse(socket, parse).await?;
-                }
-                PgWireFrontendMessage::Bind(bind) => {
-                    extended_query_handler.on_bind(socket, bind).await?;
-                }
-                PgWireFrontendMessage::Execute(execute) => {
-                    extended_query_handler.on_execute(socket, execute).await?;
-                }
-                PgWireFrontendMessage::Describe(describe) => {
-                    extended_query_handler.on_describe(socket, describe).await?;
-                }
-                PgWireFrontendMessage::Sync(sync) => {
-                    extended_query_handler.on_sync(socket, sync).await?;
-                }
-                PgWireFrontendMessage::Close(close) => {
-                    extended_query_handler.on_close(socket, close).await?;
-                }
-                _ => {}
+            msg => {
+                let start = self.buf.len();
+                msg.encode(&mut self.buf)?;
+                self.push_inline(start);
             }
         }
+        Ok(())
     }
-    Ok(())
-}
 
-async fn process_error<S, ST>(
-    socket: &mut Framed<S, PgWireMessageServerCodec<ST>>,
-    error: PgWireError,
-    wait_for_sync: bool,
-) -> Result<(), IOError>
-where
-    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
-{
-    match error {
-        PgWireError::UserError(error_info) => {
-            socket
-                .feed(PgWireBackendMessage::ErrorResponse((*error_info).into()))
-                .await?;
+    /// Append a message of `message_type` with encoded `body`, which is
+    /// written without copy.
+    pub fn add_raw(&mut self, message_type: u8, body: Bytes) {
+        let start = self.buf.len();
+        self.buf.put_u8(message_type);
+        self.buf.put_i32((4 + body.len()) as i32);
+        self.push_inline(start);
+        self.push_shared(body);
+    }
+
+    fn push_inline(&mut self, start: usize) {
+        let end = self.buf.len();
+        // merge with previous range of the shared buffer
+        if let Some(ResponseSegment::Inline(range)) = self.segments.last_mut() {
+            if range.end == start {
+                range.end = end;
+                return;
+            }
         }
-        PgWireError::ApiError(e) => {
-            let error_info = ErrorInfo::new("ERROR".to_owned(), "XX000".to_owned(), e.to_string());
-            socket
-                .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
-                .await?;
+        self.segments.push(ResponseSegment::Inline(start..end));
+    }
+
+    fn push_shared(&mut self, bytes: Bytes) {
+        if !bytes.is_empty() {
+            self.segments.push(ResponseSegment::Shared(bytes));
         }
-        _ => {
-            // Internal error
-            let error_info =
-                ErrorInfo::new("FATAL".to_owned(), "XX000".to_owned(), error.to_string());
-            socket
-                .send(PgWireBackendMessage::ErrorResponse(error_info.into()))
-                .await?;
-            return socket.close().await;
+    }
+
+    fn segment<'a>(&'a self, segment: &'a ResponseSegment) -> &'a [u8] {
+        match segment {
+            ResponseSegment::Inline(range) => &self.buf[range.clone()],
+            ResponseSegment::Shared(bytes) => bytes,
         }
     }
 
-    if wait_for_sync {
-        socket.set_state(PgWireConnectionState::AwaitingSync);
-    } else {
-        socket
-            .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
-            )))
-            .await?;
+    /// Size of encoded messages in bytes.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| self.segment(s).len()).sum()
     }
-    socket.flush().await?;
 
-    Ok(())
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Write the whole batch to `writer`, with as few `write_vectored` calls
+    /// as the writer allows.
+    pub async fn flush<W>(&self, writer: &mut W) -> PgWireResult<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        // position of next byte to write
+        let (mut index, mut offset) = (0, 0);
+        while index < self.segments.len() {
+            let slices = self.segments[index..]
+                .iter()
+                .take(MAX_IO_SLICES)
+                .enumerate()
+                .map(|(i, s)| {
+                    let data = self.segment(s);
+                    IoSlice::new(if i == 0 { &data[offset..] } else { data })
+                })
+                .collect::<Vec<_>>();
+            let mut written = writer.write_vectored(&slices).await?;
+            if written == 0 {
+                return Err(IOError::from(std::io::ErrorKind::WriteZero).into());
+            }
+
+            written += offset;
+            offset = 0;
+            while index < self.segments.len() {
+                let len = self.segment(&self.segments[index]).len();
+                if written < len {
+                    offset = written;
+                    break;
+                }
+                written -= len;
+                index += 1;
+            }
+        }
+        writer.flush().await?;
+        Ok(())
+    }
 }
 
-async fn is_sslrequest_pending(tcp_socket: &TcpStream) -> Result<bool, IOError> {
+/// Content type of TLS handshake record, the first byte of `ClientHello`
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/// Peek the code of 8 bytes request sent before startup, like `SslRequest`
+async fn peek_request_code(tcp_socket: &TcpStream) -> Result<Option<i32>, IOError> {
     let mut buf = [0u8; SslRequest::BODY_SIZE];
     let mut buf = ReadBuf::new(&mut buf);
     while buf.filled().len() < SslRequest::BODY_SIZE {
         if poll_fn(|cx| tcp_socket.poll_peek(cx, &mut buf)).await? == 0 {
             // the tcp_stream has ended
-            return Ok(false);
+            return Ok(None);
         }
     }
 
-    let mut buf = BytesMut::from(buf.filled());
-    if let Ok(Some(_)) = SslRequest::decode(&mut buf) {
-        return Ok(true);
-    }
-    Ok(false)
+    Ok(Some((&buf.filled()[4..8]).get_i32()))
+}
+
+/// Whether the client starts with a TLS handshake record, which can't be the
+/// first byte of a request length.
+async fn peek_tls_handshake(tcp_socket: &TcpStream) -> Result<bool, IOError> {
+    let mut buf = [0u8; 1];
+    let mut buf = ReadBuf::new(&mut buf);
+    poll_fn(|cx| tcp_socket.poll_peek(cx, &mut buf)).await?;
+    Ok(buf.filled() == [TLS_HANDSHAKE_RECORD])
+}
+
+/// Encryption negotiated before startup
+enum Encryption {
+    None,
+    Tls,
+    #[cfg(feature = "gss")]
+    Gss,
 }
 
 async fn peek_for_sslrequest<ST>(
     socket: &mut Framed<TcpStream, PgWireMessageServerCodec<ST>>,
     ssl_supported: bool,
-) -> Result<bool, IOError> {
-    let mut ssl = false;
-    if is_sslrequest_pending(socket.get_ref()).await? {
+    direct_tls: bool,
+    gss_supported: bool,
+) -> Result<Encryption, IOError> {
+    if ssl_supported && direct_tls && peek_tls_handshake(socket.get_ref()).await? {
+        return Ok(Encryption::Tls);
+    }
+
+    let mut code = peek_request_code(socket.get_ref()).await?;
+    if code == Some(GssEncRequest::BODY_MAGIC_NUMBER) {
+        // consume request
+        socket.next().await;
+
+        #[cfg(feature = "gss")]
+        if gss_supported {
+            socket
+                .send(PgWireBackendMessage::GssEncResponse(GssEncResponse::Accept))
+                .await?;
+            return Ok(Encryption::Gss);
+        }
+        #[cfg(not(feature = "gss"))]
+        let _ = gss_supported;
+
+        // client may continue with SslRequest
+        socket
+            .send(PgWireBackendMessage::GssEncResponse(GssEncResponse::Refuse))
+            .await?;
+        code = peek_request_code(socket.get_ref()).await?;
+    }
+
+    let mut encryption = Encryption::None;
+    if code == Some(SslRequest::BODY_MAGIC_NUMBER) {
         // consume request
         socket.next().await;
 
         let response = if ssl_supported {
-            ssl = true;
+            encryption = Encryption::Tls;
             PgWireBackendMessage::SslResponse(SslResponse::Accept)
         } else {
             PgWireBackendMessage::SslResponse(SslResponse::Refuse)
         };
         socket.send(response).await?;
     }
-    Ok(ssl)
+    Ok(encryption)
 }
 
-pub async fn process_socket<A, Q, EQ>(
-    tcp_socket: TcpStream,
-    tls_acceptor: Option<Arc<TlsAcceptor>>,
+async fn process_mux_session<S, A, Q, EQ>(
+    socket: Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
     startup_handler: Arc<A>,
     query_handler: Arc<Q>,
     extended_query_handler: Arc<EQ>,
+    max_sessions: usize,
 ) -> Result<(), IOError>
 where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
     A: StartupHandler,
     Q: SimpleQueryHandler,
     EQ: ExtendedQueryHandler,
 {
-    let addr = tcp_socket.peer_addr()?;
-    tcp_socket.set_nodelay(true)?;
+    let mut layer = MuxLayer::<EQ::Statement>::new(&socket).with_max_sessions(max_sessions);
+    let mut socket = socket.map_codec(|_| MuxCodec);
 
-    let client_info = DefaultClient::new(addr, false);
-    let mut tcp_socket = Framed::new(tcp_socket, PgWireMessageServerCodec::new(client_info));
-    let ssl = peek_for_sslrequest(&mut tcp_socket, tls_acceptor.is_some()).await?;
+    while let Some(frame) = socket.next().await {
+        let (session_id, msg) = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                // framing is lost, the connection cannot be recovered
+                tracing::warn!(error = %e, "failed to decode multiplexed frame");
+                for session_id in layer.session_ids() {
+                    let error_info =
+                        ErrorInfo::new("FATAL".to_owned(), e.sqlstate().to_owned(), e.to_string());
+                    socket
+                        .feed((
+                            session_id,
+                            PgWireBackendMessage::ErrorResponse(error_info.into()),
+                        ))
+                        .await?;
+                }
+                return socket.close().await;
+            }
+        };
+        if let PgWireFrontendMessage::Terminate(_) = msg {
+            layer.remove(session_id);
+            continue;
+        }
 
-    if !ssl {
-        // use an already configured socket.
-        let mut socket = tcp_socket;
-
-        while let Some(Ok(msg)) = socket.next().await {
-            let is_extended_query = msg.is_extended_query();
-            if let Err(e) = process_message(
-                msg,
-                &mut socket,
-                startup_handler.clone(),
-                query_handler.clone(),
-                extended_query_handler.clone(),
-            )
-            .await
-            {
-                process_error(&mut socket, e, is_extended_query).await?;
+        let session = match layer.session_mut(session_id) {
+            Ok(session) => session,
+            Err(e) => {
+                // the frame is dropped, other sessions go on
+                tracing::warn!(session_id, max_sessions, "too many multiplexed sessions");
+                let error_info = ErrorInfo::new(
+                    "FATAL".to_owned(),
+                    e.sqlstate().to_owned(),
+                    format!("{e}: too many sessions, max {max_sessions}"),
+                );
+                socket
+                    .send((
+                        session_id,
+                        PgWireBackendMessage::ErrorResponse(error_info.into()),
+                    ))
+                    .await?;
+                continue;
             }
+        };
+        let is_extended_query = msg.is_extended_query();
+        if let Err(e) = process_message(
+            msg,
+            session,
+            startup_handler.as_ref(),
+            query_handler.as_ref(),
+            extended_query_handler.as_ref(),
+            None::<&PlaceholderReplicationHandler>,
+            None,
+        )
+        .await
+        {
+            process_error(session, e, is_extended_query).await?;
+        }
+        for notice in session.take_notices() {
+            socket
+                .feed((session_id, PgWireBackendMessage::NoticeResponse(notice)))
+                .await?;
+        }
+
+        for msg in session.take_outgoing() {
+            socket.feed((session_id, msg)).await?;
+        }
+        socket.flush().await?;
+
+        if session.is_closed() {
+            layer.remove(session_id);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_authenticating<C: ClientInfo>(client: &C) -> bool {
+    matches!(
+        client.state(),
+        PgWireConnectionState::AwaitingStartup | PgWireConnectionState::AuthenticationInProgress
+    )
+}
+
+pub(crate) async fn send_auth_timeout<C>(socket: &mut C) -> Result<(), IOError>
+where
+    C: Sink<PgWireBackendMessage, Error = IOError> + Unpin,
+{
+    let error_info = ErrorInfo::new(
+        "FATAL".to_owned(),
+        "57P03".to_owned(),
+        "canceling authentication due to timeout".to_owned(),
+    );
+    socket
+        .send(PgWireBackendMessage::ErrorResponse(error_info.into()))
+        .await?;
+    socket.close().await
+}
+
+enum ConnectionEvent {
+    Message(Option<Result<PgWireFrontendMessage, PgWireError>>),
+    Push(PgWireResult<Vec<ServerPushMessage>>),
+}
+
+async fn next_event<S, ST>(
+    socket: &mut Framed<S, PgWireMessageServerCodec<ST>>,
+    server_push: Option<&Arc<dyn ServerPush>>,
+) -> ConnectionEvent
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+{
+    // only push to authenticated, idle clients
+    let server_push =
+        server_push.filter(|_| matches!(socket.state(), PgWireConnectionState::ReadyForQuery));
+
+    if let Some(server_push) = server_push {
+        let push = server_push.push_messages();
+        match select(socket.next(), push).await {
+            Either::Left((msg, _)) => ConnectionEvent::Message(msg),
+            Either::Right((pushed, _)) => ConnectionEvent::Push(pushed),
         }
     } else {
-        // mention the use of ssl
-        let client_info = DefaultClient::new(addr, true);
-        // safe to unwrap tls_acceptor here
-        let ssl_socket = tls_acceptor
-            .unwrap()
-            .accept(tcp_socket.into_inner())
-            .await?;
-        let mut socket = Framed::new(ssl_socket, PgWireMessageServerCodec::new(client_info));
-
-        while let Some(Ok(msg)) = socket.next().await {
-            let is_extended_query = msg.is_extended_query();
-            if let Err(e) = process_message(
-                msg,
-                &mut socket,
-                startup_handler.clone(),
-                query_handler.clone(),
-                extended_query_handler.clone(),
-            )
-            .await
-            {
-                process_error(&mut socket, e, is_extended_query).await?;
+        ConnectionEvent::Message(socket.next().await)
+    }
+}
+
+/// How the message loop of a connection has ended.
+enum SessionEnd {
+    /// Client sent `Terminate`.
+    Terminate,
+    /// Connection was closed by client, or by the server after an error.
+    Closed,
+    /// Client has requested multiplexing after authentication.
+    Mux,
+}
+
+async fn do_process_socket<S, A, Q, EQ, R>(
+    mut socket: Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    auth_deadline: Instant,
+    options: ProcessSocketOptions<R>,
+) -> Result<(), IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    if let Some(capacity) = options.portal_store_capacity {
+        socket.codec_mut().client_info.portal_store = MemPortalStore::with_capacity(capacity);
+    }
+    let terminate_handler = options.terminate_handler.clone();
+    let max_mux_sessions = options.max_mux_sessions;
+    let result = process_session_messages(
+        &mut socket,
+        startup_handler.clone(),
+        query_handler.clone(),
+        extended_query_handler.clone(),
+        auth_deadline,
+        options,
+    )
+    .await;
+
+    if let Ok(SessionEnd::Mux) = result {
+        return process_mux_session(
+            socket,
+            startup_handler,
+            query_handler,
+            extended_query_handler,
+            max_mux_sessions,
+        )
+        .await;
+    }
+
+    if let Some(terminate_handler) = terminate_handler.filter(|_| !is_authenticating(&socket)) {
+        let reason = match result {
+            Ok(SessionEnd::Terminate) => TerminateReason::Terminate,
+            _ => TerminateReason::Disconnect,
+        };
+        terminate_handler.on_terminate(&socket, reason).await;
+    }
+    result.map(|_| ())
+}
+
+async fn process_session_messages<S, A, Q, EQ, R>(
+    socket: &mut Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    auth_deadline: Instant,
+    options: ProcessSocketOptions<R>,
+) -> Result<SessionEnd, IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    let mut server_push = options.server_push;
+    loop {
+        let event = if is_authenticating(socket) {
+            match timeout_at(auth_deadline, next_event(socket, None)).await {
+                Ok(event) => event,
+                Err(_) => {
+                    send_auth_timeout(socket).await?;
+                    return Ok(SessionEnd::Closed);
+                }
+            }
+        } else {
+            next_event(socket, server_push.as_ref()).await
+        };
+
+        let msg = match event {
+            ConnectionEvent::Message(Some(Ok(msg))) => msg,
+            ConnectionEvent::Message(_) => return Ok(SessionEnd::Closed),
+            ConnectionEvent::Push(pushed) => {
+                let pushed = pushed?;
+                if pushed.is_empty() {
+                    // source exhausted
+                    server_push = None;
+                }
+                for msg in pushed {
+                    socket.feed(msg.into()).await?;
+                }
+                socket.flush().await?;
+                continue;
+            }
+        };
+
+        if let PgWireFrontendMessage::Terminate(_) = msg {
+            return Ok(SessionEnd::Terminate);
+        }
+        if let PgWireFrontendMessage::SslRequest(_) = msg {
+            if matches!(socket.state(), PgWireConnectionState::AwaitingStartup) {
+                // not negotiated before the session, e.g. on Unix domain
+                // socket where TLS is not available
+                socket
+                    .send(PgWireBackendMessage::SslResponse(SslResponse::Refuse))
+                    .await?;
+                continue;
+            }
+        }
+
+        let is_extended_query = msg.is_extended_query();
+        let is_cancel_request = matches!(msg, PgWireFrontendMessage::CancelRequest(_));
+        let in_startup = is_authenticating(socket);
+        if let Err(e) = process_message(
+            msg,
+            socket,
+            startup_handler.as_ref(),
+            query_handler.as_ref(),
+            extended_query_handler.as_ref(),
+            options.replication_handler.as_deref(),
+            options.fastpath_handler.as_deref(),
+        )
+        .await
+        {
+            if let Some(limiter) = options.error_rate_limiter.as_ref().filter(|_| in_startup) {
+                let delay = limiter.record_error(socket.socket_addr().ip());
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+            }
+            process_error(socket, e, is_extended_query).await?;
+        }
+        // the connection of cancel request is closed without response
+        if is_cancel_request {
+            return Ok(SessionEnd::Closed);
+        }
+        // notices emitted after the response, e.g. by spawned tasks
+        let notices = socket.take_notices();
+        if !notices.is_empty() {
+            for notice in notices {
+                socket
+                    .feed(PgWireBackendMessage::NoticeResponse(notice))
+                    .await?;
             }
+            socket.flush().await?;
+        }
+
+        // switch to multiplexing mode once the connection is authenticated
+        if in_startup
+            && matches!(socket.state(), PgWireConnectionState::ReadyForQuery)
+            && mux::is_mux_requested(socket)
+        {
+            socket
+                .send(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+                    mux::MUX_VERSION_PARAMETER.to_owned(),
+                    mux::MUX_VERSION.to_owned(),
+                )))
+                .await?;
+            return Ok(SessionEnd::Mux);
         }
     }
+}
 
-    Ok(())
+/// Default value of [`ProcessSocketOptions::auth_timeout`].
+pub const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Options for [`process_socket_with_options`].
+#[non_exhaustive]
+pub struct ProcessSocketOptions<R = PlaceholderReplicationHandler> {
+    /// Max time from accepting the connection to finishing authentication.
+    ///
+    /// The client receives an error and gets disconnected if it's not
+    /// authenticated in time.
+    pub auth_timeout: Duration,
+    /// Source of messages pushed to the client when it's idle.
+    ///
+    /// Server push is not available for multiplexed connections.
+    pub server_push: Option<Arc<dyn ServerPush>>,
+    /// Limit of errors before authentication from the same source address.
+    ///
+    /// Share the limiter among connections of a server. Errors over limit are
+    /// delayed, or connections from the source are closed right after being
+    /// accepted.
+    pub error_rate_limiter: Option<Arc<ErrorRateLimiter>>,
+    /// Handler of fastpath function calls. Calls are rejected without it.
+    ///
+    /// Fastpath is not available for multiplexed connections.
+    pub fastpath_handler: Option<Arc<dyn FastpathHandler>>,
+    /// Handler notified when an authenticated session ends, by `Terminate`
+    /// or disconnection.
+    ///
+    /// It's not called for multiplexed connections.
+    pub terminate_handler: Option<Arc<dyn TerminateHandler>>,
+    /// Handler of replication connections, set with
+    /// [`with_replication_handler`](Self::with_replication_handler).
+    ///
+    /// Connections started with `replication` parameter are in replication
+    /// mode, where `Query` messages are handled by this handler and only
+    /// replication commands are accepted. Without replication handler, the
+    /// `replication` parameter is ignored.
+    pub replication_handler: Option<Arc<R>>,
+    /// Accept TLS handshake sent without `SSLRequest`, as done by clients
+    /// with `sslnegotiation=direct`. Only takes effect with a TLS acceptor.
+    pub direct_tls: bool,
+    /// Expect a PROXY protocol header before anything else, and report the
+    /// source address in it by [`ClientInfo::socket_addr`]. Connections
+    /// without the header are closed.
+    ///
+    /// Only enable it when all connections come from a trusted proxy.
+    pub proxy_protocol: bool,
+    /// Accept `GSSENCRequest` and carry the connection over GSSAPI
+    /// encryption. It's refused without acceptor.
+    #[cfg(feature = "gss")]
+    pub gss_encryption: Option<Arc<dyn GssEncAccept>>,
+    /// Max number of prepared statements, and of portals, kept for the
+    /// connection. The least recently used one is evicted beyond it.
+    ///
+    /// Unbounded by default.
+    pub portal_store_capacity: Option<NonZeroUsize>,
+    /// Max number of sessions on a multiplexed connection,
+    /// `mux::DEFAULT_MAX_MUX_SESSIONS` by default. Messages that would open
+    /// more sessions are rejected.
+    pub max_mux_sessions: usize,
+}
+
+impl Default for ProcessSocketOptions {
+    fn default() -> Self {
+        Self {
+            auth_timeout: DEFAULT_AUTH_TIMEOUT,
+            server_push: None,
+            error_rate_limiter: None,
+            fastpath_handler: None,
+            terminate_handler: None,
+            replication_handler: None,
+            direct_tls: false,
+            proxy_protocol: false,
+            #[cfg(feature = "gss")]
+            gss_encryption: None,
+            portal_store_capacity: None,
+            max_mux_sessions: mux::DEFAULT_MAX_MUX_SESSIONS,
+        }
+    }
+}
+
+impl<R> ProcessSocketOptions<R> {
+    fn gss_encryption_enabled(&self) -> bool {
+        #[cfg(feature = "gss")]
+        return self.gss_encryption.is_some();
+        #[cfg(not(feature = "gss"))]
+        return false;
+    }
+
+    /// Serve replication connections with `replication_handler`.
+    pub fn with_replication_handler<R2>(
+        self,
+        replication_handler: Arc<R2>,
+    ) -> ProcessSocketOptions<R2> {
+        ProcessSocketOptions {
+            auth_timeout: self.auth_timeout,
+            server_push: self.server_push,
+            error_rate_limiter: self.error_rate_limiter,
+            fastpath_handler: self.fastpath_handler,
+            terminate_handler: self.terminate_handler,
+            replication_handler: Some(replication_handler),
+            direct_tls: self.direct_tls,
+            proxy_protocol: self.proxy_protocol,
+            #[cfg(feature = "gss")]
+            gss_encryption: self.gss_encryption.clone(),
+            portal_store_capacity: self.portal_store_capacity,
+            max_mux_sessions: self.max_mux_sessions,
+        }
+    }
+}
+
+impl<R> Clone for ProcessSocketOptions<R> {
+    fn clone(&self) -> Self {
+        Self {
+            auth_timeout: self.auth_timeout,
+            server_push: self.server_push.clone(),
+            error_rate_limiter: self.error_rate_limiter.clone(),
+            fastpath_handler: self.fastpath_handler.clone(),
+            terminate_handler: self.terminate_handler.clone(),
+            replication_handler: self.replication_handler.clone(),
+            direct_tls: self.direct_tls,
+            proxy_protocol: self.proxy_protocol,
+            #[cfg(feature = "gss")]
+            gss_encryption: self.gss_encryption.clone(),
+            portal_store_capacity: self.portal_store_capacity,
+            max_mux_sessions: self.max_mux_sessions,
+        }
+    }
+}
+
+impl<R> Debug for ProcessSocketOptions<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessSocketOptions")
+            .field("auth_timeout", &self.auth_timeout)
+            .field("server_push", &self.server_push.is_some())
+            .field("error_rate_limiter", &self.error_rate_limiter)
+            .field("fastpath_handler", &self.fastpath_handler.is_some())
+            .field("terminate_handler", &self.terminate_handler.is_some())
+            .field("replication_handler", &self.replication_handler.is_some())
+            .field("direct_tls", &self.direct_tls)
+            .field("proxy_protocol", &self.proxy_protocol)
+            .field("gss_encryption", &self.gss_encryption_enabled())
+            .field("portal_store_capacity", &self.portal_store_capacity)
+            .field("max_mux_sessions", &self.max_mux_sessions)
+            .finish()
+    }
+}
+
+/// ALPN protocol identifier of Postgres, registered for direct TLS
+/// negotiation. Add it to `alpn_protocols` of `rustls::ServerConfig` to
+/// advertise it.
+pub const POSTGRESQL_ALPN_PROTOCOL: &[u8] = b"postgresql";
+
+/// Server side of TLS handshake, so that `process_socket_with_tls` is not tied
+/// to a TLS implementation. It's implemented for rustls [`TlsAcceptor`], and
+/// can be implemented for `tokio_native_tls::TlsAcceptor` to use OpenSSL or
+/// platform TLS libraries.
+#[async_trait]
+pub trait TlsAccept: Send + Sync {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + Sync;
+
+    /// Perform handshake on a socket whose `SSLRequest` is accepted.
+    async fn accept(&self, socket: TcpStream) -> Result<Self::Stream, IOError>;
+
+    /// DER encoded certificate chain presented by client, if any.
+    fn peer_certificates(_stream: &Self::Stream) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    /// Host name sent by client with SNI, if any.
+    fn server_name(_stream: &Self::Stream) -> Option<String> {
+        None
+    }
+
+    /// Application protocol negotiated with ALPN, if any.
+    fn alpn_protocol(_stream: &Self::Stream) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[async_trait]
+impl TlsAccept for TlsAcceptor {
+    type Stream = tokio_rustls::server::TlsStream<TcpStream>;
+
+    async fn accept(&self, socket: TcpStream) -> Result<Self::Stream, IOError> {
+        TlsAcceptor::accept(self, socket).await
+    }
+
+    fn peer_certificates(stream: &Self::Stream) -> Option<Vec<Vec<u8>>> {
+        stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .map(|certs| certs.iter().map(|cert| cert.to_vec()).collect())
+    }
+
+    fn server_name(stream: &Self::Stream) -> Option<String> {
+        stream.get_ref().1.server_name().map(str::to_owned)
+    }
+
+    fn alpn_protocol(stream: &Self::Stream) -> Option<Vec<u8>> {
+        stream.get_ref().1.alpn_protocol().map(<[u8]>::to_vec)
+    }
+}
+
+/// rustls acceptor whose server config can be replaced at runtime, to rotate
+/// certificates without restarting the server.
+///
+/// Connections accepted after [`reload`](Self::reload) use the new config,
+/// established connections are not affected.
+#[derive(Debug)]
+pub struct ReloadableTlsAcceptor {
+    config: RwLock<Arc<rustls::ServerConfig>>,
+}
+
+impl ReloadableTlsAcceptor {
+    pub fn new(config: Arc<rustls::ServerConfig>) -> Self {
+        ReloadableTlsAcceptor {
+            config: RwLock::new(config),
+        }
+    }
+
+    /// Replace the server config for subsequently accepted connections.
+    pub fn reload(&self, config: Arc<rustls::ServerConfig>) {
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+
+    /// Current server config
+    pub fn config(&self) -> Arc<rustls::ServerConfig> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[async_trait]
+impl TlsAccept for ReloadableTlsAcceptor {
+    type Stream = tokio_rustls::server::TlsStream<TcpStream>;
+
+    async fn accept(&self, socket: TcpStream) -> Result<Self::Stream, IOError> {
+        TlsAcceptor::from(self.config()).accept(socket).await
+    }
+
+    fn peer_certificates(stream: &Self::Stream) -> Option<Vec<Vec<u8>>> {
+        TlsAcceptor::peer_certificates(stream)
+    }
+
+    fn server_name(stream: &Self::Stream) -> Option<String> {
+        TlsAcceptor::server_name(stream)
+    }
+
+    fn alpn_protocol(stream: &Self::Stream) -> Option<Vec<u8>> {
+        TlsAcceptor::alpn_protocol(stream)
+    }
+}
+
+/// Serve a client connection until it's closed.
+///
+/// See [`process_stream`] for streams other than TCP. With `tls_acceptor`, `SSLRequest` from client is accepted and the rest of
+/// protocol is carried over TLS, otherwise it's refused and client may
+/// continue in plaintext. The acceptor can be built from a
+/// `rustls::ServerConfig` with `TlsAcceptor::from`.
+pub async fn process_socket<A, Q, EQ>(
+    tcp_socket: TcpStream,
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+{
+    process_socket_with_options(
+        tcp_socket,
+        tls_acceptor,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        ProcessSocketOptions::default(),
+    )
+    .await
+}
+
+/// Same as `process_socket`, with behaviour customized by `options`.
+pub async fn process_socket_with_options<A, Q, EQ, R>(
+    tcp_socket: TcpStream,
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    options: ProcessSocketOptions<R>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    process_socket_with_tls(
+        tcp_socket,
+        tls_acceptor,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        options,
+    )
+    .await
+}
+
+/// Same as `process_socket_with_options`, with TLS handshake done by any
+/// [`TlsAccept`] implementation, like one backed by `tokio-native-tls`.
+pub async fn process_socket_with_tls<T, A, Q, EQ, R>(
+    mut tcp_socket: TcpStream,
+    tls_acceptor: Option<Arc<T>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    options: ProcessSocketOptions<R>,
+) -> Result<(), IOError>
+where
+    T: TlsAccept,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    let auth_deadline = Instant::now() + options.auth_timeout;
+    let mut addr = tcp_socket.peer_addr()?;
+    if options.proxy_protocol {
+        match timeout_at(auth_deadline, read_proxy_header(&mut tcp_socket)).await {
+            Ok(Ok(Some(source))) => addr = source,
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => return Err(e),
+            // nothing can be sent before the header
+            Err(_) => return Ok(()),
+        }
+    }
+    if let Some(limiter) = &options.error_rate_limiter {
+        if limiter.is_rejected(addr.ip()) {
+            return Ok(());
+        }
+    }
+    tcp_socket.set_nodelay(true)?;
+
+    let client_info = DefaultClient::new(addr, false);
+    let mut tcp_socket = Framed::new(tcp_socket, PgWireMessageServerCodec::new(client_info));
+    let encryption = match timeout_at(
+        auth_deadline,
+        peek_for_sslrequest(
+            &mut tcp_socket,
+            tls_acceptor.is_some(),
+            options.direct_tls,
+            options.gss_encryption_enabled(),
+        ),
+    )
+    .await
+    {
+        Ok(encryption) => encryption?,
+        Err(_) => return send_auth_timeout(&mut tcp_socket).await,
+    };
+
+    match encryption {
+        // use an already configured socket.
+        Encryption::None => {
+            do_process_socket(
+                tcp_socket,
+                startup_handler,
+                query_handler,
+                extended_query_handler,
+                auth_deadline,
+                options,
+            )
+            .await
+        }
+        Encryption::Tls => {
+            // mention the use of ssl
+            let mut client_info = DefaultClient::new(addr, true);
+            // safe to unwrap tls_acceptor here
+            let tls_acceptor = tls_acceptor.unwrap();
+            let accept = tls_acceptor.accept(tcp_socket.into_inner());
+            let Ok(ssl_socket) = timeout_at(auth_deadline, accept).await else {
+                // tls handshake is not finished, nothing can be sent to client
+                return Ok(());
+            };
+            let ssl_socket = ssl_socket?;
+            client_info.client_certificates = T::peer_certificates(&ssl_socket);
+            client_info.server_name = T::server_name(&ssl_socket);
+            client_info.alpn_protocol = T::alpn_protocol(&ssl_socket);
+            let socket = Framed::new(ssl_socket, PgWireMessageServerCodec::new(client_info));
+
+            do_process_socket(
+                socket,
+                startup_handler,
+                query_handler,
+                extended_query_handler,
+                auth_deadline,
+                options,
+            )
+            .await
+        }
+        #[cfg(feature = "gss")]
+        Encryption::Gss => {
+            // safe to unwrap as it's enabled
+            let acceptor = options.gss_encryption.clone().unwrap();
+            let accept = acceptor.accept(tcp_socket.into_inner());
+            let Ok(gss_socket) = timeout_at(auth_deadline, accept).await else {
+                // context is not established, nothing can be sent to client
+                return Ok(());
+            };
+            let (gss_socket, principal) = gss_socket?;
+            let mut client_info = DefaultClient::new(addr, false);
+            client_info.gss_principal = Some(principal);
+            let socket = Framed::new(gss_socket, PgWireMessageServerCodec::new(client_info));
+
+            do_process_socket(
+                socket,
+                startup_handler,
+                query_handler,
+                extended_query_handler,
+                auth_deadline,
+                options,
+            )
+            .await
+        }
+    }
+}
+
+/// Serve a client over any byte stream until it's closed, like an in-memory
+/// duplex stream, a tunnel, or a TLS stream established by caller.
+///
+/// `socket_addr` is reported by [`ClientInfo::socket_addr`], and
+/// `is_secure` tells whether the stream is already encrypted. `SSLRequest`
+/// is refused as TLS is up to caller. Options of the transport, like
+/// `direct_tls` and `proxy_protocol`, are ignored.
+pub async fn process_stream<S, A, Q, EQ>(
+    stream: S,
+    socket_addr: std::net::SocketAddr,
+    is_secure: bool,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+) -> Result<(), IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+{
+    process_stream_with_options(
+        stream,
+        socket_addr,
+        is_secure,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        ProcessSocketOptions::default(),
+    )
+    .await
+}
+
+/// Same as `process_stream`, with behaviour customized by `options`.
+pub async fn process_stream_with_options<S, A, Q, EQ, R>(
+    stream: S,
+    socket_addr: std::net::SocketAddr,
+    is_secure: bool,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    options: ProcessSocketOptions<R>,
+) -> Result<(), IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    let auth_deadline = Instant::now() + options.auth_timeout;
+    let client_info = DefaultClient::new(socket_addr, is_secure);
+    let socket = Framed::new(stream, PgWireMessageServerCodec::new(client_info));
+
+    do_process_socket(
+        socket,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        auth_deadline,
+        options,
+    )
+    .await
+}
+
+/// Serve a client connection over Unix domain socket until it's closed.
+///
+/// TLS is not available, `SSLRequest` is refused. Credentials of client
+/// process are available from [`ClientInfo::peer_credentials`], e.g. for
+/// peer authentication. [`ClientInfo::socket_addr`] of the connection is the
+/// unspecified address.
+#[cfg(unix)]
+pub async fn process_unix_socket<A, Q, EQ>(
+    unix_socket: UnixStream,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+{
+    process_unix_socket_with_options(
+        unix_socket,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        ProcessSocketOptions::default(),
+    )
+    .await
+}
+
+/// Same as `process_unix_socket`, with behaviour customized by `options`.
+#[cfg(unix)]
+pub async fn process_unix_socket_with_options<A, Q, EQ, R>(
+    unix_socket: UnixStream,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    options: ProcessSocketOptions<R>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: ReplicationHandler,
+{
+    let auth_deadline = Instant::now() + options.auth_timeout;
+    let cred = unix_socket.peer_cred()?;
+
+    let addr = std::net::SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, 0));
+    let mut client_info = DefaultClient::new(addr, false);
+    client_info.peer_credentials = Some(PeerCredentials::new(cred.uid(), cred.gid(), cred.pid()));
+    let socket = Framed::new(unix_socket, PgWireMessageServerCodec::new(client_info));
+
+    do_process_socket(
+        socket,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        auth_deadline,
+        options,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::api::auth::noop::NoopStartupHandler;
+    use crate::api::portal::Portal;
+    use crate::api::query::PlaceholderExtendedQueryHandler;
+    use crate::api::replication::ReplicationCommand;
+    use crate::api::results::Tag;
+    use crate::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response};
+    use crate::api::stmt::{NoopQueryParser, StoredStatement};
+    use crate::api::Type;
+    use crate::messages::data::DataRow;
+    use crate::messages::extendedquery::{
+        Bind, BindComplete, Close, Describe, Execute, Flush, Parse, ParseComplete, Sync as PgSync,
+        TARGET_TYPE_BYTE_PORTAL, TARGET_TYPE_BYTE_STATEMENT,
+    };
+    use crate::messages::fastpath::FunctionCall;
+    use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
+    use crate::messages::simplequery::Query;
+    use crate::messages::terminate::Terminate;
+
+    struct DummyQueryHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for DummyQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                auth_timeout: Duration::from_millis(100),
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        // send first byte of startup message and stall
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&[0]).await.unwrap();
+
+        let mut buf = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut buf))
+            .await
+            .expect("client is not disconnected")
+            .unwrap();
+        server.await.unwrap().unwrap();
+
+        let mut buf = BytesMut::from(&buf[..]);
+        let Some(PgWireBackendMessage::ErrorResponse(error)) =
+            PgWireBackendMessage::decode(&mut buf).unwrap()
+        else {
+            panic!("expect ErrorResponse");
+        };
+        assert!(error
+            .fields
+            .iter()
+            .any(|(code, value)| *code == b'C' && value == "57P03"));
+    }
+
+    struct DummyReplicationHandler;
+
+    #[async_trait]
+    impl ReplicationHandler for DummyReplicationHandler {
+        async fn do_replication_command<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            command: ReplicationCommand,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(vec![Response::Execution(Tag::new(command.name()))])
+        }
+    }
+
+    /// Send `messages` and read responses until `ReadyForQuery`.
+    async fn send_and_receive<S: AsyncRead + AsyncWrite + Unpin>(
+        client: &mut S,
+        messages: &[&dyn Fn(&mut BytesMut)],
+    ) -> Vec<PgWireBackendMessage> {
+        let mut buf = BytesMut::new();
+        for m in messages {
+            m(&mut buf);
+        }
+        client.write_all(&buf).await.unwrap();
+
+        let mut buf = BytesMut::new();
+        let mut received = Vec::new();
+        loop {
+            while let Some(msg) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+                let ready = matches!(msg, PgWireBackendMessage::ReadyForQuery(_));
+                received.push(msg);
+                if ready {
+                    return received;
+                }
+            }
+            assert!(client.read_buf(&mut buf).await.unwrap() > 0);
+        }
+    }
+
+    async fn connect_replication(replication: Option<&str>) -> Vec<Vec<PgWireBackendMessage>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions::default()
+                .with_replication_handler(Arc::new(DummyReplicationHandler));
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = Startup::new();
+        if let Some(replication) = replication {
+            startup
+                .parameters
+                .insert("replication".to_owned(), replication.to_owned());
+        }
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let query = |q: &'static str| {
+            move |buf: &mut BytesMut| Query::new(q.to_owned()).encode(buf).unwrap()
+        };
+        let parse = |buf: &mut BytesMut| {
+            Parse::new(None, "SELECT 1".to_owned(), vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        let mut responses = vec![];
+        responses.push(send_and_receive(&mut client, &[&startup]).await);
+        responses.push(send_and_receive(&mut client, &[&query("IDENTIFY_SYSTEM")]).await);
+        responses.push(send_and_receive(&mut client, &[&query("SELECT 1")]).await);
+        if replication.is_some() {
+            responses.push(send_and_receive(&mut client, &[&parse, &sync]).await);
+        }
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        responses
+    }
+
+    fn error_code(msg: &PgWireBackendMessage) -> Option<&str> {
+        let PgWireBackendMessage::ErrorResponse(error) = msg else {
+            return None;
+        };
+        error
+            .fields
+            .iter()
+            .find(|(code, _)| *code == b'C')
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_replication_mode() {
+        let responses = connect_replication(Some("database")).await;
+
+        let PgWireBackendMessage::CommandComplete(ref tag) = responses[1][0] else {
+            panic!("expect CommandComplete");
+        };
+        assert_eq!("IDENTIFY_SYSTEM", tag.tag);
+        // regular queries and extended query protocol are rejected
+        assert_eq!(Some("08P01"), error_code(&responses[2][0]));
+        assert_eq!(2, responses[2].len());
+        assert_eq!(Some("08P01"), error_code(&responses[3][0]));
+        assert_eq!(2, responses[3].len());
+
+        // replication commands are queries in normal mode
+        let responses = connect_replication(None).await;
+        assert_eq!(1, responses[1].len());
+        assert_eq!(1, responses[2].len());
+    }
+
+    #[tokio::test]
+    async fn test_mux_invalid_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = Startup::new();
+        startup.parameters.insert(
+            mux::MUX_VERSION_PARAMETER.to_owned(),
+            mux::MUX_VERSION.to_owned(),
+        );
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+
+        // open session 3, then send a frame with negative length
+        let mut buf = BytesMut::new();
+        buf.put_u32(3);
+        Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+        buf.put_u32(3);
+        buf.put_u8(b'Q');
+        buf.put_i32(-1);
+        client.write_all(&buf).await.unwrap();
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        server.await.unwrap().unwrap();
+
+        // mux confirmation, unless it's read with ReadyForQuery of startup,
+        // then frames of session 3
+        let mut buf = BytesMut::from(&received[..]);
+        if buf.first() == Some(&b'S') {
+            PgWireBackendMessage::decode(&mut buf).unwrap();
+        }
+        let mut last = None;
+        while !buf.is_empty() {
+            assert_eq!(3, buf.get_u32());
+            last = PgWireBackendMessage::decode(&mut buf).unwrap();
+        }
+        assert_eq!(Some("08P01"), last.as_ref().and_then(error_code));
+    }
+
+    #[tokio::test]
+    async fn test_mux_max_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                max_mux_sessions: 1,
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = Startup::new();
+        startup.parameters.insert(
+            mux::MUX_VERSION_PARAMETER.to_owned(),
+            mux::MUX_VERSION.to_owned(),
+        );
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+
+        // session 2 is over the limit, session 1 keeps working
+        let mut buf = BytesMut::new();
+        for session_id in [1, 2, 1] {
+            buf.put_u32(session_id);
+            Query::new("SELECT 1".to_owned()).encode(&mut buf).unwrap();
+        }
+        client.write_all(&buf).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        server.await.unwrap().unwrap();
+
+        let mut buf = BytesMut::from(&received[..]);
+        if buf.first() == Some(&b'S') {
+            PgWireBackendMessage::decode(&mut buf).unwrap();
+        }
+        let mut sessions: std::collections::HashMap<u32, Vec<PgWireBackendMessage>> =
+            std::collections::HashMap::new();
+        while !buf.is_empty() {
+            let session_id = buf.get_u32();
+            let msg = PgWireBackendMessage::decode(&mut buf).unwrap().unwrap();
+            sessions.entry(session_id).or_default().push(msg);
+        }
+
+        let rejected = &sessions[&2];
+        assert_eq!(1, rejected.len());
+        assert_eq!(Some("08P01"), error_code(&rejected[0]));
+        let ready = sessions[&1]
+            .iter()
+            .filter(|msg| matches!(msg, PgWireBackendMessage::ReadyForQuery(_)))
+            .count();
+        assert_eq!(2, ready);
+    }
+
+    struct EchoFastpathHandler;
+
+    #[async_trait]
+    impl FastpathHandler for EchoFastpathHandler {
+        async fn call_function(
+            &self,
+            _client: &(dyn ClientInfo + Send + Sync),
+            call: FunctionCall,
+        ) -> PgWireResult<Option<Bytes>> {
+            Ok(call.arguments.into_iter().next().flatten())
+        }
+    }
+
+    async fn call_function(
+        fastpath_handler: Option<Arc<dyn FastpathHandler>>,
+    ) -> Vec<PgWireBackendMessage> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                fastpath_handler,
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let startup = |buf: &mut BytesMut| Startup::new().encode(buf).unwrap();
+        let call = |buf: &mut BytesMut| {
+            FunctionCall::new(764, vec![0], vec![Some(Bytes::from_static(b"42"))], 0)
+                .encode(buf)
+                .unwrap()
+        };
+        send_and_receive(&mut client, &[&startup]).await;
+        let responses = send_and_receive(&mut client, &[&call]).await;
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_fastpath_function_call() {
+        let responses = call_function(Some(Arc::new(EchoFastpathHandler))).await;
+        assert_eq!(2, responses.len());
+        assert!(matches!(
+            &responses[0],
+            PgWireBackendMessage::FunctionCallResponse(response)
+                if response.result.as_deref() == Some(&b"42"[..])
+        ));
+
+        // rejected without handler
+        let responses = call_function(None).await;
+        assert_eq!(2, responses.len());
+        assert_eq!(Some("0A000"), error_code(&responses[0]));
+        assert!(matches!(
+            responses[1],
+            PgWireBackendMessage::ReadyForQuery(_)
+        ));
+    }
+
+    #[derive(Default)]
+    struct RecordingTerminateHandler(Mutex<Vec<(TerminateReason, Option<String>)>>);
+
+    #[async_trait]
+    impl TerminateHandler for RecordingTerminateHandler {
+        async fn on_terminate(
+            &self,
+            client: &(dyn ClientInfo + Send + Sync),
+            reason: TerminateReason,
+        ) {
+            let user = client.metadata().get(crate::api::METADATA_USER).cloned();
+            self.0.lock().unwrap().push((reason, user));
+        }
+    }
+
+    async fn run_session(terminate: bool) -> Vec<(TerminateReason, Option<String>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(RecordingTerminateHandler::default());
+
+        let terminate_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                terminate_handler: Some(terminate_handler),
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "alice".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+        if terminate {
+            let mut buf = BytesMut::new();
+            Terminate::new().encode(&mut buf).unwrap();
+            client.write_all(&buf).await.unwrap();
+            // server closes the connection
+            let mut rest = Vec::new();
+            client.read_to_end(&mut rest).await.unwrap();
+        }
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        let calls = handler.0.lock().unwrap();
+        calls.clone()
+    }
+
+    #[tokio::test]
+    async fn test_terminate_handler() {
+        assert_eq!(
+            vec![(TerminateReason::Terminate, Some("alice".to_owned()))],
+            run_session(true).await
+        );
+        assert_eq!(
+            vec![(TerminateReason::Disconnect, Some("alice".to_owned()))],
+            run_session(false).await
+        );
+    }
+
+    /// Serve one connection with the query handlers. Returns the client
+    /// after startup, responses of the startup and the server task.
+    async fn serve<Q, EQ>(
+        query_handler: Arc<Q>,
+        extended_query_handler: Arc<EQ>,
+    ) -> (
+        TcpStream,
+        Vec<PgWireBackendMessage>,
+        tokio::task::JoinHandle<Result<(), IOError>>,
+    )
+    where
+        Q: SimpleQueryHandler + 'static,
+        EQ: ExtendedQueryHandler + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket(
+                socket,
+                None,
+                Arc::new(NoopStartupHandler),
+                query_handler,
+                extended_query_handler,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let responses = send_and_receive(&mut client, &[&startup]).await;
+        (client, responses, server)
+    }
+
+    async fn simple_query(client: &mut TcpStream, query: &str) -> Vec<PgWireBackendMessage> {
+        let query = Query::new(query.to_owned());
+        let query = |buf: &mut BytesMut| query.encode(buf).unwrap();
+        send_and_receive(client, &[&query]).await
+    }
+
+    /// Records `BackendKeyData` of client seen by the handler
+    #[derive(Default)]
+    struct KeyDataQueryHandler(Mutex<Option<BackendKeyData>>);
+
+    #[async_trait]
+    impl SimpleQueryHandler for KeyDataQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            *self.0.lock().unwrap() = client.backend_key_data().cloned();
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backend_key_data() {
+        let handler = Arc::new(KeyDataQueryHandler::default());
+        let (mut client, responses, server) =
+            serve(handler.clone(), Arc::new(PlaceholderExtendedQueryHandler)).await;
+        let key_data = responses
+            .into_iter()
+            .find_map(|msg| match msg {
+                PgWireBackendMessage::BackendKeyData(key_data) => Some(key_data),
+                _ => None,
+            })
+            .expect("BackendKeyData is sent on startup");
+
+        simple_query(&mut client, "SELECT pg_backend_pid()").await;
+        assert_eq!(Some(key_data), *handler.0.lock().unwrap());
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Returns 3 rows for any portal, or an error for `FAIL`
+    struct RowsQueryHandler;
+
+    #[async_trait]
+    impl ExtendedQueryHandler for RowsQueryHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser)
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            if portal.statement.statement == "FAIL" {
+                return Ok(Response::Error(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42000".to_owned(),
+                    "failed".to_owned(),
+                ))));
+            }
+            let schema = Arc::new(vec![FieldInfo::new(
+                "n".to_owned(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let rows = (1..=3)
+                .map(|n| {
+                    let mut encoder = DataRowEncoder::new(schema.clone());
+                    encoder.encode_field(&n)?;
+                    encoder.finish()
+                })
+                .collect::<Vec<_>>();
+            Ok(Response::Query(QueryResponse::new_owned(
+                schema,
+                futures::stream::iter(rows),
+            )))
+        }
+    }
+
+    fn message_types(messages: &[PgWireBackendMessage]) -> Vec<u8> {
+        messages
+            .iter()
+            .map(|msg| msg.message_type().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_suspended_portal() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(RowsQueryHandler)).await;
+
+        let parse = |buf: &mut BytesMut| {
+            Parse::new(None, "SELECT n".to_owned(), vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let bind = |buf: &mut BytesMut| {
+            Bind::new(None, None, vec![], vec![], vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let execute = |buf: &mut BytesMut| Execute::new(None, 2).encode(buf).unwrap();
+        let close = |buf: &mut BytesMut| {
+            Close::new(TARGET_TYPE_BYTE_PORTAL, None)
+                .encode(buf)
+                .unwrap()
+        };
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        // the second execute resumes the suspended portal
+        let responses =
+            send_and_receive(&mut client, &[&parse, &bind, &execute, &execute, &sync]).await;
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b's', b'D', b'C', b'Z'],
+            message_types(&responses)
+        );
+
+        // closing a suspended portal drops remaining rows
+        let execute_all = |buf: &mut BytesMut| Execute::new(None, 0).encode(buf).unwrap();
+        let responses = send_and_receive(
+            &mut client,
+            &[&bind, &execute, &close, &bind, &execute_all, &sync],
+        )
+        .await;
+        assert_eq!(
+            vec![b'2', b'D', b'D', b's', b'3', b'2', b'D', b'D', b'D', b'C', b'Z'],
+            message_types(&responses)
+        );
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Returns endless rows of 1, 2, 3 and so on. Rows of statement
+    /// `BORROWED` borrow the portal.
+    struct EndlessQueryHandler;
+
+    #[async_trait]
+    impl ExtendedQueryHandler for EndlessQueryHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser)
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "n".to_owned(),
+                None,
+                None,
+                Type::INT8,
+                FieldFormat::Text,
+            )]);
+            let row_schema = schema.clone();
+            let encode = move |n: i64| {
+                let mut encoder = DataRowEncoder::new(row_schema.clone());
+                encoder.encode_field(&n)?;
+                encoder.finish()
+            };
+            if portal.statement.statement == "BORROWED" {
+                let rows =
+                    futures::stream::iter(1..).map(move |n| encode(n + portal.name.len() as i64));
+                Ok(Response::Query(QueryResponse::new(schema, rows)))
+            } else {
+                let rows = futures::stream::iter(1..).map(encode);
+                Ok(Response::Query(
+                    QueryResponse::new_owned(schema, rows).with_backpressure(1),
+                ))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_suspended_endless_portal() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(EndlessQueryHandler)).await;
+
+        let parse = |query: &'static str| {
+            move |buf: &mut BytesMut| {
+                Parse::new(None, query.to_owned(), vec![])
+                    .encode(buf)
+                    .unwrap()
+            }
+        };
+        let bind = |buf: &mut BytesMut| {
+            Bind::new(None, None, vec![], vec![], vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let execute = |buf: &mut BytesMut| Execute::new(None, 2).encode(buf).unwrap();
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        // only requested rows are pulled from the stream
+        let responses = tokio::time::timeout(
+            Duration::from_secs(5),
+            send_and_receive(
+                &mut client,
+                &[&parse("SELECT n"), &bind, &execute, &execute, &sync],
+            ),
+        )
+        .await
+        .expect("suspended portal must not drain its rows");
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b's', b'D', b'D', b's', b'Z'],
+            message_types(&responses)
+        );
+        let PgWireBackendMessage::DataRow(row) = &responses[6] else {
+            panic!("expected DataRow");
+        };
+        assert_eq!(&b"\x00\x00\x00\x014"[..], &row.data[..]);
+
+        // rows borrowing the portal can't be suspended
+        let responses =
+            send_and_receive(&mut client, &[&parse("BORROWED"), &bind, &execute, &sync]).await;
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b'E', b'Z'],
+            message_types(&responses)
+        );
+        assert_eq!(Some("0A000"), error_code(&responses[4]));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_size_cursor() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(EndlessQueryHandler)).await;
+
+        // like JDBC with setFetchSize(2), each fetch is an Execute of the
+        // named portal followed by Sync
+        let parse = |buf: &mut BytesMut| {
+            Parse::new(None, "SELECT n".to_owned(), vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let bind = |buf: &mut BytesMut| {
+            Bind::new(Some("C_1".to_owned()), None, vec![], vec![], vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let fetch =
+            |buf: &mut BytesMut| Execute::new(Some("C_1".to_owned()), 2).encode(buf).unwrap();
+        let close = |buf: &mut BytesMut| {
+            Close::new(TARGET_TYPE_BYTE_PORTAL, Some("C_1".to_owned()))
+                .encode(buf)
+                .unwrap()
+        };
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        let responses = send_and_receive(&mut client, &[&parse, &bind, &fetch, &sync]).await;
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b's', b'Z'],
+            message_types(&responses)
+        );
+        for n in [3, 5] {
+            let responses = send_and_receive(&mut client, &[&fetch, &sync]).await;
+            assert_eq!(vec![b'D', b'D', b's', b'Z'], message_types(&responses));
+            let PgWireBackendMessage::DataRow(row) = &responses[0] else {
+                panic!("expected DataRow");
+            };
+            assert_eq!(n.to_string().as_bytes(), &row.data[4..]);
+        }
+
+        // the closed cursor starts over when bound again
+        let responses = send_and_receive(&mut client, &[&close, &bind, &fetch, &sync]).await;
+        assert_eq!(
+            vec![b'3', b'2', b'D', b'D', b's', b'Z'],
+            message_types(&responses)
+        );
+        let PgWireBackendMessage::DataRow(row) = &responses[2] else {
+            panic!("expected DataRow");
+        };
+        assert_eq!(b"1", &row.data[4..]);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_extended_query_pipeline() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(RowsQueryHandler)).await;
+
+        let parse = |buf: &mut BytesMut| {
+            Parse::new(None, "SELECT n".to_owned(), vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let parse_fail = |buf: &mut BytesMut| {
+            Parse::new(None, "FAIL".to_owned(), vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let bind = |buf: &mut BytesMut| {
+            Bind::new(None, None, vec![], vec![], vec![])
+                .encode(buf)
+                .unwrap()
+        };
+        let execute = |buf: &mut BytesMut| Execute::new(None, 0).encode(buf).unwrap();
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        // queries batched before a single Sync get a single ReadyForQuery
+        let responses = send_and_receive(
+            &mut client,
+            &[&parse, &bind, &execute, &parse, &bind, &execute, &sync],
+        )
+        .await;
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b'D', b'C', b'1', b'2', b'D', b'D', b'D', b'C', b'Z'],
+            message_types(&responses)
+        );
+
+        // messages after a failed query are skipped until Sync
+        let responses = send_and_receive(
+            &mut client,
+            &[&parse_fail, &bind, &execute, &parse, &bind, &execute, &sync],
+        )
+        .await;
+        assert_eq!(vec![b'1', b'2', b'E', b'Z'], message_types(&responses));
+
+        let responses = send_and_receive(&mut client, &[&parse, &bind, &execute, &sync]).await;
+        assert_eq!(
+            vec![b'1', b'2', b'D', b'D', b'D', b'C', b'Z'],
+            message_types(&responses)
+        );
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(RowsQueryHandler)).await;
+
+        let mut buf = BytesMut::new();
+        Parse::new(None, "SELECT n".to_owned(), vec![])
+            .encode(&mut buf)
+            .unwrap();
+        Describe::new(TARGET_TYPE_BYTE_STATEMENT, None)
+            .encode(&mut buf)
+            .unwrap();
+        Flush::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+
+        // responses are written on Flush, without ReadyForQuery
+        let mut buf = BytesMut::new();
+        let mut responses = Vec::new();
+        while responses.len() < 3 {
+            while let Some(msg) = PgWireBackendMessage::decode(&mut buf).unwrap() {
+                responses.push(msg);
+            }
+            if responses.len() < 3 {
+                let read = tokio::time::timeout(Duration::from_secs(5), client.read_buf(&mut buf));
+                assert!(read.await.expect("responses are flushed").unwrap() > 0);
+            }
+        }
+        assert_eq!(vec![b'1', b't', b'n'], message_types(&responses));
+        assert!(buf.is_empty());
+
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+        let responses = send_and_receive(&mut client, &[&sync]).await;
+        assert_eq!(vec![b'Z'], message_types(&responses));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deallocate() {
+        let (mut client, _, server) =
+            serve(Arc::new(DummyQueryHandler), Arc::new(RowsQueryHandler)).await;
+
+        let parse = |name: &'static str| {
+            move |buf: &mut BytesMut| {
+                Parse::new(Some(name.to_owned()), "SELECT n".to_owned(), vec![])
+                    .encode(buf)
+                    .unwrap()
+            }
+        };
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+        let responses = send_and_receive(&mut client, &[&parse("s1"), &parse("s2"), &sync]).await;
+        assert_eq!(vec![b'1', b'1', b'Z'], message_types(&responses));
+
+        let responses = simple_query(&mut client, "DEALLOCATE s1;").await;
+        let PgWireBackendMessage::CommandComplete(ref tag) = responses[0] else {
+            panic!("expect CommandComplete");
+        };
+        assert_eq!("DEALLOCATE", tag.tag);
+        let responses = simple_query(&mut client, "DEALLOCATE s1").await;
+        assert_eq!(Some("26000"), error_code(&responses[0]));
+
+        let responses = simple_query(&mut client, "DEALLOCATE ALL").await;
+        assert_eq!(vec![b'C', b'Z'], message_types(&responses));
+        let responses = simple_query(&mut client, "DEALLOCATE s2").await;
+        assert_eq!(Some("26000"), error_code(&responses[0]));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Records names of closed statements and portals
+    #[derive(Default)]
+    struct CloseQueryHandler(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl ExtendedQueryHandler for CloseQueryHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser)
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            Ok(Response::Execution(Tag::new("OK")))
+        }
+
+        async fn do_close_statement<C>(
+            &self,
+            _client: &mut C,
+            statement: &StoredStatement<Self::Statement>,
+        ) -> PgWireResult<()>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let name = format!("statement {}", statement.id);
+            self.0.lock().unwrap().push(name);
+            Ok(())
+        }
+
+        async fn do_close_portal<C>(
+            &self,
+            _client: &mut C,
+            portal: &Portal<Self::Statement>,
+        ) -> PgWireResult<()>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let name = format!("portal {}", portal.name);
+            self.0.lock().unwrap().push(name);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_hooks() {
+        let handler = Arc::new(CloseQueryHandler::default());
+        let (mut client, _, server) = serve(Arc::new(DummyQueryHandler), handler.clone()).await;
+
+        let parse = |name: &'static str| {
+            move |buf: &mut BytesMut| {
+                Parse::new(Some(name.to_owned()), "SELECT 1".to_owned(), vec![])
+                    .encode(buf)
+                    .unwrap()
+            }
+        };
+        let bind = |buf: &mut BytesMut| {
+            Bind::new(
+                Some("p1".to_owned()),
+                Some("s1".to_owned()),
+                vec![],
+                vec![],
+                vec![],
+            )
+            .encode(buf)
+            .unwrap()
+        };
+        let close = |target_type: u8, name: &'static str| {
+            move |buf: &mut BytesMut| {
+                Close::new(target_type, Some(name.to_owned()))
+                    .encode(buf)
+                    .unwrap()
+            }
+        };
+        let sync = |buf: &mut BytesMut| PgSync::new().encode(buf).unwrap();
+
+        // closing missing ones still succeeds, without calling the hooks
+        let responses = send_and_receive(
+            &mut client,
+            &[
+                &parse("s1"),
+                &parse("s2"),
+                &parse("s3"),
+                &bind,
+                &close(TARGET_TYPE_BYTE_PORTAL, "p1"),
+                &close(TARGET_TYPE_BYTE_STATEMENT, "s1"),
+                &close(TARGET_TYPE_BYTE_STATEMENT, "missing"),
+                &sync,
+            ],
+        )
+        .await;
+        assert_eq!(
+            vec![b'1', b'1', b'1', b'2', b'3', b'3', b'3', b'Z'],
+            message_types(&responses)
+        );
+        assert_eq!(
+            vec!["portal p1".to_owned(), "statement s1".to_owned()],
+            *handler.0.lock().unwrap()
+        );
+
+        simple_query(&mut client, "DEALLOCATE s2").await;
+        simple_query(&mut client, "DEALLOCATE ALL").await;
+        assert_eq!(
+            vec!["statement s2".to_owned(), "statement s3".to_owned()],
+            handler.0.lock().unwrap()[2..]
+        );
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_guc_commands() {
+        let (mut client, _, server) = serve(
+            Arc::new(DummyQueryHandler),
+            Arc::new(PlaceholderExtendedQueryHandler),
+        )
+        .await;
+
+        let responses = simple_query(&mut client, "SHOW search_path").await;
+        assert_eq!(vec![b'T', b'D', b'C', b'Z'], message_types(&responses));
+
+        let responses = simple_query(&mut client, "SET search_path TO my_schema").await;
+        let PgWireBackendMessage::CommandComplete(ref tag) = responses[0] else {
+            panic!("expect CommandComplete");
+        };
+        assert_eq!("SET", tag.tag);
+
+        let responses = simple_query(&mut client, "SHOW search_path").await;
+        let PgWireBackendMessage::DataRow(ref row) = responses[1] else {
+            panic!("expect DataRow");
+        };
+        assert!(row.data.ends_with(b"my_schema"));
+
+        // reported parameter
+        let responses = simple_query(&mut client, "SET application_name = 'app'").await;
+        assert_eq!(vec![b'C', b'S', b'Z'], message_types(&responses));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Fails query `FAIL`, and completes others
+    struct FailingQueryHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for FailingQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            if query == "FAIL" {
+                Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42P01".to_owned(),
+                    "relation does not exist".to_owned(),
+                ))))
+            } else {
+                Ok(vec![Response::Execution(Tag::new("OK"))])
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_status() {
+        let (mut client, _, server) = serve(
+            Arc::new(FailingQueryHandler),
+            Arc::new(PlaceholderExtendedQueryHandler),
+        )
+        .await;
+
+        let mut ready_status = Vec::new();
+        for query in [
+            "BEGIN",
+            "SAVEPOINT sp",
+            "FAIL",
+            "SELECT 1",
+            "ROLLBACK TO SAVEPOINT sp",
+            "COMMIT;",
+            "FAIL",
+        ] {
+            let responses = simple_query(&mut client, query).await;
+            let Some(PgWireBackendMessage::ReadyForQuery(ready)) = responses.last() else {
+                panic!("expect ReadyForQuery");
+            };
+            ready_status.push(ready.status);
+        }
+        assert_eq!(vec![b'T', b'T', b'E', b'E', b'T', b'I', b'I'], ready_status);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Reports changed `TimeZone` on `set_config`
+    struct TimeZoneQueryHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for TimeZoneQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            if query.contains("set_config") {
+                client.push_parameter_status("TimeZone", "UTC");
+            }
+            Ok(vec![Response::Execution(Tag::new("SELECT").with_rows(1))])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_parameter_status() {
+        let (mut client, _, server) = serve(
+            Arc::new(TimeZoneQueryHandler),
+            Arc::new(PlaceholderExtendedQueryHandler),
+        )
+        .await;
+
+        // sent after the response, before ReadyForQuery
+        let responses =
+            simple_query(&mut client, "SELECT set_config('TimeZone', 'UTC', false)").await;
+        assert_eq!(vec![b'C', b'S', b'Z'], message_types(&responses));
+        assert!(matches!(
+            &responses[1],
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "TimeZone" && status.value == "UTC"
+        ));
+
+        // sent once
+        let responses = simple_query(&mut client, "SELECT 1").await;
+        assert_eq!(vec![b'C', b'Z'], message_types(&responses));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[derive(Default)]
+    struct CancelStartupHandler(Mutex<Vec<CancelRequest>>);
+
+    #[async_trait]
+    impl StartupHandler for CancelStartupHandler {
+        async fn on_startup<C>(
+            &self,
+            _client: &mut C,
+            message: PgWireFrontendMessage,
+        ) -> PgWireResult<()>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Err(PgWireError::unexpected_message("CancelRequest", &message))
+        }
+
+        async fn on_cancel_request(&self, request: CancelRequest) {
+            self.0.lock().unwrap().push(request);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(CancelStartupHandler::default());
+
+        let startup_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket(
+                socket,
+                None,
+                startup_handler,
+                Arc::new(DummyQueryHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        CancelRequest::new(42, Bytes::from_static(&[0, 0, 4, 210]))
+            .encode(&mut buf)
+            .unwrap();
+        client.write_all(&buf).await.unwrap();
+
+        // closed without response
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        server.await.unwrap().unwrap();
+        assert!(received.is_empty());
+        assert_eq!(
+            vec![CancelRequest::new(42, Bytes::from_static(&[0, 0, 4, 210]))],
+            *handler.0.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_response() {
+        let mut pipeline = PipelineResponse::new();
+        assert!(pipeline.is_empty());
+
+        pipeline
+            .add(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
+            .unwrap();
+        pipeline
+            .add(PgWireBackendMessage::BindComplete(BindComplete::new()))
+            .unwrap();
+        pipeline
+            .add(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                READY_STATUS_IDLE,
+            )))
+            .unwrap();
+        assert_eq!(16, pipeline.len());
+
+        let mut out = Vec::new();
+        pipeline.flush(&mut out).await.unwrap();
+        assert_eq!(
+            out,
+            [b'1', 0, 0, 0, 4, b'2', 0, 0, 0, 4, b'Z', 0, 0, 0, 5, b'I']
+        );
+    }
+
+    /// Writer accepting at most 3 bytes per call
+    #[derive(Default)]
+    struct SlowWriter(Vec<u8>);
+
+    impl AsyncWrite for SlowWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<Result<usize, IOError>> {
+            let n = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..n]);
+            std::task::Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), IOError>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), IOError>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vectored_response_encoder() {
+        let messages = || {
+            vec![
+                PgWireBackendMessage::BindComplete(BindComplete::new()),
+                PgWireBackendMessage::DataRow(DataRow::new(
+                    BytesMut::from(&[0, 0, 0, 1, b'a', 255, 255, 255, 255][..]),
+                    2,
+                )),
+                PgWireBackendMessage::DataRow(DataRow::new(BytesMut::new(), 0)),
+                PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(READY_STATUS_IDLE)),
+            ]
+        };
+
+        let mut pipeline = PipelineResponse::new();
+        let mut encoder = VectoredResponseEncoder::new();
+        assert!(encoder.is_empty());
+        for msg in messages() {
+            pipeline.add(msg).unwrap();
+        }
+        for msg in messages() {
+            encoder.add(msg).unwrap();
+        }
+        pipeline
+            .add(PgWireBackendMessage::NoticeResponse(NoticeResponse::new(
+                vec![],
+            )))
+            .unwrap();
+        encoder.add_raw(b'N', Bytes::from_static(&[0]));
+        assert_eq!(pipeline.len(), encoder.len());
+        // data of the first row and the raw body are separate slices,
+        // encoded headers between them are merged
+        assert_eq!(4, encoder.segments.len());
+
+        let mut expected = Vec::new();
+        pipeline.flush(&mut expected).await.unwrap();
+
+        let mut out = Vec::new();
+        encoder.flush(&mut out).await.unwrap();
+        assert_eq!(expected, out);
+
+        // partial writes
+        let mut writer = SlowWriter::default();
+        encoder.flush(&mut writer).await.unwrap();
+        assert_eq!(expected, writer.0);
+    }
+
+    /// Security of the connection seen by the handler
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    struct TlsInfo {
+        secure: bool,
+        certificates: Option<usize>,
+        server_name: Option<String>,
+        alpn_protocol: Option<Vec<u8>>,
+    }
+
+    /// Records security of the connection seen by the handler
+    #[derive(Default)]
+    struct TlsQueryHandler(Mutex<Option<TlsInfo>>);
+
+    #[async_trait]
+    impl SimpleQueryHandler for TlsQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            *self.0.lock().unwrap() = Some(TlsInfo {
+                secure: client.is_secure(),
+                certificates: client.client_certificates().map(|certs| certs.len()),
+                server_name: client.server_name().map(str::to_owned),
+                alpn_protocol: client.alpn_protocol().map(<[u8]>::to_vec),
+            });
+            Ok(vec![])
+        }
+    }
+
+    fn load_certs(pem: &str) -> Vec<rustls_pki_types::CertificateDer<'static>> {
+        rustls_pemfile::certs(&mut pem.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn load_key(pem: &str) -> rustls_pki_types::PrivateKeyDer<'static> {
+        rustls_pemfile::private_key(&mut pem.as_bytes())
+            .unwrap()
+            .unwrap()
+    }
+
+    const SERVER_CERT: &str = include_str!("../tests/ssl/server.crt");
+    const SERVER_KEY: &str = include_str!("../tests/ssl/server.key");
+    const SERVER2_CERT: &str = include_str!("../tests/ssl/server2.crt");
+    const SERVER2_KEY: &str = include_str!("../tests/ssl/server2.key");
+    const CLIENT_CERT: &str = include_str!("../tests/ssl/client.crt");
+    const CLIENT_KEY: &str = include_str!("../tests/ssl/client.key");
+
+    fn tls_acceptor() -> TlsAcceptor {
+        TlsAcceptor::from(server_config(SERVER_CERT, SERVER_KEY))
+    }
+
+    fn server_config(cert: &str, key: &str) -> Arc<rustls::ServerConfig> {
+        let mut client_roots = rustls::RootCertStore::empty();
+        client_roots.add_parsable_certificates(load_certs(CLIENT_CERT));
+        let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(client_roots))
+            .allow_unauthenticated()
+            .build()
+            .unwrap();
+        let mut config = rustls::ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(load_certs(cert), load_key(key))
+            .unwrap();
+        config.alpn_protocols = vec![POSTGRESQL_ALPN_PROTOCOL.to_vec()];
+        Arc::new(config)
+    }
+
+    /// How a test client connects to server
+    #[derive(Debug, Default, Clone, Copy)]
+    struct TlsSession {
+        /// Server has a TLS acceptor
+        tls: bool,
+        /// Start TLS handshake without `SSLRequest`
+        direct: bool,
+        /// Client presents a certificate
+        client_cert: bool,
+        /// Client requests `postgresql` protocol with ALPN
+        alpn: bool,
+    }
+
+    /// Connect with `SSLRequest` or direct TLS, returns the TLS stream if
+    /// server accepts it, or the plain stream otherwise.
+    async fn connect_tls(
+        addr: std::net::SocketAddr,
+        session: TlsSession,
+    ) -> Result<tokio_rustls::client::TlsStream<TcpStream>, TcpStream> {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        if !session.direct {
+            let mut buf = BytesMut::new();
+            buf.put_i32(SslRequest::BODY_SIZE as i32);
+            buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+            client.write_all(&buf).await.unwrap();
+            if client.read_u8().await.unwrap() != b'S' {
+                return Err(client);
+            }
+        }
+
+        let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        Ok(tls_connector(SERVER_CERT, session)
+            .connect(server_name, client)
+            .await
+            .unwrap())
+    }
+
+    /// Client trusting `server_cert`
+    fn tls_connector(server_cert: &str, session: TlsSession) -> tokio_rustls::TlsConnector {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_parsable_certificates(load_certs(server_cert));
+        let config = rustls::ClientConfig::builder().with_root_certificates(roots);
+        let mut config = if session.client_cert {
+            config
+                .with_client_auth_cert(load_certs(CLIENT_CERT), load_key(CLIENT_KEY))
+                .unwrap()
+        } else {
+            config.with_no_client_auth()
+        };
+        if session.alpn {
+            config.alpn_protocols = vec![POSTGRESQL_ALPN_PROTOCOL.to_vec()];
+        }
+        tokio_rustls::TlsConnector::from(Arc::new(config))
+    }
+
+    /// Run a query over a connection started with TLS negotiation. Returns
+    /// whether TLS is accepted, and security of the connection seen by the
+    /// handler.
+    async fn run_tls_session(session: TlsSession) -> (bool, TlsInfo) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(TlsQueryHandler::default());
+
+        let query_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                direct_tls: session.direct,
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                session.tls.then(|| Arc::new(tls_acceptor())),
+                Arc::new(NoopStartupHandler),
+                query_handler,
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let query = Query::new("SELECT 1".to_owned());
+        let query = |buf: &mut BytesMut| query.encode(buf).unwrap();
+
+        let accepted = match connect_tls(addr, session).await {
+            Ok(mut client) => {
+                send_and_receive(&mut client, &[&startup]).await;
+                send_and_receive(&mut client, &[&query]).await;
+                client.shutdown().await.unwrap();
+                true
+            }
+            Err(mut client) => {
+                send_and_receive(&mut client, &[&startup]).await;
+                send_and_receive(&mut client, &[&query]).await;
+                false
+            }
+        };
+        server.await.unwrap().unwrap();
+
+        let info = handler.0.lock().unwrap().take().expect("query is handled");
+        (accepted, info)
+    }
+
+    /// Security of a TLS connection from test client
+    fn tls_info(certificates: Option<usize>, alpn: bool) -> TlsInfo {
+        TlsInfo {
+            secure: true,
+            certificates,
+            server_name: Some("localhost".to_owned()),
+            alpn_protocol: alpn.then(|| POSTGRESQL_ALPN_PROTOCOL.to_vec()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let session = TlsSession {
+            tls: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            (true, tls_info(None, false)),
+            run_tls_session(session).await
+        );
+
+        let session = TlsSession {
+            tls: true,
+            client_cert: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            (true, tls_info(Some(1), false)),
+            run_tls_session(session).await
+        );
+
+        let session = TlsSession::default();
+        assert_eq!((false, TlsInfo::default()), run_tls_session(session).await);
+    }
+
+    #[tokio::test]
+    async fn test_direct_tls() {
+        let session = TlsSession {
+            tls: true,
+            direct: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            (true, tls_info(None, false)),
+            run_tls_session(session).await
+        );
+
+        let session = TlsSession {
+            tls: true,
+            direct: true,
+            client_cert: true,
+            alpn: true,
+        };
+        assert_eq!(
+            (true, tls_info(Some(1), true)),
+            run_tls_session(session).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tls_alpn() {
+        let session = TlsSession {
+            tls: true,
+            alpn: true,
+            ..Default::default()
+        };
+        assert_eq!((true, tls_info(None, true)), run_tls_session(session).await);
+    }
+
+    /// Accepts TLS without encryption, to test with other implementations
+    /// than rustls.
+    struct NullTlsAcceptor;
+
+    #[async_trait]
+    impl TlsAccept for NullTlsAcceptor {
+        type Stream = TcpStream;
+
+        async fn accept(&self, socket: TcpStream) -> Result<Self::Stream, IOError> {
+            Ok(socket)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_tls_acceptor() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(TlsQueryHandler::default());
+
+        let query_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket_with_tls(
+                socket,
+                Some(Arc::new(NullTlsAcceptor)),
+                Arc::new(NoopStartupHandler),
+                query_handler,
+                Arc::new(PlaceholderExtendedQueryHandler),
+                ProcessSocketOptions::default(),
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        buf.put_i32(SslRequest::BODY_SIZE as i32);
+        buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(b'S', client.read_u8().await.unwrap());
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+        simple_query(&mut client, "SELECT 1").await;
+        drop(client);
+        server.await.unwrap().unwrap();
+
+        let info = TlsInfo {
+            secure: true,
+            ..Default::default()
+        };
+        assert_eq!(Some(info), *handler.0.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reload_tls_config() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = Arc::new(ReloadableTlsAcceptor::new(server_config(
+            SERVER_CERT,
+            SERVER_KEY,
+        )));
+
+        let server_acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            for _ in 0..3 {
+                let (socket, _) = listener.accept().await.unwrap();
+                let acceptor = server_acceptor.clone();
+                tokio::spawn(process_socket_with_tls(
+                    socket,
+                    Some(acceptor),
+                    Arc::new(NoopStartupHandler),
+                    Arc::new(DummyQueryHandler),
+                    Arc::new(PlaceholderExtendedQueryHandler),
+                    ProcessSocketOptions::default(),
+                ));
+            }
+        });
+
+        // handshake with a client trusting `server_cert` only
+        let handshake = |server_cert: &'static str| async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let mut buf = BytesMut::new();
+            buf.put_i32(SslRequest::BODY_SIZE as i32);
+            buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+            client.write_all(&buf).await.unwrap();
+            assert_eq!(b'S', client.read_u8().await.unwrap());
+            let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+            tls_connector(server_cert, TlsSession::default())
+                .connect(server_name, client)
+                .await
+        };
+
+        let established = handshake(SERVER_CERT).await.unwrap();
+        acceptor.reload(server_config(SERVER2_CERT, SERVER2_KEY));
+        assert!(handshake(SERVER_CERT).await.is_err());
+        handshake(SERVER2_CERT).await.unwrap();
+
+        // established connection still works after reload
+        let mut established = established;
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let responses = send_and_receive(&mut established, &[&startup]).await;
+        assert!(matches!(
+            responses.last(),
+            Some(PgWireBackendMessage::ReadyForQuery(_))
+        ));
+        server.await.unwrap();
+    }
+
+    /// Records peer credentials of client seen by the handler
+    #[cfg(target_os = "linux")]
+    #[derive(Default)]
+    struct PeerQueryHandler(Mutex<Option<PeerCredentials>>);
+
+    #[cfg(target_os = "linux")]
+    #[async_trait]
+    impl SimpleQueryHandler for PeerQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            *self.0.lock().unwrap() = client.peer_credentials();
+            Ok(vec![])
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (mut client, server_socket) = UnixStream::pair().unwrap();
+        let handler = Arc::new(PeerQueryHandler::default());
+        let server = tokio::spawn(process_unix_socket(
+            server_socket,
+            Arc::new(NoopStartupHandler),
+            handler.clone(),
+            Arc::new(PlaceholderExtendedQueryHandler),
+        ));
+
+        // TLS is refused
+        let mut buf = BytesMut::new();
+        buf.put_i32(SslRequest::BODY_SIZE as i32);
+        buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(b'N', client.read_u8().await.unwrap());
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let query = Query::new("SELECT 1".to_owned());
+        let query = |buf: &mut BytesMut| query.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+        send_and_receive(&mut client, &[&query]).await;
+        drop(client);
+        server.await.unwrap().unwrap();
+
+        let process = std::fs::metadata("/proc/self").unwrap();
+        let expected = PeerCredentials::new(
+            process.uid(),
+            process.gid(),
+            Some(std::process::id() as i32),
+        );
+        assert_eq!(Some(expected), *handler.0.lock().unwrap());
+    }
+
+    /// Records socket address of client seen by the handler
+    #[derive(Default)]
+    struct AddrQueryHandler(Mutex<Option<std::net::SocketAddr>>);
+
+    #[async_trait]
+    impl SimpleQueryHandler for AddrQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            *self.0.lock().unwrap() = Some(client.socket_addr());
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(AddrQueryHandler::default());
+
+        let query_handler = handler.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let options = ProcessSocketOptions {
+                proxy_protocol: true,
+                ..Default::default()
+            };
+            process_socket_with_options(
+                socket,
+                Some(Arc::new(tls_acceptor())),
+                Arc::new(NoopStartupHandler),
+                query_handler,
+                Arc::new(PlaceholderExtendedQueryHandler),
+                options,
+            )
+            .await
+        });
+
+        // the header comes before SSLRequest
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        buf.put_slice(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 5432\r\n");
+        buf.put_i32(SslRequest::BODY_SIZE as i32);
+        buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(b'S', client.read_u8().await.unwrap());
+        let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        let mut client = tls_connector(SERVER_CERT, TlsSession::default())
+            .connect(server_name, client)
+            .await
+            .unwrap();
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let query = Query::new("SELECT 1".to_owned());
+        let query = |buf: &mut BytesMut| query.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+        send_and_receive(&mut client, &[&query]).await;
+        client.shutdown().await.unwrap();
+        server.await.unwrap().unwrap();
+
+        assert_eq!(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            *handler.0.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_stream() {
+        let (mut client, server_stream) = tokio::io::duplex(1024);
+        let handler = Arc::new(AddrQueryHandler::default());
+        let addr: std::net::SocketAddr = "10.0.0.1:5432".parse().unwrap();
+        let server = tokio::spawn(process_stream(
+            server_stream,
+            addr,
+            false,
+            Arc::new(NoopStartupHandler),
+            handler.clone(),
+            Arc::new(PlaceholderExtendedQueryHandler),
+        ));
+
+        // TLS is refused
+        let mut buf = BytesMut::new();
+        buf.put_i32(SslRequest::BODY_SIZE as i32);
+        buf.put_i32(SslRequest::BODY_MAGIC_NUMBER);
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(b'N', client.read_u8().await.unwrap());
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "pgwire".to_owned());
+        let startup = move |buf: &mut BytesMut| startup.encode(buf).unwrap();
+        let query = Query::new("SELECT 1".to_owned());
+        let query = |buf: &mut BytesMut| query.encode(buf).unwrap();
+        send_and_receive(&mut client, &[&startup]).await;
+        send_and_receive(&mut client, &[&query]).await;
+        drop(client);
+        server.await.unwrap().unwrap();
+
+        assert_eq!(Some(addr), *handler.0.lock().unwrap());
+    }
 }
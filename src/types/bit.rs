@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use bytes::{Buf, BufMut, BytesMut};
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Bit string value of postgres `bit(n)` and `varbit(n)` types.
+///
+/// Bits are packed into bytes, most significant bit first. The final byte is
+/// right-padded with zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PgBit {
+    bits: Vec<u8>,
+    len: u32,
+}
+
+impl PgBit {
+    /// Create bit string from a slice of booleans.
+    pub fn from_bits(bits: &[bool]) -> Self {
+        let mut packed = vec![0u8; bytes_for(bits.len() as u32)];
+        for (idx, bit) in bits.iter().enumerate() {
+            if *bit {
+                packed[idx / 8] |= 0x80 >> (idx % 8);
+            }
+        }
+
+        PgBit {
+            bits: packed,
+            len: bits.len() as u32,
+        }
+    }
+
+    /// Create bit string of `len` bits from packed bytes.
+    ///
+    /// Missing bytes are treated as zeros, extra bytes and padding bits beyond
+    /// `len` are dropped.
+    pub fn from_bytes(bytes: &[u8], len: u32) -> Self {
+        let mut packed = vec![0u8; bytes_for(len)];
+        let n = packed.len().min(bytes.len());
+        packed[..n].copy_from_slice(&bytes[..n]);
+
+        let rem = len % 8;
+        if rem != 0 {
+            if let Some(last) = packed.last_mut() {
+                *last &= 0xffu8 << (8 - rem);
+            }
+        }
+
+        PgBit { bits: packed, len }
+    }
+
+    /// Get bit at `idx`.
+    ///
+    /// Panics if `idx` is out of bounds.
+    pub fn get_bit(&self, idx: u32) -> bool {
+        assert!(
+            idx < self.len,
+            "bit index {idx} out of bounds for length {}",
+            self.len
+        );
+        self.bits[(idx / 8) as usize] & (0x80 >> (idx % 8)) != 0
+    }
+
+    /// Number of bits
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Test if the bit string has no bits
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get packed bytes of the bit string
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+fn bytes_for(len: u32) -> usize {
+    ((len + 7) / 8) as usize
+}
+
+impl fmt::Display for PgBit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for idx in 0..self.len {
+            f.write_str(if self.get_bit(idx) { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PgBit {
+    type Err = Box<dyn Error + Sync + Send>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bits = s
+            .chars()
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(format!("\"{c}\" is not a valid binary digit")),
+            })
+            .collect::<Result<Vec<bool>, String>>()?;
+        Ok(PgBit::from_bits(&bits))
+    }
+}
+
+impl ToSql for PgBit {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i32(self.len as i32);
+        out.put_slice(&self.bits);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        matches!(*ty, Type::BIT | Type::VARBIT)
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgBit {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.remaining() < 4 {
+            return Err("invalid bit string: missing length".into());
+        }
+        let len = raw.get_i32();
+        if len < 0 {
+            return Err("invalid bit string: negative length".into());
+        }
+        let len = len as u32;
+        if raw.len() != bytes_for(len) {
+            return Err("invalid bit string: length mismatch".into());
+        }
+
+        Ok(PgBit::from_bytes(raw, len))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::BIT | Type::VARBIT)
+    }
+}
+
+impl ToSqlText for PgBit {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bit_binary_roundtrip() {
+        let bits = PgBit::from_bits(&[true, false, true, true, false, false, false, false, true]);
+        assert_eq!(9, bits.len());
+        assert_eq!(&[0b1011_0000, 0b1000_0000], bits.as_bytes());
+
+        let mut buf = BytesMut::new();
+        bits.to_sql(&Type::VARBIT, &mut buf).unwrap();
+        assert_eq!(&[0, 0, 0, 9, 0b1011_0000, 0b1000_0000], buf.as_ref());
+
+        let decoded = PgBit::from_sql(&Type::VARBIT, &buf).unwrap();
+        assert_eq!(bits, decoded);
+        assert!(decoded.get_bit(8));
+        assert!(!decoded.get_bit(7));
+
+        assert!(PgBit::from_sql(&Type::BIT, &[0, 0, 0, 9, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_bit_text() {
+        let bits = PgBit::from_bytes(&[0xff, 0xff], 10);
+        let mut buf = BytesMut::new();
+        bits.to_sql_text(&Type::BIT, &mut buf).unwrap();
+        assert_eq!("1111111111", String::from_utf8_lossy(buf.as_ref()));
+
+        assert_eq!(bits, "1111111111".parse::<PgBit>().unwrap());
+        assert!("10x".parse::<PgBit>().is_err());
+    }
+}
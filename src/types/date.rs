@@ -0,0 +1,325 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use bytes::{Buf, BufMut, BytesMut};
+use chrono::{Duration, NaiveDate};
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Julian day of postgres epoch 2000-01-01
+const POSTGRES_EPOCH_JDATE: i32 = 2_451_545;
+/// Julian day of 5874898-01-01, the first date out of postgres's range
+const JULIAN_MAX: i64 = 2_147_483_494;
+/// Days of a 400 year cycle of the Gregorian calendar
+const DAYS_PER_CYCLE: i64 = 146_097;
+
+/// Value of postgres `date` type, as days since 2000-01-01.
+///
+/// Dates use the proleptic Gregorian calendar like postgres does. In text
+/// format there is no year 0: the year before `0001` is `0001 BC`, which is
+/// year 0 in astronomical numbering of `NaiveDate`.
+///
+/// Unlike `NaiveDate`, `infinity` and `-infinity` can be represented.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PgDate(pub i32);
+
+impl PgDate {
+    pub const INFINITY: PgDate = PgDate(i32::MAX);
+    pub const NEG_INFINITY: PgDate = PgDate(i32::MIN);
+
+    /// Create date from Julian day number, where day 0 is 4714-11-24 BC.
+    pub fn from_julian(julian_day: i32) -> PgDate {
+        PgDate(julian_day.saturating_sub(POSTGRES_EPOCH_JDATE))
+    }
+
+    /// Julian day number of this date.
+    pub fn to_julian(&self) -> i32 {
+        self.0.saturating_add(POSTGRES_EPOCH_JDATE)
+    }
+
+    /// Test if the date is neither `infinity` nor `-infinity`
+    pub fn is_finite(&self) -> bool {
+        *self != PgDate::INFINITY && *self != PgDate::NEG_INFINITY
+    }
+}
+
+/// Julian day of a date of the proleptic Gregorian calendar, with year in
+/// astronomical numbering.
+///
+/// Same algorithm as `date2j` of postgres.
+fn date_to_julian(year: i64, month: u32, day: u32) -> i64 {
+    let (y, m) = if month > 2 {
+        (year + 4800, month as i64 + 1)
+    } else {
+        (year + 4799, month as i64 + 13)
+    };
+    let century = y.div_euclid(100);
+    y * 365 - 32167 + y.div_euclid(4) - century
+        + century.div_euclid(4)
+        + 7834 * m / 256
+        + day as i64
+}
+
+/// Year, month and day of a Julian day, reverse of `date_to_julian`.
+///
+/// Same algorithm as `j2date` of postgres, extended to negative Julian days
+/// by shifting whole 400 year cycles.
+fn julian_to_date(julian_day: i64) -> (i64, u32, u32) {
+    let cycles = if julian_day < 0 {
+        julian_day.div_euclid(DAYS_PER_CYCLE)
+    } else {
+        0
+    };
+
+    let mut julian = julian_day - cycles * DAYS_PER_CYCLE + 32044;
+    let mut quad = julian / DAYS_PER_CYCLE;
+    let extra = (julian - quad * DAYS_PER_CYCLE) * 4 + 3;
+    julian += 60 + quad * 3 + extra / DAYS_PER_CYCLE;
+    quad = julian / 1461;
+    julian -= quad * 1461;
+    let mut y = julian * 4 / 1461;
+    julian = if y != 0 {
+        (julian + 305) % 365
+    } else {
+        (julian + 306) % 366
+    } + 123;
+    y += quad * 4;
+    let year = y - 4800 + cycles * 400;
+    quad = julian * 2141 / 65536;
+    let day = julian - 7834 * quad / 256;
+    let month = (quad + 10) % 12 + 1;
+    (year, month as u32, day as u32)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn postgres_epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+}
+
+impl From<NaiveDate> for PgDate {
+    fn from(date: NaiveDate) -> PgDate {
+        // all dates of chrono are within range of i32 days
+        PgDate((date - postgres_epoch()).num_days() as i32)
+    }
+}
+
+impl TryFrom<PgDate> for NaiveDate {
+    type Error = Box<dyn Error + Sync + Send>;
+
+    fn try_from(date: PgDate) -> Result<Self, Self::Error> {
+        if !date.is_finite() {
+            return Err(format!("{date} cannot be represented as NaiveDate").into());
+        }
+        postgres_epoch()
+            .checked_add_signed(Duration::days(date.0 as i64))
+            .ok_or_else(|| format!("date out of range: {} days", date.0).into())
+    }
+}
+
+impl fmt::Display for PgDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == PgDate::INFINITY {
+            return f.write_str("infinity");
+        }
+        if *self == PgDate::NEG_INFINITY {
+            return f.write_str("-infinity");
+        }
+
+        let (year, month, day) = julian_to_date(self.0 as i64 + POSTGRES_EPOCH_JDATE as i64);
+        if year > 0 {
+            write!(f, "{:04}-{:02}-{:02}", year, month, day)
+        } else {
+            write!(f, "{:04}-{:02}-{:02} BC", 1 - year, month, day)
+        }
+    }
+}
+
+impl FromStr for PgDate {
+    type Err = Box<dyn Error + Sync + Send>;
+
+    /// Parse date in ISO format, with optional ` BC` suffix. Dates out of
+    /// postgres's range, from 4714-11-24 BC to 5874897-12-31, are rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("infinity") {
+            return Ok(PgDate::INFINITY);
+        }
+        if s.eq_ignore_ascii_case("-infinity") {
+            return Ok(PgDate::NEG_INFINITY);
+        }
+
+        let invalid = || format!("invalid date: {s:?}");
+        let suffix_start = s.len().saturating_sub(3);
+        let (ymd, bc) = match (s.get(..suffix_start), s.get(suffix_start..)) {
+            (Some(ymd), Some(suffix)) if suffix.eq_ignore_ascii_case(" bc") => (ymd, true),
+            _ => (s, false),
+        };
+        let mut parts = ymd.splitn(3, '-');
+        let mut next = || -> Result<u32, String> {
+            parts
+                .next()
+                .and_then(|p| p.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let (year, month, day) = (next()?, next()?, next()?);
+        // there is no year 0 in text format
+        if year == 0 {
+            return Err(invalid().into());
+        }
+        let year = if bc { 1 - year as i64 } else { year as i64 };
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(invalid().into());
+        }
+
+        let julian_day = date_to_julian(year, month, day);
+        if !(0..JULIAN_MAX).contains(&julian_day) {
+            return Err(format!("date out of range: {s:?}").into());
+        }
+        Ok(PgDate::from_julian(julian_day as i32))
+    }
+}
+
+impl ToSql for PgDate {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i32(self.0);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        *ty == Type::DATE
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgDate {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 4 {
+            return Err("invalid date: expected 4 bytes".into());
+        }
+        Ok(PgDate(raw.get_i32()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+}
+
+impl ToSqlText for PgDate {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Datelike;
+
+    use super::*;
+
+    #[test]
+    fn test_date_from_naive_date() {
+        let date = PgDate::from(NaiveDate::from_ymd_opt(1, 1, 1).unwrap());
+        assert_eq!(PgDate(-730119), date);
+        assert_eq!("0001-01-01", date.to_string());
+
+        // year 0 of chrono is 1 BC
+        let date = PgDate::from(NaiveDate::from_ymd_opt(0, 12, 31).unwrap());
+        assert_eq!(PgDate(-730120), date);
+        assert_eq!("0001-12-31 BC", date.to_string());
+
+        assert_eq!(
+            NaiveDate::from_ymd_opt(0, 12, 31).unwrap(),
+            NaiveDate::try_from(date).unwrap()
+        );
+        assert!(NaiveDate::try_from(PgDate::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_julian_day() {
+        assert_eq!(2451545, PgDate(0).to_julian());
+        assert_eq!(PgDate(-10957), PgDate::from_julian(2440588));
+
+        let julian_zero = PgDate::from_julian(0);
+        assert_eq!(PgDate(-2451545), julian_zero);
+        assert_eq!("4714-11-24 BC", julian_zero.to_string());
+        assert_eq!(0, julian_zero.to_julian());
+    }
+
+    #[test]
+    fn test_date_text() {
+        for (s, days) in [
+            ("2000-01-01", 0),
+            ("0001-01-01", -730119),
+            ("0001-12-31 BC", -730120),
+            ("4714-11-24 BC", -2451545),
+            ("5874897-12-31", 2145031948),
+        ] {
+            let date = s.parse::<PgDate>().unwrap();
+            assert_eq!(PgDate(days), date);
+
+            let mut buf = BytesMut::new();
+            date.to_sql_text(&Type::DATE, &mut buf).unwrap();
+            assert_eq!(s, String::from_utf8_lossy(buf.as_ref()));
+        }
+
+        assert_eq!(PgDate::INFINITY, "infinity".parse().unwrap());
+        assert_eq!("-infinity", PgDate::NEG_INFINITY.to_string());
+        assert!("0000-01-01".parse::<PgDate>().is_err());
+        assert!("0000-01-01 BC".parse::<PgDate>().is_err());
+        assert!("2000-13-01".parse::<PgDate>().is_err());
+        assert!("2023-02-29".parse::<PgDate>().is_err());
+        assert!("4714-11-23 BC".parse::<PgDate>().is_err());
+        assert!("5874898-01-01".parse::<PgDate>().is_err());
+    }
+
+    #[test]
+    fn test_julian_to_date() {
+        // matches chrono for all dates it supports
+        let mut date = NaiveDate::from_ymd_opt(-5000, 1, 1).unwrap();
+        while date.year() < 2500 {
+            let julian_day = PgDate::from(date).to_julian() as i64;
+            let ymd = (date.year() as i64, date.month(), date.day());
+            assert_eq!(ymd, julian_to_date(julian_day));
+            assert_eq!(julian_day, date_to_julian(ymd.0, ymd.1, ymd.2));
+            date += Duration::days(13);
+        }
+    }
+
+    #[test]
+    fn test_date_binary() {
+        let date = "0044-03-15 BC".parse::<PgDate>().unwrap();
+        let mut buf = BytesMut::new();
+        date.to_sql(&Type::DATE, &mut buf).unwrap();
+        assert_eq!(date.0.to_be_bytes(), buf.as_ref());
+
+        let decoded = PgDate::from_sql(&Type::DATE, &buf).unwrap();
+        assert_eq!(date, decoded);
+        assert_eq!("0044-03-15 BC", decoded.to_string());
+
+        // binary format of postgres-types for NaiveDate is the same
+        let naive = NaiveDate::from_sql(&Type::DATE, &buf).unwrap();
+        assert_eq!(date, PgDate::from(naive));
+    }
+}
@@ -0,0 +1,368 @@
+use std::error::Error;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+use crate::api::ClientInfo;
+
+/// Session parameter holding the date style
+pub const PARAMETER_DATE_STYLE: &str = "DateStyle";
+
+/// Order of day, month and year fields in ambiguous date input, the second
+/// part of `DateStyle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateOrder {
+    /// month-day-year, postgres default
+    #[default]
+    Mdy,
+    /// day-month-year
+    Dmy,
+    /// year-month-day
+    Ymd,
+}
+
+/// Parser of text format date and time values according to `DateStyle`.
+///
+/// ISO 8601 input is always accepted. Numeric dates like `01/02/2023` or
+/// `01.02.2023` are interpreted with the configured `DateOrder`, and the
+/// `Postgres` output style like `Wed Dec 17 07:37:16 1997` is also supported.
+/// Time zones can be given as `UTC`, `GMT`, `Z` or numeric offsets like
+/// `+08:00`, time zone names and abbreviations are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, new)]
+pub struct DateStyleParser {
+    order: DateOrder,
+}
+
+impl DateStyleParser {
+    /// Create parser from value of `DateStyle`, for example `ISO, DMY` or
+    /// `German`.
+    ///
+    /// Unknown parts are ignored. Like postgres, `German` implies `DMY` unless
+    /// order is specified explicitly.
+    pub fn from_date_style(date_style: &str) -> DateStyleParser {
+        let mut order = None;
+        let mut default_order = DateOrder::default();
+        for part in date_style
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+        {
+            match part.to_ascii_uppercase().as_str() {
+                "MDY" | "US" | "NONEURO" | "NONEUROPEAN" => order = Some(DateOrder::Mdy),
+                "DMY" | "EURO" | "EUROPEAN" => order = Some(DateOrder::Dmy),
+                "YMD" => order = Some(DateOrder::Ymd),
+                "GERMAN" => default_order = DateOrder::Dmy,
+                _ => {}
+            }
+        }
+
+        DateStyleParser::new(order.unwrap_or(default_order))
+    }
+
+    /// Create parser from `DateStyle` of client session, falls back to
+    /// default `MDY` order when it's not set.
+    pub fn from_client<C: ClientInfo>(client: &C) -> DateStyleParser {
+        client
+            .metadata()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(PARAMETER_DATE_STYLE))
+            .map(|(_, v)| DateStyleParser::from_date_style(v))
+            .unwrap_or_default()
+    }
+
+    pub fn order(&self) -> DateOrder {
+        self.order
+    }
+
+    /// Parse text value of `date`
+    pub fn parse_date(&self, s: &str) -> Result<NaiveDate, Box<dyn Error + Sync + Send>> {
+        let (date, time, tz) = self.split(s)?;
+        if time.is_some() || tz.is_some() {
+            return Err(format!("invalid date: {s}").into());
+        }
+        Ok(date)
+    }
+
+    /// Parse text value of `timestamp`, time zone in input is ignored like
+    /// postgres does.
+    pub fn parse_timestamp(&self, s: &str) -> Result<NaiveDateTime, Box<dyn Error + Sync + Send>> {
+        let (date, time, _) = self.split(s)?;
+        Ok(date.and_time(time.unwrap_or_default()))
+    }
+
+    /// Parse text value of `timestamptz`, UTC is assumed when time zone is
+    /// not specified.
+    pub fn parse_timestamptz(
+        &self,
+        s: &str,
+    ) -> Result<DateTime<FixedOffset>, Box<dyn Error + Sync + Send>> {
+        let (date, time, tz) = self.split(s)?;
+        let offset = tz.unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        offset
+            .from_local_datetime(&date.and_time(time.unwrap_or_default()))
+            .single()
+            .ok_or_else(|| format!("invalid timestamp: {s}").into())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn split(
+        &self,
+        s: &str,
+    ) -> Result<(NaiveDate, Option<NaiveTime>, Option<FixedOffset>), Box<dyn Error + Sync + Send>>
+    {
+        let invalid = || format!("invalid date/time: {s}");
+        let s = s.trim();
+
+        if s.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return parse_postgres_style(s).ok_or_else(|| invalid().into());
+        }
+
+        let mut parts = s.splitn(2, [' ', 'T']);
+        let date = parts.next().ok_or_else(invalid)?;
+        let date = self.parse_date_part(date).ok_or_else(invalid)?;
+
+        let Some(rest) = parts.next().map(str::trim).filter(|r| !r.is_empty()) else {
+            return Ok((date, None, None));
+        };
+        let (time, tz) = match rest.split_once(' ') {
+            Some((time, tz)) => (time, Some(tz.trim())),
+            None => match rest.rfind(['+', '-', 'Z']) {
+                Some(idx) => (&rest[..idx], Some(&rest[idx..])),
+                None => (rest, None),
+            },
+        };
+        let time = parse_time(time).ok_or_else(invalid)?;
+        let tz = tz
+            .map(|tz| parse_offset(tz).ok_or_else(invalid))
+            .transpose()?;
+
+        Ok((date, Some(time), tz))
+    }
+
+    fn parse_date_part(&self, s: &str) -> Option<NaiveDate> {
+        let mut fields = s.split(['-', '/', '.']);
+        let (a, b, c) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() {
+            return None;
+        }
+
+        // a leading 4-digit year is never ambiguous
+        let (y, m, d) = if a.len() > 2 {
+            (a, b, c)
+        } else {
+            match self.order {
+                DateOrder::Mdy => (c, a, b),
+                DateOrder::Dmy => (c, b, a),
+                DateOrder::Ymd => (a, b, c),
+            }
+        };
+
+        NaiveDate::from_ymd_opt(y.parse().ok()?, m.parse().ok()?, d.parse().ok()?)
+    }
+}
+
+/// Parse `Wed Dec 17 07:37:16 1997 -0800`, day of week and time zone are
+/// optional
+fn parse_postgres_style(s: &str) -> Option<(NaiveDate, Option<NaiveTime>, Option<FixedOffset>)> {
+    let mut tokens = s.split_whitespace().peekable();
+    if tokens.peek()?.len() == 3 && parse_month(tokens.peek()?).is_none() {
+        // day of week
+        tokens.next();
+    }
+    let month = parse_month(tokens.next()?)?;
+    let day = tokens.next()?.parse().ok()?;
+    let time = parse_time(tokens.next()?)?;
+    let year = tokens.next()?.parse().ok()?;
+    let tz = tokens.next().map(parse_offset);
+    if tokens.next().is_some() {
+        return None;
+    }
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    match tz {
+        // unsupported time zone
+        Some(None) => None,
+        tz => Some((date, Some(time), tz.flatten())),
+    }
+}
+
+fn parse_month(s: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let s = s.get(..3)?.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|m| *m == s)
+        .map(|idx| idx as u32 + 1)
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()
+}
+
+/// Parse `Z`, `UTC`, `GMT` and numeric offsets like `+08`, `-08:00` or `+0800`
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    if matches!(s.to_ascii_uppercase().as_str(), "Z" | "UTC" | "GMT") {
+        return FixedOffset::east_opt(0);
+    }
+
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = s[1..].replace(':', "");
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Types that can be parsed from text format with `DateStyleParser`
+pub trait FromDateStyleText: Sized {
+    fn from_date_style_text(
+        parser: &DateStyleParser,
+        s: &str,
+    ) -> Result<Self, Box<dyn Error + Sync + Send>>;
+}
+
+impl FromDateStyleText for NaiveDate {
+    fn from_date_style_text(
+        parser: &DateStyleParser,
+        s: &str,
+    ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        parser.parse_date(s)
+    }
+}
+
+impl FromDateStyleText for NaiveDateTime {
+    fn from_date_style_text(
+        parser: &DateStyleParser,
+        s: &str,
+    ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        parser.parse_timestamp(s)
+    }
+}
+
+impl FromDateStyleText for DateTime<FixedOffset> {
+    fn from_date_style_text(
+        parser: &DateStyleParser,
+        s: &str,
+    ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        parser.parse_timestamptz(s)
+    }
+}
+
+impl FromDateStyleText for DateTime<Utc> {
+    fn from_date_style_text(
+        parser: &DateStyleParser,
+        s: &str,
+    ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        parser.parse_timestamptz(s).map(|t| t.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_date_style() {
+        assert_eq!(
+            DateOrder::Mdy,
+            DateStyleParser::from_date_style("ISO").order()
+        );
+        assert_eq!(
+            DateOrder::Dmy,
+            DateStyleParser::from_date_style("ISO, DMY").order()
+        );
+        assert_eq!(
+            DateOrder::Dmy,
+            DateStyleParser::from_date_style("German").order()
+        );
+        assert_eq!(
+            DateOrder::Ymd,
+            DateStyleParser::from_date_style("German, YMD").order()
+        );
+        assert_eq!(
+            DateOrder::Ymd,
+            DateStyleParser::from_date_style("ISO YMD").order()
+        );
+        assert_eq!(
+            DateOrder::Dmy,
+            DateStyleParser::from_date_style("SQL, European").order()
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let date = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        let mdy = DateStyleParser::new(DateOrder::Mdy);
+        let dmy = DateStyleParser::new(DateOrder::Dmy);
+        let ymd = DateStyleParser::new(DateOrder::Ymd);
+
+        for p in [mdy, dmy, ymd] {
+            assert_eq!(date, p.parse_date("2023-02-01").unwrap());
+        }
+        assert_eq!(date, mdy.parse_date("02/01/2023").unwrap());
+        assert_eq!(date, dmy.parse_date("01.02.2023").unwrap());
+        assert_eq!(date, dmy.parse_date("01/02/2023").unwrap());
+        assert_eq!(date, ymd.parse_date("2023.02.01").unwrap());
+
+        assert!(mdy.parse_date("13/01/2023").is_err());
+        assert!(mdy.parse_date("2023-02-01 10:00:00").is_err());
+        assert!(mdy.parse_date("hello").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let ts = NaiveDate::from_ymd_opt(1997, 12, 17)
+            .unwrap()
+            .and_hms_micro_opt(7, 37, 16, 500000)
+            .unwrap();
+        let mdy = DateStyleParser::new(DateOrder::Mdy);
+        let dmy = DateStyleParser::new(DateOrder::Dmy);
+
+        assert_eq!(ts, mdy.parse_timestamp("1997-12-17 07:37:16.5").unwrap());
+        assert_eq!(ts, mdy.parse_timestamp("1997-12-17T07:37:16.5").unwrap());
+        assert_eq!(ts, mdy.parse_timestamp("12/17/1997 07:37:16.50").unwrap());
+        assert_eq!(
+            ts,
+            dmy.parse_timestamp("17.12.1997 07:37:16.50 UTC").unwrap()
+        );
+        assert_eq!(
+            ts,
+            mdy.parse_timestamp("Wed Dec 17 07:37:16.5 1997").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamptz() {
+        let ts = FixedOffset::west_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(1997, 12, 17, 7, 37, 16)
+            .unwrap();
+        let mdy = DateStyleParser::new(DateOrder::Mdy);
+
+        assert_eq!(ts, mdy.parse_timestamptz("1997-12-17 07:37:16-08").unwrap());
+        assert_eq!(
+            ts,
+            mdy.parse_timestamptz("1997-12-17 07:37:16-08:00").unwrap()
+        );
+        assert_eq!(
+            ts,
+            mdy.parse_timestamptz("Wed Dec 17 07:37:16 1997 -0800")
+                .unwrap()
+        );
+        assert_eq!(
+            ts.with_timezone(&Utc),
+            mdy.parse_timestamptz("1997-12-17 15:37:16Z").unwrap()
+        );
+        assert!(mdy
+            .parse_timestamptz("1997-12-17 07:37:16 Mars/Olympus")
+            .is_err());
+    }
+}
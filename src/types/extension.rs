@@ -0,0 +1,160 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::error::{PgWireError, PgWireResult};
+
+/// Encoding and decoding of a postgres extension type.
+///
+/// Extension types like `ltree` have no fixed oid, it's assigned when the
+/// extension is created in a database. So extensions are identified by type
+/// name. To use them in a `FieldInfo`, create the type with runtime oid using
+/// `Type::new`.
+pub trait TypeExtension: Send + Sync {
+    /// Name of the postgres type, like `ltree`.
+    fn type_name(&self) -> &str;
+
+    /// Encode value into binary format.
+    fn encode_binary(&self, value: &dyn Any, buf: &mut BytesMut) -> PgWireResult<()>;
+
+    /// Encode value into text format.
+    fn encode_text(&self, value: &dyn Any, buf: &mut BytesMut) -> PgWireResult<()>;
+
+    /// Decode value from binary format.
+    fn decode_binary(&self, buf: &[u8]) -> PgWireResult<Box<dyn Any>>;
+}
+
+/// Collection of `TypeExtension`s, keyed by type name.
+#[derive(Default, Clone)]
+pub struct ExtensionRegistry {
+    extensions: HashMap<String, Arc<dyn TypeExtension>>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry::default()
+    }
+
+    /// Register an extension. An existing extension for the same type name is
+    /// replaced.
+    pub fn register(&mut self, extension: Arc<dyn TypeExtension>) {
+        self.extensions
+            .insert(extension.type_name().to_owned(), extension);
+    }
+
+    /// Get extension by type name
+    pub fn get(&self, type_name: &str) -> Option<&Arc<dyn TypeExtension>> {
+        self.extensions.get(type_name)
+    }
+}
+
+impl std::fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtensionRegistry")
+            .field("extensions", &self.extensions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+const LTREE_BINARY_VERSION: u8 = 1;
+
+/// `TypeExtension` for types provided by `ltree` extension: `ltree`, `lquery`
+/// and `ltxtquery`.
+///
+/// Values are `String`s. All three types share the same binary format: a
+/// version byte followed by the text representation.
+#[derive(Debug, Clone)]
+pub struct LtreeExtension {
+    type_name: &'static str,
+}
+
+impl LtreeExtension {
+    pub fn ltree() -> LtreeExtension {
+        LtreeExtension { type_name: "ltree" }
+    }
+
+    pub fn lquery() -> LtreeExtension {
+        LtreeExtension {
+            type_name: "lquery",
+        }
+    }
+
+    pub fn ltxtquery() -> LtreeExtension {
+        LtreeExtension {
+            type_name: "ltxtquery",
+        }
+    }
+
+    fn value<'a>(&self, value: &'a dyn Any) -> PgWireResult<&'a str> {
+        if let Some(s) = value.downcast_ref::<String>() {
+            Ok(s)
+        } else if let Some(s) = value.downcast_ref::<&str>() {
+            Ok(s)
+        } else {
+            Err(PgWireError::InvalidRustTypeForExtension(
+                self.type_name.to_owned(),
+            ))
+        }
+    }
+}
+
+impl TypeExtension for LtreeExtension {
+    fn type_name(&self) -> &str {
+        self.type_name
+    }
+
+    fn encode_binary(&self, value: &dyn Any, buf: &mut BytesMut) -> PgWireResult<()> {
+        let value = self.value(value)?;
+        buf.put_u8(LTREE_BINARY_VERSION);
+        buf.put_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn encode_text(&self, value: &dyn Any, buf: &mut BytesMut) -> PgWireResult<()> {
+        let value = self.value(value)?;
+        buf.put_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn decode_binary(&self, mut buf: &[u8]) -> PgWireResult<Box<dyn Any>> {
+        if !buf.has_remaining() || buf.get_u8() != LTREE_BINARY_VERSION {
+            return Err(PgWireError::FailedToParseParameter(
+                format!("unsupported {} binary version", self.type_name).into(),
+            ));
+        }
+        let value = std::str::from_utf8(buf)
+            .map_err(|e| PgWireError::FailedToParseParameter(Box::new(e)))?;
+        Ok(Box::new(value.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ltree_extension() {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(Arc::new(LtreeExtension::ltree()));
+        registry.register(Arc::new(LtreeExtension::lquery()));
+
+        let ext = registry.get("ltree").unwrap();
+        assert!(registry.get("ltxtquery").is_none());
+
+        let mut buf = BytesMut::new();
+        ext.encode_binary(&"Top.Science.Astronomy".to_owned(), &mut buf)
+            .unwrap();
+        assert_eq!(b"\x01Top.Science.Astronomy", buf.as_ref());
+
+        let decoded = ext.decode_binary(&buf).unwrap();
+        assert_eq!(
+            "Top.Science.Astronomy",
+            decoded.downcast_ref::<String>().unwrap()
+        );
+
+        assert!(ext.encode_binary(&1i32, &mut buf).is_err());
+        assert!(ext.decode_binary(b"\x02Top").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::error::Error;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use postgres_types::{Kind, Type, WasNull};
+
+use super::{DateStyleParser, PgBit, PgDate, PgNumeric};
+
+pub trait FromSqlText: Sized {
+    /// Creates value from text format of Postgres type.
+    ///
+    /// This trait is modelled after `FromSql` from postgres-types, which is
+    /// for binary decoding. Date and time are parsed in ISO style, use
+    /// `FromDateStyleText` for input in other `DateStyle`.
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>>;
+
+    /// Creates value from `NULL`, which is an error unless `Self` is an
+    /// `Option`.
+    fn from_sql_null(_ty: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(WasNull))
+    }
+}
+
+fn to_str(input: &[u8]) -> Result<&str, Box<dyn Error + Sync + Send>> {
+    std::str::from_utf8(input).map_err(Into::into)
+}
+
+impl<T: FromSqlText> FromSqlText for Option<T> {
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        T::from_sql_text(ty, input).map(Some)
+    }
+
+    fn from_sql_null(_ty: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(None)
+    }
+}
+
+impl FromSqlText for bool {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let s = to_str(input)?.trim().to_ascii_lowercase();
+        // like postgres, any unambiguous prefix of the keywords is accepted
+        let prefix_of = |word: &str, min_len: usize| s.len() >= min_len && word.starts_with(&s);
+        if prefix_of("true", 1) || prefix_of("yes", 1) || prefix_of("on", 2) || s == "1" {
+            Ok(true)
+        } else if prefix_of("false", 1) || prefix_of("no", 1) || prefix_of("off", 2) || s == "0" {
+            Ok(false)
+        } else {
+            Err(format!("invalid input for type boolean: {s}").into())
+        }
+    }
+}
+
+impl FromSqlText for String {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        to_str(input).map(ToOwned::to_owned)
+    }
+}
+
+macro_rules! impl_from_sql_text {
+    ($t:ty) => {
+        impl FromSqlText for $t {
+            fn from_sql_text(
+                _ty: &Type,
+                input: &[u8],
+            ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+                to_str(input)?.trim().parse::<$t>().map_err(Into::into)
+            }
+        }
+    };
+}
+
+impl_from_sql_text!(i8);
+impl_from_sql_text!(i16);
+impl_from_sql_text!(i32);
+impl_from_sql_text!(i64);
+impl_from_sql_text!(u32);
+impl_from_sql_text!(f32);
+impl_from_sql_text!(f64);
+impl_from_sql_text!(PgBit);
+impl_from_sql_text!(PgDate);
+impl_from_sql_text!(PgNumeric);
+
+impl FromSqlText for Vec<u8> {
+    /// Decodes `bytea` in either hex format like `\x0102` or the
+    /// traditional escape format.
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if let Some(hex) = input.strip_prefix(b"\\x") {
+            return hex::decode(hex).map_err(Into::into);
+        }
+
+        let mut out = Vec::with_capacity(input.len());
+        let mut iter = input.iter().copied();
+        while let Some(b) = iter.next() {
+            if b != b'\\' {
+                out.push(b);
+                continue;
+            }
+            match iter.next() {
+                Some(b'\\') => out.push(b'\\'),
+                Some(d0 @ b'0'..=b'3') => {
+                    let mut value = d0 - b'0';
+                    for _ in 0..2 {
+                        match iter.next() {
+                            Some(d @ b'0'..=b'7') => value = value * 8 + (d - b'0'),
+                            _ => return Err("invalid input syntax for type bytea".into()),
+                        }
+                    }
+                    out.push(value);
+                }
+                _ => return Err("invalid input syntax for type bytea".into()),
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromSqlText for NaiveDate {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        DateStyleParser::default().parse_date(to_str(input)?)
+    }
+}
+
+impl FromSqlText for NaiveDateTime {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        DateStyleParser::default().parse_timestamp(to_str(input)?)
+    }
+}
+
+impl FromSqlText for NaiveTime {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let s = to_str(input)?.trim();
+        NaiveTime::parse_from_str(s, "%H:%M:%S%.f")
+            .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+            .map_err(Into::into)
+    }
+}
+
+impl FromSqlText for DateTime<FixedOffset> {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        DateStyleParser::default().parse_timestamptz(to_str(input)?)
+    }
+}
+
+impl FromSqlText for DateTime<Utc> {
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        DateTime::<FixedOffset>::from_sql_text(ty, input).map(|t| t.with_timezone(&Utc))
+    }
+}
+
+impl<T: FromSqlText> FromSqlText for Vec<T> {
+    /// Decodes one-dimensional array like `{1,NULL,"a b"}`, elements are
+    /// decoded with the member type of `ty`.
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let member_type = match ty.kind() {
+            Kind::Array(member) => member,
+            _ => ty,
+        };
+        let s = to_str(input)?.trim();
+        let invalid = || format!("malformed array literal: {s}");
+        let body = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or_else(invalid)?;
+
+        let mut values = Vec::new();
+        if body.trim().is_empty() {
+            return Ok(values);
+        }
+
+        let mut chars = body.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            let value = if chars.next_if_eq(&'"').is_some() {
+                let mut element = String::new();
+                loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        '"' => break,
+                        '\\' => element.push(chars.next().ok_or_else(invalid)?),
+                        c => element.push(c),
+                    }
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                T::from_sql_text(member_type, element.as_bytes())?
+            } else {
+                let mut element = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    match c {
+                        // nested arrays are not supported
+                        '{' | '}' | '"' => return Err(invalid().into()),
+                        '\\' => element.push(chars.next().ok_or_else(invalid)?),
+                        c => element.push(c),
+                    }
+                }
+                let element = element.trim_end();
+                if element.is_empty() {
+                    return Err(invalid().into());
+                } else if element.eq_ignore_ascii_case("NULL") {
+                    T::from_sql_null(member_type)?
+                } else {
+                    T::from_sql_text(member_type, element.as_bytes())?
+                }
+            };
+            values.push(value);
+
+            match chars.next() {
+                Some(',') => {}
+                None => return Ok(values),
+                Some(_) => return Err(invalid().into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_sql_text() {
+        assert!(bool::from_sql_text(&Type::BOOL, b"t").unwrap());
+        assert!(bool::from_sql_text(&Type::BOOL, b"on").unwrap());
+        assert!(!bool::from_sql_text(&Type::BOOL, b"FALSE").unwrap());
+        assert!(!bool::from_sql_text(&Type::BOOL, b"0").unwrap());
+        assert!(bool::from_sql_text(&Type::BOOL, b"o").is_err());
+
+        assert_eq!(-42, i32::from_sql_text(&Type::INT4, b" -42").unwrap());
+        assert!(i16::from_sql_text(&Type::INT2, b"70000").is_err());
+        assert_eq!(1.5, f64::from_sql_text(&Type::FLOAT8, b"1.5").unwrap());
+        assert!(f32::from_sql_text(&Type::FLOAT4, b"NaN").unwrap().is_nan());
+        assert_eq!(
+            "-1.50",
+            PgNumeric::from_sql_text(&Type::NUMERIC, b" -1.50")
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            f64::INFINITY,
+            f64::from_sql_text(&Type::FLOAT8, b"Infinity").unwrap()
+        );
+        assert_eq!(
+            "hello",
+            String::from_sql_text(&Type::TEXT, b"hello").unwrap()
+        );
+        assert_eq!(
+            vec![1u8, 0xff],
+            Vec::<u8>::from_sql_text(&Type::BYTEA, b"\\x01ff").unwrap()
+        );
+        assert_eq!(
+            b"a\\\x01".to_vec(),
+            Vec::<u8>::from_sql_text(&Type::BYTEA, b"a\\\\\\001").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_datetime_from_sql_text() {
+        let ts = NaiveDate::from_ymd_opt(2023, 2, 1)
+            .unwrap()
+            .and_hms_micro_opt(10, 20, 30, 500000)
+            .unwrap();
+        assert_eq!(
+            ts,
+            NaiveDateTime::from_sql_text(&Type::TIMESTAMP, b"2023-02-01 10:20:30.5").unwrap()
+        );
+        assert_eq!(
+            ts.date(),
+            NaiveDate::from_sql_text(&Type::DATE, b"2023-02-01").unwrap()
+        );
+        assert_eq!(
+            ts.time(),
+            NaiveTime::from_sql_text(&Type::TIME, b"10:20:30.5").unwrap()
+        );
+        assert_eq!(
+            ts.and_utc(),
+            DateTime::<Utc>::from_sql_text(&Type::TIMESTAMPTZ, b"2023-02-01 18:20:30.5+08")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_array_from_sql_text() {
+        assert_eq!(
+            vec![1, 2, 3],
+            Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"{1, 2,3}").unwrap()
+        );
+        assert_eq!(
+            Vec::<i32>::new(),
+            Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"{}").unwrap()
+        );
+        assert_eq!(
+            vec![
+                Some("a b".to_owned()),
+                None,
+                Some("NULL".to_owned()),
+                Some("x,\"y\"".to_owned())
+            ],
+            Vec::<Option<String>>::from_sql_text(
+                &Type::TEXT_ARRAY,
+                br#"{"a b",NULL,"NULL","x,\"y\""}"#
+            )
+            .unwrap()
+        );
+
+        assert!(Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"{1,NULL}").is_err());
+        assert!(Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"{{1},{2}}").is_err());
+        assert!(Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"{1,}").is_err());
+        assert!(Vec::<i32>::from_sql_text(&Type::INT4_ARRAY, b"1,2").is_err());
+    }
+}
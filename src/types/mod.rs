@@ -6,6 +6,24 @@ use chrono::offset::Utc;
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use postgres_types::{IsNull, Type, WrongType};
 
+mod bit;
+mod date;
+mod datestyle;
+mod extension;
+mod from_sql_text;
+mod numeric;
+#[cfg(feature = "xml")]
+mod xml;
+
+pub use bit::PgBit;
+pub use date::PgDate;
+pub use datestyle::{DateOrder, DateStyleParser, FromDateStyleText, PARAMETER_DATE_STYLE};
+pub use extension::{ExtensionRegistry, LtreeExtension, TypeExtension};
+pub use from_sql_text::FromSqlText;
+pub use numeric::PgNumeric;
+#[cfg(feature = "xml")]
+pub use xml::PgXml;
+
 pub trait ToSqlText: fmt::Debug {
     /// Converts value to text format of Postgres type.
     ///
@@ -20,7 +38,7 @@ pub trait ToSqlText: fmt::Debug {
         Self: Sized;
 }
 
-impl<'a, T> ToSqlText for &'a T
+impl<T> ToSqlText for &T
 where
     T: ToSqlText,
 {
@@ -71,7 +89,7 @@ impl ToSqlText for String {
     }
 }
 
-impl<'a> ToSqlText for &'a str {
+impl ToSqlText for &str {
     fn to_sql_text(
         &self,
         _ty: &Type,
@@ -196,7 +214,8 @@ impl ToSqlText for NaiveDate {
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
         let fmt = match *ty {
-            Type::DATE => self.format("%Y-%m-%d").to_string(),
+            // formatted by `PgDate` for BC years
+            Type::DATE => PgDate::from(*self).to_string(),
             _ => Err(Box::new(WrongType::new::<NaiveDate>(ty.clone())))?,
         };
 
@@ -0,0 +1,471 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use bytes::{Buf, BufMut, BytesMut};
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+const SIGN_POSITIVE: u16 = 0x0000;
+const SIGN_NEGATIVE: u16 = 0x4000;
+const SIGN_NAN: u16 = 0xC000;
+const SIGN_POSITIVE_INFINITY: u16 = 0xD000;
+const SIGN_NEGATIVE_INFINITY: u16 = 0xF000;
+/// Max display scale of postgres `numeric`
+const DSCALE_MAX: i64 = 0x3FFF;
+/// Decimal digits in one base-10000 digit
+const DEC_DIGITS: i64 = 4;
+
+/// Value of postgres `numeric` type, in its binary layout: base-10000 digits
+/// with the weight of the first digit, and display scale which is the number
+/// of decimal digits after the point.
+///
+/// Values are parsed from and displayed as decimal text, like `-12.3400` or
+/// `1.5e3`. With feature `rust_decimal` or `bigdecimal`, it converts from
+/// and to `Decimal` or `BigDecimal`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PgNumeric {
+    sign: u16,
+    weight: i16,
+    scale: u16,
+    digits: Vec<i16>,
+}
+
+impl PgNumeric {
+    pub fn nan() -> PgNumeric {
+        PgNumeric::special(SIGN_NAN)
+    }
+
+    pub fn infinity() -> PgNumeric {
+        PgNumeric::special(SIGN_POSITIVE_INFINITY)
+    }
+
+    pub fn neg_infinity() -> PgNumeric {
+        PgNumeric::special(SIGN_NEGATIVE_INFINITY)
+    }
+
+    fn special(sign: u16) -> PgNumeric {
+        PgNumeric {
+            sign,
+            weight: 0,
+            scale: 0,
+            digits: vec![],
+        }
+    }
+
+    /// Test if the value is `NaN`
+    pub fn is_nan(&self) -> bool {
+        self.sign == SIGN_NAN
+    }
+
+    /// Test if the value is neither `NaN` nor infinite
+    pub fn is_finite(&self) -> bool {
+        matches!(self.sign, SIGN_POSITIVE | SIGN_NEGATIVE)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        matches!(self.sign, SIGN_NEGATIVE | SIGN_NEGATIVE_INFINITY)
+    }
+
+    /// Weight of the first digit, as a power of 10000
+    pub fn weight(&self) -> i16 {
+        self.weight
+    }
+
+    /// Number of decimal digits after the point
+    pub fn scale(&self) -> u16 {
+        self.scale
+    }
+
+    /// Base-10000 digits, without leading and trailing zeros
+    pub fn digits(&self) -> &[i16] {
+        &self.digits
+    }
+
+    /// Create value from decimal digits without sign, where the decimal point
+    /// is after the first `point` digits, which can be out of `digits`.
+    fn from_decimal_digits(
+        negative: bool,
+        digits: &str,
+        point: i64,
+        scale: i64,
+    ) -> Result<PgNumeric, Box<dyn Error + Sync + Send>> {
+        let trimmed = digits.trim_start_matches('0');
+        let mut point = point - (digits.len() - trimmed.len()) as i64;
+        let trimmed = trimmed.trim_end_matches('0');
+
+        let scale = scale.max(0);
+        if scale > DSCALE_MAX {
+            return Err("numeric scale out of range".into());
+        }
+        if trimmed.is_empty() {
+            return Ok(PgNumeric {
+                sign: SIGN_POSITIVE,
+                weight: 0,
+                scale: scale as u16,
+                digits: vec![],
+            });
+        }
+
+        // align groups of 4 digits on the decimal point
+        let pad_left = (DEC_DIGITS - point.rem_euclid(DEC_DIGITS)) % DEC_DIGITS;
+        point += pad_left;
+        let weight = point.div_euclid(DEC_DIGITS) - 1;
+        if weight > i16::MAX as i64 || weight < i16::MIN as i64 {
+            return Err("value overflows numeric format".into());
+        }
+
+        let mut padded = "0".repeat(pad_left as usize);
+        padded.push_str(trimmed);
+        while padded.len() as i64 % DEC_DIGITS != 0 {
+            padded.push('0');
+        }
+        let digits = padded
+            .as_bytes()
+            .chunks(DEC_DIGITS as usize)
+            .map(|group| {
+                group
+                    .iter()
+                    .fold(0i16, |acc, d| acc * 10 + (d - b'0') as i16)
+            })
+            .collect();
+
+        Ok(PgNumeric {
+            sign: if negative {
+                SIGN_NEGATIVE
+            } else {
+                SIGN_POSITIVE
+            },
+            weight: weight as i16,
+            scale: scale as u16,
+            digits,
+        })
+    }
+
+    /// Digit of weight `self.weight - idx`, zero if it's not stored
+    fn digit(&self, idx: i64) -> i16 {
+        usize::try_from(idx)
+            .ok()
+            .and_then(|idx| self.digits.get(idx))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl fmt::Display for PgNumeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sign {
+            SIGN_NAN => return f.write_str("NaN"),
+            SIGN_POSITIVE_INFINITY => return f.write_str("Infinity"),
+            SIGN_NEGATIVE_INFINITY => return f.write_str("-Infinity"),
+            SIGN_NEGATIVE => f.write_char('-')?,
+            _ => {}
+        }
+
+        let weight = self.weight as i64;
+        if weight < 0 {
+            f.write_char('0')?;
+        } else {
+            write!(f, "{}", self.digit(0))?;
+            for idx in 1..=weight {
+                write!(f, "{:04}", self.digit(idx))?;
+            }
+        }
+
+        if self.scale > 0 {
+            let mut fraction = String::new();
+            let mut idx = weight + 1;
+            while fraction.len() < self.scale as usize {
+                write!(fraction, "{:04}", self.digit(idx))?;
+                idx += 1;
+            }
+            fraction.truncate(self.scale as usize);
+            write!(f, ".{fraction}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PgNumeric {
+    type Err = Box<dyn Error + Sync + Send>;
+
+    /// Parse decimal number with optional exponent, `NaN` or `Infinity`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid input syntax for type numeric: {s:?}");
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "nan" => return Ok(PgNumeric::nan()),
+            "infinity" | "+infinity" | "inf" | "+inf" => return Ok(PgNumeric::infinity()),
+            "-infinity" | "-inf" => return Ok(PgNumeric::neg_infinity()),
+            _ => {}
+        }
+
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => {
+                (mantissa, exponent.parse::<i32>().map_err(|_| invalid())?)
+            }
+            None => (unsigned, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if integer.is_empty() && fraction.is_empty() || !is_digits(integer) || !is_digits(fraction)
+        {
+            return Err(invalid().into());
+        }
+
+        let exponent = exponent as i64;
+        PgNumeric::from_decimal_digits(
+            negative,
+            &format!("{integer}{fraction}"),
+            integer.len() as i64 + exponent,
+            fraction.len() as i64 - exponent,
+        )
+    }
+}
+
+impl ToSql for PgNumeric {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i16(self.digits.len() as i16);
+        out.put_i16(self.weight);
+        out.put_u16(self.sign);
+        out.put_u16(self.scale);
+        for digit in &self.digits {
+            out.put_i16(*digit);
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        *ty == Type::NUMERIC
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgNumeric {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() < 8 {
+            return Err("invalid numeric: expected at least 8 bytes".into());
+        }
+        let ndigits = raw.get_i16();
+        let weight = raw.get_i16();
+        let sign = raw.get_u16();
+        let scale = raw.get_u16();
+        if ndigits < 0 || raw.len() != ndigits as usize * 2 {
+            return Err("invalid numeric: wrong number of digits".into());
+        }
+        if !matches!(
+            sign,
+            SIGN_POSITIVE
+                | SIGN_NEGATIVE
+                | SIGN_NAN
+                | SIGN_POSITIVE_INFINITY
+                | SIGN_NEGATIVE_INFINITY
+        ) {
+            return Err(format!("invalid numeric sign: {sign:#06x}").into());
+        }
+
+        let digits = (0..ndigits).map(|_| raw.get_i16()).collect::<Vec<_>>();
+        if digits.iter().any(|d| !(0..10000).contains(d)) {
+            return Err("invalid numeric: digit out of range".into());
+        }
+        Ok(PgNumeric {
+            sign,
+            weight,
+            scale,
+            digits,
+        })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+}
+
+impl ToSqlText for PgNumeric {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl From<rust_decimal::Decimal> for PgNumeric {
+    fn from(value: rust_decimal::Decimal) -> PgNumeric {
+        let digits = value.mantissa().unsigned_abs().to_string();
+        let scale = value.scale() as i64;
+        // scale of `Decimal` is at most 28, always in range
+        PgNumeric::from_decimal_digits(
+            value.is_sign_negative(),
+            &digits,
+            digits.len() as i64 - scale,
+            scale,
+        )
+        .unwrap_or_else(|_| PgNumeric::nan())
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl TryFrom<PgNumeric> for rust_decimal::Decimal {
+    type Error = Box<dyn Error + Sync + Send>;
+
+    /// Fails for `NaN`, infinity, and values out of range or precision of
+    /// `Decimal`.
+    fn try_from(value: PgNumeric) -> Result<Self, Self::Error> {
+        if !value.is_finite() {
+            return Err(format!("cannot convert {value} to Decimal").into());
+        }
+        rust_decimal::Decimal::from_str_exact(&value.to_string()).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "bigdecimal")]
+impl From<bigdecimal::BigDecimal> for PgNumeric {
+    /// Values out of range of `numeric` are converted to `NaN`.
+    fn from(value: bigdecimal::BigDecimal) -> PgNumeric {
+        use bigdecimal::num_bigint::Sign;
+
+        let (mantissa, scale) = value.as_bigint_and_exponent();
+        let digits = mantissa.magnitude().to_string();
+        PgNumeric::from_decimal_digits(
+            mantissa.sign() == Sign::Minus,
+            &digits,
+            digits.len() as i64 - scale,
+            scale,
+        )
+        .unwrap_or_else(|_| PgNumeric::nan())
+    }
+}
+
+#[cfg(feature = "bigdecimal")]
+impl TryFrom<PgNumeric> for bigdecimal::BigDecimal {
+    type Error = Box<dyn Error + Sync + Send>;
+
+    /// Fails for `NaN` and infinity.
+    fn try_from(value: PgNumeric) -> Result<Self, Self::Error> {
+        if !value.is_finite() {
+            return Err(format!("cannot convert {value} to BigDecimal").into());
+        }
+        bigdecimal::BigDecimal::from_str(&value.to_string()).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn numeric(s: &str) -> PgNumeric {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_numeric() {
+        let n = numeric("12345.678");
+        assert_eq!(
+            (1, 3, &[1, 2345, 6780][..]),
+            (n.weight(), n.scale(), n.digits())
+        );
+        assert_eq!("12345.678", n.to_string());
+
+        let n = numeric("-0.0001");
+        assert!(n.is_negative());
+        assert_eq!((-1, 4, &[1][..]), (n.weight(), n.scale(), n.digits()));
+        assert_eq!("-0.0001", n.to_string());
+
+        let n = numeric("1e-8");
+        assert_eq!((-2, 8, &[1][..]), (n.weight(), n.scale(), n.digits()));
+        assert_eq!("0.00000001", n.to_string());
+
+        let n = numeric("10000");
+        assert_eq!((1, 0, &[1][..]), (n.weight(), n.scale(), n.digits()));
+        assert_eq!("10000", n.to_string());
+
+        assert_eq!("1500", numeric("1.5e3").to_string());
+        assert_eq!("0.00", numeric("-0.00").to_string());
+        assert!(!numeric("-0.00").is_negative());
+        assert_eq!("100.10", numeric("+0100.10").to_string());
+        assert!(numeric("NaN").is_nan());
+        assert_eq!("-Infinity", numeric("-infinity").to_string());
+
+        for s in ["", ".", "1.2.3", "abc", "1e", "--1", "1e100000000"] {
+            assert!(s.parse::<PgNumeric>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_numeric_binary() {
+        let mut buf = BytesMut::new();
+        numeric("-12345.678")
+            .to_sql(&Type::NUMERIC, &mut buf)
+            .unwrap();
+        assert_eq!(
+            &[0, 3, 0, 1, 0x40, 0, 0, 3, 0, 1, 0x09, 0x29, 0x1a, 0x7c][..],
+            &buf[..]
+        );
+        assert_eq!(
+            numeric("-12345.678"),
+            PgNumeric::from_sql(&Type::NUMERIC, &buf).unwrap()
+        );
+
+        for s in ["0", "NaN", "Infinity", "0.000001", "99999999.99"] {
+            let mut buf = BytesMut::new();
+            numeric(s).to_sql(&Type::NUMERIC, &mut buf).unwrap();
+            assert_eq!(
+                s,
+                PgNumeric::from_sql(&Type::NUMERIC, &buf)
+                    .unwrap()
+                    .to_string()
+            );
+        }
+
+        assert!(PgNumeric::from_sql(&Type::NUMERIC, &[0, 1, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(
+            PgNumeric::from_sql(&Type::NUMERIC, &[0, 1, 0, 0, 0, 0, 0, 0, 0x27, 0x10]).is_err()
+        );
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn test_rust_decimal() {
+        use rust_decimal::Decimal;
+
+        let decimal = Decimal::from_str("-12345.6780").unwrap();
+        let n = PgNumeric::from(decimal);
+        assert_eq!("-12345.6780", n.to_string());
+        assert_eq!(decimal, Decimal::try_from(n).unwrap());
+
+        assert!(Decimal::try_from(PgNumeric::nan()).is_err());
+        assert!(Decimal::try_from(numeric("1e40")).is_err());
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn test_bigdecimal() {
+        use bigdecimal::BigDecimal;
+
+        let decimal = BigDecimal::from_str("-1234567890123456789012345678901234.5").unwrap();
+        let n = PgNumeric::from(decimal.clone());
+        assert_eq!("-1234567890123456789012345678901234.5", n.to_string());
+        assert_eq!(decimal, BigDecimal::try_from(n).unwrap());
+
+        let n = PgNumeric::from(BigDecimal::from_str("1e20").unwrap());
+        assert_eq!("100000000000000000000", n.to_string());
+        assert!(BigDecimal::try_from(PgNumeric::infinity()).is_err());
+    }
+}
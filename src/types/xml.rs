@@ -0,0 +1,113 @@
+use std::error::Error;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::ToSqlText;
+
+/// Value of postgres `xml` type.
+///
+/// The content is checked to be well-formed before it's encoded, in both text
+/// and binary format, so malformed documents are never sent to clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PgXml(pub String);
+
+impl PgXml {
+    /// Check if the content is well-formed xml.
+    pub fn validate(&self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let mut reader = Reader::from_str(&self.0);
+        let mut depth = 0usize;
+        loop {
+            match reader.read_event()? {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth = depth.saturating_sub(1),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if depth != 0 {
+            return Err("invalid xml: unclosed element".into());
+        }
+        Ok(())
+    }
+}
+
+impl From<String> for PgXml {
+    fn from(s: String) -> Self {
+        PgXml(s)
+    }
+}
+
+impl ToSql for PgXml {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.validate()?;
+        out.put_slice(self.0.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        *ty == Type::XML
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgXml {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(PgXml(std::str::from_utf8(raw)?.to_owned()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::XML
+    }
+}
+
+impl ToSqlText for PgXml {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        self.validate()?;
+        out.put_slice(self.0.as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xml() {
+        let xml = PgXml::from("<a><b x=\"1\">text</b><c/></a>".to_owned());
+        let mut buf = BytesMut::new();
+        xml.to_sql(&Type::XML, &mut buf).unwrap();
+        assert_eq!(xml.0.as_bytes(), buf.as_ref());
+        assert_eq!(xml, PgXml::from_sql(&Type::XML, &buf).unwrap());
+
+        let mut buf = BytesMut::new();
+        assert!(PgXml("<a><b></a>".to_owned())
+            .to_sql(&Type::XML, &mut buf)
+            .is_err());
+        assert!(PgXml("<a>".to_owned())
+            .to_sql(&Type::XML, &mut buf)
+            .is_err());
+
+        let mut buf = BytesMut::new();
+        xml.to_sql_text(&Type::XML, &mut buf).unwrap();
+        assert_eq!(xml.0.as_bytes(), buf.as_ref());
+        assert!(PgXml("<a><b></a>".to_owned())
+            .to_sql_text(&Type::XML, &mut buf)
+            .is_err());
+    }
+}
@@ -96,7 +96,7 @@ impl SimpleQueryHandler for DummyDatabase {
                 ),
                 (Some(2), None, None, None),
             ];
-            let data_row_stream = stream::iter(data.into_iter()).map(move |r| {
+            let data_row_stream = stream::iter(data).map(move |r| {
                 let mut encoder = DataRowEncoder::new(schema_ref.clone());
 
                 encoder.encode_field(&r.0)?;
@@ -150,7 +150,7 @@ impl ExtendedQueryHandler for DummyDatabase {
             ];
             let schema = Arc::new(self.schema(&portal.result_column_format));
             let schema_ref = schema.clone();
-            let data_row_stream = stream::iter(data.into_iter()).map(move |r| {
+            let data_row_stream = stream::iter(data).map(move |r| {
                 let mut encoder = DataRowEncoder::new(schema_ref.clone());
 
                 encoder.encode_field(&r.0)?;
@@ -161,7 +161,10 @@ impl ExtendedQueryHandler for DummyDatabase {
                 encoder.finish()
             });
 
-            Ok(Response::Query(QueryResponse::new(schema, data_row_stream)))
+            Ok(Response::Query(QueryResponse::new_owned(
+                schema,
+                data_row_stream,
+            )))
         } else {
             Ok(Response::Execution(Tag::new("OK").with_rows(1)))
         }